@@ -0,0 +1,43 @@
+//! Splitter throughput over a long document.
+//!
+//! Run with `cargo bench --bench chunking`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+use openai_test::libs::splitter::{
+    sentences, RecursiveCharacterSplitter, SlidingWindowSplitter, Splitter, TokenSplitter,
+};
+
+/// Copies of `resources/longer.txt` in the benchmarked document, about 280 KB.
+const COPIES: usize = 100;
+
+fn document() -> String {
+    include_str!("../resources/longer.txt").repeat(COPIES)
+}
+
+fn bench_splitters(c: &mut Criterion) {
+    let text = document();
+    // The splitters are async, but only `SemanticSplitter` (left out, it calls the
+    // embeddings API) awaits anything.
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("split");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.sample_size(20);
+
+    let splitters: Vec<(&str, Box<dyn Splitter>)> = vec![
+        ("token", Box::new(TokenSplitter::builder().build())),
+        ("sliding_window", Box::new(SlidingWindowSplitter::builder().build())),
+        ("recursive_character", Box::new(RecursiveCharacterSplitter::builder().build())),
+    ];
+    for (name, splitter) in &splitters {
+        group.bench_function(*name, |b| {
+            b.iter(|| runtime.block_on(splitter.split(black_box(&text))).unwrap().len())
+        });
+    }
+    group.bench_function("sentences", |b| b.iter(|| sentences(black_box(&text)).len()));
+    group.finish();
+}
+
+criterion_group!(benches, bench_splitters);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+//! Encoding and decoding of the embedding BLOBs kept by the `Database` backends.
+//!
+//! Run with `cargo bench --bench embedding_codec`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use openai_test::libs::database::{convert_binary_to_embeddings, convert_embeddings_to_binary};
+
+/// Dimensions of ada-002 / text-embedding-3-small and of text-embedding-3-large.
+const DIMENSIONS: [usize; 2] = [1536, 3072];
+
+fn embedding(dimension: usize) -> Vec<f32> {
+    (0..dimension).map(|i| (i as f32).sin() / 7.0).collect()
+}
+
+fn bench_codec(c: &mut Criterion) {
+    let mut group = c.benchmark_group("embedding_codec");
+    for dimension in DIMENSIONS {
+        let values = embedding(dimension);
+        let encoded = convert_embeddings_to_binary(&values);
+        // BLOBs written before the header was added: bare little-endian floats.
+        let legacy: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        group.throughput(Throughput::Bytes(encoded.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("encode", dimension), &values, |b, values| {
+            b.iter(|| convert_embeddings_to_binary(black_box(values)))
+        });
+        group.bench_with_input(BenchmarkId::new("decode", dimension), &encoded, |b, encoded| {
+            b.iter(|| convert_binary_to_embeddings(black_box(encoded)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("decode_legacy", dimension), &legacy, |b, legacy| {
+            b.iter(|| convert_binary_to_embeddings(black_box(legacy)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_codec);
+criterion_main!(benches);
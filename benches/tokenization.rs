@@ -0,0 +1,29 @@
+//! cl100k_base encoding through the encoder cached in `openai_api`.
+//!
+//! Run with `cargo bench --bench tokenization`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use openai_test::libs::openai_api::{get_tokens, truncate_to_tokens};
+
+fn bench_encode(c: &mut Criterion) {
+    let paragraph = include_str!("../resources/longer.txt");
+    // Load the encoder outside the measurements.
+    get_tokens("warm up").unwrap();
+
+    let mut group = c.benchmark_group("get_tokens");
+    for copies in [1, 10, 100] {
+        let text = paragraph.repeat(copies);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(text.len()), &text, |b, text| {
+            b.iter(|| get_tokens(black_box(text)).unwrap().len())
+        });
+    }
+    group.finish();
+
+    let text = paragraph.repeat(10);
+    c.bench_function("truncate_to_tokens/512", |b| b.iter(|| truncate_to_tokens(black_box(&text), 512).len()));
+}
+
+criterion_group!(benches, bench_encode);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+//! Upsert payload construction: serde_json versus `write_vectors_json`.
+//!
+//! Run with `cargo bench --bench vector_serialization`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use openai_test::libs::pinecone_data::{write_vectors_json, Metadata, Vector};
+
+const BATCH_SIZE: usize = 100;
+const DIMENSION: usize = 1536;
+
+fn batch() -> Vec<Vector> {
+    (0..BATCH_SIZE)
+        .map(|i| {
+            let values = (0..DIMENSION).map(|j| ((i * DIMENSION + j) as f32).sin() / 7.0).collect();
+            let mut metadata = Metadata::new();
+            metadata.insert("document_id".to_string(), format!("doc-{}", i / 10).into());
+            metadata.insert("text".to_string(), "Refunds are issued within 30 days of purchase. ".repeat(8).into());
+            Vector::builder().id(format!("doc-{}-{}", i / 10, i % 10)).values(values).metadata(metadata).build()
+        })
+        .collect()
+}
+
+fn bench_upsert_payload(c: &mut Criterion) {
+    let vectors = batch();
+    let mut group = c.benchmark_group("upsert_payload");
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+
+    group.bench_function("serde_json", |b| b.iter(|| serde_json::to_vec(black_box(&vectors)).unwrap()));
+
+    let mut buffer = Vec::new();
+    group.bench_function("write_vectors_json", |b| {
+        b.iter(|| {
+            buffer.clear();
+            write_vectors_json(black_box(&vectors), &mut buffer);
+            buffer.len()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_upsert_payload);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        // protox parses the .proto in Rust, so building does not need protoc installed.
+        println!("cargo:rerun-if-changed=proto/rag.proto");
+        let descriptors = protox::compile(["proto/rag.proto"], ["proto"])?;
+        tonic_build::configure().build_client(false).compile_fds(descriptors)?;
+    }
+    Ok(())
+}
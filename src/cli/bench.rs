@@ -0,0 +1,139 @@
+use std::error::Error;
+
+use clap::{Args, Subcommand};
+
+use openai_test::libs::bench::{BenchReport, Benchmark};
+use openai_test::libs::pinecone_api::PineconeClient;
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    #[command(subcommand)]
+    pub operation: Operation,
+
+    /// Requests to send.
+    #[arg(long, global = true, default_value_t = 100)]
+    pub requests: usize,
+
+    /// Requests in flight at once.
+    #[arg(long, global = true, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// Requests sent before measuring, to warm up connections.
+    #[arg(long, global = true, default_value_t = 0)]
+    pub warmup: usize,
+
+    /// Run against local fake OpenAI and Pinecone servers instead of the live services,
+    /// to measure this client's own overhead.
+    #[cfg(feature = "test-util")]
+    #[arg(long, global = true)]
+    pub fake: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Operation {
+    /// Embed a text once per request.
+    Embed {
+        /// Embedding model id.
+        #[arg(long, default_value = "text-embedding-ada-002")]
+        model: String,
+
+        /// Text to embed. Defaults to about 200 tokens of filler.
+        #[arg(long)]
+        text: Option<String>,
+    },
+    /// Upsert a batch of random vectors per request; they are deleted afterwards.
+    Upsert {
+        /// Namespace to write to.
+        #[arg(long, default_value = "bench")]
+        namespace: String,
+
+        /// Dimension of the index.
+        #[arg(long, default_value_t = 1536)]
+        dimension: usize,
+
+        /// Vectors per request.
+        #[arg(long, default_value_t = 100)]
+        batch_size: usize,
+    },
+    /// Query for the closest vectors to a random vector per request.
+    Query {
+        /// Namespace to query.
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Dimension of the index.
+        #[arg(long, default_value_t = 1536)]
+        dimension: usize,
+
+        #[arg(long, default_value_t = 10)]
+        top_k: i64,
+    },
+}
+
+impl Tabular for BenchReport {
+    fn headers(&self) -> Vec<&'static str> {
+        vec![
+            "operation", "requests", "errors", "concurrency", "rps", "mean_ms", "p50_ms", "p95_ms", "p99_ms", "max_ms",
+        ]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let ms = |value: Option<f64>| value.map_or("-".to_string(), |value| format!("{:.1}", value));
+        let latency = self.latency_ms();
+        vec![vec![
+            self.operation().clone(),
+            self.requests().to_string(),
+            latency.map_or(self.requests(), |latency| latency.errors()).to_string(),
+            self.concurrency().to_string(),
+            format!("{:.1}", self.throughput()),
+            ms(latency.map(|latency| latency.mean())),
+            ms(latency.map(|latency| latency.p50())),
+            ms(latency.map(|latency| latency.p95())),
+            ms(latency.map(|latency| latency.p99())),
+            ms(latency.map(|latency| latency.max())),
+        ]]
+    }
+}
+
+pub async fn run(args: BenchArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "test-util")]
+    let _fakes = match args.fake {
+        true => Some(openai_test::libs::test_util::FakeServices::start().await),
+        false => None,
+    };
+
+    let bench = Benchmark::builder()
+        .requests(args.requests)
+        .concurrency(args.concurrency)
+        .warmup(args.warmup)
+        .build();
+    let report = match &args.operation {
+        Operation::Embed { model, text } => {
+            let filler = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+            bench.embed(model, text.as_deref().unwrap_or(&filler)).await
+        }
+        Operation::Upsert {
+            namespace,
+            dimension,
+            batch_size,
+        } => bench.upsert(&PineconeClient::namespace(namespace), *dimension, *batch_size).await,
+        Operation::Query {
+            namespace,
+            dimension,
+            top_k,
+        } => {
+            let client = match namespace {
+                Some(namespace) => PineconeClient::namespace(namespace),
+                None => PineconeClient::default(),
+            };
+            bench.query(&client, *dimension, *top_k).await
+        }
+    };
+
+    if let Some(error) = report.first_error() {
+        format.note(&format!("First error: {}", error));
+    }
+    format.print(&report)
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use clap::{Args, CommandFactory};
+use clap_complete::Shell;
+
+use super::Cli;
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /// Shell to print the completion script for.
+    #[arg(value_enum, required_unless_present = "man")]
+    pub shell: Option<Shell>,
+
+    /// Write man pages, one per subcommand, into this directory instead.
+    #[arg(long, conflicts_with = "shell")]
+    pub man: Option<PathBuf>,
+}
+
+/// Prints the completion script for a shell, or writes the man pages, generated from the
+/// command definitions so they never fall behind the flags.
+///
+/// ```sh
+/// openai-pinecone completions bash > ~/.local/share/bash-completion/completions/openai-pinecone
+/// openai-pinecone completions zsh > ~/.zfunc/_openai-pinecone
+/// openai-pinecone completions fish > ~/.config/fish/completions/openai-pinecone.fish
+/// openai-pinecone completions --man /usr/local/share/man/man1
+/// ```
+pub fn run(args: CompletionsArgs) -> Result<(), Box<dyn Error>> {
+    let mut command = Cli::command();
+    if let Some(dir) = &args.man {
+        fs::create_dir_all(dir)?;
+        clap_mangen::generate_to(command, dir)?;
+        eprintln!("Wrote man pages to {}", dir.display());
+        return Ok(());
+    }
+
+    if let Some(shell) = args.shell {
+        let name = command.get_name().to_string();
+        clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+    }
+    Ok(())
+}
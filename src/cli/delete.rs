@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use clap::{ArgGroup, Args};
+use serde::Serialize;
+
+use openai_test::libs::audit::{AuditLog, AuditOperation};
+use openai_test::libs::pinecone_api::{self, describe_index_stats, PineconeClient};
+use openai_test::libs::pinecone_data::Filter;
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("target").required(true).args(["ids", "filter", "all"])))]
+pub struct DeleteArgs {
+    /// Comma separated ids of the vectors to delete.
+    #[arg(long, value_delimiter = ',')]
+    pub ids: Vec<String>,
+
+    /// Delete the vectors whose metadata matches this JSON filter, e.g. '{"source": "old"}'.
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Delete every vector of the namespace. Needs `--confirm`.
+    #[arg(long)]
+    pub all: bool,
+
+    /// Confirms `--all`. Without it, `--all` only reports how many vectors it would delete.
+    #[arg(long)]
+    pub confirm: bool,
+
+    /// Namespace to delete from.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Local database the audit log is kept in.
+    #[arg(long, default_value = "openai-pinecone.db")]
+    pub db: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DeleteOutput {
+    operation: AuditOperation,
+    namespace: Option<String>,
+
+    /// Vectors targeted, if known; deletes by filter are not counted.
+    affected: Option<u64>,
+}
+
+impl Tabular for DeleteOutput {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["operation", "namespace", "affected"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            format!("{:?}", self.operation),
+            self.namespace.clone().unwrap_or_default(),
+            self.affected.map(|affected| affected.to_string()).unwrap_or_default(),
+        ]]
+    }
+}
+
+/// Deletes vectors by id, by filter, or all of a namespace, recording the delete in the
+/// audit log of `--db`. Refuses `--all` without `--confirm`.
+pub async fn run(args: DeleteArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let filter: Option<Filter> = args.filter.as_deref().map(serde_json::from_str).transpose()?;
+    let client = match &args.namespace {
+        Some(namespace) => PineconeClient::namespace(namespace),
+        None => PineconeClient::default(),
+    };
+
+    let (operation, affected) = if args.all {
+        let stats = describe_index_stats().await?;
+        let namespace = args.namespace.clone().unwrap_or_default();
+        let count = stats.namespaces().get(&namespace).map_or(0, |namespace| namespace.vector_count());
+        if !args.confirm {
+            return Err(format!(
+                "refusing to delete all {} vectors of namespace {:?} without --confirm",
+                count, namespace
+            )
+            .into());
+        }
+        (AuditOperation::DeleteAll, Some(count))
+    } else if filter.is_some() {
+        (AuditOperation::DeleteByFilter, None)
+    } else {
+        (AuditOperation::Delete, Some(args.ids.len() as u64))
+    };
+
+    pinecone_api::set_audit_log(Arc::new(AuditLog::new(super::open_database(&args.db)?)));
+    match (filter, affected) {
+        (_, Some(count)) if args.all => client.delete_all_counted(count).await?,
+        (Some(filter), _) => client.delete_by_filter(filter).await?,
+        (None, _) => client.delete(args.ids).await?,
+    };
+
+    format.print(&DeleteOutput {
+        operation,
+        namespace: args.namespace,
+        affected,
+    })
+}
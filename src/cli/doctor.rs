@@ -0,0 +1,331 @@
+use std::env;
+use std::error::Error;
+
+use clap::Args;
+use serde::Serialize;
+
+use openai_test::libs::health::{check_openai, HealthStatus};
+use openai_test::libs::models;
+use openai_test::libs::pinecone_api::{api_version, describe_index_stats};
+use openai_test::libs::pinecone_data::IndexStats;
+use openai_test::libs::profiles;
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Args)]
+pub struct DoctorArgs {
+    /// Local state database to check.
+    #[arg(long, default_value = "openai-pinecone.db")]
+    pub db: String,
+
+    /// Embedding model the index is expected to hold vectors of.
+    #[arg(long, default_value = "text-embedding-ada-002")]
+    pub embedding_model: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// Result of one check, with what to do about it unless it passed.
+#[derive(Debug, Serialize)]
+struct Check {
+    name: &'static str,
+    #[serde(rename = "status")]
+    outcome: Outcome,
+    detail: String,
+    fix: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DoctorOutput {
+    checks: Vec<Check>,
+    failed: usize,
+}
+
+impl Tabular for DoctorOutput {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["status", "check", "detail", "fix"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.checks
+            .iter()
+            .map(|check| {
+                let status = match check.outcome {
+                    Outcome::Ok => "ok",
+                    Outcome::Warn => "warn",
+                    Outcome::Fail => "FAIL",
+                };
+                vec![
+                    status.to_string(),
+                    check.name.to_string(),
+                    check.detail.clone(),
+                    check.fix.clone().unwrap_or_default(),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            outcome: Outcome::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            name,
+            outcome: Outcome::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Check {
+            name,
+            outcome: Outcome::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Checks the environment, OpenAI and Pinecone credentials, the index dimension, and the
+/// local database, printing a fix for every problem found. Fails if any check failed;
+/// warnings alone do not.
+pub async fn run(args: DoctorArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    dotenv::dotenv().ok();
+    let mut checks = Vec::new();
+
+    let openai_configured = env::var_os("OPENAI_API_KEY").is_some() || env::var_os("OPENAI_API_KEYS").is_some();
+    checks.push(check_openai_key());
+    let pinecone_configured = env::var_os("PINECONE_API_KEY").is_some();
+    checks.push(if pinecone_configured {
+        Check::ok("PINECONE_API_KEY", "set")
+    } else {
+        Check::fail(
+            "PINECONE_API_KEY",
+            "not set",
+            "Add PINECONE_API_KEY=<key> to the environment or .env, from the API Keys page of the Pinecone console.",
+        )
+    });
+    checks.push(match env::var("PINECONE_INDEX") {
+        Ok(index) => Check::ok("PINECONE_INDEX", index),
+        Err(_) => Check::warn(
+            "PINECONE_INDEX",
+            "not set, requests go to the built-in default index host",
+            "Set PINECONE_INDEX to the name of your index.",
+        ),
+    });
+    checks.push(match api_version() {
+        Some(version) if is_api_version(&version) => Check::ok("PINECONE_API_VERSION", version),
+        Some(version) => Check::fail(
+            "PINECONE_API_VERSION",
+            format!("{:?} is not a version", version),
+            "Set PINECONE_API_VERSION to a release like \"2024-07\", or unset it.",
+        ),
+        None => Check::ok("PINECONE_API_VERSION", "not pinned"),
+    });
+    checks.push(check_profiles());
+    checks.push(check_model_chain());
+    #[cfg(feature = "encryption")]
+    checks.push(check_encryption_key());
+
+    if !openai_configured {
+        checks.push(Check::warn("openai", "skipped, no API key", "Set an OpenAI API key first."));
+    } else {
+        checks.push(match super::use_key_pool() {
+            Err(e) => Check::fail("openai", e.to_string(), "Fix OPENAI_API_KEYS: a comma separated list of keys."),
+            Ok(()) => {
+                let health = check_openai().await;
+                match health.status() {
+                    HealthStatus::Up => Check::ok("openai", health.detail()),
+                    HealthStatus::Down => Check::fail("openai", health.detail(), auth_fix(health.detail(), "OpenAI")),
+                }
+            }
+        });
+    }
+
+    if let Some(model) = models::lookup(&args.embedding_model).filter(|model| model.embedding_dimension().is_none()) {
+        checks.push(Check::fail(
+            "embedding model",
+            format!("{} is not an embedding model", model.name()),
+            "Pass an embedding model with --embedding-model, e.g. text-embedding-ada-002.",
+        ));
+    }
+    if !pinecone_configured {
+        checks.push(Check::warn("pinecone", "skipped, no API key", "Set PINECONE_API_KEY first."));
+    } else {
+        match describe_index_stats().await {
+            Ok(stats) => {
+                checks.push(Check::ok("pinecone", format!("{} vectors", stats.total_vector_count())));
+                checks.push(check_dimension(&stats, &args.embedding_model));
+            }
+            Err(e) => checks.push(Check::fail("pinecone", e.to_string(), auth_fix(&e.to_string(), "Pinecone"))),
+        }
+    }
+
+    checks.push(check_database(&args.db));
+
+    let failed = checks.iter().filter(|check| check.outcome == Outcome::Fail).count();
+    let total = checks.len();
+    format.print(&DoctorOutput { checks, failed })?;
+    if failed > 0 {
+        return Err(format!("{} of {} checks failed", failed, total).into());
+    }
+    Ok(())
+}
+
+fn check_openai_key() -> Check {
+    if let Ok(keys) = env::var("OPENAI_API_KEYS") {
+        let count = keys.split(',').filter(|key| !key.trim().is_empty()).count();
+        return match count {
+            0 => Check::fail("OPENAI_API_KEYS", "set but empty", "List at least one key, or unset it to use OPENAI_API_KEY."),
+            _ => Check::ok("OPENAI_API_KEYS", format!("{} keys", count)),
+        };
+    }
+    match env::var("OPENAI_API_KEY") {
+        Ok(key) if key.starts_with("sk-") => Check::ok("OPENAI_API_KEY", "set"),
+        Ok(_) => Check::warn(
+            "OPENAI_API_KEY",
+            "set, but does not start with \"sk-\"",
+            "Check OPENAI_API_KEY holds a secret key, not an organization or project id.",
+        ),
+        Err(_) => Check::fail(
+            "OPENAI_API_KEY",
+            "not set",
+            "Add OPENAI_API_KEY=sk-... to the environment or .env, or list several keys in OPENAI_API_KEYS.",
+        ),
+    }
+}
+
+fn check_profiles() -> Check {
+    match env::var_os("GENERATION_PROFILES") {
+        None => Check::ok("GENERATION_PROFILES", "not set"),
+        Some(path) => match profiles::load_profiles(&path) {
+            Ok(count) => Check::ok("GENERATION_PROFILES", format!("{} profiles", count)),
+            Err(e) => Check::fail(
+                "GENERATION_PROFILES",
+                e.to_string(),
+                format!("Fix or remove {}: a JSON object of profile name to profile.", path.to_string_lossy()),
+            ),
+        },
+    }
+}
+
+fn check_model_chain() -> Check {
+    let Ok(chain) = env::var("OPENAI_MODEL_CHAIN") else {
+        return Check::ok("OPENAI_MODEL_CHAIN", "not set");
+    };
+    let models: Vec<&str> = chain.split(',').map(str::trim).filter(|model| !model.is_empty()).collect();
+    let unknown: Vec<&str> = models.iter().copied().filter(|model| models::lookup(model).is_none()).collect();
+    match (models.len(), unknown.is_empty()) {
+        (0, _) => Check::fail("OPENAI_MODEL_CHAIN", "set but empty", "List the chat models to fall back through, or unset it."),
+        (_, true) => Check::ok("OPENAI_MODEL_CHAIN", models.join(" -> ")),
+        (_, false) => Check::warn(
+            "OPENAI_MODEL_CHAIN",
+            format!("{} not in the model registry", unknown.join(", ")),
+            "Check the model names for typos.",
+        ),
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn check_encryption_key() -> Check {
+    use openai_test::libs::encryption::{Cipher, ENCRYPTION_KEY_VAR};
+
+    if env::var_os(ENCRYPTION_KEY_VAR).is_none() {
+        return Check::ok(ENCRYPTION_KEY_VAR, "not set");
+    }
+    match Cipher::from_env() {
+        Ok(_) => Check::ok(ENCRYPTION_KEY_VAR, "valid"),
+        Err(e) => Check::fail(
+            ENCRYPTION_KEY_VAR,
+            e.to_string(),
+            format!("Set {} to 32 random bytes, base64 encoded (openssl rand -base64 32).", ENCRYPTION_KEY_VAR),
+        ),
+    }
+}
+
+fn check_dimension(stats: &IndexStats, embedding_model: &str) -> Check {
+    let Some(expected) = models::lookup(embedding_model).and_then(|model| model.embedding_dimension()) else {
+        return Check::warn(
+            "index dimension",
+            format!("{}, unknown for {}", stats.dimension(), embedding_model),
+            "Check the index dimension matches the vectors of your embedding model.",
+        );
+    };
+    if stats.dimension() == expected {
+        Check::ok("index dimension", format!("{}, matches {}", expected, embedding_model))
+    } else {
+        Check::fail(
+            "index dimension",
+            format!("{}, but {} returns {} dimensions", stats.dimension(), embedding_model, expected),
+            format!(
+                "Embed with the model the index was created for, or create an index of dimension {} and re-ingest.",
+                expected
+            ),
+        )
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn check_database(path: &str) -> Check {
+    use openai_test::libs::sql_lite::{SQLiteDB, SCHEMA_VERSION};
+
+    match SQLiteDB::schema_version(path) {
+        Ok(None) => Check::ok("database", format!("{} does not exist yet, it is created on first use", path)),
+        Ok(Some(version)) if version == SCHEMA_VERSION => Check::ok("database", format!("schema version {}", version)),
+        Ok(Some(version)) if version < SCHEMA_VERSION => Check::warn(
+            "database",
+            format!("schema version {}, current is {}", version, SCHEMA_VERSION),
+            format!("Back up {}; it is upgraded the next time a command opens it.", path),
+        ),
+        Ok(Some(version)) => Check::fail(
+            "database",
+            format!("schema version {} is newer than this build supports ({})", version, SCHEMA_VERSION),
+            "Upgrade openai-pinecone, or point --db at another file.",
+        ),
+        Err(e) => Check::fail("database", e.to_string(), format!("Check {} is a SQLite database you can read.", path)),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn check_database(path: &str) -> Check {
+    match super::open_database(path) {
+        Ok(_) => Check::ok("database", format!("{} opens", path)),
+        Err(e) => Check::fail("database", e.to_string(), format!("Check {} is readable and not in use.", path)),
+    }
+}
+
+/// What to do about a failed request to `service`, judged from the status in `error`.
+fn auth_fix(error: &str, service: &str) -> String {
+    if error.contains("401") || error.contains("403") {
+        format!("The {} API key was rejected; replace it with a valid one.", service)
+    } else if error.contains("404") {
+        format!("{} could not find the resource; check the index name or base URL.", service)
+    } else if error.contains("429") {
+        format!("{} is rate limiting or the account is out of quota; check billing and try again.", service)
+    } else {
+        format!("Check your network connection and that {} is reachable.", service)
+    }
+}
+
+/// Whether `version` looks like "2024-07".
+fn is_api_version(version: &str) -> bool {
+    let mut parts = version.split('-');
+    let year = parts.next().filter(|year| year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()));
+    let month = parts.next().and_then(|month| month.parse::<u8>().ok()).filter(|month| (1..=12).contains(month));
+    year.is_some() && month.is_some() && parts.next().is_none()
+}
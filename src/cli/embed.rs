@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use serde::Serialize;
+
+use openai_test::libs::embedding_writer::{embed_to_writer, EmbeddingWriter};
+use openai_test::libs::pipeline::Document;
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Args)]
+pub struct EmbedArgs {
+    /// JSON Lines file of documents ({"id": ..., "text": ...}) to embed.
+    pub input: PathBuf,
+
+    /// JSON Lines file the embeddings are written to, one record per document.
+    #[arg(long, default_value = "embeddings.jsonl")]
+    pub out: PathBuf,
+
+    /// Embedding model id.
+    #[arg(long, default_value = "text-embedding-ada-002")]
+    pub model: String,
+
+    /// Number of embedding requests in flight.
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedOutput<'a> {
+    file: &'a Path,
+    embeddings: usize,
+}
+
+impl Tabular for EmbedOutput<'_> {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["file", "embeddings"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.file.display().to_string(), self.embeddings.to_string()]]
+    }
+}
+
+/// Embeds every document of the input file, streaming both files so memory use does not
+/// grow with their size. Lines that are not valid documents are reported and skipped.
+pub async fn run(args: EmbedArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let input = BufReader::new(File::open(&args.input)?);
+    let documents = input
+        .lines()
+        .enumerate()
+        .filter_map(|(n, line)| match line.map_err(|e| e.to_string()).and_then(|line| {
+            serde_json::from_str::<Document>(&line).map_err(|e| e.to_string())
+        }) {
+            Ok(document) => Some((document.id().clone(), document.text().clone())),
+            Err(e) => {
+                format.note(&format!("{}:{}: skipped: {}", args.input.display(), n + 1, e));
+                None
+            }
+        });
+
+    let mut writer = EmbeddingWriter::new(BufWriter::new(File::create(&args.out)?));
+    let result = embed_to_writer(documents, &args.model, args.concurrency, &mut writer).await;
+    writer.flush()?;
+
+    format.print(&EmbedOutput {
+        file: &args.out,
+        embeddings: writer.written(),
+    })?;
+    result.map(|_| ())
+}
@@ -0,0 +1,76 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use serde::Serialize;
+
+use openai_test::libs::npy::EmbeddingMatrix;
+use openai_test::libs::pinecone_api::PineconeClient;
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// File to write: .npz for a zip archive, anything else for a plain .npy array.
+    #[arg(long, default_value = "embeddings.npy")]
+    pub out: PathBuf,
+
+    /// JSON Lines file written by `embed` to export. Without it, vectors are fetched from
+    /// the index.
+    #[arg(long)]
+    pub from: Option<PathBuf>,
+
+    /// Namespace to export from the index.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Only export vectors whose id starts with this prefix, e.g. "doc#chunk".
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ExportOutput<'a> {
+    file: &'a Path,
+    ids_file: &'a Path,
+    rows: usize,
+    dimension: usize,
+}
+
+impl Tabular for ExportOutput<'_> {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["file", "ids_file", "rows", "dimension"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.file.display().to_string(),
+            self.ids_file.display().to_string(),
+            self.rows.to_string(),
+            self.dimension.to_string(),
+        ]]
+    }
+}
+
+pub async fn run(args: ExportArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let matrix = match &args.from {
+        Some(path) => EmbeddingMatrix::from_jsonl(BufReader::new(File::open(path)?))?,
+        None => {
+            let client = match &args.namespace {
+                Some(namespace) => PineconeClient::namespace(namespace),
+                None => PineconeClient::default(),
+            };
+            EmbeddingMatrix::from_index(&client, &args.prefix).await?
+        }
+    };
+
+    let sidecar = matrix.save(&args.out)?;
+    format.print(&ExportOutput {
+        file: &args.out,
+        ids_file: &sidecar,
+        rows: matrix.len(),
+        dimension: matrix.dimension(),
+    })
+}
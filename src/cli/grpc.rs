@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use clap::Args;
+use serde_json::Value;
+use tonic::metadata::MetadataMap;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use openai_test::libs::jobs::{self, JobError, JobKind, JobQueue, JobWorker};
+use openai_test::libs::pinecone_data::Metadata;
+use openai_test::libs::pipeline::Document;
+use openai_test::libs::rag::RagChat;
+use openai_test::libs::search::SemanticSearch;
+
+mod proto {
+    tonic::include_proto!("rag.v1");
+}
+
+use proto::rag_server::{Rag, RagServer};
+
+/// Largest `top_k` a search request may ask for.
+const MAX_TOP_K: i64 = 100;
+
+#[derive(Debug, Args)]
+pub struct GrpcArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    pub addr: SocketAddr,
+
+    /// Key clients must send as `authorization: Bearer <key>` metadata.
+    #[arg(long, env = "SERVER_API_KEY", hide_env_values = true)]
+    pub api_key: String,
+
+    /// Pinecone namespace to ingest into and search.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Local database ingestion jobs are queued in.
+    #[arg(long, default_value = "openai-pinecone.db")]
+    pub db: String,
+
+    /// Largest accepted request message, in bytes.
+    #[arg(long, default_value_t = 1024 * 1024)]
+    pub max_message_bytes: usize,
+
+    /// Requests handled at once per connection; further requests wait.
+    #[arg(long, default_value_t = 16)]
+    pub max_concurrent: usize,
+}
+
+#[derive(Debug)]
+struct RagService {
+    api_key: Arc<String>,
+    namespace: Option<String>,
+    jobs: JobQueue,
+}
+
+/// Runs the gRPC service defined in `proto/rag.proto` and a worker for the ingestion jobs
+/// it queues.
+pub async fn run(args: GrpcArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let jobs = JobQueue::new(super::open_database(&args.db)?);
+    tokio::spawn(JobWorker::builder().queue(jobs.clone()).build().run());
+
+    let service = RagService {
+        api_key: Arc::new(args.api_key),
+        namespace: args.namespace,
+        jobs,
+    };
+
+    println!("Listening on {}", args.addr);
+    Server::builder()
+        .concurrency_limit_per_connection(args.max_concurrent.max(1))
+        .add_service(RagServer::new(service).max_decoding_message_size(args.max_message_bytes))
+        .serve(args.addr)
+        .await?;
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl Rag for RagService {
+    async fn ingest(&self, request: Request<proto::IngestRequest>) -> Result<Response<proto::Job>, Status> {
+        self.authorize(request.metadata())?;
+
+        let documents: Vec<Document> = request
+            .into_inner()
+            .documents
+            .into_iter()
+            .map(|document| {
+                Document::builder()
+                    .id(document.id)
+                    .text(document.text)
+                    .metadata(json_map(document.metadata))
+                    .build()
+            })
+            .collect();
+        if documents.is_empty() {
+            return Err(Status::invalid_argument("no documents"));
+        }
+
+        let job = self
+            .jobs
+            .enqueue(JobKind::IngestDocuments {
+                documents,
+                namespace: self.namespace.clone(),
+            })
+            .await
+            .map_err(job_status)?;
+        Ok(Response::new(job.into()))
+    }
+
+    async fn get_job(&self, request: Request<proto::GetJobRequest>) -> Result<Response<proto::Job>, Status> {
+        self.authorize(request.metadata())?;
+
+        let job = self.jobs.get(&request.into_inner().id).await.map_err(job_status)?;
+        Ok(Response::new(job.into()))
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchResponse>, Status> {
+        self.authorize(request.metadata())?;
+
+        let request = request.into_inner();
+        let top_k = request.top_k.unwrap_or(10);
+        if !(1..=MAX_TOP_K).contains(&top_k) {
+            return Err(Status::invalid_argument(format!(
+                "top_k must be between 1 and {}",
+                MAX_TOP_K
+            )));
+        }
+
+        let search = SemanticSearch::builder().top_k(top_k);
+        let search = match (&self.namespace, request.filter.is_empty()) {
+            (Some(namespace), false) => search.namespace(namespace.clone()).filter(json_map(request.filter)).build(),
+            (Some(namespace), true) => search.namespace(namespace.clone()).build(),
+            (None, false) => search.filter(json_map(request.filter)).build(),
+            (None, true) => search.build(),
+        };
+
+        let matches = search
+            .search(&request.query)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?
+            .into_iter()
+            .map(|m| proto::Match {
+                id: m.id().clone(),
+                score: m.score(),
+                metadata: m.metadata().iter().map(|(key, value)| (key.clone(), text(value))).collect(),
+            })
+            .collect();
+        Ok(Response::new(proto::SearchResponse { matches }))
+    }
+
+    async fn chat(&self, request: Request<proto::ChatRequest>) -> Result<Response<proto::ChatResponse>, Status> {
+        self.authorize(request.metadata())?;
+
+        let chat = match &self.namespace {
+            Some(namespace) => RagChat::builder()
+                .search(SemanticSearch::builder().namespace(namespace.clone()).top_k(4).build())
+                .build(),
+            None => RagChat::builder().build(),
+        };
+
+        let answer = chat
+            .ask(&request.into_inner().question)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        Ok(Response::new(proto::ChatResponse {
+            answer: answer.answer().clone(),
+            sources: answer
+                .sources()
+                .iter()
+                .map(|source| proto::Source {
+                    id: source.id().clone(),
+                    score: source.score(),
+                    source: source.source().clone(),
+                })
+                .collect(),
+            refused: answer.refused(),
+        }))
+    }
+}
+
+impl RagService {
+    // `Status` is what every handler returns; boxing it here would only move the unboxing.
+    #[allow(clippy::result_large_err)]
+    fn authorize(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        let key = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match key {
+            Some(key) if constant_time_eq(key.as_bytes(), self.api_key.as_bytes()) => Ok(()),
+            _ => Err(Status::unauthenticated("missing or invalid API key")),
+        }
+    }
+}
+
+impl From<jobs::Job> for proto::Job {
+    fn from(job: jobs::Job) -> Self {
+        let status = match job.status() {
+            jobs::JobStatus::Queued => proto::JobStatus::Queued,
+            jobs::JobStatus::Running => proto::JobStatus::Running,
+            jobs::JobStatus::Failed => proto::JobStatus::Failed,
+            jobs::JobStatus::Done => proto::JobStatus::Done,
+        };
+        proto::Job {
+            id: job.id().clone(),
+            status: status.into(),
+            created_at: job.created_at(),
+            updated_at: job.updated_at(),
+            error: job.error().clone(),
+            result_json: job.result().as_ref().map(|result| result.to_string()),
+        }
+    }
+}
+
+fn job_status(e: JobError) -> Status {
+    match e {
+        JobError::NotFound(_) => Status::not_found(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+/// Metadata or a filter from the string map of a message, whose values are all strings.
+fn json_map(map: HashMap<String, String>) -> Metadata {
+    map.into_iter().map(|(key, value)| (key, value.into())).collect()
+}
+
+/// A metadata value as a string map value: strings as they are, anything else as JSON.
+fn text(value: &Value) -> String {
+    value.as_str().map_or_else(|| value.to_string(), str::to_string)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,296 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use serde_json::Value;
+
+use openai_test::libs::budget::TokenBudget;
+use openai_test::libs::failures::FailureReport;
+use openai_test::libs::loaders::directory::SkippedFile;
+use openai_test::libs::openai_api::{set_budget, set_usage_ledger};
+use openai_test::libs::pipeline::{IngestionPipeline, IngestionReport};
+use openai_test::libs::redact::Redactor;
+use openai_test::libs::spool::UpsertSpool;
+use openai_test::libs::splitter::{
+    RecursiveCharacterSplitter, SemanticSplitter, SlidingWindowSplitter, Splitter, TokenSplitter,
+};
+use openai_test::libs::usage::UsageLedger;
+use openai_test::libs::watch::DirectoryWatcher;
+
+use super::output::{OutputFormat, Tabular};
+
+/// How often the spool is flushed in watch mode.
+const SPOOL_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Args)]
+pub struct IngestArgs {
+    /// Directory to ingest.
+    pub dir: PathBuf,
+
+    /// Pinecone namespace to upsert into.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Strategy used to split documents into chunks.
+    #[arg(long, value_enum, default_value_t = SplitterKind::Token)]
+    pub splitter: SplitterKind,
+
+    /// Chunk size: tokens for token, window, and semantic splitting; characters for recursive.
+    #[arg(long)]
+    pub chunk_size: Option<usize>,
+
+    /// Tokens shared by consecutive chunks (window splitter).
+    #[arg(long, default_value_t = 64)]
+    pub overlap: usize,
+
+    /// Mask emails, phone numbers, API keys, and SSNs before embedding.
+    #[arg(long)]
+    pub redact: bool,
+
+    /// Keep running and re-ingest files as they change.
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Local database the path to vector id mapping (watch mode), token usage, and time of
+    /// the last ingestion are kept in.
+    #[arg(long, default_value = "openai-pinecone.db")]
+    pub db: String,
+
+    /// Where chunks that failed to embed or upsert are written, for `retry`.
+    #[arg(long, default_value = "ingest-failures.json")]
+    pub failure_report: PathBuf,
+
+    /// Refuse OpenAI requests once an API key has used this many tokens today (counted in `--db`).
+    #[arg(long)]
+    pub daily_token_budget: Option<u64>,
+
+    /// Keep upserts in `--db` while Pinecone is unreachable and upsert them once it answers
+    /// again, including those left by an earlier run.
+    #[arg(long)]
+    pub spool: bool,
+
+    /// Metadata added to every vector, as KEY=VALUE; may be repeated. Values that parse as
+    /// JSON, such as numbers and booleans, are stored as such, others as strings.
+    #[arg(long = "metadata", value_name = "KEY=VALUE", value_parser = parse_metadata)]
+    pub metadata: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Args)]
+pub struct RetryArgs {
+    /// Failure report written by a previous `ingest` run.
+    pub report: PathBuf,
+
+    /// Pinecone namespace to upsert into.
+    #[arg(long)]
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SplitterKind {
+    /// Fixed token budget on word boundaries.
+    Token,
+    /// Sliding token window with overlap.
+    Window,
+    /// Paragraphs, then lines, sentences, and words.
+    Recursive,
+    /// Breaks between dissimilar sentences.
+    Semantic,
+}
+
+impl IngestArgs {
+    fn splitter(&self) -> Arc<dyn Splitter> {
+        match self.splitter {
+            SplitterKind::Token => {
+                Arc::new(TokenSplitter::builder().max_tokens(self.chunk_size.unwrap_or(512)).build())
+            }
+            SplitterKind::Window => Arc::new(
+                SlidingWindowSplitter::builder()
+                    .window_tokens(self.chunk_size.unwrap_or(512))
+                    .overlap_tokens(self.overlap)
+                    .build(),
+            ),
+            SplitterKind::Recursive => Arc::new(
+                RecursiveCharacterSplitter::builder()
+                    .chunk_chars(self.chunk_size.unwrap_or(2000))
+                    .build(),
+            ),
+            SplitterKind::Semantic => {
+                Arc::new(SemanticSplitter::builder().max_tokens(self.chunk_size.unwrap_or(512)).build())
+            }
+        }
+    }
+}
+
+pub async fn run(args: IngestArgs, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let db = super::open_database(&args.db)?;
+    set_usage_ledger(Arc::new(UsageLedger::new(db.clone())));
+    if let Some(daily_tokens) = args.daily_token_budget {
+        let budget = TokenBudget::builder()
+            .db(db.clone())
+            .daily_tokens(daily_tokens)
+            .build();
+        set_budget(Arc::new(budget));
+    }
+
+    let splitter = args.splitter();
+    let defaults = HashMap::from([(args.namespace.clone().unwrap_or_default(), args.metadata.iter().cloned().collect())]);
+    let builder = IngestionPipeline::builder()
+        .splitter(splitter)
+        .state(db.clone())
+        .default_metadata(defaults);
+    let pipeline = match (args.namespace, args.redact) {
+        (Some(namespace), true) => builder.namespace(namespace).redactor(Redactor::new()).build(),
+        (Some(namespace), false) => builder.namespace(namespace).build(),
+        (None, true) => builder.redactor(Redactor::new()).build(),
+        (None, false) => builder.build(),
+    };
+    let spool = args.spool.then(|| Arc::new(UpsertSpool::builder().db(db.clone()).build()));
+    let pipeline = match &spool {
+        Some(spool) => {
+            flush_spool(spool, format).await;
+            pipeline.with_spool(spool.clone())
+        }
+        None => pipeline,
+    };
+
+    if !args.watch {
+        let report = pipeline.ingest_directory(&args.dir).await?;
+        if let Some(spool) = &spool {
+            flush_spool(spool, format).await;
+        }
+        let failure_report = write_failures(&report, &args.failure_report, format)?;
+        return print_report(&report, failure_report, format);
+    }
+    if let Some(spool) = spool {
+        tokio::spawn(spool.flush_every(SPOOL_FLUSH_INTERVAL));
+    }
+
+    let watcher = DirectoryWatcher::builder()
+        .root(args.dir)
+        .pipeline(pipeline)
+        .state(db)
+        .build();
+
+    let report = watcher.sync_all().await?;
+    let failure_report = write_failures(&report, &args.failure_report, format)?;
+    print_report(&report, failure_report, format)?;
+    format.note("Watching for changes...");
+    watcher.run().await?;
+
+    Ok(())
+}
+
+/// Upserts what is spooled, reporting what could not be yet.
+async fn flush_spool(spool: &UpsertSpool, format: OutputFormat) {
+    match spool.flush().await {
+        Ok(0) => {}
+        Ok(flushed) => format.note(&format!("Flushed {} spooled vectors to Pinecone", flushed)),
+        Err(e) => {
+            let batches = spool.len().await.unwrap_or_default();
+            format.note(&format!("{} batches stay spooled, Pinecone is still failing: {}", batches, e));
+        }
+    }
+}
+
+/// Re-ingests the chunks listed in a failure report, rewriting it with what still fails.
+pub async fn retry(args: RetryArgs, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = match args.namespace {
+        Some(namespace) => IngestionPipeline::builder().namespace(namespace).build(),
+        None => IngestionPipeline::builder().build(),
+    };
+
+    let failures = FailureReport::read(&args.report)?;
+    let report = pipeline.retry_failures(&failures).await?;
+
+    if report.failed().is_empty() {
+        std::fs::remove_file(&args.report)?;
+    }
+    let failure_report = write_failures(&report, &args.report, format)?;
+    print_report(&report, failure_report, format)
+}
+
+#[derive(Debug, Serialize)]
+struct IngestOutput<'a> {
+    documents: usize,
+    chunks: usize,
+    upserted: i64,
+
+    /// Chunks skipped because an earlier attempt already upserted them.
+    duplicates: usize,
+
+    /// Chunks spooled during a Pinecone outage, upserted once it is over.
+    spooled: usize,
+    failed: usize,
+
+    /// Where the failed chunks were written, for `retry`.
+    failure_report: Option<&'a Path>,
+    redactions: &'a BTreeMap<String, BTreeMap<String, usize>>,
+    skipped: &'a Vec<SkippedFile>,
+}
+
+impl Tabular for IngestOutput<'_> {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["documents", "chunks", "upserted", "duplicates", "spooled", "failed", "skipped"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.documents.to_string(),
+            self.chunks.to_string(),
+            self.upserted.to_string(),
+            self.duplicates.to_string(),
+            self.spooled.to_string(),
+            self.failed.to_string(),
+            self.skipped.len().to_string(),
+        ]]
+    }
+}
+
+/// Writes the failed chunks of `report` to `path`, if any, and returns the path.
+fn write_failures<'a>(report: &IngestionReport, path: &'a Path, format: OutputFormat) -> std::io::Result<Option<&'a Path>> {
+    if report.failed().is_empty() {
+        return Ok(None);
+    }
+    report.failure_report().write(path)?;
+    format.note(&format!("{} chunks failed; see {}", report.failed().len(), path.display()));
+    Ok(Some(path))
+}
+
+fn parse_metadata(pair: &str) -> Result<(String, Value), String> {
+    let (key, value) = pair.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got {:?}", pair))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    Ok((key.to_string(), value))
+}
+
+fn print_report(
+    report: &IngestionReport,
+    failure_report: Option<&Path>,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    format.print(&IngestOutput {
+        documents: report.documents(),
+        chunks: report.chunks(),
+        upserted: report.upserted(),
+        duplicates: report.duplicates(),
+        spooled: report.spooled(),
+        failed: report.failed().len(),
+        failure_report,
+        redactions: report.redactions(),
+        skipped: report.skipped(),
+    })?;
+
+    // The JSON holds these too; tables list them below the counts.
+    if format == OutputFormat::Table {
+        for (document, counts) in report.redactions() {
+            let counts: Vec<String> = counts.iter().map(|(name, count)| format!("{} {}", count, name)).collect();
+            println!("Redacted {}: {}", document, counts.join(", "));
+        }
+        for skipped in report.skipped() {
+            println!("Skipped {}: {:?}", skipped.path().display(), skipped.reason());
+        }
+    }
+    Ok(())
+}
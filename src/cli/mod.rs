@@ -0,0 +1,158 @@
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+
+use openai_test::libs::api_keys::{KeyPool, KeySelection};
+use openai_test::libs::database::Database;
+use openai_test::libs::fallback::ModelChain;
+use openai_test::libs::openai_api::{set_key_pool, set_model_chain};
+use openai_test::libs::profiles;
+
+use output::OutputFormat;
+
+pub mod bench;
+pub mod completions;
+pub mod delete;
+pub mod doctor;
+pub mod embed;
+pub mod export;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod ingest;
+pub mod output;
+pub mod project;
+pub mod search;
+#[cfg(feature = "server")]
+pub mod serve;
+pub mod stats;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod verify;
+
+#[derive(Debug, Parser)]
+#[command(name = "openai-pinecone", about = "Ingest and query documents with OpenAI and Pinecone")]
+pub struct Cli {
+    /// Format results are printed in.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Ingest a directory into Pinecone.
+    Ingest(ingest::IngestArgs),
+    /// Retry the chunks listed in an ingestion failure report.
+    Retry(ingest::RetryArgs),
+    /// Embed a JSON Lines file of documents into a JSON Lines file of embeddings.
+    Embed(embed::EmbedArgs),
+    /// Export embeddings to NumPy .npy or .npz, with their ids in a JSON sidecar.
+    Export(export::ExportArgs),
+    /// Project embeddings to 2D coordinates for plotting, as CSV or JSON.
+    Project(project::ProjectArgs),
+    /// Measure the latency and throughput of embedding, upsert, or query requests.
+    Bench(bench::BenchArgs),
+    /// Print shell completions, or write man pages.
+    Completions(completions::CompletionsArgs),
+    /// Delete vectors by id, by metadata filter, or all of a namespace (`--all --confirm`),
+    /// recording each delete in the audit log.
+    Delete(delete::DeleteArgs),
+    /// Check configuration and connectivity, and suggest fixes for what is wrong.
+    Doctor(doctor::DoctorArgs),
+    /// Summarize the index, local database, chat cache, token usage, and ingestion.
+    Stats(stats::StatsArgs),
+    /// Search the index, optionally in an interactive browser (`--tui`), or run a batch of
+    /// queries from a file (`--batch`).
+    #[command(alias = "query")]
+    Search(search::SearchArgs),
+    /// Replay cached chat requests and report answers that changed.
+    Verify(verify::VerifyArgs),
+    /// Serve ingest, search, and chat over HTTP.
+    #[cfg(feature = "server")]
+    Serve(serve::ServeArgs),
+    /// Serve ingest, search, and chat over gRPC (see proto/rag.proto).
+    #[cfg(feature = "grpc")]
+    ServeGrpc(grpc::GrpcArgs),
+}
+
+impl Cli {
+    pub async fn run(self) -> Result<(), Box<dyn Error>> {
+        let format = self.output;
+        // These run before the configuration is applied: doctor reports what is wrong with
+        // it, and completions need none.
+        let command = match self.command {
+            Command::Completions(args) => return completions::run(args),
+            Command::Doctor(args) => return doctor::run(args, format).await,
+            command => command,
+        };
+        use_key_pool()?;
+        use_profiles()?;
+        use_model_chain();
+        match command {
+            Command::Ingest(args) => ingest::run(args, format).await,
+            Command::Retry(args) => ingest::retry(args, format).await,
+            Command::Embed(args) => embed::run(args, format).await,
+            Command::Export(args) => export::run(args, format).await,
+            Command::Project(args) => project::run(args, format).await,
+            Command::Completions(_) | Command::Doctor(_) => unreachable!("handled above"),
+            Command::Bench(args) => bench::run(args, format).await,
+            Command::Delete(args) => delete::run(args, format).await,
+            Command::Search(args) => search::run(args, format).await,
+            Command::Stats(args) => stats::run(args, format).await,
+            Command::Verify(args) => verify::run(args, format).await,
+            #[cfg(feature = "server")]
+            Command::Serve(args) => serve::run(args).await,
+            #[cfg(feature = "grpc")]
+            Command::ServeGrpc(args) => grpc::run(args).await,
+        }
+    }
+}
+
+/// Rotates between the comma separated keys in `OPENAI_API_KEYS`, if set, re-reading them
+/// every minute so keys can be swapped without a restart.
+fn use_key_pool() -> Result<(), Box<dyn Error>> {
+    dotenv::dotenv().ok();
+    if env::var_os("OPENAI_API_KEYS").is_none() {
+        return Ok(());
+    }
+
+    let pool = Arc::new(KeyPool::from_env("OPENAI_API_KEYS", KeySelection::LeastRecentlyThrottled)?);
+    set_key_pool(pool.clone());
+    tokio::spawn(pool.reload_every(Duration::from_secs(60)));
+    Ok(())
+}
+
+/// Registers the generation profiles of the JSON file named by `GENERATION_PROFILES`, if set.
+fn use_profiles() -> Result<(), Box<dyn Error>> {
+    if let Some(path) = env::var_os("GENERATION_PROFILES") {
+        profiles::load_profiles(path)?;
+    }
+    Ok(())
+}
+
+/// Falls back through the comma separated chat models in `OPENAI_MODEL_CHAIN`, if set,
+/// e.g. "gpt-4o,gpt-4o-mini,gpt-3.5-turbo".
+fn use_model_chain() {
+    if let Ok(models) = env::var("OPENAI_MODEL_CHAIN") {
+        let models = models.split(',').map(str::trim).filter(|model| !model.is_empty()).map(str::to_string).collect();
+        set_model_chain(Arc::new(ModelChain::new(models)));
+    }
+}
+
+/// Opens the local state database at `path`: SQLite when built with the `sqlite` feature,
+/// otherwise sled.
+pub fn open_database(path: &str) -> Result<Arc<dyn Database>, Box<dyn Error>> {
+    #[cfg(feature = "sqlite")]
+    return Ok(Arc::new(openai_test::libs::sql_lite::SQLiteDB::new(path)?));
+
+    #[cfg(all(not(feature = "sqlite"), feature = "sled"))]
+    return Ok(Arc::new(openai_test::libs::sled_db::SledDB::new(path)?));
+
+    #[cfg(not(any(feature = "sqlite", feature = "sled")))]
+    Err(format!("cannot open {}: built without the sqlite and sled features", path).into())
+}
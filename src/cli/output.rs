@@ -0,0 +1,78 @@
+use std::error::Error;
+use std::io;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// How commands print their result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for reading in a terminal.
+    #[default]
+    Table,
+    /// One JSON document, for scripts and jq.
+    Json,
+    /// The table's rows as CSV, with a header line.
+    Csv,
+}
+
+/// A command result printable in every `OutputFormat`.
+///
+/// The JSON form is the serde serialization, which scripts rely on, so fields are only ever
+/// added to it. Table and CSV print the same `headers` and `rows`.
+pub trait Tabular: Serialize {
+    fn headers(&self) -> Vec<&'static str>;
+
+    fn rows(&self) -> Vec<Vec<String>>;
+}
+
+impl OutputFormat {
+    /// Prints `result` to stdout.
+    pub fn print<T: Tabular>(self, result: &T) -> Result<(), Box<dyn Error>> {
+        match self {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(result)?),
+            OutputFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(io::stdout());
+                writer.write_record(result.headers())?;
+                for row in result.rows() {
+                    writer.write_record(&row)?;
+                }
+                writer.flush()?;
+            }
+            OutputFormat::Table => print_table(&result.headers(), &result.rows()),
+        }
+        Ok(())
+    }
+
+    /// Prints a progress or diagnostic message: to stdout along a table, to stderr
+    /// otherwise, so stdout holds only the JSON or CSV.
+    pub fn note(self, message: &str) {
+        match self {
+            OutputFormat::Table => println!("{}", message),
+            OutputFormat::Json | OutputFormat::Csv => eprintln!("{}", message),
+        }
+    }
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<String>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<w$}", cell, w = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    line(headers.iter().map(|header| header.to_string()).collect());
+    line(widths.iter().map(|width| "-".repeat(*width)).collect());
+    for row in rows {
+        line(row.clone());
+    }
+}
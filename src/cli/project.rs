@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+
+use openai_test::libs::cluster::KMeans;
+use openai_test::libs::npy::EmbeddingMatrix;
+use openai_test::libs::pinecone_api::PineconeClient;
+use openai_test::libs::projection::{ProjectionMethod, Projector};
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// The two principal components.
+    Pca,
+    /// Two random directions.
+    Random,
+}
+
+#[derive(Debug, Args)]
+pub struct ProjectArgs {
+    /// File to write: .json for JSON, anything else for CSV.
+    #[arg(long, default_value = "projection.csv")]
+    pub out: PathBuf,
+
+    /// JSON Lines file written by `embed` to project. Without it, vectors are fetched from
+    /// the index.
+    #[arg(long)]
+    pub from: Option<PathBuf>,
+
+    /// Namespace to project from the index.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Only project vectors whose id starts with this prefix.
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+
+    #[arg(long, value_enum, default_value = "pca")]
+    pub method: Method,
+
+    /// Also cluster the embeddings into this many k-means clusters, for coloring the plot.
+    #[arg(long)]
+    pub clusters: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectOutput<'a> {
+    file: &'a Path,
+    points: usize,
+
+    /// Share of the variance along x and y, for PCA.
+    explained_variance: Option<[f32; 2]>,
+}
+
+impl Tabular for ProjectOutput<'_> {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["file", "points", "explained_x", "explained_y"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let [x, y] = match self.explained_variance {
+            Some([x, y]) => [format!("{:.3}", x), format!("{:.3}", y)],
+            None => [String::new(), String::new()],
+        };
+        vec![vec![self.file.display().to_string(), self.points.to_string(), x, y]]
+    }
+}
+
+pub async fn run(args: ProjectArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let matrix = match &args.from {
+        Some(path) => EmbeddingMatrix::from_jsonl(BufReader::new(File::open(path)?))?,
+        None => {
+            let client = match &args.namespace {
+                Some(namespace) => PineconeClient::namespace(namespace),
+                None => PineconeClient::default(),
+            };
+            EmbeddingMatrix::from_index(&client, &args.prefix).await?
+        }
+    };
+    let embeddings: Vec<(String, Vec<f32>)> = matrix
+        .ids()
+        .iter()
+        .enumerate()
+        .filter_map(|(n, id)| Some((id.clone(), matrix.row(n)?.to_vec())))
+        .collect();
+
+    let method = match args.method {
+        Method::Pca => ProjectionMethod::Pca,
+        Method::Random => ProjectionMethod::Random,
+    };
+    let mut projection = Projector::builder().method(method).build().project(&embeddings)?;
+    if let Some(k) = args.clusters {
+        projection = projection.with_clusters(&KMeans::builder().k(k).build().fit(&embeddings)?);
+    }
+
+    let output = match args.out.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => projection.to_json()?,
+        _ => projection.to_csv()?,
+    };
+    fs::write(&args.out, output)?;
+
+    format.print(&ProjectOutput {
+        file: &args.out,
+        points: projection.points().len(),
+        explained_variance: projection.explained_variance(),
+    })
+}
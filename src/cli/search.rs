@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use clap::Args;
+use serde::Serialize;
+
+use openai_test::libs::pinecone_api::index_host;
+use openai_test::libs::pinecone_data::Match;
+use openai_test::libs::search::{LatencySummary, SemanticSearch};
+
+use super::output::{OutputFormat, Tabular};
+
+/// Characters of chunk text shown per match in tables.
+const EXCERPT_CHARS: usize = 80;
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    /// Text to search for.
+    #[arg(required_unless_present = "batch")]
+    pub query: Option<String>,
+
+    /// File of queries, one per line, to run concurrently instead. Their matches are
+    /// written to `--results` and their latency percentiles printed.
+    #[arg(long, conflicts_with = "query")]
+    pub batch: Option<PathBuf>,
+
+    /// JSON Lines file the matches of a batch are written to, one line per query.
+    #[arg(long, default_value = "results.jsonl")]
+    pub results: PathBuf,
+
+    /// Queries of a batch in flight at once.
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// Pinecone namespace to search.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Number of matches.
+    #[arg(long, default_value_t = 10)]
+    pub top_k: i64,
+
+    /// Drop matches scoring below this.
+    #[arg(long)]
+    pub min_score: Option<f32>,
+
+    /// Rescale scores to cosine similarities by the metric of the index, so --min-score
+    /// means the same on any index.
+    #[arg(long)]
+    pub normalize_scores: bool,
+
+    /// Embedding model the namespace was ingested with.
+    #[arg(long, default_value = "text-embedding-ada-002")]
+    pub embedding_model: String,
+
+    /// Data plane URL or name of a replica of the index, e.g. in another region. Every
+    /// query is sent to both and the first answer is used.
+    #[arg(long)]
+    pub replica: Option<String>,
+
+    /// Browse the matches in an interactive terminal interface.
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    pub tui: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchOutput<'a> {
+    query: &'a str,
+    matches: &'a [Match],
+}
+
+impl Tabular for SearchOutput<'_> {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["rank", "score", "id", "source", "text"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.matches
+            .iter()
+            .enumerate()
+            .map(|(n, m)| {
+                vec![
+                    (n + 1).to_string(),
+                    format!("{:.4}", m.score()),
+                    m.id().clone(),
+                    m.metadata_str("source").unwrap_or_default().to_string(),
+                    excerpt(m.metadata_str("text").unwrap_or_default()),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BatchOutput<'a> {
+    results_file: &'a Path,
+    elapsed_ms: f64,
+    queries_per_second: f64,
+
+    /// None if every query failed.
+    latency_ms: Option<LatencySummary>,
+}
+
+impl Tabular for BatchOutput<'_> {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["queries", "errors", "qps", "mean_ms", "p50_ms", "p90_ms", "p95_ms", "p99_ms", "max_ms"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let Some(latency) = &self.latency_ms else {
+            return Vec::new();
+        };
+        let ms = |value: f64| format!("{:.1}", value);
+        vec![vec![
+            latency.requests().to_string(),
+            latency.errors().to_string(),
+            format!("{:.1}", self.queries_per_second),
+            ms(latency.mean()),
+            ms(latency.p50()),
+            ms(latency.p90()),
+            ms(latency.p95()),
+            ms(latency.p99()),
+            ms(latency.max()),
+        ]]
+    }
+}
+
+pub async fn run(args: SearchArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let builder = SemanticSearch::builder()
+        .embedding_model(args.embedding_model.clone())
+        .top_k(args.top_k)
+        .normalize_scores(args.normalize_scores);
+    let search = match (args.namespace.clone(), args.min_score) {
+        (Some(namespace), Some(min_score)) => builder.namespace(namespace).min_score(min_score).build(),
+        (Some(namespace), None) => builder.namespace(namespace).build(),
+        (None, Some(min_score)) => builder.min_score(min_score).build(),
+        (None, None) => builder.build(),
+    };
+    let search = match &args.replica {
+        Some(replica) if replica.contains("://") => search.with_replica(replica.clone()),
+        Some(index) => search.with_replica(index_host(index).await?),
+        None => search,
+    };
+    let query = match (&args.query, &args.batch) {
+        (_, Some(batch)) => return run_batch(&search, batch, &args, format).await,
+        (Some(query), None) => query,
+        (None, None) => return Err("pass a query or --batch".into()),
+    };
+    let matches = search.search(query).await?;
+    note_race(&search, format);
+
+    #[cfg(feature = "tui")]
+    if args.tui {
+        return super::tui::browse(query, args.namespace.as_deref(), matches).await;
+    }
+
+    format.print(&SearchOutput {
+        query,
+        matches: &matches,
+    })
+}
+
+async fn run_batch(
+    search: &SemanticSearch,
+    batch: &Path,
+    args: &SearchArgs,
+    format: OutputFormat,
+) -> Result<(), Box<dyn Error>> {
+    let queries: Vec<String> = fs::read_to_string(batch)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let started = Instant::now();
+    let results = search.query_many(&queries, args.concurrency).await;
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let mut writer = BufWriter::new(File::create(&args.results)?);
+    for result in &results {
+        serde_json::to_writer(&mut writer, result)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+
+    for result in &results {
+        if let Some(error) = result.error() {
+            format.note(&format!("{:?} failed: {}", result.query(), error));
+        }
+    }
+    note_race(search, format);
+    format.print(&BatchOutput {
+        results_file: &args.results,
+        elapsed_ms: elapsed * 1000.0,
+        queries_per_second: if elapsed > 0.0 { results.len() as f64 / elapsed } else { 0.0 },
+        latency_ms: LatencySummary::from_results(&results),
+    })
+}
+
+/// Reports which of the index and its replica answered first.
+fn note_race(search: &SemanticSearch, format: OutputFormat) {
+    let stats = search.race_stats();
+    if stats.primary_wins() + stats.replica_wins() + stats.failures() > 0 {
+        format.note(&format!(
+            "First answer: index {}, replica {}, neither {}",
+            stats.primary_wins(),
+            stats.replica_wins(),
+            stats.failures()
+        ));
+    }
+}
+
+/// The first line of `text`, cut to `EXCERPT_CHARS`.
+fn excerpt(text: &str) -> String {
+    let line = text.lines().find(|line| !line.trim().is_empty()).unwrap_or("").trim();
+    if line.chars().count() <= EXCERPT_CHARS {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(EXCERPT_CHARS - 1).collect();
+    format!("{}…", cut)
+}
@@ -0,0 +1,276 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{DefaultBodyLimit, Path, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use clap::Args;
+use serde::Deserialize;
+use tower::limit::ConcurrencyLimitLayer;
+
+use openai_test::libs::cache::QueryCache;
+use openai_test::libs::context::RequestContext;
+use openai_test::libs::database::Database;
+use openai_test::libs::health::{self, HealthStatus};
+use openai_test::libs::jobs::{JobError, JobKind, JobQueue, JobWorker};
+use openai_test::libs::pinecone_data::Filter;
+use openai_test::libs::pipeline::Document;
+use openai_test::libs::rag::{RagChat, RagError};
+use openai_test::libs::search::{SearchError, SemanticSearch};
+
+/// `top_k` of search requests that do not set one.
+const DEFAULT_TOP_K: i64 = 10;
+
+/// Largest `top_k` a search request may ask for.
+const MAX_TOP_K: i64 = 100;
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub addr: SocketAddr,
+
+    /// Key clients must send as `Authorization: Bearer <key>` or `x-api-key`.
+    #[arg(long, env = "SERVER_API_KEY", hide_env_values = true)]
+    pub api_key: String,
+
+    /// Pinecone namespace to ingest into and search.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Local database ingestion jobs are queued in.
+    #[arg(long, default_value = "openai-pinecone.db")]
+    pub db: String,
+
+    /// Largest accepted request body, in bytes.
+    #[arg(long, default_value_t = 1024 * 1024)]
+    pub max_body_bytes: usize,
+
+    /// Requests handled at once; further requests wait.
+    #[arg(long, default_value_t = 16)]
+    pub max_concurrent: usize,
+
+    /// Seconds to answer repeated searches from memory instead of embedding and querying
+    /// them again. Off when unset.
+    #[arg(long)]
+    pub cache_ttl: Option<u64>,
+
+    /// File of queries, one per line, searched before listening so their results are
+    /// cached when the first requests arrive.
+    #[arg(long, requires = "cache_ttl")]
+    pub warmup_queries: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+struct AppState {
+    api_key: Arc<String>,
+    namespace: Option<String>,
+    db: Arc<dyn Database>,
+    jobs: JobQueue,
+    query_cache: Option<QueryCache>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IngestBody {
+    documents: Vec<Document>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchBody {
+    query: String,
+    top_k: Option<i64>,
+    filter: Option<Filter>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatBody {
+    question: String,
+}
+
+/// Error response: a status code and a JSON `{"error": message}` body.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<JobError> for ApiError {
+    fn from(e: JobError) -> Self {
+        match e {
+            JobError::NotFound(_) => ApiError(StatusCode::NOT_FOUND, e.to_string()),
+            _ => ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    }
+}
+
+impl From<SearchError> for ApiError {
+    fn from(e: SearchError) -> Self {
+        ApiError(StatusCode::BAD_GATEWAY, e.to_string())
+    }
+}
+
+impl From<RagError> for ApiError {
+    fn from(e: RagError) -> Self {
+        ApiError(StatusCode::BAD_GATEWAY, e.to_string())
+    }
+}
+
+/// Runs the HTTP service and a worker for the ingestion jobs it queues.
+pub async fn run(args: ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let db = super::open_database(&args.db)?;
+    let jobs = JobQueue::new(db.clone());
+    tokio::spawn(JobWorker::builder().queue(jobs.clone()).build().run());
+
+    let state = AppState {
+        api_key: Arc::new(args.api_key),
+        namespace: args.namespace,
+        db,
+        jobs,
+        query_cache: args.cache_ttl.map(|secs| QueryCache::memory(Duration::from_secs(secs))),
+    };
+
+    let queries: Vec<String> = match &args.warmup_queries {
+        Some(path) => fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => Vec::new(),
+    };
+    let primer = semantic_search(&state, DEFAULT_TOP_K, None);
+    let report = health::warmup(Some(state.db.as_ref()), Some(&primer), &queries).await;
+    for step in report.backends() {
+        match step.status() {
+            HealthStatus::Up => println!("Warmed up {} in {}ms: {}", step.name(), step.latency_ms(), step.detail()),
+            HealthStatus::Down => println!("Warm-up of {} failed: {}", step.name(), step.detail()),
+        }
+    }
+
+    let app = Router::new()
+        .route("/ingest", post(ingest))
+        .route("/jobs/:id", get(job))
+        .route("/search", post(search))
+        .route("/chat", post(chat))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        // Added after the API key check, so probes need no key.
+        .route("/health", get(health))
+        .layer(DefaultBodyLimit::max(args.max_body_bytes))
+        .layer(ConcurrencyLimitLayer::new(args.max_concurrent.max(1)))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(args.addr).await?;
+    println!("Listening on {}", args.addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let key = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|value| value.to_str().ok()));
+
+    match key {
+        Some(key) if constant_time_eq(key.as_bytes(), state.api_key.as_bytes()) => {
+            request_context(headers).scope(next.run(request)).await
+        }
+        _ => ApiError(StatusCode::UNAUTHORIZED, "missing or invalid API key".to_string()).into_response(),
+    }
+}
+
+/// Context of a request from its `x-user-id` and `x-request-id` headers, so OpenAI calls
+/// carry the end user and traces the request id.
+fn request_context(headers: &HeaderMap) -> RequestContext {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+    match (header("x-user-id"), header("x-request-id")) {
+        (Some(user), Some(trace_id)) => RequestContext::builder().user(user).trace_id(trace_id).build(),
+        (Some(user), None) => RequestContext::builder().user(user).build(),
+        (None, Some(trace_id)) => RequestContext::builder().trace_id(trace_id).build(),
+        (None, None) => RequestContext::default(),
+    }
+}
+
+/// Queues the documents for ingestion and returns the job, which can be polled at
+/// `/jobs/{id}`.
+async fn ingest(State(state): State<AppState>, Json(body): Json<IngestBody>) -> Result<Response, ApiError> {
+    if body.documents.is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "no documents".to_string()));
+    }
+
+    let job = state
+        .jobs
+        .enqueue(JobKind::IngestDocuments {
+            documents: body.documents,
+            namespace: state.namespace.clone(),
+        })
+        .await?;
+    Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+}
+
+/// Readiness of OpenAI, Pinecone, and the job database: 200 if all are up, else 503.
+async fn health(State(state): State<AppState>) -> Response {
+    let report = health::check_all(Some(state.db.as_ref())).await;
+    let status = match report.ready() {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(report)).into_response()
+}
+
+async fn job(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response, ApiError> {
+    Ok(Json(state.jobs.get(&id).await?).into_response())
+}
+
+async fn search(State(state): State<AppState>, Json(body): Json<SearchBody>) -> Result<Response, ApiError> {
+    let top_k = body.top_k.unwrap_or(DEFAULT_TOP_K);
+    if !(1..=MAX_TOP_K).contains(&top_k) {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("top_k must be between 1 and {}", MAX_TOP_K),
+        ));
+    }
+
+    let search = semantic_search(&state, top_k, body.filter);
+    Ok(Json(search.search(&body.query).await?).into_response())
+}
+
+/// Search of the `/search` endpoint, also used to warm up its cache.
+fn semantic_search(state: &AppState, top_k: i64, filter: Option<Filter>) -> SemanticSearch {
+    let search = SemanticSearch::builder().top_k(top_k);
+    let search = match (state.namespace.clone(), filter) {
+        (Some(namespace), Some(filter)) => search.namespace(namespace).filter(filter).build(),
+        (Some(namespace), None) => search.namespace(namespace).build(),
+        (None, Some(filter)) => search.filter(filter).build(),
+        (None, None) => search.build(),
+    };
+    match &state.query_cache {
+        Some(cache) => search.with_cache(cache.clone()),
+        None => search,
+    }
+}
+
+async fn chat(State(state): State<AppState>, Json(body): Json<ChatBody>) -> Result<Response, ApiError> {
+    let chat = match state.namespace {
+        Some(namespace) => RagChat::builder()
+            .search(SemanticSearch::builder().namespace(namespace).top_k(4).build())
+            .build(),
+        None => RagChat::builder().build(),
+    };
+
+    Ok(Json(chat.ask(&body.question).await?).into_response())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
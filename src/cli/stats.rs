@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use clap::Args;
+use serde::Serialize;
+
+use openai_test::libs::cache::ChatCache;
+use openai_test::libs::pinecone_api::describe_index_stats;
+use openai_test::libs::pipeline::last_ingestions;
+use openai_test::libs::usage::UsageLedger;
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// Local database the cache, token usage, and ingestion times are kept in.
+    #[arg(long, default_value = "openai-pinecone.db")]
+    pub db: String,
+}
+
+#[derive(Debug, Serialize)]
+struct StatsOutput {
+    index: Section<IndexSummary>,
+
+    /// Rows per key prefix (the part of the id before the first ':').
+    database: Section<BTreeMap<String, usize>>,
+    cache: CacheSummary,
+    usage: BTreeMap<String, UsageSummary>,
+    total_cost: f64,
+
+    /// Time of the last ingestion into each namespace, in seconds since the Unix epoch.
+    ingestions: Section<BTreeMap<String, u64>>,
+}
+
+/// A part of the stats, or why it could not be read.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Section<T> {
+    Ok(T),
+    Error { error: String },
+}
+
+#[derive(Debug, Serialize)]
+struct IndexSummary {
+    namespaces: BTreeMap<String, u64>,
+    total_vector_count: u64,
+    dimension: u32,
+    index_fullness: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct CacheSummary {
+    entries: usize,
+    hits: u64,
+    misses: u64,
+    hit_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct UsageSummary {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+
+    /// List price in USD, if the model is known.
+    cost: Option<f64>,
+}
+
+impl<T> Section<T> {
+    fn from_result<E: ToString>(result: Result<T, E>) -> Self {
+        match result {
+            Ok(value) => Section::Ok(value),
+            Err(e) => Section::Error { error: e.to_string() },
+        }
+    }
+}
+
+impl Tabular for StatsOutput {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["section", "item", "value"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut row = |section: &str, item: &str, value: String| {
+            rows.push(vec![section.to_string(), item.to_string(), value]);
+        };
+
+        match &self.index {
+            Section::Ok(index) => {
+                for (namespace, count) in &index.namespaces {
+                    row("index", &namespace_label(namespace), format!("{} vectors", count));
+                }
+                row("index", "total", format!("{} vectors", index.total_vector_count));
+                row("index", "dimension", index.dimension.to_string());
+                row("index", "fullness", format!("{:.1}%", index.index_fullness * 100.0));
+            }
+            Section::Error { error } => row("index", "error", error.clone()),
+        }
+
+        match &self.database {
+            Section::Ok(counts) => {
+                for (prefix, count) in counts {
+                    row("database", prefix, format!("{} rows", count));
+                }
+                row("database", "total", format!("{} rows", counts.values().sum::<usize>()));
+            }
+            Section::Error { error } => row("database", "error", error.clone()),
+        }
+
+        row("cache", "entries", self.cache.entries.to_string());
+        row("cache", "hits", self.cache.hits.to_string());
+        row("cache", "misses", self.cache.misses.to_string());
+        let hit_rate = self.cache.hit_rate.map_or("-".to_string(), |rate| format!("{:.1}%", rate * 100.0));
+        row("cache", "hit rate", hit_rate);
+
+        for (model, usage) in &self.usage {
+            row(
+                "usage",
+                model,
+                format!(
+                    "{} requests, {} prompt + {} completion tokens, {}",
+                    usage.requests,
+                    usage.prompt_tokens,
+                    usage.completion_tokens,
+                    usage.cost.map_or("unknown cost".to_string(), |cost| format!("${:.4}", cost))
+                ),
+            );
+        }
+        row("usage", "total cost", format!("${:.4}", self.total_cost));
+
+        match &self.ingestions {
+            Section::Ok(ingestions) if ingestions.is_empty() => row("ingestion", "last", "never".to_string()),
+            Section::Ok(ingestions) => {
+                for (namespace, at) in ingestions {
+                    row("ingestion", &namespace_label(namespace), format_timestamp(*at));
+                }
+            }
+            Section::Error { error } => row("ingestion", "error", error.clone()),
+        }
+        rows
+    }
+}
+
+/// Prints the state of the index, the local database, the chat cache, token usage, and
+/// ingestion in one table. Sections whose source is unavailable show the error instead.
+pub async fn run(args: StatsArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let db = super::open_database(&args.db)?;
+
+    let index = Section::from_result(describe_index_stats().await.map(|stats| IndexSummary {
+        namespaces: stats
+            .namespaces()
+            .iter()
+            .map(|(namespace, namespace_stats)| (namespace.clone(), namespace_stats.vector_count()))
+            .collect(),
+        total_vector_count: stats.total_vector_count(),
+        dimension: stats.dimension(),
+        index_fullness: stats.index_fullness(),
+    }));
+
+    let database = Section::from_result(db.ids("").await.map(|ids| {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for id in &ids {
+            let prefix = id.split(':').next().unwrap_or(id);
+            *counts.entry(prefix.to_string()).or_default() += 1;
+        }
+        counts
+    }));
+
+    let chat_cache = ChatCache::new(db.clone());
+    let stats = chat_cache.stats().await?;
+    let cache = CacheSummary {
+        entries: chat_cache.entries().await?,
+        hits: stats.hits(),
+        misses: stats.misses(),
+        hit_rate: stats.hit_rate(),
+    };
+
+    let usage: BTreeMap<String, UsageSummary> = UsageLedger::new(db.clone())
+        .usage()
+        .await?
+        .into_iter()
+        .map(|(model, usage)| {
+            let summary = UsageSummary {
+                requests: usage.requests(),
+                prompt_tokens: usage.prompt_tokens(),
+                completion_tokens: usage.completion_tokens(),
+                cost: usage.cost(&model),
+            };
+            (model, summary)
+        })
+        .collect();
+    let total_cost = usage.values().filter_map(|usage| usage.cost).sum();
+
+    let ingestions = Section::from_result(
+        last_ingestions(db.as_ref()).await.map(|ingestions| ingestions.into_iter().collect()),
+    );
+
+    format.print(&StatsOutput {
+        index,
+        database,
+        cache,
+        usage,
+        total_cost,
+        ingestions,
+    })
+}
+
+fn namespace_label(namespace: &str) -> String {
+    if namespace.is_empty() {
+        "(default)".to_string()
+    } else {
+        namespace.to_string()
+    }
+}
+
+/// Seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS UTC".
+fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
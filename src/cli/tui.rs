@@ -0,0 +1,182 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use openai_test::libs::pinecone_api::PineconeClient;
+use openai_test::libs::pinecone_data::Match;
+
+const HELP: &str = "↑/↓ select  PgUp/PgDn scroll  o open source  d delete vector  q quit";
+
+/// State of the search result browser.
+struct Browser<'a> {
+    query: &'a str,
+    client: PineconeClient,
+    matches: Vec<Match>,
+    list: ListState,
+    scroll: u16,
+
+    /// Id of the vector waiting for the delete to be confirmed.
+    confirm_delete: Option<String>,
+    status: String,
+}
+
+/// Lists `matches` of `query` with their scores, and shows the metadata and chunk text of
+/// the selected one. The source document can be opened with the system's default
+/// application, and the vector deleted from `namespace` after a confirmation.
+pub async fn browse(query: &str, namespace: Option<&str>, matches: Vec<Match>) -> Result<(), Box<dyn Error>> {
+    let mut browser = Browser {
+        query,
+        client: match namespace {
+            Some(namespace) => PineconeClient::namespace(namespace),
+            None => PineconeClient::default(),
+        },
+        list: ListState::default().with_selected((!matches.is_empty()).then_some(0)),
+        matches,
+        scroll: 0,
+        confirm_delete: None,
+        status: HELP.to_string(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = browser.run(&mut terminal).await;
+    ratatui::restore();
+    result
+}
+
+impl Browser<'_> {
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if !event::poll(Duration::from_millis(250))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if let Some(id) = self.confirm_delete.take() {
+                if key.code == KeyCode::Char('y') {
+                    self.delete(&id).await;
+                } else {
+                    self.status = format!("Kept {}", id);
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => self.select(1),
+                KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+                KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+                KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+                KeyCode::Char('o') => self.open_source(),
+                KeyCode::Char('d') => {
+                    if let Some(selected) = self.selected() {
+                        let id = selected.id().clone();
+                        self.status = format!("Delete {} from the index? y to confirm, any other key to keep", id);
+                        self.confirm_delete = Some(id);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn selected(&self) -> Option<&Match> {
+        self.list.selected().and_then(|n| self.matches.get(n))
+    }
+
+    fn select(&mut self, step: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0) as isize;
+        let next = (current + step).clamp(0, self.matches.len() as isize - 1);
+        self.list.select(Some(next as usize));
+        self.scroll = 0;
+    }
+
+    fn open_source(&mut self) {
+        let Some(source) = self.selected().and_then(|m| m.metadata_str("source")).map(str::to_string) else {
+            self.status = "The match has no source metadata".to_string();
+            return;
+        };
+        self.status = if !Path::new(&source).exists() && !source.contains("://") {
+            format!("{} does not exist here", source)
+        } else {
+            match open::that_detached(&source) {
+                Ok(()) => format!("Opened {}", source),
+                Err(e) => format!("Failed to open {}: {}", source, e),
+            }
+        };
+    }
+
+    async fn delete(&mut self, id: &str) {
+        match self.client.delete(vec![id.to_string()]).await {
+            Ok(_) => {
+                self.matches.retain(|m| m.id() != id);
+                let last = self.matches.len().checked_sub(1);
+                self.list.select(self.list.selected().zip(last).map(|(n, last)| n.min(last)));
+                self.scroll = 0;
+                self.status = format!("Deleted {}", id);
+            }
+            Err(e) => self.status = format!("Failed to delete {}: {}", id, e),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|m| ListItem::new(Line::from(vec![Span::raw(format!("{:.4} ", m.score())).bold(), Span::raw(m.id().clone())])))
+            .collect();
+        let title = format!(" {} matches for {:?} ", self.matches.len(), self.query);
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, left, &mut self.list);
+
+        let preview = match self.selected() {
+            None => vec![Line::from("No matches")],
+            Some(selected) => preview(selected),
+        };
+        let preview = Paragraph::new(preview)
+            .block(Block::bordered().title(" Preview "))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(preview, right);
+
+        frame.render_widget(Paragraph::new(self.status.as_str()).dim(), status);
+    }
+}
+
+/// Id, score, metadata other than the text, then the chunk text.
+fn preview(selected: &Match) -> Vec<Line<'static>> {
+    let mut lines = vec![
+        Line::from(vec![Span::raw("id: ").bold(), Span::raw(selected.id().clone())]),
+        Line::from(vec![Span::raw("score: ").bold(), Span::raw(format!("{:.4}", selected.score()))]),
+    ];
+    let mut metadata: Vec<_> = selected.metadata().iter().filter(|(key, _)| *key != "text").collect();
+    metadata.sort_by_key(|(key, _)| *key);
+    for (key, value) in metadata {
+        let value = value.as_str().map_or_else(|| value.to_string(), str::to_string);
+        lines.push(Line::from(vec![Span::raw(format!("{}: ", key)).bold(), Span::raw(value)]));
+    }
+    lines.push(Line::from(""));
+    let text = selected.metadata_str("text").unwrap_or("(no text metadata)");
+    lines.extend(text.lines().map(|line| Line::from(line.to_string())));
+    lines
+}
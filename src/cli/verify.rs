@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::Args;
+use serde::Serialize;
+
+use openai_test::libs::cache::ChatCache;
+use openai_test::libs::openai_api::OpenAIRequest;
+
+use super::output::{OutputFormat, Tabular};
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// JSON file with a list of chat request bodies to replay.
+    pub requests: PathBuf,
+
+    /// Local database the responses were cached in.
+    #[arg(long, default_value = "openai-pinecone.db")]
+    pub db: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifyOutput {
+    results: Vec<VerifyResult>,
+    drifted: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifyResult {
+    prompt_hash: String,
+
+    /// "matches", "drifted", or "not_recorded".
+    status: &'static str,
+}
+
+impl Tabular for VerifyOutput {
+    fn headers(&self) -> Vec<&'static str> {
+        vec!["prompt_hash", "status"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.results
+            .iter()
+            .map(|result| vec![result.prompt_hash.clone(), result.status.to_string()])
+            .collect()
+    }
+}
+
+/// Replays every request and reports whether the answer still matches the cached one.
+/// Fails if any answer drifted.
+pub async fn run(args: VerifyArgs, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let requests: Vec<OpenAIRequest> = serde_json::from_str(&fs::read_to_string(&args.requests)?)?;
+    let cache = ChatCache::new(super::open_database(&args.db)?);
+
+    let mut output = VerifyOutput {
+        results: Vec::new(),
+        drifted: 0,
+    };
+    for request in &requests {
+        let check = cache.verify(request).await?;
+        let status = match check.recorded_hash() {
+            None => "not_recorded",
+            Some(_) if check.matches() => "matches",
+            Some(_) => {
+                output.drifted += 1;
+                "drifted"
+            }
+        };
+        output.results.push(VerifyResult {
+            prompt_hash: check.prompt_hash().to_string(),
+            status,
+        });
+    }
+
+    format.print(&output)?;
+    if output.drifted > 0 {
+        return Err(format!("{} of {} responses drifted", output.drifted, requests.len()).into());
+    }
+    Ok(())
+}
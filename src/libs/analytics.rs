@@ -0,0 +1,223 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::database::{upsert, Database};
+use super::observer::{Observer, ObserverError, QuerySummary};
+
+/// Key of the list of every vector id with recorded retrievals.
+const RETRIEVAL_INDEX_KEY: &str = "retrievals:index";
+
+/// Prefix of the keys retrieval counts are stored under.
+const RETRIEVAL_KEY_PREFIX: &str = "retrieval:";
+
+/// How often one vector was returned by queries. Timestamps are unix seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetrievalStats {
+    id: String,
+    retrievals: u64,
+    total_score: f64,
+    best_score: f32,
+    first_retrieved: u64,
+    last_retrieved: u64,
+}
+
+/// Records which vectors queries return, to find the chunks that are never retrieved and
+/// guide pruning and re-chunking of the corpus.
+///
+/// Register it as the observer of a `SemanticSearch` (or in an `ObserverSet`); every match
+/// of every query is counted per vector id in `db`, with its score and time.
+///
+/// # Example
+///
+/// ```rust
+/// let analytics = Arc::new(RetrievalAnalytics::new(Arc::new(SQLiteDB::new("analytics.db")?)));
+/// let search = SemanticSearch::builder().observer(analytics.clone()).build();
+///
+/// let ids = PineconeClient::default().list_ids("").await?;
+/// for id in analytics.dead_content(&ids, month_ago).await? {
+///     println!("{} was not retrieved in the last month", id);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetrievalAnalytics {
+    db: Arc<dyn Database>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl RetrievalAnalytics {
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        RetrievalAnalytics {
+            db,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Counts a retrieval of vector `id` with `score` at `at`.
+    pub async fn record(&self, id: &str, score: f32, at: u64) -> Result<(), Box<dyn Error>> {
+        let _guard = self.lock.lock().await;
+        let existing = self.stats(id).await?;
+        let stats = match existing {
+            Some(mut stats) => {
+                stats.retrievals += 1;
+                stats.total_score += f64::from(score);
+                stats.best_score = stats.best_score.max(score);
+                stats.first_retrieved = stats.first_retrieved.min(at);
+                stats.last_retrieved = stats.last_retrieved.max(at);
+                stats
+            }
+            None => {
+                let mut ids = self.ids().await?;
+                ids.push(id.to_string());
+                upsert(self.db.as_ref(), RETRIEVAL_INDEX_KEY, &serde_json::to_string(&ids)?).await?;
+                RetrievalStats {
+                    id: id.to_string(),
+                    retrievals: 1,
+                    total_score: f64::from(score),
+                    best_score: score,
+                    first_retrieved: at,
+                    last_retrieved: at,
+                }
+            }
+        };
+        upsert(self.db.as_ref(), &retrieval_key(id), &serde_json::to_string(&stats)?).await
+    }
+
+    /// Retrievals of vector `id`, if it was ever retrieved.
+    pub async fn stats(&self, id: &str) -> Result<Option<RetrievalStats>, Box<dyn Error>> {
+        match self.db.read(&retrieval_key(id)).await {
+            Ok(data) => Ok(Some(serde_json::from_str(&data)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Retrievals of every vector ever retrieved.
+    pub async fn all(&self) -> Result<Vec<RetrievalStats>, Box<dyn Error>> {
+        let mut all = Vec::new();
+        for id in self.ids().await? {
+            if let Some(stats) = self.stats(&id).await? {
+                all.push(stats);
+            }
+        }
+        Ok(all)
+    }
+
+    /// The `n` most retrieved vectors, most first.
+    pub async fn most_retrieved(&self, n: usize) -> Result<Vec<RetrievalStats>, Box<dyn Error>> {
+        let mut all = self.all().await?;
+        all.sort_by(|a, b| b.retrievals.cmp(&a.retrievals).then_with(|| a.id.cmp(&b.id)));
+        all.truncate(n);
+        Ok(all)
+    }
+
+    /// The `n` least retrieved of the vectors retrieved at least once, least first. Vectors
+    /// never retrieved are reported by `dead_content`.
+    pub async fn least_retrieved(&self, n: usize) -> Result<Vec<RetrievalStats>, Box<dyn Error>> {
+        let mut all = self.all().await?;
+        all.sort_by(|a, b| a.retrievals.cmp(&b.retrievals).then_with(|| a.id.cmp(&b.id)));
+        all.truncate(n);
+        Ok(all)
+    }
+
+    /// The vectors of `ids`, e.g. every id of the index, that no query returned since
+    /// `since`, in the order of `ids`.
+    pub async fn dead_content(&self, ids: &[String], since: u64) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut dead = Vec::new();
+        for id in ids {
+            match self.stats(id).await? {
+                Some(stats) if stats.last_retrieved >= since => {}
+                _ => dead.push(id.clone()),
+            }
+        }
+        Ok(dead)
+    }
+
+    async fn ids(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        match self.db.read(RETRIEVAL_INDEX_KEY).await {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl Observer for RetrievalAnalytics {
+    async fn on_query(&self, summary: &QuerySummary) -> Result<(), ObserverError> {
+        let now = unix_now();
+        for (n, id) in summary.match_ids().iter().enumerate() {
+            // Summaries without scores count as retrievals scoring 0.
+            let score = summary.match_scores().get(n).copied().unwrap_or(0.0);
+            self.record(id, score, now)
+                .await
+                .map_err(|e| ObserverError::DatabaseError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl RetrievalStats {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn retrievals(&self) -> u64 {
+        self.retrievals
+    }
+
+    /// Mean score of the vector's retrievals.
+    pub fn mean_score(&self) -> f64 {
+        self.total_score / self.retrievals.max(1) as f64
+    }
+
+    pub fn best_score(&self) -> f32 {
+        self.best_score
+    }
+
+    pub fn first_retrieved(&self) -> u64 {
+        self.first_retrieved
+    }
+
+    pub fn last_retrieved(&self) -> u64 {
+        self.last_retrieved
+    }
+}
+
+fn retrieval_key(id: &str) -> String {
+    format!("{}{}", RETRIEVAL_KEY_PREFIX, id)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[tokio::test]
+    async fn test_retrieval_analytics() {
+        let analytics = RetrievalAnalytics::new(Arc::new(SQLiteDB::new(":memory:").unwrap()));
+        let summary = |ids: &[&str]| {
+            QuerySummary::new(None, None, 2, None, ids.iter().map(|id| id.to_string()).collect(), Some(0.9), 1)
+                .with_scores(vec![0.9, 0.5])
+        };
+        analytics.on_query(&summary(&["a", "b"])).await.unwrap();
+        analytics.on_query(&summary(&["a"])).await.unwrap();
+        analytics.record("c", 0.1, 100).await.unwrap();
+
+        let most = analytics.most_retrieved(1).await.unwrap();
+        assert_eq!((most[0].id().as_str(), most[0].retrievals()), ("a", 2));
+        assert!((most[0].mean_score() - 0.9).abs() < 1e-6);
+        assert_eq!(analytics.least_retrieved(3).await.unwrap()[0].id(), "b");
+
+        let ids: Vec<String> = ["a", "b", "c", "d"].iter().map(|id| id.to_string()).collect();
+        assert_eq!(analytics.dead_content(&ids, 1000).await.unwrap(), vec!["c", "d"]);
+    }
+}
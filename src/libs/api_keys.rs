@@ -0,0 +1,229 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+#[cfg(not(feature = "wasm"))]
+use std::time::Instant;
+
+use thiserror::Error;
+#[cfg(feature = "wasm")]
+use web_time::Instant;
+
+use super::rate_limit::sleep;
+
+#[derive(Debug, Error)]
+pub enum KeyPoolError {
+    #[error("NoKeys: {0}")]
+    NoKeys(String),
+
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+}
+
+/// How a `KeyPool` picks the key of the next request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeySelection {
+    /// Each key in turn.
+    #[default]
+    RoundRobin,
+    /// Keys never throttled first, then the one throttled longest ago, so a key that just
+    /// hit its rate limit rests while others take the load.
+    LeastRecentlyThrottled,
+}
+
+#[derive(Debug, Clone)]
+enum KeySource {
+    Fixed,
+    Env(String),
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct PooledKey {
+    key: String,
+    throttled_at: Option<Instant>,
+}
+
+/// Several OpenAI API keys shared by every request, see `openai_api::set_key_pool`.
+///
+/// Keys loaded from an environment variable or a file can be reloaded while the process
+/// runs, e.g. to rotate a leaked key out of a long-running ingestion service.
+///
+/// # Example
+///
+/// ```rust
+/// // OPENAI_API_KEYS=sk-one,sk-two
+/// let pool = Arc::new(KeyPool::from_env("OPENAI_API_KEYS", KeySelection::LeastRecentlyThrottled)?);
+/// openai_api::set_key_pool(pool.clone());
+/// tokio::spawn(pool.reload_every(Duration::from_secs(60)));
+/// ```
+#[derive(Debug)]
+pub struct KeyPool {
+    selection: KeySelection,
+    source: KeySource,
+    keys: RwLock<Vec<PooledKey>>,
+    next: AtomicUsize,
+}
+
+impl KeyPool {
+    pub fn new(keys: Vec<String>, selection: KeySelection) -> Result<Self, KeyPoolError> {
+        Self::with_source(keys, selection, KeySource::Fixed)
+    }
+
+    /// Keys from the comma separated environment variable `var`.
+    pub fn from_env(var: &str, selection: KeySelection) -> Result<Self, KeyPoolError> {
+        let source = KeySource::Env(var.to_string());
+        Self::with_source(load(&source)?, selection, source)
+    }
+
+    /// Keys from a file with one key per line. Blank lines and lines starting with `#` are
+    /// ignored.
+    pub fn from_file<P: AsRef<Path>>(path: P, selection: KeySelection) -> Result<Self, KeyPoolError> {
+        let source = KeySource::File(path.as_ref().to_path_buf());
+        Self::with_source(load(&source)?, selection, source)
+    }
+
+    fn with_source(keys: Vec<String>, selection: KeySelection, source: KeySource) -> Result<Self, KeyPoolError> {
+        if keys.is_empty() {
+            return Err(KeyPoolError::NoKeys("no API keys given".to_string()));
+        }
+        Ok(KeyPool {
+            selection,
+            source,
+            keys: RwLock::new(keys.into_iter().map(PooledKey::new).collect()),
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Re-reads the keys from the environment variable or file the pool was created from
+    /// and returns how many there are. Keys that stay keep their throttling history. On
+    /// error, or if no keys are left, the current keys stay in use.
+    pub fn reload(&self) -> Result<usize, KeyPoolError> {
+        if let KeySource::Fixed = self.source {
+            return Ok(self.read().len());
+        }
+
+        let keys = load(&self.source)?;
+        if keys.is_empty() {
+            return Err(KeyPoolError::NoKeys("reloaded source has no API keys".to_string()));
+        }
+
+        let mut pooled = self.keys.write().unwrap_or_else(|e| e.into_inner());
+        let reloaded: Vec<PooledKey> = keys
+            .into_iter()
+            .map(|key| match pooled.iter().find(|pooled| pooled.key == key) {
+                Some(existing) => existing.clone(),
+                None => PooledKey::new(key),
+            })
+            .collect();
+        *pooled = reloaded;
+        Ok(pooled.len())
+    }
+
+    /// Reloads the keys every `interval`, forever; spawn it next to a long-running service.
+    /// Failed reloads are reported and retried at the next interval.
+    pub async fn reload_every(self: Arc<Self>, interval: Duration) {
+        loop {
+            sleep(interval).await;
+            if let Err(e) = self.reload() {
+                tracing::warn!("Failed to reload API keys: {}", e);
+            }
+        }
+    }
+
+    /// The key to send the next request with.
+    pub fn select(&self) -> String {
+        let keys = self.read();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % keys.len();
+        let mut rotated = keys[start..].iter().chain(&keys[..start]);
+
+        let selected = match self.selection {
+            KeySelection::RoundRobin => rotated.next(),
+            // `Option` orders `None` first; ties go to the key next in turn.
+            KeySelection::LeastRecentlyThrottled => rotated.min_by_key(|pooled| pooled.throttled_at),
+        };
+        selected.map(|pooled| pooled.key.clone()).unwrap_or_default()
+    }
+
+    /// Records that a request with `key` was rate limited.
+    pub fn mark_throttled(&self, key: &str) {
+        let mut keys = self.keys.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(pooled) = keys.iter_mut().find(|pooled| pooled.key == key) {
+            pooled.throttled_at = Some(Instant::now());
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Vec<PooledKey>> {
+        self.keys.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl PooledKey {
+    fn new(key: String) -> Self {
+        PooledKey { key, throttled_at: None }
+    }
+}
+
+fn load(source: &KeySource) -> Result<Vec<String>, KeyPoolError> {
+    let keys = match source {
+        KeySource::Fixed => return Ok(Vec::new()),
+        KeySource::Env(var) => {
+            dotenv::dotenv().ok();
+            let value = env::var(var).map_err(|_| KeyPoolError::NoKeys(format!("{} is not set", var)))?;
+            value.split(',').map(str::trim).map(str::to_string).collect::<Vec<_>>()
+        }
+        KeySource::File(path) => fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect(),
+    };
+    Ok(keys.into_iter().filter(|key| !key.is_empty()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_selection() {
+        let pool = KeyPool::new(keys(&["a", "b", "c"]), KeySelection::RoundRobin).unwrap();
+        let selected: Vec<String> = (0..4).map(|_| pool.select()).collect();
+        assert_eq!(selected, keys(&["a", "b", "c", "a"]));
+
+        let pool = KeyPool::new(keys(&["a", "b"]), KeySelection::LeastRecentlyThrottled).unwrap();
+        pool.mark_throttled("a");
+        assert_eq!(pool.select(), "b");
+        assert_eq!(pool.select(), "b");
+        pool.mark_throttled("b");
+        assert_eq!(pool.select(), "a");
+
+        assert!(KeyPool::new(Vec::new(), KeySelection::RoundRobin).is_err());
+    }
+
+    #[test]
+    fn test_reload() {
+        let path = env::temp_dir().join(format!("api-keys-{}.txt", std::process::id()));
+        fs::write(&path, "# rotated monthly\na\n\nb\n").unwrap();
+        let pool = KeyPool::from_file(&path, KeySelection::LeastRecentlyThrottled).unwrap();
+        pool.mark_throttled("a");
+
+        fs::write(&path, "a\nc\n").unwrap();
+        assert_eq!(pool.reload().unwrap(), 2);
+        assert_eq!(pool.select(), "c");
+        assert_eq!(pool.select(), "c");
+
+        fs::write(&path, "\n").unwrap();
+        assert!(pool.reload().is_err());
+        assert_eq!(pool.select(), "c");
+        fs::remove_file(&path).unwrap();
+    }
+}
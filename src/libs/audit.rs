@@ -0,0 +1,219 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(not(feature = "wasm"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "wasm")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use super::context::{self, RequestContext};
+use super::database::Database;
+use super::pinecone_data::{Filter, IdList, PineconeRequest};
+
+/// Prefix of the keys audit entries are stored under.
+const AUDIT_KEY_PREFIX: &str = "audit:";
+
+/// Orders the entries this process appends within the same millisecond.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("DatabaseError: {0}")]
+    DatabaseError(String),
+}
+
+/// Kind of destructive operation an `AuditEntry` records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    /// Deletes vectors by id.
+    Delete,
+    /// Deletes the vectors matching a metadata filter.
+    DeleteByFilter,
+    /// Deletes every vector of a namespace, which drops it, e.g. when a `TempNamespace`
+    /// is closed.
+    DeleteAll,
+}
+
+/// One destructive operation on the index: what was asked, for whom, and how it went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the operation finished, in milliseconds since the Unix epoch.
+    timestamp_ms: u64,
+    operation: AuditOperation,
+    namespace: Option<String>,
+    /// The `RequestContext` the operation ran in.
+    user: Option<String>,
+    tenant: Option<String>,
+    trace_id: Option<String>,
+    #[serde(default)]
+    ids: Vec<String>,
+    filter: Option<Filter>,
+    /// Vectors the operation targeted: the ids, or the namespace's vector count before a
+    /// `DeleteAll`. Unknown for deletes by filter, which Pinecone does not count.
+    affected: Option<u64>,
+    /// Why the operation failed, if it did.
+    error: Option<String>,
+}
+
+/// Append-only log of the deletes sent to the index, kept in a `Database` as JSON
+/// `AuditEntry`s, see `pinecone_api::set_audit_log`.
+///
+/// Entries are only ever created, under keys ordered by time, never updated or deleted.
+/// An entry is appended once the delete returned, failed or not. A failure to append is
+/// logged and counted in `failures`, and does not fail the delete, which already happened.
+/// Keys end with a random suffix, so processes sharing the database do not collide.
+///
+/// # Example
+///
+/// ```rust
+/// let log = Arc::new(AuditLog::new(Arc::new(SQLiteDB::new("audit.db")?)));
+/// pinecone_api::set_audit_log(log.clone());
+///
+/// PineconeClient::namespace("scratch").delete_all().await?;
+/// for entry in log.entries().await? {
+///     println!("{:?} {:?} {:?}", entry.operation(), entry.namespace(), entry.affected());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    db: Arc<dyn Database>,
+    failures: Arc<AtomicU64>,
+}
+
+impl AuditLog {
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        AuditLog {
+            db,
+            failures: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Entries that failed to append, e.g. to alert on deletes missing from the log.
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    /// Appends `entry` to the log.
+    pub async fn append(&self, entry: &AuditEntry) -> Result<(), AuditError> {
+        let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let key = format!(
+            "{}{:013}-{:010}-{:016x}",
+            AUDIT_KEY_PREFIX,
+            entry.timestamp_ms,
+            sequence,
+            random_suffix(sequence)
+        );
+        let result = match serde_json::to_string(entry) {
+            Ok(data) => self.db.create(&key, &data).await.map_err(|e| AuditError::DatabaseError(e.to_string())),
+            Err(e) => Err(AuditError::DatabaseError(e.to_string())),
+        };
+        if result.is_err() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Every entry, oldest first. The database must be able to list its ids.
+    pub async fn entries(&self) -> Result<Vec<AuditEntry>, AuditError> {
+        let keys = self
+            .db
+            .ids(AUDIT_KEY_PREFIX)
+            .await
+            .map_err(|e| AuditError::DatabaseError(e.to_string()))?;
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            let data = self.db.read(&key).await.map_err(|e| AuditError::DatabaseError(e.to_string()))?;
+            entries.push(serde_json::from_str(&data).map_err(|e| AuditError::DatabaseError(e.to_string()))?);
+        }
+        Ok(entries)
+    }
+}
+
+impl AuditOperation {
+    /// The operation the delete `request` performs.
+    pub fn of(request: &PineconeRequest) -> Self {
+        if *request.delete_all() == Some(true) {
+            AuditOperation::DeleteAll
+        } else if request.filter().is_some() {
+            AuditOperation::DeleteByFilter
+        } else {
+            AuditOperation::Delete
+        }
+    }
+}
+
+impl AuditEntry {
+    /// Entry of the delete `request`, sent in the current `RequestContext`.
+    pub(crate) fn new(request: &PineconeRequest, affected: Option<u64>, error: Option<String>) -> Self {
+        let context = RequestContext::current().unwrap_or_default();
+        let ids = match request.ids() {
+            Some(IdList::TextIds(ids)) => ids.clone(),
+            _ => Vec::new(),
+        };
+        AuditEntry {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64),
+            operation: AuditOperation::of(request),
+            namespace: request.namespace().clone().or_else(context::current_namespace),
+            user: context.user().clone(),
+            tenant: context.tenant().clone(),
+            trace_id: context.trace_id().clone(),
+            ids,
+            filter: request.filter().clone(),
+            affected,
+            error,
+        }
+    }
+
+    pub fn timestamp_ms(&self) -> u64 {
+        self.timestamp_ms
+    }
+
+    pub fn operation(&self) -> AuditOperation {
+        self.operation
+    }
+
+    pub fn namespace(&self) -> &Option<String> {
+        &self.namespace
+    }
+
+    pub fn user(&self) -> &Option<String> {
+        &self.user
+    }
+
+    pub fn tenant(&self) -> &Option<String> {
+        &self.tenant
+    }
+
+    pub fn trace_id(&self) -> &Option<String> {
+        &self.trace_id
+    }
+
+    pub fn ids(&self) -> &Vec<String> {
+        &self.ids
+    }
+
+    pub fn filter(&self) -> &Option<Filter> {
+        &self.filter
+    }
+
+    pub fn affected(&self) -> Option<u64> {
+        self.affected
+    }
+
+    pub fn error(&self) -> &Option<String> {
+        &self.error
+    }
+}
+
+/// Random 64 bits, seeded differently in every process, telling apart the keys of
+/// processes appending in the same millisecond.
+fn random_suffix(sequence: u64) -> u64 {
+    RandomState::new().hash_one(sequence)
+}
@@ -0,0 +1,228 @@
+use std::future::Future;
+use std::time::Instant;
+
+use futures::{stream, StreamExt};
+use serde::Serialize;
+use typed_builder::TypedBuilder;
+
+use super::cluster::XorShift;
+use super::math::normalize;
+use super::pinecone_api::PineconeClient;
+use super::pinecone_data::{PineconeRequest, Vector};
+use super::pipeline::embed;
+use super::rate_limit::Priority;
+use super::search::LatencySummary;
+
+/// Ids of the vectors written by `Benchmark::upsert` start with this, so leftovers of an
+/// interrupted run can be found with `list_ids`.
+pub const BENCH_ID_PREFIX: &str = "bench#";
+
+/// Vectors deleted per request when cleaning up after `Benchmark::upsert`.
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// Latencies and throughput of one benchmarked operation.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    operation: String,
+    requests: usize,
+    concurrency: usize,
+    elapsed_ms: f64,
+
+    /// Completed requests per second, failed ones included.
+    throughput: f64,
+
+    /// None if every request failed.
+    latency_ms: Option<LatencySummary>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_error: Option<String>,
+}
+
+/// Sends `requests` requests of an operation with up to `concurrency` in flight and
+/// reports their latency percentiles and throughput, to size concurrency limits.
+///
+/// Requests go to whatever the clients are configured for: the live services, or the fakes
+/// of `test_util`. Rate limits of `rate_limit` apply to embeddings, so raise them to measure
+/// the service rather than the limiter.
+///
+/// # Fields
+///
+/// * `requests`: Optional. Requests to send. Defaults to 100.
+/// * `concurrency`: Optional. Requests in flight at once. Defaults to 8.
+/// * `warmup`: Optional. Requests sent first and left out of the report, so connection
+///   setup is not measured. Defaults to 0.
+///
+/// # Example
+///
+/// ```rust
+/// let bench = Benchmark::builder().requests(500).concurrency(32).build();
+/// let report = bench.query(&PineconeClient::default(), 1536, 10).await;
+/// println!("{}", serde_json::to_string_pretty(&report)?);
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Benchmark {
+    #[builder(default = 100)]
+    requests: usize,
+
+    #[builder(default = 8)]
+    concurrency: usize,
+
+    #[builder(default = 0)]
+    warmup: usize,
+}
+
+impl Benchmark {
+    /// Benchmarks any operation; `request(n)` sends the `n`th request.
+    pub async fn measure<F, Fut, T, E>(&self, operation: &str, request: F) -> BenchReport
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: ToString,
+    {
+        if self.warmup > 0 {
+            stream::iter(0..self.warmup)
+                .map(&request)
+                .buffer_unordered(self.concurrency.max(1))
+                .for_each(|_| async {})
+                .await;
+        }
+
+        let started = Instant::now();
+        let outcomes: Vec<Result<f64, String>> = stream::iter(self.warmup..self.warmup + self.requests)
+            .map(|n| {
+                let request = &request;
+                async move {
+                    let sent = Instant::now();
+                    request(n)
+                        .await
+                        .map(|_| sent.elapsed().as_secs_f64() * 1000.0)
+                        .map_err(|e| e.to_string())
+                }
+            })
+            .buffer_unordered(self.concurrency.max(1))
+            .collect()
+            .await;
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let mut latencies = Vec::with_capacity(outcomes.len());
+        let mut errors = Vec::new();
+        for outcome in outcomes {
+            match outcome {
+                Ok(latency) => latencies.push(latency),
+                Err(e) => errors.push(e),
+            }
+        }
+        BenchReport {
+            operation: operation.to_string(),
+            requests: self.requests,
+            concurrency: self.concurrency.max(1),
+            elapsed_ms: elapsed * 1000.0,
+            throughput: if elapsed > 0.0 { self.requests as f64 / elapsed } else { 0.0 },
+            latency_ms: LatencySummary::from_latencies(latencies, errors.len()),
+            first_error: errors.into_iter().next(),
+        }
+    }
+
+    /// Embeds `text` with `model` once per request.
+    pub async fn embed(&self, model: &str, text: &str) -> BenchReport {
+        self.measure("embed", |_| embed(model, text, Priority::Interactive)).await
+    }
+
+    /// Upserts `batch_size` random vectors of `dimension` per request into the handle's
+    /// namespace, then deletes them again.
+    pub async fn upsert(&self, client: &PineconeClient, dimension: usize, batch_size: usize) -> BenchReport {
+        let run = std::process::id();
+        let ids = |n: usize| -> Vec<String> {
+            (0..batch_size).map(|i| format!("{}{}-{}-{}", BENCH_ID_PREFIX, run, n, i)).collect()
+        };
+
+        let report = self
+            .measure("upsert", |n| {
+                let mut rng = XorShift::new(n as u64 + 1);
+                let vectors: Vec<Vector> = ids(n)
+                    .into_iter()
+                    .map(|id| Vector::builder().id(id).values(random_unit(&mut rng, dimension)).build())
+                    .collect();
+                client.upsert(vectors)
+            })
+            .await;
+
+        let written: Vec<String> = (0..self.warmup + self.requests).flat_map(ids).collect();
+        for batch in written.chunks(DELETE_BATCH_SIZE) {
+            if let Err(e) = client.delete(batch.to_vec()).await {
+                tracing::warn!("Failed to delete benchmark vectors, remove ids starting with {:?}: {}", BENCH_ID_PREFIX, e);
+                break;
+            }
+        }
+        report
+    }
+
+    /// Queries the handle's namespace for the `top_k` closest vectors to a random vector of
+    /// `dimension` per request.
+    pub async fn query(&self, client: &PineconeClient, dimension: usize, top_k: i64) -> BenchReport {
+        self.measure("query", |n| {
+            let mut rng = XorShift::new(n as u64 + 1);
+            let request = PineconeRequest::builder()
+                .vector(Vector::builder().values(random_unit(&mut rng, dimension)).build())
+                .top_k(top_k)
+                .include_metadata(true)
+                .build();
+            client.query(request)
+        })
+        .await
+    }
+}
+
+impl BenchReport {
+    pub fn operation(&self) -> &String {
+        &self.operation
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub fn elapsed_ms(&self) -> f64 {
+        self.elapsed_ms
+    }
+
+    pub fn throughput(&self) -> f64 {
+        self.throughput
+    }
+
+    pub fn latency_ms(&self) -> Option<&LatencySummary> {
+        self.latency_ms.as_ref()
+    }
+
+    pub fn first_error(&self) -> &Option<String> {
+        &self.first_error
+    }
+}
+
+fn random_unit(rng: &mut XorShift, dimension: usize) -> Vec<f32> {
+    let values: Vec<f32> = (0..dimension).map(|_| rng.unit() * 2.0 - 1.0).collect();
+    normalize(&values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_measure() {
+        let bench = Benchmark::builder().requests(20).concurrency(4).warmup(2).build();
+        let report = bench
+            .measure("flaky", |n| async move { if n % 10 == 0 { Err(format!("request {} failed", n)) } else { Ok(()) } })
+            .await;
+
+        let latency = report.latency_ms().unwrap();
+        // Requests 2..22 are measured; 10 and 20 fail.
+        assert_eq!((latency.requests(), latency.errors()), (20, 2));
+        assert!(report.first_error().as_deref().is_some_and(|e| e.ends_with("failed")));
+        assert!(report.throughput() > 0.0);
+    }
+}
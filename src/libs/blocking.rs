@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::future::Future;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use tokio::runtime::{Builder, Runtime};
+
+use super::classify::{ClassificationReport, Classifier, ClassifyError};
+use super::openai_api::{
+    OpenAICompletionRequest, OpenAICompletionResponse, OpenAIEmbeddingRequest, OpenAIEmbeddingResponse, OpenAIRequest,
+    OpenAIResponse,
+};
+use super::pinecone_api::PineconeApiError;
+use super::pinecone_data::{Match, PineconeRequest, PineconeResponse};
+use super::pipeline::{Document, IngestionPipeline, IngestionReport, PipelineError};
+use super::rag::{RagAnswer, RagChat, RagError};
+use super::search::{SearchError, SemanticSearch};
+
+lazy_static! {
+    static ref RUNTIME: Runtime = Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("openai-pinecone-blocking")
+        .enable_all()
+        .build()
+        .expect("Failed to start blocking runtime.");
+}
+
+/// Runs `future` to completion on the module's internal runtime.
+///
+/// Every function in this module goes through here, so sync programs can use the clients
+/// without setting up tokio. Panics if called from inside an async runtime; async code
+/// should await the regular APIs instead.
+///
+/// # Example
+///
+/// ```rust
+/// let request = OpenAIRequest::builder()
+///     .model("gpt-3.5-turbo".to_string())
+///     .messages(vec![Message::builder().role("user".to_string()).content("Hi".to_string()).build()])
+///     .build();
+/// let response = blocking::chat(&request)?;
+/// ```
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(future)
+}
+
+pub fn chat(request: &OpenAIRequest) -> Result<OpenAIResponse, Box<dyn Error>> {
+    block_on(request.send())
+}
+
+pub fn complete(request: &OpenAICompletionRequest) -> Result<OpenAICompletionResponse, Box<dyn Error>> {
+    block_on(request.send())
+}
+
+pub fn embed(request: &OpenAIEmbeddingRequest) -> Result<OpenAIEmbeddingResponse, Box<dyn Error>> {
+    block_on(request.send())
+}
+
+pub fn upsert(request: &PineconeRequest) -> Result<PineconeResponse, PineconeApiError> {
+    block_on(request.upsert())
+}
+
+pub fn query(request: &PineconeRequest) -> Result<PineconeResponse, PineconeApiError> {
+    block_on(request.query())
+}
+
+pub fn update(request: &PineconeRequest) -> Result<PineconeResponse, PineconeApiError> {
+    block_on(request.update())
+}
+
+pub fn fetch(request: &PineconeRequest) -> Result<PineconeResponse, PineconeApiError> {
+    block_on(request.fetch())
+}
+
+pub fn delete(request: &PineconeRequest) -> Result<PineconeResponse, PineconeApiError> {
+    block_on(request.delete())
+}
+
+pub fn ingest(pipeline: &IngestionPipeline, documents: &[Document]) -> Result<IngestionReport, PipelineError> {
+    block_on(pipeline.ingest(documents))
+}
+
+pub fn ingest_directory<P: AsRef<Path>>(
+    pipeline: &IngestionPipeline,
+    root: P,
+) -> Result<IngestionReport, PipelineError> {
+    block_on(pipeline.ingest_directory(root))
+}
+
+pub fn search(search: &SemanticSearch, query: &str) -> Result<Vec<Match>, SearchError> {
+    block_on(search.search(query))
+}
+
+pub fn ask(chat: &RagChat, question: &str) -> Result<RagAnswer, RagError> {
+    block_on(chat.ask(question))
+}
+
+pub fn classify(classifier: &Classifier, text: &str, labels: &[String]) -> Result<String, ClassifyError> {
+    block_on(classifier.classify(text, labels))
+}
+
+pub fn classify_many(
+    classifier: &Classifier,
+    items: &[(String, String)],
+    labels: &[String],
+    concurrency: usize,
+) -> ClassificationReport {
+    block_on(classifier.classify_many(items, labels, concurrency))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_on() {
+        let value = block_on(async {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            42
+        });
+        assert_eq!(value, 42);
+
+        // Usable from several threads at once.
+        let handles: Vec<_> = (0..4).map(|i| std::thread::spawn(move || block_on(async move { i }))).collect();
+        let values: Vec<i32> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use typed_builder::TypedBuilder;
+
+/// Strips template noise from document text before it is chunked and embedded, so vectors
+/// represent content: lines repeated across documents, such as headers, footers and
+/// navigation, configured `lines`, and `stop_words`.
+///
+/// A line is boilerplate once it is found in at least `min_documents` documents and at
+/// least `min_share` of the documents of an ingestion run, see `learn`. Lines are compared
+/// with surrounding whitespace trimmed and inner whitespace collapsed. Boilerplate learned
+/// in one run is stripped from the documents of later runs too, e.g. by `update_document`.
+///
+/// # Fields
+///
+/// * `min_documents`: Optional. Fewest documents a line must be found in. Defaults to 3.
+/// * `min_share`: Optional. Smallest share of the documents a line must be found in.
+///   Defaults to 0.5.
+/// * `lines`: Optional. Lines always stripped, e.g. `["Skip to main content"]`.
+/// * `stop_words`: Optional. Words removed from every line, compared case-insensitively
+///   with surrounding punctuation ignored.
+///
+/// # Example
+///
+/// ```rust
+/// let filter = BoilerplateFilter::builder().lines(vec!["Back to top".to_string()]).build();
+/// let pipeline = IngestionPipeline::builder().boilerplate(filter).build();
+///
+/// let report = pipeline.ingest(&documents).await?;
+/// for (text, count) in report.boilerplate() {
+///     println!("removed {:?} {} times", text, count);
+/// }
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct BoilerplateFilter {
+    #[builder(default = 3)]
+    min_documents: usize,
+
+    #[builder(default = 0.5)]
+    min_share: f64,
+
+    #[builder(default)]
+    lines: Vec<String>,
+
+    #[builder(default)]
+    stop_words: Vec<String>,
+
+    /// Normalized lines detected as boilerplate so far.
+    #[builder(setter(skip), default)]
+    detected: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl BoilerplateFilter {
+    /// Detects the lines repeated across `texts`, the texts of the documents of one
+    /// ingestion run, and strips them from now on.
+    pub fn learn<'a>(&self, texts: impl IntoIterator<Item = &'a str>) {
+        let mut documents = 0;
+        let mut found_in: HashMap<String, usize> = HashMap::new();
+        for text in texts {
+            documents += 1;
+            let lines: HashSet<String> = text.lines().map(normalize).filter(|line| !line.is_empty()).collect();
+            for line in lines {
+                *found_in.entry(line).or_default() += 1;
+            }
+        }
+
+        let threshold = self.min_documents.max((self.min_share * documents as f64).ceil() as usize).max(1);
+        let mut detected = self.detected.lock().unwrap();
+        detected.extend(found_in.into_iter().filter(|(_, count)| *count >= threshold).map(|(line, _)| line));
+    }
+
+    /// Lines stripped: the configured ones and those detected so far, normalized.
+    pub fn boilerplate(&self) -> BTreeSet<String> {
+        let mut lines = self.detected.lock().unwrap().clone();
+        lines.extend(self.lines.iter().map(|line| normalize(line)));
+        lines
+    }
+
+    /// `text` without its boilerplate lines and stop words, counting each removed line
+    /// and stop word in `removed`. Line breaks of the lines kept are kept.
+    pub fn strip(&self, text: &str, removed: &mut BTreeMap<String, usize>) -> String {
+        let boilerplate = self.boilerplate();
+        let stop_words: HashSet<String> = self.stop_words.iter().map(|word| word.to_lowercase()).collect();
+
+        let mut kept = Vec::new();
+        for line in text.lines() {
+            let normalized = normalize(line);
+            if !normalized.is_empty() && boilerplate.contains(&normalized) {
+                *removed.entry(normalized).or_default() += 1;
+                continue;
+            }
+            if stop_words.is_empty() {
+                kept.push(line.to_string());
+                continue;
+            }
+
+            let words: Vec<&str> = line
+                .split_whitespace()
+                .filter(|word| {
+                    let bare = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+                    let stop = stop_words.contains(&bare);
+                    if stop {
+                        *removed.entry(bare).or_default() += 1;
+                    }
+                    !stop
+                })
+                .collect();
+            kept.push(words.join(" "));
+        }
+        kept.join("\n")
+    }
+}
+
+fn normalize(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::libs::pipeline::{Document, IngestionPipeline};
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::FakeServices;
+
+    #[test]
+    fn test_boilerplate() {
+        let page = |body: &str| format!("ACME Docs | Home | Pricing\n\n{}\n\n© 2024  ACME Inc.", body);
+        let pages = [page("Refunds take 30 days."), page("Orders ship in two days."), page("Returns are free.")];
+        let filter = BoilerplateFilter::builder()
+            .lines(vec!["Draft".to_string()])
+            .stop_words(vec!["the".to_string()])
+            .build();
+        filter.learn(pages.iter().map(String::as_str));
+        assert_eq!(filter.boilerplate().len(), 3);
+
+        let mut removed = BTreeMap::new();
+        let stripped = filter.strip("Draft\nThe refund policy.\n\nACME Docs | Home | Pricing\n© 2024 ACME Inc.", &mut removed);
+        assert_eq!(stripped, "refund policy.\n");
+        assert_eq!(removed["ACME Docs | Home | Pricing"], 1);
+        assert_eq!(removed["© 2024 ACME Inc."], 1);
+        assert_eq!((removed["Draft"], removed["the"]), (1, 1));
+
+        // Too few documents to tell boilerplate from content.
+        let fresh = BoilerplateFilter::builder().build();
+        fresh.learn(pages[..2].iter().map(String::as_str));
+        assert!(fresh.boilerplate().is_empty());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_ingest_strips_boilerplate() {
+        let fakes = FakeServices::start().await;
+        let pages: Vec<Document> = ["Refunds take 30 days.", "Orders ship in two days.", "Returns are free."]
+            .iter()
+            .enumerate()
+            .map(|(n, body)| {
+                let text = format!("ACME Help Center\n{}\nContact us | Privacy", body);
+                Document::builder().id(format!("page-{}", n)).text(text).build()
+            })
+            .collect();
+        let report = IngestionPipeline::builder()
+            .namespace("stripped".to_string())
+            .boilerplate(BoilerplateFilter::builder().build())
+            .build()
+            .ingest(&pages)
+            .await
+            .unwrap();
+        assert_eq!(report.boilerplate()["ACME Help Center"], 3);
+        assert_eq!(report.boilerplate()["Contact us | Privacy"], 3);
+        let stripped = fakes.metadata(Some("stripped"), &report.vector_ids()[0]).unwrap();
+        assert!(!stripped["text"].as_str().unwrap().contains("ACME"));
+    }
+}
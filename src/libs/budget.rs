@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(feature = "wasm"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use typed_builder::TypedBuilder;
+#[cfg(feature = "wasm")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use super::database::{upsert, Database};
+use super::rate_limit::sleep;
+
+/// Prefix of the keys daily usage is stored under in the `Database`.
+const BUDGET_KEY_PREFIX: &str = "token-budget:";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Error)]
+pub enum BudgetError {
+    #[error("BudgetExceeded: {0}")]
+    BudgetExceeded(String),
+
+    #[error("DatabaseError: {0}")]
+    DatabaseError(String),
+}
+
+/// What a `TokenBudget` does with a request that would exceed the day's budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BudgetAction {
+    /// Fail the request with `BudgetError::BudgetExceeded`.
+    #[default]
+    Refuse,
+    /// Hold the request until the budget resets at midnight UTC.
+    Wait,
+}
+
+/// Daily token budget of each OpenAI API key, see `openai_api::set_budget`.
+///
+/// Tokens are counted per key and UTC day in `db`, so the count survives restarts and is
+/// shared by processes using the same database. Keys are stored as a short hash, never in
+/// the clear. A request is let through while the key's usage today plus the request's
+/// estimated tokens (prompt and `max_tokens`) fits the budget, and its actual usage is
+/// added once it returns; concurrent requests can overshoot by what they have in flight.
+///
+/// # Fields
+///
+/// * `db`: Required. Database the daily usage is kept in.
+/// * `daily_tokens`: Required. Tokens each key may use per day.
+/// * `on_exceeded`: Optional. Defaults to `BudgetAction::Refuse`.
+///
+/// # Example
+///
+/// ```rust
+/// let budget = TokenBudget::builder()
+///     .db(Arc::new(SQLiteDB::new("openai-pinecone.db")?))
+///     .daily_tokens(2_000_000)
+///     .build();
+/// openai_api::set_budget(Arc::new(budget));
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct TokenBudget {
+    db: Arc<dyn Database>,
+    daily_tokens: u64,
+
+    #[builder(default)]
+    on_exceeded: BudgetAction,
+
+    /// Serializes read-modify-write of the usage counts within this process.
+    #[builder(setter(skip), default)]
+    lock: Arc<Mutex<()>>,
+}
+
+impl TokenBudget {
+    /// Waits until `tokens` more tokens fit `api_key`'s budget for today, or fails if
+    /// the budget refuses them.
+    pub async fn reserve(&self, api_key: &str, tokens: u32) -> Result<(), BudgetError> {
+        if u64::from(tokens) > self.daily_tokens {
+            return Err(BudgetError::BudgetExceeded(format!(
+                "request needs {} tokens, more than the daily budget of {}",
+                tokens, self.daily_tokens
+            )));
+        }
+
+        loop {
+            let used = self.used(api_key).await?;
+            if used + u64::from(tokens) <= self.daily_tokens {
+                return Ok(());
+            }
+
+            match self.on_exceeded {
+                BudgetAction::Refuse => {
+                    return Err(BudgetError::BudgetExceeded(format!(
+                        "key {} used {} of {} tokens today",
+                        key_id(api_key),
+                        used,
+                        self.daily_tokens
+                    )))
+                }
+                BudgetAction::Wait => sleep(until_tomorrow()).await,
+            }
+        }
+    }
+
+    /// Adds `tokens` to what `api_key` used today.
+    pub async fn record(&self, api_key: &str, tokens: u32) -> Result<(), BudgetError> {
+        let _guard = self.lock.lock().await;
+        let used = self.used(api_key).await? + u64::from(tokens);
+        upsert(self.db.as_ref(), &usage_key(api_key, today()), &used.to_string())
+            .await
+            .map_err(|e| BudgetError::DatabaseError(e.to_string()))
+    }
+
+    /// Tokens `api_key` used today.
+    pub async fn used(&self, api_key: &str) -> Result<u64, BudgetError> {
+        match self.db.read(&usage_key(api_key, today())).await {
+            Ok(data) => data.parse().map_err(|_| BudgetError::DatabaseError(format!("invalid usage {:?}", data))),
+            // Nothing recorded yet today.
+            Err(_) => Ok(0),
+        }
+    }
+
+    pub fn daily_tokens(&self) -> u64 {
+        self.daily_tokens
+    }
+}
+
+/// Short, non-reversible id of `api_key`.
+fn key_id(api_key: &str) -> String {
+    let digest = Sha256::digest(api_key.as_bytes());
+    digest[..6].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn usage_key(api_key: &str, day: u64) -> String {
+    format!("{}{}:{}", BUDGET_KEY_PREFIX, key_id(api_key), day)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Days since the Unix epoch, in UTC.
+fn today() -> u64 {
+    unix_now() / SECONDS_PER_DAY
+}
+
+fn until_tomorrow() -> Duration {
+    Duration::from_secs(SECONDS_PER_DAY - unix_now() % SECONDS_PER_DAY)
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[tokio::test]
+    async fn test_budget() {
+        let budget = TokenBudget::builder()
+            .db(Arc::new(SQLiteDB::new(":memory:").unwrap()))
+            .daily_tokens(100)
+            .build();
+
+        budget.reserve("sk-a", 60).await.unwrap();
+        budget.record("sk-a", 60).await.unwrap();
+        budget.record("sk-a", 30).await.unwrap();
+        assert_eq!(budget.used("sk-a").await.unwrap(), 90);
+
+        assert!(matches!(budget.reserve("sk-a", 20).await, Err(BudgetError::BudgetExceeded(_))));
+        budget.reserve("sk-b", 20).await.unwrap();
+        assert!(budget.reserve("sk-b", 101).await.is_err());
+    }
+}
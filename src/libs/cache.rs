@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(feature = "wasm"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+#[cfg(feature = "wasm")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use super::database::{upsert, Database};
+use super::openai_api::{OpenAIRequest, OpenAIResponse};
+use super::pinecone_data::Match;
+
+/// Prefix of the keys cached chat completions are stored under in the `Database`.
+const CHAT_CACHE_KEY_PREFIX: &str = "chat-cache:";
+
+/// Key the hit and miss counts are stored under, outside the prefix of cached responses.
+const CHAT_CACHE_STATS_KEY: &str = "chat-cache-stats";
+
+/// Prefix of the keys cached search results are stored under in the `Database`.
+const QUERY_CACHE_KEY_PREFIX: &str = "query-cache:";
+
+/// Database-backed cache of deterministic chat completions.
+///
+/// Only requests with `temperature` set to 0 are cached, keyed by a hash of the model,
+/// the normalized messages, and the remaining sampling parameters. Identical requests
+/// then return the stored response without calling the API, which keeps test suites and
+/// batch jobs that re-run the same prompts cheap. Lookups of cacheable requests are
+/// counted as hits or misses, see `stats`.
+///
+/// # Example
+///
+/// ```rust
+/// let cache = ChatCache::new(Arc::new(SQLiteDB::new("cache.db")?));
+/// let response = request.send_cached(&cache).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChatCache {
+    db: Arc<dyn Database>,
+    lock: Arc<Mutex<()>>,
+}
+
+/// Lookups of a `ChatCache` since it was first used with its database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+}
+
+impl ChatCache {
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        ChatCache {
+            db,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// The cached response for `request`, if it is cacheable and has been stored.
+    pub async fn get(&self, request: &OpenAIRequest) -> Option<OpenAIResponse> {
+        request.cache_key()?;
+        let response = self.lookup(request).await;
+        // A lost count only skews the hit rate.
+        self.count(response.is_some()).await.ok();
+        response
+    }
+
+    async fn lookup(&self, request: &OpenAIRequest) -> Option<OpenAIResponse> {
+        let key = request.cache_key()?;
+        let data = self.db.read(&cache_key(&key)).await.ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    async fn count(&self, hit: bool) -> Result<(), Box<dyn Error>> {
+        let _guard = self.lock.lock().await;
+        let mut stats = self.stats().await?;
+        if hit {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+        upsert(self.db.as_ref(), CHAT_CACHE_STATS_KEY, &serde_json::to_string(&stats)?).await
+    }
+
+    /// Hits and misses of `get` so far.
+    pub async fn stats(&self) -> Result<CacheStats, Box<dyn Error>> {
+        match self.db.read(CHAT_CACHE_STATS_KEY).await {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(_) => Ok(CacheStats::default()),
+        }
+    }
+
+    /// Number of cached responses, for backends that can list their ids.
+    pub async fn entries(&self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.db.ids(CHAT_CACHE_KEY_PREFIX).await?.len())
+    }
+
+    /// Stores `response` for `request`. Requests that are not deterministic are ignored.
+    pub async fn put(&self, request: &OpenAIRequest, response: &OpenAIResponse) -> Result<(), Box<dyn Error>> {
+        let Some(key) = request.cache_key() else {
+            return Ok(());
+        };
+        let data = serde_json::to_string(response)?;
+        upsert(self.db.as_ref(), &cache_key(&key), &data).await
+    }
+
+    /// Replays `request` against the API and compares the answer with the recorded one, to
+    /// detect model drift. The cache is left unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let check = cache.verify(&request).await?;
+    /// if !check.matches() {
+    ///     println!("{} drifted", check.prompt_hash());
+    /// }
+    /// ```
+    pub async fn verify(&self, request: &OpenAIRequest) -> Result<ReplayCheck, Box<dyn Error>> {
+        let prompt_hash = request.prompt_hash()?;
+        let recorded = self.lookup(request).await;
+        let replayed = request.send().await?;
+
+        Ok(ReplayCheck {
+            prompt_hash,
+            recorded_hash: recorded.map(|response| response.content_hash()),
+            replayed_hash: replayed.content_hash(),
+        })
+    }
+
+    /// Removes the cached response for `request`.
+    pub async fn invalidate(&self, request: &OpenAIRequest) -> Result<(), Box<dyn Error>> {
+        match request.cache_key() {
+            Some(key) => self.db.delete(&cache_key(&key)).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Outcome of replaying a recorded prompt, see `ChatCache::verify`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplayCheck {
+    prompt_hash: String,
+
+    /// `None` if no response was recorded for the prompt.
+    recorded_hash: Option<String>,
+    replayed_hash: String,
+}
+
+impl ReplayCheck {
+    /// Whether a response was recorded and the replay answered the same.
+    pub fn matches(&self) -> bool {
+        self.recorded_hash.as_ref() == Some(&self.replayed_hash)
+    }
+
+    pub fn prompt_hash(&self) -> &str {
+        &self.prompt_hash
+    }
+
+    pub fn recorded_hash(&self) -> &Option<String> {
+        &self.recorded_hash
+    }
+
+    pub fn replayed_hash(&self) -> &str {
+        &self.replayed_hash
+    }
+}
+
+/// Read-through cache of recent search results, see `SemanticSearch::with_cache`.
+///
+/// Results are keyed by a hash of the normalized query text, the namespace, `top_k`, and
+/// the other settings that change the matches (see `query_key`), and expire `ttl` after
+/// they were stored. Repeated searches within the TTL, common when a UI re-runs a query,
+/// then skip both the embedding and the Pinecone query. Vectors upserted or deleted in the
+/// meantime do not show up until the entry expires, so keep the TTL short or `clear` the
+/// cache after ingesting.
+///
+/// The memory backend is shared by the clones of the cache, e.g. the handlers of one
+/// server; the database backend by every process using the database.
+///
+/// # Example
+///
+/// ```rust
+/// let cache = QueryCache::memory(Duration::from_secs(60));
+/// let search = SemanticSearch::builder().top_k(5).build().with_cache(cache.clone());
+/// let matches = search.search("How are refunds processed?").await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueryCache {
+    store: QueryStore,
+    ttl: Duration,
+    stats: Arc<Mutex<CacheStats>>,
+}
+
+#[derive(Debug, Clone)]
+enum QueryStore {
+    Memory(Arc<Mutex<HashMap<String, CachedMatches>>>),
+    Database(Arc<dyn Database>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedMatches {
+    /// Milliseconds since the Unix epoch.
+    expires_at: u64,
+    matches: Vec<Match>,
+}
+
+impl QueryCache {
+    /// Keeps results in this process.
+    pub fn memory(ttl: Duration) -> Self {
+        QueryCache::with_store(QueryStore::Memory(Arc::default()), ttl)
+    }
+
+    /// Keeps results in `db`, where other processes find them too.
+    pub fn database(db: Arc<dyn Database>, ttl: Duration) -> Self {
+        QueryCache::with_store(QueryStore::Database(db), ttl)
+    }
+
+    fn with_store(store: QueryStore, ttl: Duration) -> Self {
+        QueryCache {
+            store,
+            ttl,
+            stats: Arc::default(),
+        }
+    }
+
+    /// The matches stored under `key`, unless they expired.
+    pub async fn get(&self, key: &str) -> Option<Vec<Match>> {
+        let cached = match &self.store {
+            QueryStore::Memory(entries) => entries.lock().await.get(key).cloned(),
+            QueryStore::Database(db) => match db.read(&query_cache_key(key)).await {
+                Ok(data) => serde_json::from_str(&data).ok(),
+                Err(_) => None,
+            },
+        };
+        let matches = cached.filter(|cached| cached.expires_at > unix_now_ms()).map(|cached| cached.matches);
+
+        let mut stats = self.stats.lock().await;
+        if matches.is_some() {
+            stats.hits += 1;
+        } else {
+            stats.misses += 1;
+        }
+        matches
+    }
+
+    /// Stores `matches` under `key` for the TTL. Expired entries of the memory backend are
+    /// dropped on the way; those of the database backend are overwritten when stored again.
+    pub async fn put(&self, key: &str, matches: &[Match]) -> Result<(), Box<dyn Error>> {
+        let now = unix_now_ms();
+        let cached = CachedMatches {
+            expires_at: now + self.ttl.as_millis() as u64,
+            matches: matches.to_vec(),
+        };
+        match &self.store {
+            QueryStore::Memory(entries) => {
+                let mut entries = entries.lock().await;
+                entries.retain(|_, cached| cached.expires_at > now);
+                entries.insert(key.to_string(), cached);
+                Ok(())
+            }
+            QueryStore::Database(db) => {
+                upsert(db.as_ref(), &query_cache_key(key), &serde_json::to_string(&cached)?).await
+            }
+        }
+    }
+
+    /// Drops every cached result, e.g. after ingesting. The database backend must be able
+    /// to list its ids.
+    pub async fn clear(&self) -> Result<(), Box<dyn Error>> {
+        match &self.store {
+            QueryStore::Memory(entries) => entries.lock().await.clear(),
+            QueryStore::Database(db) => {
+                for id in db.ids(QUERY_CACHE_KEY_PREFIX).await? {
+                    db.delete(&id).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hits and misses of `get` through this cache and its clones.
+    pub async fn stats(&self) -> CacheStats {
+        *self.stats.lock().await
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+/// Cache key of the `top_k` results of `query` in `namespace`. The query is trimmed,
+/// lowercased and its whitespace collapsed, so trivially different spellings share an entry.
+/// `settings` describes whatever else shapes the results, e.g. the embedding model and the
+/// metadata filter.
+pub fn query_key(query: &str, namespace: Option<&str>, top_k: i64, settings: &str) -> String {
+    let normalized = query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let data = serde_json::json!([normalized, namespace, top_k, settings]).to_string();
+    Sha256::digest(data.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl CacheStats {
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Share of lookups that were hits, or `None` before the first lookup.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+fn cache_key(key: &str) -> String {
+    format!("{}{}", CHAT_CACHE_KEY_PREFIX, key)
+}
+
+fn query_cache_key(key: &str) -> String {
+    format!("{}{}", QUERY_CACHE_KEY_PREFIX, key)
+}
+
+fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::libs::search::SemanticSearch;
+    use crate::libs::test_util::FakeServices;
+
+    #[tokio::test]
+    async fn test_query_cache_hits() {
+        let fakes = FakeServices::seeded().await;
+        // A repeat of a cached query, spelled differently, sends no requests.
+        let cache = QueryCache::memory(Duration::from_secs(60));
+        let cached = SemanticSearch::builder().top_k(1).build().with_cache(cache.clone());
+        cached.search("When are refunds issued?").await.unwrap();
+        let sent = fakes.server().received_requests().await.unwrap().len();
+        assert_eq!(cached.search("  when are REFUNDS  issued? ").await.unwrap()[0].id(), "refunds#chunk0");
+        assert_eq!(fakes.server().received_requests().await.unwrap().len(), sent);
+        assert_eq!((cache.stats().await.hits(), cache.stats().await.misses()), (1, 1));
+
+        let other_k = SemanticSearch::builder().top_k(2).build().with_cache(cache.clone());
+        other_k.search("When are refunds issued?").await.unwrap();
+        assert_eq!(cache.stats().await.misses(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_expiry() {
+        let fakes = FakeServices::seeded().await;
+        let sent = fakes.server().received_requests().await.unwrap().len();
+        let expiring = SemanticSearch::builder().top_k(1).build().with_cache(QueryCache::memory(Duration::ZERO));
+        expiring.search("When are refunds issued?").await.unwrap();
+        expiring.search("When are refunds issued?").await.unwrap();
+        // Two requests (embedding and query) per search, expired entries being misses.
+        assert_eq!(fakes.server().received_requests().await.unwrap().len(), sent + 4);
+    }
+}
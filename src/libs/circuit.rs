@@ -0,0 +1,142 @@
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(not(feature = "wasm"))]
+use std::time::Instant;
+
+use serde::Serialize;
+use typed_builder::TypedBuilder;
+#[cfg(feature = "wasm")]
+use web_time::Instant;
+
+/// Stops sending requests to a service that keeps failing, so callers fail fast, or fall
+/// back, instead of waiting on timeouts. See `pinecone_api::set_circuit_breaker`.
+///
+/// After `failure_threshold` failures in a row the circuit opens and requests are refused
+/// for `cooldown`. Then it is half-open: one trial request is let through, whose success
+/// closes the circuit and whose failure opens it for another `cooldown`. A trial that never
+/// reports back, e.g. because it was cancelled, is replaced after `cooldown`.
+///
+/// # Fields
+///
+/// * `failure_threshold`: Optional. Failures in a row that open the circuit. Defaults to 5.
+/// * `cooldown`: Optional. How long the circuit stays open. Defaults to 30 seconds.
+///
+/// # Example
+///
+/// ```rust
+/// let breaker = CircuitBreaker::builder().failure_threshold(3).cooldown(Duration::from_secs(10)).build();
+/// pinecone_api::set_circuit_breaker(Arc::new(breaker));
+/// ```
+#[derive(Debug, TypedBuilder)]
+pub struct CircuitBreaker {
+    #[builder(default = 5)]
+    failure_threshold: u32,
+
+    #[builder(default = Duration::from_secs(30))]
+    cooldown: Duration,
+
+    #[builder(setter(skip), default)]
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    opened_at: Option<Instant>,
+    trial_started: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests are sent.
+    Closed,
+    /// Requests are refused until the cooldown is over.
+    Open,
+    /// The cooldown is over; the next request is a trial.
+    HalfOpen,
+}
+
+impl CircuitBreaker {
+    /// Whether a request may be sent now. Report its outcome with `record`.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(opened_at) = state.opened_at else {
+            return true;
+        };
+        if opened_at.elapsed() < self.cooldown {
+            return false;
+        }
+        match state.trial_started {
+            Some(started) if started.elapsed() < self.cooldown => false,
+            _ => {
+                state.trial_started = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    /// Reports whether a request allowed by `allow` succeeded. Only failures of the service
+    /// should count, not requests it rightly rejected.
+    pub fn record(&self, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        if success {
+            *state = BreakerState::default();
+            return;
+        }
+        state.failures += 1;
+        state.trial_started = None;
+        if state.failures >= self.failure_threshold || state.opened_at.is_some() {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.cooldown => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::builder()
+            .failure_threshold(2)
+            .cooldown(Duration::from_millis(50))
+            .build();
+        breaker.record(false);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record(false);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        // One trial at a time; its failure opens the circuit again.
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        breaker.record(false);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        breaker.record(true);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow() && breaker.allow());
+    }
+}
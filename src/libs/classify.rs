@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::cache::ChatCache;
+use super::failures::{FailedItem, FailureReport, FailureStage};
+use super::openai_api::{truncate_to_tokens, Message, OpenAIRequest, OpenAIResponse, ResponseFormat};
+use super::pinecone_api::PineconeApiError;
+use super::pinecone_data::PineconeRequest;
+use super::pipeline::Chunk;
+use super::rate_limit::Priority;
+
+/// Tokens of item text sent to the model per classification.
+const CLASSIFY_INPUT_TOKENS: usize = 3000;
+
+#[derive(Debug, Error)]
+pub enum ClassifyError {
+    #[error("ChatError: {0}")]
+    ChatError(String),
+
+    #[error("InvalidLabel: {0}")]
+    InvalidLabel(String),
+
+    #[error(transparent)]
+    PineconeError(#[from] PineconeApiError),
+}
+
+#[derive(Debug, Deserialize)]
+struct LabelResponse {
+    label: String,
+}
+
+/// Assigns each text one of a fixed set of labels with the chat model.
+///
+/// Requests use JSON mode at temperature 0. A response that is not valid JSON or names a
+/// label outside the set is retried up to `retries` times, telling the model what was wrong.
+/// When `store` is set, labels are written to the metadata of the Pinecone vector with the
+/// item's id under `metadata_key`.
+///
+/// # Fields
+///
+/// * `model`: Optional. Chat model id. Defaults to "gpt-3.5-turbo".
+/// * `retries`: Optional. Extra attempts per item after an unusable response. Defaults to 2.
+/// * `store`: Optional. Write labels to the vectors' metadata. Defaults to true.
+/// * `metadata_key`: Optional. Metadata field labels are stored in. Defaults to "label".
+/// * `namespace`: Optional. Pinecone namespace of the vectors.
+/// * `cache`: Optional. Response cache, so re-running a job does not re-bill identical items.
+///
+/// # Example
+///
+/// ```rust
+/// let labels = vec!["bug".to_string(), "feature".to_string(), "question".to_string()];
+/// let report = Classifier::builder()
+///     .metadata_key("kind".to_string())
+///     .build()
+///     .classify_many(&items, &labels, 8)
+///     .await;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Classifier {
+    #[builder(default = "gpt-3.5-turbo".to_string())]
+    model: String,
+
+    #[builder(default = 2)]
+    retries: usize,
+
+    #[builder(default = true)]
+    store: bool,
+
+    #[builder(default = "label".to_string())]
+    metadata_key: String,
+
+    #[builder(setter(strip_option), default)]
+    namespace: Option<String>,
+
+    #[builder(setter(strip_option), default)]
+    cache: Option<ChatCache>,
+}
+
+/// Labels assigned by a `classify_many` run, keyed by item id.
+#[derive(Debug, Clone, Default)]
+pub struct ClassificationReport {
+    labels: HashMap<String, String>,
+    failed: Vec<FailedItem>,
+}
+
+/// Classifies `(id, text)` items with a default `Classifier`, storing each label as the
+/// `label` metadata of the vector with that id.
+pub async fn classify_many(
+    items: &[(String, String)],
+    labels: &[String],
+    concurrency: usize,
+) -> ClassificationReport {
+    Classifier::builder()
+        .build()
+        .classify_many(items, labels, concurrency)
+        .await
+}
+
+impl Classifier {
+    /// Classifies `(id, text)` items, `concurrency` at a time. Items that fail are listed in
+    /// the report's `failed` with the error; the rest of the batch still completes.
+    pub async fn classify_many(
+        &self,
+        items: &[(String, String)],
+        labels: &[String],
+        concurrency: usize,
+    ) -> ClassificationReport {
+        let items = items.iter().map(|(id, text)| (id.clone(), text.clone(), 0)).collect();
+        self.classify_attempt(items, labels, concurrency).await
+    }
+
+    /// Reclassifies the items of a failure report. Items failing again are reported with
+    /// their retry count incremented.
+    pub async fn retry_failures(
+        &self,
+        report: &FailureReport,
+        labels: &[String],
+        concurrency: usize,
+    ) -> ClassificationReport {
+        let items = report
+            .items()
+            .iter()
+            .filter(|item| item.stage() == FailureStage::Classification)
+            .map(|item| (item.chunk().id().clone(), item.chunk().text().clone(), item.retries() + 1))
+            .collect();
+        self.classify_attempt(items, labels, concurrency).await
+    }
+
+    async fn classify_attempt(
+        &self,
+        items: Vec<(String, String, u32)>,
+        labels: &[String],
+        concurrency: usize,
+    ) -> ClassificationReport {
+        let results: Vec<(String, String, u32, Result<String, ClassifyError>)> = stream::iter(items)
+            .map(|(id, text, retries)| self.classify_item(id, text, retries, labels))
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut report = ClassificationReport::default();
+        for (id, text, retries, result) in results {
+            match result {
+                Ok(label) => {
+                    report.labels.insert(id, label);
+                }
+                Err(e) => {
+                    let chunk = Chunk::builder().id(id).text(text).build();
+                    report
+                        .failed
+                        .push(FailedItem::new(FailureStage::Classification, e.to_string(), retries, chunk));
+                }
+            }
+        }
+        report
+    }
+
+    /// Returns the label the model assigns to `text`.
+    pub async fn classify(&self, text: &str, labels: &[String]) -> Result<String, ClassifyError> {
+        let mut messages = vec![
+            Message::builder()
+                .role("system".to_string())
+                .content(format!(
+                    "Classify the text the user sends into exactly one of these labels: {}. \
+                     Respond only with JSON of the form {{\"label\": string}}.",
+                    serde_json::to_string(labels).unwrap_or_default()
+                ))
+                .build(),
+            Message::builder()
+                .role("user".to_string())
+                .content(truncate_to_tokens(text, CLASSIFY_INPUT_TOKENS).to_string())
+                .build(),
+        ];
+
+        let mut last_error = ClassifyError::InvalidLabel("no attempts made".to_string());
+        for _ in 0..=self.retries {
+            let content = self.complete(messages.clone()).await?;
+            match parse_label(&content, labels) {
+                Ok(label) => return Ok(label),
+                Err(e) => {
+                    messages.push(Message::builder().role("assistant".to_string()).content(content).build());
+                    messages.push(
+                        Message::builder()
+                            .role("user".to_string())
+                            .content(format!("{} Answer again with one of the allowed labels.", e))
+                            .build(),
+                    );
+                    last_error = e;
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    async fn classify_item(
+        &self,
+        id: String,
+        text: String,
+        retries: u32,
+        labels: &[String],
+    ) -> (String, String, u32, Result<String, ClassifyError>) {
+        let result = match self.classify(&text, labels).await {
+            Ok(label) if self.store => self.store_label(&id, &label).await.map(|_| label),
+            result => result,
+        };
+        (id, text, retries, result)
+    }
+
+    async fn complete(&self, messages: Vec<Message>) -> Result<String, ClassifyError> {
+        let request = OpenAIRequest::builder()
+            .model(self.model.clone())
+            .messages(messages)
+            .temperature(0.0)
+            .response_format(ResponseFormat::json_object())
+            .priority(Priority::Batch)
+            .build();
+
+        let response: Result<OpenAIResponse, String> = match &self.cache {
+            Some(cache) => request.send_cached(cache).await.map_err(|e| e.to_string()),
+            None => request.send().await.map_err(|e| e.to_string()),
+        };
+
+        response
+            .map_err(ClassifyError::ChatError)?
+            .choices()
+            .first()
+            .map(|choice| choice.message().content().to_string())
+            .ok_or_else(|| ClassifyError::ChatError("response has no choices".to_string()))
+    }
+
+    async fn store_label(&self, id: &str, label: &str) -> Result<(), ClassifyError> {
+        let mut metadata = HashMap::new();
+        metadata.insert(self.metadata_key.clone(), label.into());
+
+        let request = match &self.namespace {
+            Some(namespace) => PineconeRequest::builder()
+                .id(id.to_string())
+                .metadata(metadata)
+                .namespace(namespace.clone())
+                .build(),
+            None => PineconeRequest::builder().id(id.to_string()).metadata(metadata).build(),
+        };
+
+        request.update().await?;
+        Ok(())
+    }
+}
+
+/// Extracts the label from a JSON response, matching the allowed labels case-insensitively.
+fn parse_label(content: &str, labels: &[String]) -> Result<String, ClassifyError> {
+    let response: LabelResponse = serde_json::from_str(content.trim())
+        .map_err(|e| ClassifyError::InvalidLabel(format!("Response is not valid JSON: {}.", e)))?;
+
+    labels
+        .iter()
+        .find(|label| label.eq_ignore_ascii_case(response.label.trim()))
+        .cloned()
+        .ok_or_else(|| ClassifyError::InvalidLabel(format!("\"{}\" is not an allowed label.", response.label)))
+}
+
+impl ClassificationReport {
+    pub fn labels(&self) -> &HashMap<String, String> {
+        &self.labels
+    }
+
+    /// Items that could not be classified or stored.
+    pub fn failed(&self) -> &Vec<FailedItem> {
+        &self.failed
+    }
+
+    /// The failed items as a report that can be written out and retried later.
+    pub fn failure_report(&self) -> FailureReport {
+        FailureReport::new(self.failed.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_label() {
+        let labels = vec!["bug".to_string(), "feature".to_string()];
+
+        assert_eq!(parse_label("{\"label\": \"Bug\"}", &labels).unwrap(), "bug");
+        assert!(matches!(
+            parse_label("{\"label\": \"question\"}", &labels),
+            Err(ClassifyError::InvalidLabel(_))
+        ));
+        assert!(parse_label("bug", &labels).is_err());
+    }
+}
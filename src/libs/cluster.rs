@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::math::{euclidean_distance, mean_pool, normalize};
+use super::pinecone_api::PineconeApiError;
+use super::pinecone_data::{IdList, PineconeRequest};
+
+/// Most ids requested per Pinecone fetch, keeping the query string a reasonable length.
+const FETCH_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum ClusterError {
+    #[error("InvalidInput: {0}")]
+    InvalidInput(String),
+
+    #[error(transparent)]
+    PineconeError(#[from] PineconeApiError),
+}
+
+/// K-means clustering of embeddings.
+///
+/// Centroids are seeded with k-means++ from a fixed `seed`, so the same input always gives
+/// the same clusters. With `normalize` set, vectors are scaled to unit length first, which
+/// makes the Euclidean distances used here rank like cosine similarity.
+///
+/// # Fields
+///
+/// * `k`: Required. Number of clusters.
+/// * `max_iterations`: Optional. Upper bound on assignment/update rounds. Defaults to 100.
+/// * `normalize`: Optional. Cluster unit-length vectors. Defaults to true.
+/// * `seed`: Optional. Seed of the centroid initialization. Defaults to 42.
+///
+/// # Example
+///
+/// ```rust
+/// let embeddings = fetch_embeddings(&ids, None).await?;
+/// let clustering = KMeans::builder().k(8).build().fit(&embeddings)?;
+/// clustering.store("cluster", None).await?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct KMeans {
+    k: usize,
+
+    #[builder(default = 100)]
+    max_iterations: usize,
+
+    #[builder(default = true)]
+    normalize: bool,
+
+    #[builder(default = 42)]
+    seed: u64,
+}
+
+/// Result of a k-means run.
+#[derive(Debug, Clone)]
+pub struct Clustering {
+    assignments: HashMap<String, usize>,
+    centroids: Vec<Vec<f32>>,
+    iterations: usize,
+}
+
+impl KMeans {
+    /// Clusters `(id, embedding)` pairs. All embeddings must have the same dimension.
+    pub fn fit(&self, embeddings: &[(String, Vec<f32>)]) -> Result<Clustering, ClusterError> {
+        if self.k == 0 || self.k > embeddings.len() {
+            return Err(ClusterError::InvalidInput(format!(
+                "k must be between 1 and the number of embeddings ({}), got {}",
+                embeddings.len(),
+                self.k
+            )));
+        }
+        let dimension = embeddings[0].1.len();
+        if let Some((id, _)) = embeddings.iter().find(|(_, v)| v.len() != dimension) {
+            return Err(ClusterError::InvalidInput(format!("{} has a different dimension", id)));
+        }
+
+        let points: Vec<Vec<f32>> = embeddings
+            .iter()
+            .map(|(_, v)| if self.normalize { normalize(v) } else { v.clone() })
+            .collect();
+
+        let mut centroids = self.initial_centroids(&points);
+        let mut labels = vec![usize::MAX; points.len()];
+        let mut iterations = 0;
+
+        while iterations < self.max_iterations {
+            iterations += 1;
+            let next: Vec<usize> = points.iter().map(|p| nearest(p, &centroids)).collect();
+            if next == labels {
+                break;
+            }
+            labels = next;
+
+            for (cluster, centroid) in centroids.iter_mut().enumerate() {
+                let members: Vec<Vec<f32>> = points
+                    .iter()
+                    .zip(&labels)
+                    .filter(|(_, &label)| label == cluster)
+                    .map(|(p, _)| p.clone())
+                    .collect();
+                // An emptied cluster keeps its previous centroid.
+                if let Some(mean) = mean_pool(&members) {
+                    *centroid = if self.normalize { normalize(&mean) } else { mean };
+                }
+            }
+        }
+
+        let assignments = embeddings
+            .iter()
+            .zip(labels)
+            .map(|((id, _), label)| (id.clone(), label))
+            .collect();
+
+        Ok(Clustering {
+            assignments,
+            centroids,
+            iterations,
+        })
+    }
+
+    /// k-means++: each further centroid is drawn with probability proportional to the
+    /// squared distance from the closest centroid chosen so far.
+    fn initial_centroids(&self, points: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        let mut rng = XorShift::new(self.seed);
+        let mut centroids = vec![points[rng.below(points.len())].clone()];
+
+        while centroids.len() < self.k {
+            let weights: Vec<f32> = points
+                .iter()
+                .map(|p| {
+                    let d = euclidean_distance(p, &centroids[nearest(p, &centroids)]);
+                    d * d
+                })
+                .collect();
+            let total: f32 = weights.iter().sum();
+
+            let next = if total == 0.0 {
+                // Fewer distinct points than clusters; any point will do.
+                rng.below(points.len())
+            } else {
+                let mut target = rng.unit() * total;
+                weights
+                    .iter()
+                    .position(|&w| {
+                        target -= w;
+                        target <= 0.0
+                    })
+                    .unwrap_or(points.len() - 1)
+            };
+            centroids.push(points[next].clone());
+        }
+
+        centroids
+    }
+}
+
+impl Clustering {
+    /// Writes each vector's cluster index to its Pinecone metadata under `metadata_key`.
+    /// Returns the number of vectors updated.
+    pub async fn store(&self, metadata_key: &str, namespace: Option<&str>) -> Result<usize, ClusterError> {
+        for (id, cluster) in &self.assignments {
+            let mut metadata = HashMap::new();
+            metadata.insert(metadata_key.to_string(), (*cluster).into());
+
+            let request = match namespace {
+                Some(namespace) => PineconeRequest::builder()
+                    .id(id.clone())
+                    .metadata(metadata)
+                    .namespace(namespace.to_string())
+                    .build(),
+                None => PineconeRequest::builder().id(id.clone()).metadata(metadata).build(),
+            };
+            request.update().await?;
+        }
+
+        Ok(self.assignments.len())
+    }
+
+    /// Cluster index of each id.
+    pub fn assignments(&self) -> &HashMap<String, usize> {
+        &self.assignments
+    }
+
+    pub fn centroids(&self) -> &Vec<Vec<f32>> {
+        &self.centroids
+    }
+
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Ids assigned to `cluster`, sorted.
+    pub fn members(&self, cluster: usize) -> Vec<&String> {
+        let mut members: Vec<&String> = self
+            .assignments
+            .iter()
+            .filter(|(_, &c)| c == cluster)
+            .map(|(id, _)| id)
+            .collect();
+        members.sort();
+        members
+    }
+}
+
+/// Fetches the embeddings of `ids` from Pinecone. Ids that do not exist are left out.
+pub async fn fetch_embeddings(ids: &[String], namespace: Option<&str>) -> Result<Vec<(String, Vec<f32>)>, ClusterError> {
+    let mut embeddings = Vec::with_capacity(ids.len());
+    for batch in ids.chunks(FETCH_BATCH_SIZE) {
+        let request = match namespace {
+            Some(namespace) => PineconeRequest::builder()
+                .ids(IdList::TextIds(batch.to_vec()))
+                .namespace(namespace.to_string())
+                .build(),
+            None => PineconeRequest::builder().ids(IdList::TextIds(batch.to_vec())).build(),
+        };
+
+        let response = request.fetch().await?;
+        let mut vectors = response.vectors().clone().unwrap_or_default();
+        for id in batch {
+            if let Some(vector) = vectors.remove(id) {
+                embeddings.push((id.clone(), vector.values().clone()));
+            }
+        }
+    }
+
+    Ok(embeddings)
+}
+
+fn nearest(point: &[f32], centroids: &[Vec<f32>]) -> usize {
+    centroids
+        .iter()
+        .map(|c| euclidean_distance(point, c))
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Small deterministic generator for seeding; clustering does not need more.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        XorShift(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub(crate) fn unit(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedding(id: &str, values: &[f32]) -> (String, Vec<f32>) {
+        (id.to_string(), values.to_vec())
+    }
+
+    #[test]
+    fn test_fit() {
+        let embeddings = vec![
+            embedding("a1", &[1.0, 0.0]),
+            embedding("a2", &[0.9, 0.1]),
+            embedding("a3", &[1.0, 0.2]),
+            embedding("b1", &[0.0, 1.0]),
+            embedding("b2", &[0.1, 0.9]),
+        ];
+
+        let clustering = KMeans::builder().k(2).build().fit(&embeddings).unwrap();
+        let a = clustering.assignments()["a1"];
+        let b = clustering.assignments()["b1"];
+
+        assert_ne!(a, b);
+        assert_eq!(clustering.members(a), vec!["a1", "a2", "a3"]);
+        assert_eq!(clustering.members(b), vec!["b1", "b2"]);
+        assert_eq!(clustering.centroids().len(), 2);
+    }
+
+    #[test]
+    fn test_fit_invalid_input() {
+        let embeddings = vec![embedding("a", &[1.0, 0.0]), embedding("b", &[1.0])];
+
+        assert!(KMeans::builder().k(3).build().fit(&embeddings).is_err());
+        assert!(KMeans::builder().k(2).build().fit(&embeddings).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use super::openai_api::{get_tokens, truncate_to_tokens, Message, OpenAIRequest, ResponseFormat};
+use super::pinecone_data::Match;
+use super::pipeline::embed;
+use super::rag::{RagError, RagSource, DEFAULT_REFUSAL};
+use super::rate_limit::Priority;
+use super::search::{SearchError, SemanticSearch};
+
+const COMPARISON_PROMPT: &str = "You compare collections of documents. The context holds \
+numbered excerpts grouped by collection, each group under a \"Source: <name>\" heading. Answer \
+the user's question separately for each source, using only that source's excerpts, and say so \
+when they do not contain the answer. Then compare and contrast the answers. Respond only with \
+JSON of the form {\"answers\": {\"<source name>\": string}, \"comparison\": string}.";
+
+/// Answers the same question from two or more namespaces side by side, e.g. two versions of
+/// a policy or the documentation of competing products.
+///
+/// The question is embedded once and the closest chunks of each namespace are retrieved.
+/// The chat model gets each namespace's excerpts under its own heading, and answers per
+/// namespace before comparing.
+///
+/// # Fields
+///
+/// * `namespaces`: Required. The namespaces to compare, at least two. They label the answers.
+/// * `model`: Optional. Chat model id. Defaults to "gpt-3.5-turbo".
+/// * `embedding_model`: Optional. Model the namespaces were embedded with. Defaults to "text-embedding-ada-002".
+/// * `top_k`: Optional. Chunks retrieved per namespace. Defaults to 4.
+/// * `context_tokens`: Optional. Token budget of each namespace's context. Defaults to 1500.
+///
+/// # Example
+///
+/// ```rust
+/// let chat = ComparisonChat::builder()
+///     .namespaces(vec!["policy-2022".to_string(), "policy-2023".to_string()])
+///     .build();
+/// let comparison = chat.compare("How long is the refund window?").await?;
+/// for answer in comparison.answers() {
+///     println!("{}: {}", answer.namespace(), answer.answer());
+/// }
+/// println!("{}", comparison.comparison());
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ComparisonChat {
+    namespaces: Vec<String>,
+
+    #[builder(default = "gpt-3.5-turbo".to_string())]
+    model: String,
+
+    #[builder(default = "text-embedding-ada-002".to_string())]
+    embedding_model: String,
+
+    #[builder(default = 4)]
+    top_k: i64,
+
+    #[builder(default = 1500)]
+    context_tokens: usize,
+}
+
+/// Answer of one namespace in a `Comparison`, with the excerpts it was given.
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceAnswer {
+    namespace: String,
+    answer: String,
+    sources: Vec<RagSource>,
+}
+
+/// Result of a `ComparisonChat`: one answer per namespace, in the order they were given,
+/// and the model's comparison of them.
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    answers: Vec<SourceAnswer>,
+    comparison: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComparisonResponse {
+    #[serde(default)]
+    answers: HashMap<String, String>,
+
+    #[serde(default)]
+    comparison: String,
+}
+
+impl ComparisonChat {
+    pub async fn compare(&self, question: &str) -> Result<Comparison, RagError> {
+        if self.namespaces.len() < 2 {
+            return Err(RagError::ChatError("a comparison needs at least two namespaces".to_string()));
+        }
+        let values = embed(&self.embedding_model, question, Priority::Interactive)
+            .await
+            .map_err(SearchError::from)?;
+        let retrieved = try_join_all(self.namespaces.iter().map(|namespace| self.retrieve(namespace, values.clone())))
+            .await?;
+
+        let response = OpenAIRequest::builder()
+            .model(self.model.clone())
+            .messages(self.messages(question, &retrieved))
+            .temperature(0.0)
+            .response_format(ResponseFormat::json_object())
+            .build()
+            .send()
+            .await
+            .map_err(|e| RagError::ChatError(e.to_string()))?;
+        let content = response
+            .choices()
+            .first()
+            .map(|choice| choice.message().content().to_string())
+            .ok_or_else(|| RagError::ChatError("response has no choices".to_string()))?;
+        let mut parsed: ComparisonResponse = serde_json::from_str(content.trim())
+            .map_err(|e| RagError::ChatError(format!("unexpected comparison response: {}", e)))?;
+
+        let answers = self
+            .namespaces
+            .iter()
+            .zip(retrieved)
+            .map(|(namespace, matches)| SourceAnswer {
+                answer: parsed
+                    .answers
+                    .remove(namespace)
+                    .unwrap_or_else(|| DEFAULT_REFUSAL.to_string()),
+                namespace: namespace.clone(),
+                sources: matches.iter().map(RagSource::from).collect(),
+            })
+            .collect();
+
+        Ok(Comparison {
+            answers,
+            comparison: parsed.comparison,
+        })
+    }
+
+    async fn retrieve(&self, namespace: &str, values: Vec<f32>) -> Result<Vec<Match>, RagError> {
+        let search = SemanticSearch::builder()
+            .embedding_model(self.embedding_model.clone())
+            .namespace(namespace.to_string())
+            .top_k(self.top_k)
+            .build();
+        Ok(search.search_vector(values).await?)
+    }
+
+    /// Builds the chat messages: the instructions with each namespace's numbered excerpts
+    /// under a "Source:" heading, then the question. `retrieved` holds the matches of each
+    /// namespace, in order.
+    pub fn messages(&self, question: &str, retrieved: &[Vec<Match>]) -> Vec<Message> {
+        let mut context = String::new();
+        for (namespace, matches) in self.namespaces.iter().zip(retrieved) {
+            context.push_str(&format!("Source: {}\n", namespace));
+            let mut remaining = self.context_tokens;
+            for (n, m) in matches.iter().enumerate() {
+                let Some(text) = m.metadata().get("text") else {
+                    continue;
+                };
+                let excerpt = format!("[{}] {}\n", n + 1, text);
+                let truncated = truncate_to_tokens(&excerpt, remaining);
+                if truncated.is_empty() {
+                    break;
+                }
+                remaining = remaining.saturating_sub(get_tokens(truncated).map_or(0, |t| t.len()));
+                context.push_str(truncated);
+            }
+            context.push('\n');
+        }
+
+        let system = format!("{}\n\nContext:\n{}", COMPARISON_PROMPT, context.trim_end());
+        vec![
+            Message::builder()
+                .role("system".to_string())
+                .content(system)
+                .build(),
+            Message::builder()
+                .role("user".to_string())
+                .content(question.to_string())
+                .build(),
+        ]
+    }
+}
+
+impl SourceAnswer {
+    pub fn namespace(&self) -> &String {
+        &self.namespace
+    }
+
+    pub fn answer(&self) -> &String {
+        &self.answer
+    }
+
+    pub fn sources(&self) -> &Vec<RagSource> {
+        &self.sources
+    }
+}
+
+impl Comparison {
+    pub fn answers(&self) -> &Vec<SourceAnswer> {
+        &self.answers
+    }
+
+    /// The answer of `namespace`, if it was compared.
+    pub fn answer(&self, namespace: &str) -> Option<&SourceAnswer> {
+        self.answers.iter().find(|answer| answer.namespace == namespace)
+    }
+
+    pub fn comparison(&self) -> &String {
+        &self.comparison
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::libs::temp_namespace::TempNamespace;
+    use crate::libs::test_util::{sample_documents, FakeServices};
+
+    #[tokio::test]
+    async fn test_compare() {
+        let fakes = FakeServices::start().await;
+        let documents = sample_documents();
+        let session = TempNamespace::new();
+        session.upload(&documents).await.unwrap();
+        let other = TempNamespace::new();
+        other.upload(&documents[..1]).await.unwrap();
+        let chat = ComparisonChat::builder()
+            .namespaces(vec![session.name().to_string(), other.name().to_string()])
+            .top_k(1)
+            .build();
+        fakes.set_chat_reply(&serde_json::json!({
+            "answers": { session.name(): "Two business days." },
+            "comparison": "Only the first covers shipping.",
+        }).to_string());
+
+        let comparison = chat.compare("how fast do orders ship").await.unwrap();
+        assert_eq!(comparison.answers()[0].answer(), "Two business days.");
+        assert_eq!(comparison.answers()[0].sources()[0].id(), "shipping#chunk0");
+        assert_eq!(comparison.answer(other.name()).unwrap().answer(), DEFAULT_REFUSAL);
+        assert_eq!(comparison.comparison(), "Only the first covers shipping.");
+        other.close().await.unwrap();
+        session.close().await.unwrap();
+    }
+}
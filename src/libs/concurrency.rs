@@ -0,0 +1,197 @@
+use std::fmt::Display;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tokio::sync::Notify;
+use typed_builder::TypedBuilder;
+
+/// Error message fragments of rate-limited (429) and timed-out requests, as reqwest and the
+/// OpenAI and Pinecone clients format them.
+const OVERLOAD_MARKERS: [&str; 3] = ["429", "Too Many Requests", "timed out"];
+
+/// Concurrency limit that adapts to the service it guards: additive increase while
+/// requests succeed, multiplicative decrease when they are rate limited or time out (AIMD).
+///
+/// Each success raises the limit by `1 / limit`, so about one more request is allowed per
+/// round of successes. An overloaded request halves it, once per round: requests that
+/// started before the last cut do not cut it again. Other errors leave the limit alone.
+///
+/// # Fields
+///
+/// * `initial`: Optional. Limit to start from. Defaults to 4.
+/// * `min`: Optional. Lowest limit. Defaults to 1.
+/// * `max`: Optional. Highest limit. Defaults to 32.
+///
+/// # Example
+///
+/// ```rust
+/// let limit = AdaptiveConcurrency::builder().max(16).build();
+/// let permit = limit.acquire().await;
+/// let result = client.upsert(vectors).await;
+/// permit.finish(&result);
+/// ```
+#[derive(Debug, TypedBuilder)]
+pub struct AdaptiveConcurrency {
+    #[builder(default = 4)]
+    initial: usize,
+
+    #[builder(default = 1)]
+    min: usize,
+
+    #[builder(default = 32)]
+    max: usize,
+
+    #[builder(setter(skip), default = Mutex::new(State::new(initial.clamp(min.max(1), max.max(1)))))]
+    state: Mutex<State>,
+
+    #[builder(setter(skip), default)]
+    released: Notify,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: f64,
+    in_flight: usize,
+    last_decrease: Option<Instant>,
+}
+
+impl State {
+    fn new(limit: usize) -> Self {
+        State {
+            limit: limit as f64,
+            in_flight: 0,
+            last_decrease: None,
+        }
+    }
+}
+
+/// A slot of an `AdaptiveConcurrency`, released when dropped.
+#[derive(Debug)]
+pub struct Permit<'a> {
+    limiter: &'a AdaptiveConcurrency,
+    started: Instant,
+}
+
+impl AdaptiveConcurrency {
+    /// Waits until fewer requests than the current limit are in flight and takes a slot.
+    pub async fn acquire(&self) -> Permit<'_> {
+        loop {
+            // Registered before checking, so a release in between is not missed.
+            let released = self.released.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < self.limit_of(&state) {
+                    state.in_flight += 1;
+                    return Permit {
+                        limiter: self,
+                        started: Instant::now(),
+                    };
+                }
+            }
+            released.await;
+        }
+    }
+
+    /// Requests currently allowed in flight.
+    pub fn limit(&self) -> usize {
+        self.limit_of(&self.state.lock().unwrap())
+    }
+
+    pub fn initial(&self) -> usize {
+        self.initial
+    }
+
+    pub fn min(&self) -> usize {
+        self.min.max(1)
+    }
+
+    /// Upper bound of the limit, e.g. to size a `buffer_unordered`.
+    pub fn max(&self) -> usize {
+        self.max.max(1)
+    }
+
+    fn limit_of(&self, state: &State) -> usize {
+        (state.limit as usize).clamp(self.min(), self.max())
+    }
+
+    fn record(&self, started: Instant, overloaded: Option<bool>) {
+        let mut state = self.state.lock().unwrap();
+        match overloaded {
+            Some(false) => state.limit = (state.limit + 1.0 / state.limit).min(self.max() as f64),
+            Some(true) if state.last_decrease.is_none_or(|at| started > at) => {
+                state.limit = (state.limit / 2.0).max(self.min() as f64);
+                state.last_decrease = Some(Instant::now());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Permit<'_> {
+    /// Reports how the request went and releases the slot. Dropping the permit instead
+    /// releases it without changing the limit.
+    pub fn finish<T, E: Display>(self, result: &Result<T, E>) {
+        let overloaded = match result {
+            Ok(_) => Some(false),
+            Err(e) if is_overload(&e.to_string()) => Some(true),
+            Err(_) => None,
+        };
+        self.limiter.record(self.started, overloaded);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().in_flight -= 1;
+        self.limiter.released.notify_waiters();
+    }
+}
+
+/// Whether an error message reports a rate-limited (429) or timed-out request.
+pub fn is_overload(message: &str) -> bool {
+    OVERLOAD_MARKERS.iter().any(|marker| message.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_aimd() {
+        let limiter = AdaptiveConcurrency::builder().initial(4).max(6).build();
+        let ok: Result<(), String> = Ok(());
+        let throttled: Result<(), String> = Err("Error status: 429 Too Many Requests".to_string());
+
+        // Each success adds 1/limit: five at a limit of 4 add one slot.
+        for _ in 0..5 {
+            limiter.acquire().await.finish(&ok);
+        }
+        assert_eq!(limiter.limit(), 5);
+
+        // Requests in flight together are one round: only the first 429 halves the limit.
+        let first = limiter.acquire().await;
+        let second = limiter.acquire().await;
+        first.finish(&throttled);
+        second.finish(&throttled);
+        assert_eq!(limiter.limit(), 2);
+
+        limiter.acquire().await.finish(&Err::<(), _>("invalid vector"));
+        assert_eq!(limiter.limit(), 2);
+        for _ in 0..4 {
+            limiter.acquire().await.finish(&throttled);
+        }
+        assert_eq!(limiter.limit(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits() {
+        let limiter = AdaptiveConcurrency::builder().initial(1).build();
+        let held = limiter.acquire().await;
+        let waiting = limiter.acquire();
+        tokio::pin!(waiting);
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        drop(held);
+        assert!(futures::poll!(waiting.as_mut()).is_ready());
+    }
+}
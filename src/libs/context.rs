@@ -0,0 +1,153 @@
+use std::future::Future;
+
+use tracing::Span;
+use typed_builder::TypedBuilder;
+
+tokio::task_local! {
+    static CONTEXT: RequestContext;
+}
+
+/// Who a unit of work is done for, carried to every OpenAI and Pinecone call made inside
+/// `scope`.
+///
+/// Inside the scope:
+/// * OpenAI requests without a `user` are sent with the context's `user`.
+/// * Pinecone requests without a `namespace` go to the `tenant`'s namespace.
+/// * Every request runs in a tracing span carrying the user, tenant, and trace id.
+/// * With the `otel` feature, the steps of the work are exported as one trace, see
+///   `telemetry::OtlpExporter`.
+///
+/// The context is task-local: it follows `.await`s, including the concurrent streams the
+/// pipeline uses, but not `tokio::spawn`ed tasks.
+///
+/// # Fields
+///
+/// * `user`: Optional. End-user id sent to OpenAI for abuse monitoring.
+/// * `tenant`: Optional. Tenant whose Pinecone namespace requests default to.
+/// * `trace_id`: Optional. Id correlating the spans of one request, e.g. from an
+///   `x-request-id` header.
+///
+/// # Example
+///
+/// ```rust
+/// let context = RequestContext::builder()
+///     .user("user-42".to_string())
+///     .tenant("acme".to_string())
+///     .build();
+///
+/// // Embeds with `user: "user-42"` and upserts into the "acme" namespace.
+/// let report = context.scope(pipeline.ingest(&documents)).await?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, TypedBuilder)]
+pub struct RequestContext {
+    #[builder(setter(strip_option), default)]
+    user: Option<String>,
+
+    #[builder(setter(strip_option), default)]
+    tenant: Option<String>,
+
+    #[builder(setter(strip_option), default)]
+    trace_id: Option<String>,
+}
+
+impl RequestContext {
+    /// Runs `future` with this context. A scope inside another replaces the outer context.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, traced("request", future)).await
+    }
+
+    /// The context of the enclosing `scope`, if any.
+    pub fn current() -> Option<RequestContext> {
+        CONTEXT.try_with(|context| context.clone()).ok()
+    }
+
+    pub fn user(&self) -> &Option<String> {
+        &self.user
+    }
+
+    pub fn tenant(&self) -> &Option<String> {
+        &self.tenant
+    }
+
+    pub fn trace_id(&self) -> &Option<String> {
+        &self.trace_id
+    }
+}
+
+/// `user` of the current context, for requests that set none.
+pub(crate) fn current_user() -> Option<String> {
+    CONTEXT.try_with(|context| context.user.clone()).ok().flatten()
+}
+
+/// Namespace of the current context's tenant, for requests that set none.
+pub(crate) fn current_namespace() -> Option<String> {
+    CONTEXT.try_with(|context| context.tenant.clone()).ok().flatten()
+}
+
+/// `user` carrying the current trace, for OpenAI requests without a user in their context.
+pub(crate) fn trace_user() -> Option<String> {
+    #[cfg(feature = "otel")]
+    return super::telemetry::current_span().map(|span| format!("trace:{}", span.trace_id()));
+    #[cfg(not(feature = "otel"))]
+    None
+}
+
+/// W3C `traceparent` header of the current trace, for downstream requests.
+pub(crate) fn traceparent() -> Option<String> {
+    #[cfg(feature = "otel")]
+    return super::telemetry::traceparent();
+    #[cfg(not(feature = "otel"))]
+    None
+}
+
+/// Runs `future` as the step `name` of the current trace, exported with the `otel` feature.
+pub(crate) async fn traced<F: Future>(name: &str, future: F) -> F::Output {
+    #[cfg(feature = "otel")]
+    return super::telemetry::traced(name, future).await;
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = name;
+        future.await
+    }
+}
+
+/// Span of a downstream call, carrying the current context.
+pub(crate) fn span(operation: &str) -> Span {
+    let context = RequestContext::current().unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        operation,
+        user = context.user.as_deref(),
+        tenant = context.tenant.as_deref(),
+        trace_id = context.trace_id.as_deref(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope() {
+        assert_eq!(RequestContext::current(), None);
+        assert_eq!(current_user(), None);
+
+        let outer = RequestContext::builder().user("a".to_string()).tenant("acme".to_string()).build();
+        outer
+            .scope(async {
+                assert_eq!(current_user().as_deref(), Some("a"));
+                assert_eq!(current_namespace().as_deref(), Some("acme"));
+
+                let inner = RequestContext::builder().user("b".to_string()).build();
+                inner
+                    .scope(async {
+                        assert_eq!(current_user().as_deref(), Some("b"));
+                        assert_eq!(current_namespace(), None);
+                    })
+                    .await;
+
+                assert_eq!(current_user().as_deref(), Some("a"));
+            })
+            .await;
+    }
+}
@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::database::{upsert, Database};
+use super::eval::{evaluate, EvalCase, EvalReport};
+use super::pipeline::{Document, IngestionPipeline, IngestionReport, PipelineError};
+use super::search::SemanticSearch;
+
+/// Prefix of the keys active namespace pointers are stored under in the `Database`.
+const ACTIVE_NAMESPACE_KEY_PREFIX: &str = "active-namespace:";
+
+/// Name of the pointer a `Cutover` switches unless given another.
+pub const DEFAULT_POINTER: &str = "default";
+
+#[derive(Debug, Error)]
+pub enum CutoverError {
+    #[error(transparent)]
+    PipelineError(#[from] PipelineError),
+
+    #[error("DatabaseError: {0}")]
+    DatabaseError(String),
+
+    #[error("CutoverError: shadow namespace {0:?} is already active")]
+    AlreadyActive(String),
+
+    #[error("CutoverError: {0} documents failed to ingest into the shadow namespace")]
+    Incomplete(usize),
+
+    #[error("CutoverError: active namespace changed from {expected:?} to {found:?} during the cutover")]
+    Conflict { expected: String, found: String },
+}
+
+/// Outcome of `Cutover::run`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CutoverReport {
+    ingestion: IngestionReport,
+    active: EvalReport,
+    shadow: EvalReport,
+
+    /// Whether the pointer now names the shadow namespace.
+    switched: bool,
+}
+
+/// Blue/green cutover of the namespace searches are served from.
+///
+/// The namespace in use is named by a pointer stored in `db`, read with `active_namespace`.
+/// `run` ingests documents into the shadow namespace of `pipeline`, evaluates `search` on
+/// both namespaces with the same cases, and switches the pointer to the shadow namespace
+/// if its hit rate is at most `max_hit_rate_drop` below the active one's. The switch is a
+/// single write, so readers see one namespace or the other. It is refused if the pointer
+/// moved since the run started, but the check and the write are not atomic: of two
+/// cutovers switching the same pointer at once, the last one wins, so run one at a time.
+/// The old namespace is kept for a rollback with `set_active_namespace`.
+///
+/// # Fields
+///
+/// * `db`: Required. Database the pointer is kept in.
+/// * `pipeline`: Required. Pipeline ingesting into the shadow namespace.
+/// * `pointer`: Optional. Name of the pointer. Defaults to `DEFAULT_POINTER`.
+/// * `search`: Optional. Search evaluated on both namespaces; its namespace is ignored.
+///   Defaults to the 10 closest vectors.
+/// * `concurrency`: Optional. Evaluation queries in flight at once. Defaults to 8.
+/// * `max_hit_rate_drop`: Optional. Largest hit rate loss accepted. Defaults to 0.
+/// * `dry_run`: Optional. Reports without switching. Defaults to false.
+///
+/// # Example
+///
+/// ```rust
+/// let cutover = Cutover::builder()
+///     .db(Arc::new(SQLiteDB::new("openai-pinecone.db")?))
+///     .pipeline(IngestionPipeline::builder().namespace("docs-green".to_string()).build())
+///     .build();
+/// let report = cutover.run(&documents, &cases).await?;
+/// println!("hit rate {:+.2}, switched: {}", report.hit_rate_delta(), report.switched());
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Cutover {
+    db: Arc<dyn Database>,
+    pipeline: IngestionPipeline,
+
+    #[builder(default = DEFAULT_POINTER.to_string())]
+    pointer: String,
+
+    #[builder(default = SemanticSearch::builder().build())]
+    search: SemanticSearch,
+
+    #[builder(default = 8)]
+    concurrency: usize,
+
+    #[builder(default)]
+    max_hit_rate_drop: f64,
+
+    #[builder(default)]
+    dry_run: bool,
+}
+
+impl Cutover {
+    /// Ingests `documents` into the shadow namespace, evaluates both namespaces on `cases`
+    /// and switches if the shadow one is good enough. Nothing is switched if any document
+    /// fails to ingest.
+    pub async fn run(&self, documents: &[Document], cases: &[EvalCase]) -> Result<CutoverReport, CutoverError> {
+        let shadow = self.pipeline.namespace().clone().unwrap_or_default();
+        let active = active_namespace(self.db.as_ref(), &self.pointer).await?.unwrap_or_default();
+        if shadow == active {
+            return Err(CutoverError::AlreadyActive(shadow));
+        }
+
+        let ingestion = self.pipeline.ingest(documents).await?;
+        if !ingestion.failed().is_empty() {
+            return Err(CutoverError::Incomplete(ingestion.failed().len()));
+        }
+
+        let active_report = evaluate(&self.search.clone().with_namespace(active.clone()), cases, self.concurrency).await;
+        let shadow_report = evaluate(&self.search.clone().with_namespace(shadow.clone()), cases, self.concurrency).await;
+        let switched =
+            !self.dry_run && shadow_report.hit_rate() + self.max_hit_rate_drop >= active_report.hit_rate();
+        if switched {
+            let found = active_namespace(self.db.as_ref(), &self.pointer).await?.unwrap_or_default();
+            if found != active {
+                return Err(CutoverError::Conflict { expected: active, found });
+            }
+            set_active_namespace(self.db.as_ref(), &self.pointer, &shadow).await?;
+        }
+
+        Ok(CutoverReport {
+            ingestion,
+            active: active_report,
+            shadow: shadow_report,
+            switched,
+        })
+    }
+}
+
+/// Namespace the pointer `pointer` names, "" for the default one, None if it was never set.
+pub async fn active_namespace(db: &dyn Database, pointer: &str) -> Result<Option<String>, CutoverError> {
+    match db.read(&active_namespace_key(pointer)).await {
+        Ok(data) => serde_json::from_str(&data).map_err(|e| CutoverError::DatabaseError(e.to_string())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Points `pointer` at `namespace`, e.g. to roll a cutover back.
+pub async fn set_active_namespace(db: &dyn Database, pointer: &str, namespace: &str) -> Result<(), CutoverError> {
+    let data = serde_json::to_string(namespace).map_err(|e| CutoverError::DatabaseError(e.to_string()))?;
+    upsert(db, &active_namespace_key(pointer), &data)
+        .await
+        .map_err(|e| CutoverError::DatabaseError(e.to_string()))
+}
+
+fn active_namespace_key(pointer: &str) -> String {
+    format!("{}{}", ACTIVE_NAMESPACE_KEY_PREFIX, pointer)
+}
+
+impl CutoverReport {
+    pub fn ingestion(&self) -> &IngestionReport {
+        &self.ingestion
+    }
+
+    pub fn active(&self) -> &EvalReport {
+        &self.active
+    }
+
+    pub fn shadow(&self) -> &EvalReport {
+        &self.shadow
+    }
+
+    pub fn switched(&self) -> bool {
+        self.switched
+    }
+
+    /// Shadow hit rate minus active hit rate.
+    pub fn hit_rate_delta(&self) -> f64 {
+        self.shadow.hit_rate() - self.active.hit_rate()
+    }
+
+    /// Shadow MRR minus active MRR.
+    pub fn mrr_delta(&self) -> f64 {
+        self.shadow.mrr() - self.active.mrr()
+    }
+
+    /// Shadow p95 latency minus active p95 latency, in milliseconds. None if every query
+    /// of either namespace failed.
+    pub fn p95_delta_ms(&self) -> Option<f64> {
+        Some(self.shadow.latency_ms()?.p95() - self.active.latency_ms()?.p95())
+    }
+}
+
+#[cfg(all(test, feature = "test-util", feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+    use crate::libs::test_util::{sample_documents, FakeServices};
+
+    /// A `Cutover` from "blue", holding the sample documents, to "green".
+    async fn blue_to_green(db: &Arc<SQLiteDB>, dry_run: bool) -> Cutover {
+        IngestionPipeline::builder().namespace("blue".to_string()).build().ingest(&sample_documents()).await.unwrap();
+        set_active_namespace(db.as_ref(), DEFAULT_POINTER, "blue").await.unwrap();
+        Cutover::builder()
+            .db(db.clone())
+            .pipeline(IngestionPipeline::builder().namespace("green".to_string()).build())
+            .search(SemanticSearch::builder().top_k(1).build())
+            .dry_run(dry_run)
+            .build()
+    }
+
+    fn cases() -> [EvalCase; 1] {
+        [EvalCase::builder()
+            .query("when are refunds issued".to_string())
+            .relevant_ids(vec!["refunds".to_string()])
+            .build()]
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_keeps_active_namespace() {
+        let _fakes = FakeServices::start().await;
+        let db = Arc::new(SQLiteDB::new(":memory:").unwrap());
+        assert_eq!(active_namespace(db.as_ref(), DEFAULT_POINTER).await.unwrap(), None);
+        let report = blue_to_green(&db, true).await.run(&sample_documents(), &cases()).await.unwrap();
+        assert!(!report.switched());
+        assert_eq!(active_namespace(db.as_ref(), DEFAULT_POINTER).await.unwrap().as_deref(), Some("blue"));
+    }
+
+    #[tokio::test]
+    async fn test_cutover_switches_namespace() {
+        let _fakes = FakeServices::start().await;
+        let db = Arc::new(SQLiteDB::new(":memory:").unwrap());
+        let cutover = blue_to_green(&db, false).await;
+        let report = cutover.run(&sample_documents(), &cases()).await.unwrap();
+        assert_eq!((report.active().namespace().as_str(), report.shadow().namespace().as_str()), ("blue", "green"));
+        assert_eq!((report.active().hit_rate(), report.shadow().mrr()), (1.0, 1.0));
+        assert_eq!(report.hit_rate_delta(), 0.0);
+        assert!(report.switched() && report.p95_delta_ms().is_some());
+        assert_eq!(active_namespace(db.as_ref(), DEFAULT_POINTER).await.unwrap().as_deref(), Some("green"));
+        assert!(matches!(cutover.run(&sample_documents(), &cases()).await, Err(CutoverError::AlreadyActive(_))));
+    }
+}
@@ -1,15 +1,81 @@
-use std::array::TryFromSliceError;
-use std::convert::TryInto;
 use std::error::Error;
 use std::fmt::Debug;
 use async_trait::async_trait;
+use thiserror::Error;
 
+/// Storage of text items by id.
+///
+/// Backends with a soft-delete mode (`SQLiteDB::with_soft_delete`,
+/// `PlanetScaleDB::with_soft_delete`) keep deleted items as tombstones stamped with a
+/// `deleted_at` time: they read as missing, but can be restored or audited until purged.
+/// The tombstone methods default to a backend without one, where `delete` removes items.
 #[async_trait]
-pub trait Database: Debug {
+pub trait Database: Debug + Send + Sync {
     async fn create(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>>;
     async fn read(&self, id: &str) -> Result<String, Box<dyn Error>>;
     async fn update(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>>;
     async fn delete(&self, id: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Brings the deleted item `id` back.
+    async fn restore(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        Err(format!("cannot restore {}: deleted items are not kept", id).into())
+    }
+
+    /// When `id` was deleted, in seconds since the Unix epoch, if it is a tombstone.
+    async fn deleted_at(&self, _id: &str) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    /// Every tombstone with its deletion time, oldest first, for deletion audits.
+    async fn tombstones(&self) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    /// Removes `id` for good, live or deleted.
+    async fn purge(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.delete(id).await
+    }
+
+    /// Removes the least recently read items with embeddings until those left take at
+    /// most `max_bytes`, and returns how many were removed. Backends that do not track
+    /// `last_accessed` keep everything.
+    async fn evict_embeddings(&self, _max_bytes: u64) -> Result<usize, Box<dyn Error>> {
+        Ok(0)
+    }
+
+    /// Ids of the live items starting with `prefix`, sorted.
+    async fn ids(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        Err(format!("cannot list ids starting with {:?}: not supported by this backend", prefix).into())
+    }
+}
+
+/// Creates `id` or, if it already exists, replaces its data. A tombstone of `id` is
+/// restored with the new data.
+pub async fn upsert(db: &dyn Database, id: &str, data: &str) -> Result<(), Box<dyn Error>> {
+    if db.read(id).await.is_ok() {
+        db.update(id, data).await
+    } else if db.deleted_at(id).await?.is_some() {
+        db.restore(id).await?;
+        db.update(id, data).await
+    } else {
+        db.create(id, data).await
+    }
+}
+
+/// Ids to evict from `items`, `(id, size in bytes)` ordered least recently used first, so
+/// the rest fit in `max_bytes`.
+#[cfg(any(feature = "native", feature = "sqlite"))]
+pub(crate) fn eviction_victims(items: Vec<(String, u64)>, max_bytes: u64) -> Vec<String> {
+    let mut excess = items.iter().map(|(_, size)| size).sum::<u64>().saturating_sub(max_bytes);
+    let mut victims = Vec::new();
+    for (id, size) in items {
+        if excess == 0 {
+            break;
+        }
+        excess = excess.saturating_sub(size);
+        victims.push(id);
+    }
+    victims
 }
 
 pub enum DatabaseOperation {
@@ -19,19 +85,138 @@ pub enum DatabaseOperation {
     Delete,
 }
 
-pub fn convert_binary_to_embeddings(binary_data: &[u8]) -> Result<Vec<f32>, TryFromSliceError> {
-    let mut embeddings = Vec::with_capacity(binary_data.len() / 4);
-    for chunk in binary_data.chunks_exact(4) {
-        let value = f32::from_le_bytes(chunk.try_into()?);
-        embeddings.push(value);
+/// Magic bytes opening every encoded embedding.
+const EMBEDDING_MAGIC: &[u8; 3] = b"EMB";
+
+/// Current version of the embedding encoding.
+const EMBEDDING_VERSION: u8 = 1;
+
+/// Length of the header: magic, version, byte order, and dimension.
+const EMBEDDING_HEADER_LEN: usize = 9;
+
+const LITTLE_ENDIAN: u8 = 0;
+const BIG_ENDIAN: u8 = 1;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EmbeddingCodecError {
+    #[error("UnsupportedVersion: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("HeaderError: {0}")]
+    HeaderError(String),
+
+    #[error("LengthError: {0}")]
+    LengthError(String),
+}
+
+/// Decodes an embedding stored by `convert_embeddings_to_binary`.
+///
+/// Data without the magic bytes is read as the headerless little-endian floats written
+/// before the encoding was versioned, so existing BLOBs stay readable.
+pub fn convert_binary_to_embeddings(binary_data: &[u8]) -> Result<Vec<f32>, EmbeddingCodecError> {
+    if !binary_data.starts_with(EMBEDDING_MAGIC) {
+        return decode_floats(binary_data, LITTLE_ENDIAN);
+    }
+    if binary_data.len() < EMBEDDING_HEADER_LEN {
+        return Err(EmbeddingCodecError::HeaderError(format!(
+            "{} bytes is shorter than the header",
+            binary_data.len()
+        )));
+    }
+
+    let (header, data) = binary_data.split_at(EMBEDDING_HEADER_LEN);
+    if header[3] != EMBEDDING_VERSION {
+        return Err(EmbeddingCodecError::UnsupportedVersion(header[3]));
     }
-    Ok(embeddings)
+    let byte_order = header[4];
+    let dimension = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as usize;
+
+    if data.len() != dimension * 4 {
+        return Err(EmbeddingCodecError::LengthError(format!(
+            "header declares {} values, found {} bytes",
+            dimension,
+            data.len()
+        )));
+    }
+    decode_floats(data, byte_order)
 }
 
+/// Encodes an embedding as the magic bytes, version, byte order (little-endian), and
+/// dimension, followed by the values.
 pub fn convert_embeddings_to_binary(embeddings: &[f32]) -> Vec<u8> {
-    let mut bytes = Vec::new();
+    let mut bytes = Vec::with_capacity(EMBEDDING_HEADER_LEN + embeddings.len() * 4);
+    bytes.extend_from_slice(EMBEDDING_MAGIC);
+    bytes.push(EMBEDDING_VERSION);
+    bytes.push(LITTLE_ENDIAN);
+    bytes.extend_from_slice(&(embeddings.len() as u32).to_le_bytes());
     for value in embeddings {
         bytes.extend_from_slice(&value.to_le_bytes());
     }
     bytes
 }
+
+fn decode_floats(data: &[u8], byte_order: u8) -> Result<Vec<f32>, EmbeddingCodecError> {
+    if !data.len().is_multiple_of(4) {
+        return Err(EmbeddingCodecError::LengthError(format!(
+            "{} bytes is not a whole number of values",
+            data.len()
+        )));
+    }
+
+    let from_bytes = match byte_order {
+        LITTLE_ENDIAN => f32::from_le_bytes,
+        BIG_ENDIAN => f32::from_be_bytes,
+        other => return Err(EmbeddingCodecError::HeaderError(format!("unknown byte order {}", other))),
+    };
+    Ok(data
+        .chunks_exact(4)
+        .map(|chunk| from_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_embedding_round_trip(embeddings in prop::collection::vec(any::<f32>(), 0..2048)) {
+            let decoded = convert_binary_to_embeddings(&convert_embeddings_to_binary(&embeddings)).unwrap();
+            // Compare bits, so NaNs round-trip too.
+            let bits = |values: &[f32]| values.iter().map(|value| value.to_bits()).collect::<Vec<_>>();
+            prop_assert_eq!(bits(&decoded), bits(&embeddings));
+        }
+
+        #[test]
+        fn test_truncated_embedding_is_rejected(embeddings in prop::collection::vec(any::<f32>(), 1..256), cut in 1usize..4) {
+            let binary = convert_embeddings_to_binary(&embeddings);
+            prop_assert!(convert_binary_to_embeddings(&binary[..binary.len() - cut]).is_err());
+            prop_assert!(convert_binary_to_embeddings(&binary[..binary.len() - 4]).is_err());
+        }
+    }
+
+    #[cfg(any(feature = "native", feature = "sqlite"))]
+    #[test]
+    fn test_eviction_victims() {
+        let items = vec![("old".to_string(), 10), ("mid".to_string(), 10), ("new".to_string(), 10)];
+        assert!(eviction_victims(items.clone(), 30).is_empty());
+        assert_eq!(eviction_victims(items.clone(), 25), vec!["old"]);
+        assert_eq!(eviction_victims(items, 5), vec!["old", "mid", "new"]);
+    }
+
+    #[test]
+    fn test_embedding_formats() {
+        let legacy: Vec<u8> = [0.5f32, -2.0].iter().flat_map(|value| value.to_le_bytes()).collect();
+        assert_eq!(convert_binary_to_embeddings(&legacy).unwrap(), vec![0.5, -2.0]);
+
+        let mut big_endian = b"EMB\x01\x01".to_vec();
+        big_endian.extend_from_slice(&2u32.to_le_bytes());
+        big_endian.extend([0.5f32, -2.0].iter().flat_map(|value| value.to_be_bytes()));
+        assert_eq!(convert_binary_to_embeddings(&big_endian).unwrap(), vec![0.5, -2.0]);
+
+        let mut future = convert_embeddings_to_binary(&[1.0]);
+        future[3] = 2;
+        assert_eq!(convert_binary_to_embeddings(&future), Err(EmbeddingCodecError::UnsupportedVersion(2)));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::libs::database::{convert_binary_to_embeddings, convert_embeddings_to_binary, Database};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::primitives::Blob;
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, AttributeValue, BillingMode, KeySchemaElement, KeyType, ScalarAttributeType,
+};
+use aws_sdk_dynamodb::Client;
+use std::error::Error;
+
+/// `Database` over a DynamoDB table, for serverless deployments without a SQL server.
+///
+/// Items are keyed by the string partition key `id`; the text is stored in the `data`
+/// attribute and embeddings as a binary `embedding` attribute, mirroring the columns of
+/// `SQLiteDB`. Credentials and region come from the standard AWS environment (e.g. a
+/// Lambda's execution role).
+///
+/// # Example
+///
+/// ```rust
+/// let db = DynamoDB::new("openai-pinecone").await;
+/// db.create_table().await?; // once, if the table does not exist yet
+/// let queue = JobQueue::new(Arc::new(db));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DynamoDB {
+    client: Client,
+    table: String,
+}
+
+impl DynamoDB {
+    /// Connects with the configuration loaded from the environment.
+    pub async fn new(table: &str) -> Self {
+        let config = aws_config::load_from_env().await;
+        Self::from_client(Client::new(&config), table)
+    }
+
+    pub fn from_client(client: Client, table: &str) -> Self {
+        DynamoDB {
+            client,
+            table: table.to_string(),
+        }
+    }
+
+    /// Creates the table with on-demand billing.
+    pub async fn create_table(&self) -> Result<(), Box<dyn Error>> {
+        self.client
+            .create_table()
+            .table_name(&self.table)
+            .attribute_definitions(
+                AttributeDefinition::builder()
+                    .attribute_name("id")
+                    .attribute_type(ScalarAttributeType::S)
+                    .build()?,
+            )
+            .key_schema(KeySchemaElement::builder().attribute_name("id").key_type(KeyType::Hash).build()?)
+            .billing_mode(BillingMode::PayPerRequest)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn insert_embedding_data(&self, id: &str, data: &str, embeddings: &[f32]) -> Result<(), Box<dyn Error>> {
+        self.client
+            .put_item()
+            .table_name(&self.table)
+            .item("id", AttributeValue::S(id.to_string()))
+            .item("data", AttributeValue::S(data.to_string()))
+            .item("embedding", AttributeValue::B(Blob::new(convert_embeddings_to_binary(embeddings))))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Every stored embedding with its id.
+    pub async fn embeddings(&self) -> Result<Vec<(String, Vec<f32>)>, Box<dyn Error>> {
+        let mut embeddings = Vec::new();
+        let mut pages = self
+            .client
+            .scan()
+            .table_name(&self.table)
+            .projection_expression("id, embedding")
+            .into_paginator()
+            .items()
+            .send();
+
+        while let Some(item) = pages.next().await {
+            let item = item?;
+            if let (Some(AttributeValue::S(id)), Some(AttributeValue::B(binary))) =
+                (item.get("id"), item.get("embedding"))
+            {
+                embeddings.push((id.clone(), convert_binary_to_embeddings(binary.as_ref())?));
+            }
+        }
+        Ok(embeddings)
+    }
+}
+
+#[async_trait]
+impl Database for DynamoDB {
+    async fn create(&self, id: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.client
+            .put_item()
+            .table_name(&self.table)
+            .item("id", AttributeValue::S(id.to_string()))
+            .item("data", AttributeValue::S(data.to_string()))
+            .condition_expression("attribute_not_exists(id)")
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn read(&self, id: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("id", AttributeValue::S(id.to_string()))
+            .consistent_read(true)
+            .send()
+            .await?;
+
+        match output.item().and_then(|item| item.get("data")) {
+            Some(AttributeValue::S(data)) => Ok(data.clone()),
+            _ => Err(format!("item {} not found", id).into()),
+        }
+    }
+
+    async fn update(&self, id: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self
+            .client
+            .update_item()
+            .table_name(&self.table)
+            .key("id", AttributeValue::S(id.to_string()))
+            .update_expression("SET #data = :data")
+            .expression_attribute_names("#data", "data")
+            .expression_attribute_values(":data", AttributeValue::S(data.to_string()))
+            .condition_expression("attribute_exists(id)")
+            .send()
+            .await;
+
+        // Like SQL's UPDATE, updating a missing item changes nothing.
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_conditional_check_failed_exception()) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.client
+            .delete_item()
+            .table_name(&self.table)
+            .key("id", AttributeValue::S(id.to_string()))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn ids(&self, prefix: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut pages = self
+            .client
+            .scan()
+            .table_name(&self.table)
+            .projection_expression("id")
+            .filter_expression("begins_with(id, :prefix)")
+            .expression_attribute_values(":prefix", AttributeValue::S(prefix.to_string()))
+            .consistent_read(true)
+            .into_paginator()
+            .items()
+            .send();
+
+        let mut ids = Vec::new();
+        while let Some(item) = pages.next().await {
+            if let Some(AttributeValue::S(id)) = item?.get("id") {
+                ids.push(id.clone());
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_dynamodb() {
+        let db = DynamoDB::new("openai-pinecone-test").await;
+
+        db.delete("a").await.unwrap();
+        db.create("a", "one").await.unwrap();
+        assert!(db.create("a", "again").await.is_err());
+        db.update("a", "two").await.unwrap();
+        assert_eq!(db.read("a").await.unwrap(), "two");
+        db.delete("a").await.unwrap();
+        assert!(db.read("a").await.is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::io::{self, Write};
+
+use futures::{stream, StreamExt};
+
+use super::openai_api::OpenAIEmbeddingRequest;
+use super::pinecone_data::{write_floats_json, write_json};
+
+/// Writes embeddings as JSON Lines, one `{"id", "model", "embedding"}` record per line,
+/// without holding more than the record being written.
+///
+/// # Example
+///
+/// ```rust
+/// let mut writer = EmbeddingWriter::new(BufWriter::new(File::create("embeddings.jsonl")?));
+/// let texts = documents.iter().map(|d| (d.id().clone(), d.text().clone()));
+/// embed_to_writer(texts, "text-embedding-ada-002", 4, &mut writer).await?;
+/// writer.flush()?;
+/// ```
+#[derive(Debug)]
+pub struct EmbeddingWriter<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    written: usize,
+}
+
+impl<W: Write> EmbeddingWriter<W> {
+    pub fn new(writer: W) -> Self {
+        EmbeddingWriter {
+            writer,
+            buffer: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Writes one record. The line is built in a buffer reused across records, then
+    /// written whole.
+    pub fn write(&mut self, id: &str, model: &str, embedding: &[f32]) -> io::Result<()> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(b"{\"id\":");
+        write_json(id, &mut self.buffer);
+        self.buffer.extend_from_slice(b",\"model\":");
+        write_json(model, &mut self.buffer);
+        self.buffer.extend_from_slice(b",\"embedding\":");
+        write_floats_json(embedding, &mut self.buffer);
+        self.buffer.extend_from_slice(b"}\n");
+
+        self.writer.write_all(&self.buffer)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Number of records written.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Embeds each `(id, text)` of `inputs` with `model`, `concurrency` at a time, and writes
+/// each embedding to `writer` as soon as it arrives, so records are in completion order.
+/// Inputs are pulled only as requests free up, so an iterator over a large file is never
+/// loaded whole. Stops at the first failed request; what was written before stays.
+/// Returns the number of records written.
+pub async fn embed_to_writer<I, W>(
+    inputs: I,
+    model: &str,
+    concurrency: usize,
+    writer: &mut EmbeddingWriter<W>,
+) -> Result<usize, Box<dyn Error>>
+where
+    I: IntoIterator<Item = (String, String)>,
+    W: Write,
+{
+    let mut embeddings = stream::iter(inputs)
+        .map(|(id, text)| async move {
+            let response = OpenAIEmbeddingRequest::builder()
+                .model(model.to_string())
+                .input(text)
+                .build()
+                .send()
+                .await
+                .map_err(|e| format!("{}: {}", id, e))?;
+            Ok::<_, String>((id, response))
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    let mut written = 0;
+    while let Some(result) = embeddings.next().await {
+        let (id, response) = result?;
+        for embedding in response.data() {
+            writer.write(&id, response.model(), embedding.embedding())?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedding_writer() {
+        let mut writer = EmbeddingWriter::new(Vec::new());
+        writer.write("a", "text-embedding-ada-002", &[0.5, -1.0]).unwrap();
+        writer.write("b\"", "text-embedding-ada-002", &[]).unwrap();
+        assert_eq!(writer.written(), 2);
+
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let records: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records[0]["embedding"], serde_json::json!([0.5, -1.0]));
+        assert_eq!(records[1]["id"], "b\"");
+    }
+}
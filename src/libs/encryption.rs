@@ -0,0 +1,217 @@
+use std::env;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use thiserror::Error;
+
+use super::database::Database;
+
+/// Environment variable `Cipher::from_env` reads the base64 encoded key from.
+pub const ENCRYPTION_KEY_VAR: &str = "DATA_ENCRYPTION_KEY";
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("KeyError: {0}")]
+    KeyError(String),
+
+    #[error("EncryptError: encryption failed")]
+    EncryptError,
+
+    #[error("DecryptError: {0}")]
+    DecryptError(String),
+}
+
+/// AES-256-GCM cipher for data stored at rest.
+///
+/// Every value is encrypted with a fresh random nonce, stored in front of the ciphertext,
+/// so identical texts do not produce identical rows. Decryption authenticates the data and
+/// fails on anything tampered with or written under another key.
+///
+/// # Example
+///
+/// ```rust
+/// // Key from the environment...
+/// let cipher = Cipher::from_env()?;
+/// // ...or from a key management service.
+/// let cipher = Cipher::from_provider(async { kms_decrypt_data_key().await }).await?;
+///
+/// let db = SQLiteDB::new("mirror.db")?.with_cipher(cipher);
+/// ```
+#[derive(Clone)]
+pub struct Cipher {
+    cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cipher { .. }")
+    }
+}
+
+impl Cipher {
+    /// A cipher with a 32-byte key.
+    pub fn new(key: &[u8]) -> Result<Self, EncryptionError> {
+        if key.len() != 32 {
+            return Err(EncryptionError::KeyError(format!("expected 32 key bytes, got {}", key.len())));
+        }
+        Ok(Cipher {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        })
+    }
+
+    /// A cipher with the base64 encoded key in `DATA_ENCRYPTION_KEY`.
+    pub fn from_env() -> Result<Self, EncryptionError> {
+        dotenv::dotenv().ok();
+        let key = env::var(ENCRYPTION_KEY_VAR)
+            .map_err(|_| EncryptionError::KeyError(format!("{} is not set", ENCRYPTION_KEY_VAR)))?;
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| EncryptionError::KeyError(e.to_string()))?;
+        Self::new(&key)
+    }
+
+    /// A cipher with the key returned by `provider`, e.g. a data key decrypted by a KMS.
+    pub async fn from_provider<F, E>(provider: F) -> Result<Self, EncryptionError>
+    where
+        F: Future<Output = Result<Vec<u8>, E>>,
+        E: std::fmt::Display,
+    {
+        let key = provider.await.map_err(|e| EncryptionError::KeyError(e.to_string()))?;
+        Self::new(&key)
+    }
+
+    /// A new random key, base64 encoded for `DATA_ENCRYPTION_KEY`.
+    pub fn generate_key() -> String {
+        STANDARD.encode(Aes256Gcm::generate_key(OsRng))
+    }
+
+    /// Encrypts `plaintext` to nonce followed by ciphertext.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| EncryptionError::EncryptError)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(EncryptionError::DecryptError("data too short".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| EncryptionError::DecryptError("wrong key or corrupted data".to_string()))
+    }
+
+    /// Encrypts `text` to base64, for text columns.
+    pub fn encrypt_text(&self, text: &str) -> Result<String, EncryptionError> {
+        Ok(STANDARD.encode(self.encrypt(text.as_bytes())?))
+    }
+
+    pub fn decrypt_text(&self, sealed: &str) -> Result<String, EncryptionError> {
+        let sealed = STANDARD
+            .decode(sealed)
+            .map_err(|e| EncryptionError::DecryptError(e.to_string()))?;
+        String::from_utf8(self.decrypt(&sealed)?).map_err(|e| EncryptionError::DecryptError(e.to_string()))
+    }
+}
+
+/// Encrypts the data of any `Database` before it is stored, and decrypts it on read.
+///
+/// Ids stay in plaintext so items can still be looked up. Use it for backends without
+/// built-in encryption; `SQLiteDB::with_cipher` also covers embeddings.
+///
+/// # Example
+///
+/// ```rust
+/// let db = EncryptedDatabase::new(Arc::new(DynamoDB::new("docs").await), Cipher::from_env()?);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EncryptedDatabase {
+    db: Arc<dyn Database>,
+    cipher: Cipher,
+}
+
+impl EncryptedDatabase {
+    pub fn new(db: Arc<dyn Database>, cipher: Cipher) -> Self {
+        EncryptedDatabase { db, cipher }
+    }
+}
+
+#[async_trait]
+impl Database for EncryptedDatabase {
+    async fn create(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>> {
+        let sealed = self.cipher.encrypt_text(data)?;
+        self.db.create(id, &sealed).await
+    }
+
+    async fn read(&self, id: &str) -> Result<String, Box<dyn Error>> {
+        let sealed = self.db.read(id).await?;
+        Ok(self.cipher.decrypt_text(&sealed)?)
+    }
+
+    async fn update(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>> {
+        let sealed = self.cipher.encrypt_text(data)?;
+        self.db.update(id, &sealed).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.delete(id).await
+    }
+
+    async fn restore(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.restore(id).await
+    }
+
+    async fn deleted_at(&self, id: &str) -> Result<Option<u64>, Box<dyn Error>> {
+        self.db.deleted_at(id).await
+    }
+
+    async fn tombstones(&self) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+        self.db.tombstones().await
+    }
+
+    async fn purge(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.db.purge(id).await
+    }
+
+    async fn evict_embeddings(&self, max_bytes: u64) -> Result<usize, Box<dyn Error>> {
+        self.db.evict_embeddings(max_bytes).await
+    }
+
+    async fn ids(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.db.ids(prefix).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cipher() {
+        let cipher = Cipher::new(&STANDARD.decode(Cipher::generate_key()).unwrap()).unwrap();
+
+        let sealed = cipher.encrypt_text("secret").unwrap();
+        assert!(!sealed.contains("secret"));
+        assert_ne!(sealed, cipher.encrypt_text("secret").unwrap());
+        assert_eq!(cipher.decrypt_text(&sealed).unwrap(), "secret");
+
+        let other = Cipher::new(&[7; 32]).unwrap();
+        assert!(other.decrypt_text(&sealed).is_err());
+        assert!(Cipher::new(&[0; 16]).is_err());
+    }
+}
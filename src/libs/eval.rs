@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use super::pinecone_data::chunk_prefix;
+use super::search::{LatencySummary, SemanticSearch};
+
+/// A query of an evaluation set and the ids that should be retrieved for it. An id matches
+/// the vector with that id or, for a document id, any of its chunks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, TypedBuilder)]
+pub struct EvalCase {
+    query: String,
+    relevant_ids: Vec<String>,
+}
+
+/// Retrieval quality and latency of a search over an evaluation set, see `evaluate`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EvalReport {
+    /// Namespace searched, "" for the default one.
+    namespace: String,
+    queries: usize,
+    errors: usize,
+
+    /// Share of the queries with a relevant id among their matches.
+    hit_rate: f64,
+
+    /// Mean reciprocal rank of the first relevant match, 0 for queries without one.
+    mrr: f64,
+
+    /// None if every query failed.
+    latency_ms: Option<LatencySummary>,
+}
+
+/// Runs every case of `cases` through `search` with up to `concurrency` in flight and
+/// scores its matches. Failed queries count as misses.
+///
+/// # Example
+///
+/// ```rust
+/// let cases: Vec<EvalCase> = serde_json::from_str(&std::fs::read_to_string("eval.json")?)?;
+/// let report = evaluate(&SemanticSearch::builder().top_k(5).build(), &cases, 8).await;
+/// println!("hit rate {:.2}, MRR {:.2}", report.hit_rate(), report.mrr());
+/// ```
+pub async fn evaluate(search: &SemanticSearch, cases: &[EvalCase], concurrency: usize) -> EvalReport {
+    let queries: Vec<String> = cases.iter().map(|case| case.query.clone()).collect();
+    let results = search.query_many(&queries, concurrency).await;
+
+    let ranks: Vec<Option<usize>> = cases
+        .iter()
+        .zip(&results)
+        .map(|(case, result)| result.matches().iter().position(|m| case.is_relevant(m.id())))
+        .collect();
+    let queries = cases.len().max(1) as f64;
+    EvalReport {
+        namespace: search.namespace().clone().unwrap_or_default(),
+        queries: cases.len(),
+        errors: results.iter().filter(|result| result.error().is_some()).count(),
+        hit_rate: ranks.iter().filter(|rank| rank.is_some()).count() as f64 / queries,
+        mrr: ranks.iter().flatten().map(|rank| 1.0 / (rank + 1) as f64).sum::<f64>() / queries,
+        latency_ms: LatencySummary::from_results(&results),
+    }
+}
+
+impl EvalCase {
+    pub fn query(&self) -> &String {
+        &self.query
+    }
+
+    pub fn relevant_ids(&self) -> &Vec<String> {
+        &self.relevant_ids
+    }
+
+    fn is_relevant(&self, id: &str) -> bool {
+        self.relevant_ids
+            .iter()
+            .any(|relevant| id == relevant || id.starts_with(&chunk_prefix(relevant)))
+    }
+}
+
+impl EvalReport {
+    pub fn namespace(&self) -> &String {
+        &self.namespace
+    }
+
+    pub fn queries(&self) -> usize {
+        self.queries
+    }
+
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        self.hit_rate
+    }
+
+    pub fn mrr(&self) -> f64 {
+        self.mrr
+    }
+
+    pub fn latency_ms(&self) -> Option<&LatencySummary> {
+        self.latency_ms.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::pinecone_data::chunk_id;
+
+    #[test]
+    fn test_is_relevant() {
+        let case = EvalCase::builder()
+            .query("when are refunds issued".to_string())
+            .relevant_ids(vec!["refunds".to_string(), "faq#chunk3".to_string()])
+            .build();
+        assert!(case.is_relevant(&chunk_id("refunds", 2)));
+        assert!(case.is_relevant("faq#chunk3"));
+        assert!(!case.is_relevant("faq#chunk30"));
+        assert!(!case.is_relevant(&chunk_id("refunds-old", 0)));
+    }
+}
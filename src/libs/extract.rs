@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::openai_api::{Message, OpenAIRequest, ResponseFormat};
+
+const EXTRACTION_PROMPT: &str = "Extract the requested information from the text the user \
+sends. Use only what the text states; use null for anything it does not mention. Respond only \
+with JSON matching the schema.";
+
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("ChatError: {0}")]
+    ChatError(String),
+
+    #[error("InvalidOutput: no valid output after {attempts} attempts, last error: {error}")]
+    InvalidOutput { attempts: usize, error: String },
+}
+
+/// A type that can describe itself as a JSON Schema, so the chat model can be constrained
+/// to produce it. Implemented for strings, numbers, booleans, `Option`, `Vec`, and
+/// `HashMap<String, _>`; structs build theirs with `object_schema`.
+///
+/// # Example
+///
+/// ```rust
+/// #[derive(Debug, Deserialize)]
+/// struct Invoice {
+///     vendor: String,
+///     total: f64,
+///     due_date: Option<String>,
+/// }
+///
+/// impl JsonSchema for Invoice {
+///     fn json_schema() -> Value {
+///         object_schema(&[
+///             ("vendor", String::json_schema()),
+///             ("total", f64::json_schema()),
+///             ("due_date", Option::<String>::json_schema()),
+///         ])
+///     }
+///
+///     fn validate(&self) -> Result<(), String> {
+///         if self.total < 0.0 {
+///             return Err("total must not be negative".to_string());
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// let invoice: Invoice = extract(&text).await?;
+/// ```
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+
+    /// Name sent with the schema. Defaults to the type's name.
+    fn schema_name() -> String {
+        let name = std::any::type_name::<Self>();
+        let name = name.split('<').next().unwrap_or(name);
+        name.rsplit("::").next().unwrap_or(name).to_string()
+    }
+
+    /// Checks what the schema cannot express, e.g. ranges. The error is sent back to the
+    /// model on retry, so it should say what is wrong.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Schema of an object with `properties`, all required and no others allowed, as structured
+/// outputs require. Make a property optional with an `Option` schema.
+pub fn object_schema(properties: &[(&str, Value)]) -> Value {
+    let required: Vec<&str> = properties.iter().map(|(name, _)| *name).collect();
+    let properties: serde_json::Map<String, Value> = properties
+        .iter()
+        .map(|(name, schema)| (name.to_string(), schema.clone()))
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+macro_rules! impl_json_schema {
+    ($schema_type:literal: $($t:ty),+) => {
+        $(
+            impl JsonSchema for $t {
+                fn json_schema() -> Value {
+                    json!({ "type": $schema_type })
+                }
+            }
+        )+
+    };
+}
+
+impl_json_schema!("string": String);
+impl_json_schema!("boolean": bool);
+impl_json_schema!("integer": i8, i16, i32, i64, u8, u16, u32, u64, usize);
+impl_json_schema!("number": f32, f64);
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema() -> Value {
+        json!({ "anyOf": [T::json_schema(), { "type": "null" }] })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.as_ref().map_or(Ok(()), T::validate)
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.iter().try_for_each(T::validate)
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for HashMap<String, T> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.values().try_for_each(T::validate)
+    }
+}
+
+/// Turns text into a typed value with a schema-constrained chat request.
+///
+/// Replies that do not parse into `T`, or that `T::validate` rejects, are sent back to the
+/// model with the error, up to `max_retries` times.
+///
+/// # Fields
+///
+/// * `model`: Optional. Chat model, one that supports structured outputs. Defaults to "gpt-4o-mini".
+/// * `max_retries`: Optional. Attempts after the first invalid reply. Defaults to 2.
+/// * `instructions`: Optional. Added to the system prompt, e.g. to explain fields.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Extractor {
+    #[builder(default = "gpt-4o-mini".to_string())]
+    model: String,
+
+    #[builder(default = 2)]
+    max_retries: usize,
+
+    #[builder(setter(strip_option), default)]
+    instructions: Option<String>,
+}
+
+/// Extracts a `T` from `text` with the default `Extractor`.
+pub async fn extract<T: JsonSchema + DeserializeOwned>(text: &str) -> Result<T, ExtractError> {
+    Extractor::builder().build().extract(text).await
+}
+
+impl Extractor {
+    pub async fn extract<T: JsonSchema + DeserializeOwned>(&self, text: &str) -> Result<T, ExtractError> {
+        let system = match &self.instructions {
+            Some(instructions) => format!("{}\n{}", EXTRACTION_PROMPT, instructions),
+            None => EXTRACTION_PROMPT.to_string(),
+        };
+        let mut messages = vec![
+            Message::builder().role("system".to_string()).content(system).build(),
+            Message::builder().role("user".to_string()).content(text.to_string()).build(),
+        ];
+        let format = ResponseFormat::json_schema(&T::schema_name(), T::json_schema());
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let response = OpenAIRequest::builder()
+                .model(self.model.clone())
+                .messages(messages.clone())
+                .temperature(0.0)
+                .response_format(format.clone())
+                .build()
+                .send()
+                .await
+                .map_err(|e| ExtractError::ChatError(e.to_string()))?;
+            let content = response
+                .choices()
+                .first()
+                .map(|choice| choice.message().content().to_string())
+                .ok_or_else(|| ExtractError::ChatError("response has no choices".to_string()))?;
+
+            let error = match parse::<T>(&content) {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if attempts > self.max_retries {
+                return Err(ExtractError::InvalidOutput { attempts, error });
+            }
+            messages.push(Message::builder().role("assistant".to_string()).content(content).build());
+            messages.push(
+                Message::builder()
+                    .role("user".to_string())
+                    .content(format!("That output is invalid: {}. Respond again with only the corrected JSON.", error))
+                    .build(),
+            );
+        }
+    }
+}
+
+/// Parses and validates a reply.
+fn parse<T: JsonSchema + DeserializeOwned>(content: &str) -> Result<T, String> {
+    let value: T = serde_json::from_str(content.trim()).map_err(|e| e.to_string())?;
+    value.validate()?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::FakeServices;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Contact {
+        name: String,
+        age: Option<u32>,
+        emails: Vec<String>,
+    }
+
+    impl JsonSchema for Contact {
+        fn json_schema() -> Value {
+            object_schema(&[
+                ("name", String::json_schema()),
+                ("age", Option::<u32>::json_schema()),
+                ("emails", Vec::<String>::json_schema()),
+            ])
+        }
+
+        fn validate(&self) -> Result<(), String> {
+            match self.age {
+                Some(age) if age > 150 => Err(format!("age {} is not plausible", age)),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let schema = Contact::json_schema();
+        assert_eq!(schema["required"], json!(["name", "age", "emails"]));
+        assert_eq!(schema["properties"]["age"]["anyOf"][1], json!({ "type": "null" }));
+        assert_eq!(Contact::schema_name(), "Contact");
+        assert_eq!(Vec::<Contact>::schema_name(), "Vec");
+
+        let contact: Contact = parse(r#"{"name": "Ada", "age": null, "emails": ["ada@example.com"]}"#).unwrap();
+        assert_eq!(contact.emails, ["ada@example.com"]);
+        assert!(parse::<Contact>(r#"{"name": "Ada", "emails": []}"#).is_ok());
+        assert!(parse::<Contact>(r#"{"name": 1, "age": null, "emails": []}"#).is_err());
+        assert_eq!(
+            parse::<Contact>(r#"{"name": "Ada", "age": 200, "emails": []}"#).unwrap_err(),
+            "age 200 is not plausible"
+        );
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_extract() {
+        let fakes = FakeServices::start().await;
+        fakes.set_chat_reply(r#"{"vendor": "Acme", "total": "12.50"}"#);
+        let invoice: HashMap<String, String> = extract("Acme invoice, total $12.50").await.unwrap();
+        assert_eq!(invoice["vendor"], "Acme");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_extract_invalid_output() {
+        let fakes = FakeServices::start().await;
+        fakes.set_chat_reply("Acme, $12.50");
+        match Extractor::builder().max_retries(1).build().extract::<Vec<String>>("Acme invoice").await {
+            Err(ExtractError::InvalidOutput { attempts, .. }) => assert_eq!(attempts, 2),
+            other => panic!("expected invalid output, got {:?}", other),
+        }
+    }
+}
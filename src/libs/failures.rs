@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::pipeline::Chunk;
+
+/// Step of a batch operation an item failed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureStage {
+    Enrichment,
+    Embedding,
+    Upsert,
+    Classification,
+}
+
+/// An item a batch operation could not process.
+///
+/// The chunk is kept in full so the item can be reprocessed from the report alone; its id
+/// and metadata (`document_id`, `source`) point back to where it came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedItem {
+    stage: FailureStage,
+    reason: String,
+    retries: u32,
+    chunk: Chunk,
+}
+
+/// Machine-readable list of the items a run failed on, written at the end of the run and
+/// fed back to `retry_failures` to reprocess just those items.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailureReport {
+    items: Vec<FailedItem>,
+}
+
+impl FailedItem {
+    pub fn new(stage: FailureStage, reason: String, retries: u32, chunk: Chunk) -> Self {
+        FailedItem {
+            stage,
+            reason,
+            retries,
+            chunk,
+        }
+    }
+
+    pub fn stage(&self) -> FailureStage {
+        self.stage
+    }
+
+    pub fn reason(&self) -> &String {
+        &self.reason
+    }
+
+    /// Number of times the item was retried before this failure.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    pub fn chunk(&self) -> &Chunk {
+        &self.chunk
+    }
+}
+
+impl FailureReport {
+    pub fn new(items: Vec<FailedItem>) -> Self {
+        FailureReport { items }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the report as pretty-printed JSON.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, data)
+    }
+
+    pub fn items(&self) -> &Vec<FailedItem> {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let chunk = Chunk::builder().id("doc-0".to_string()).text("text".to_string()).build();
+        let report = FailureReport::new(vec![FailedItem::new(
+            FailureStage::Embedding,
+            "timeout".to_string(),
+            1,
+            chunk,
+        )]);
+
+        let path = std::env::temp_dir().join(format!("failure-report-{}.json", std::process::id()));
+        report.write(&path).unwrap();
+        let read = FailureReport::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.len(), 1);
+        assert_eq!(read.items()[0].stage(), FailureStage::Embedding);
+        assert_eq!(read.items()[0].retries(), 1);
+        assert_eq!(read.items()[0].chunk().id(), "doc-0");
+        assert!(serde_json::to_string(&read).unwrap().contains("\"stage\":\"embedding\""));
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+
+use super::openai_api::OpenAIApiError;
+
+/// Ordered chat models a request falls back through, e.g. gpt-4o, then gpt-4o-mini, then
+/// gpt-3.5-turbo. See `openai_api::set_model_chain`.
+///
+/// A request for one of the models is sent to it first, then to each later model in turn
+/// while the previous one fails in a way another model may not (see `should_fall_back`).
+/// Requests for models outside the chain are sent as they are. The model that answered is
+/// counted for every request, see `served`.
+///
+/// # Example
+///
+/// ```rust
+/// let chain = Arc::new(ModelChain::new(vec![
+///     "gpt-4o".to_string(),
+///     "gpt-4o-mini".to_string(),
+///     "gpt-3.5-turbo".to_string(),
+/// ]));
+/// openai_api::set_model_chain(chain.clone());
+/// // ...
+/// println!("{:?}", chain.served());
+/// ```
+#[derive(Debug, Default)]
+pub struct ModelChain {
+    models: Vec<String>,
+    served: Mutex<HashMap<String, u64>>,
+}
+
+impl ModelChain {
+    pub fn new(models: Vec<String>) -> Self {
+        ModelChain {
+            models,
+            served: Mutex::default(),
+        }
+    }
+
+    /// Models to try, in order, for a request of `model`: itself and the models after it in
+    /// the chain, or only itself if it is not in the chain.
+    pub fn candidates(&self, model: &str) -> Vec<String> {
+        match self.models.iter().position(|m| m == model) {
+            Some(start) => self.models[start..].to_vec(),
+            None => vec![model.to_string()],
+        }
+    }
+
+    /// Counts a request answered by `model`.
+    pub fn record(&self, model: &str) {
+        *self.served.lock().unwrap().entry(model.to_string()).or_default() += 1;
+    }
+
+    /// Requests answered so far, by the model that answered them.
+    pub fn served(&self) -> HashMap<String, u64> {
+        self.served.lock().unwrap().clone()
+    }
+
+    pub fn models(&self) -> &[String] {
+        &self.models
+    }
+}
+
+/// Whether a failed chat completion may succeed with another model: the model is unknown
+/// or not available to the key (404, 403), rate limited (429), or overloaded (5xx), or the
+/// request timed out.
+pub fn should_fall_back(error: &(dyn Error + 'static)) -> bool {
+    match error.downcast_ref::<OpenAIApiError>() {
+        Some(OpenAIApiError::Status(status, _)) => matches!(status, 403 | 404 | 429 | 500..=599),
+        _ => error.to_string().contains("timed out"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let chain = ModelChain::new(vec!["gpt-4o".to_string(), "gpt-4o-mini".to_string(), "gpt-3.5-turbo".to_string()]);
+        assert_eq!(chain.candidates("gpt-4o-mini"), ["gpt-4o-mini", "gpt-3.5-turbo"]);
+        assert_eq!(chain.candidates("gpt-4"), ["gpt-4"]);
+
+        let not_found = OpenAIApiError::Status(404, "The model `gpt-4o` does not exist".to_string());
+        assert!(should_fall_back(&not_found));
+        assert!(!should_fall_back(&OpenAIApiError::Status(400, "Invalid 'messages'".to_string())));
+        assert!(should_fall_back(&*Box::<dyn Error>::from("Failed to send request: operation timed out")));
+    }
+}
@@ -0,0 +1,331 @@
+use std::convert::TryInto;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use typed_builder::TypedBuilder;
+
+use super::{openai_api, pinecone_api};
+
+/// Faults a `FaultProxy` injects into the requests it relays. Each rate is the chance of a
+/// request getting that fault, drawn from `seed`, so a plan injects the same faults into the
+/// same sequence of requests. A request gets at most one of them, plus latency.
+///
+/// # Fields
+///
+/// * `paths`: Optional. Path prefixes of the requests faults are injected into, e.g.
+///   "/vectors/upsert". Defaults to every request.
+/// * `max_latency`: Optional. Requests are delayed by up to this long. Defaults to none.
+/// * `drop_rate`: Optional. Requests delivered whose response is lost: the connection is
+///   closed without an answer.
+/// * `malformed_rate`: Optional. Successful responses whose JSON body is cut in half.
+/// * `partial_batch_rate`: Optional. Batches (requests with several `vectors`) of which only
+///   the first half is delivered, answered with a 500.
+/// * `seed`: Optional. Defaults to 1.
+///
+/// # Example
+///
+/// ```rust
+/// let plan = FaultPlan::builder()
+///     .paths(vec!["/vectors/upsert".to_string()])
+///     .drop_rate(0.2)
+///     .partial_batch_rate(0.2)
+///     .build();
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct FaultPlan {
+    #[builder(default)]
+    paths: Vec<String>,
+
+    #[builder(default)]
+    max_latency: Duration,
+
+    #[builder(default)]
+    drop_rate: f64,
+
+    #[builder(default)]
+    malformed_rate: f64,
+
+    #[builder(default)]
+    partial_batch_rate: f64,
+
+    #[builder(default = 1)]
+    seed: u64,
+}
+
+impl Default for FaultPlan {
+    fn default() -> Self {
+        FaultPlan::builder().build()
+    }
+}
+
+/// Requests a `FaultProxy` relayed since its plan was set, and the faults injected into them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    requests: u64,
+    delayed: u64,
+    dropped: u64,
+    malformed: u64,
+    partial: u64,
+}
+
+impl FaultStats {
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    pub fn delayed(&self) -> u64 {
+        self.delayed
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn malformed(&self) -> u64 {
+        self.malformed
+    }
+
+    pub fn partial(&self) -> u64 {
+        self.partial
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    Drop,
+    Malformed,
+    PartialBatch,
+}
+
+#[derive(Debug, Default)]
+struct ProxyState {
+    plan: FaultPlan,
+    stats: FaultStats,
+    sequence: u64,
+}
+
+impl ProxyState {
+    /// Latency and fault of the next request to `path`, whose body is `body`.
+    fn draw(&mut self, path: &str, body: &[u8]) -> (Duration, Option<Fault>) {
+        let plan = &self.plan;
+        if !plan.paths.is_empty() && !plan.paths.iter().any(|prefix| path.starts_with(prefix.as_str())) {
+            return (Duration::ZERO, None);
+        }
+        self.sequence += 1;
+        self.stats.requests += 1;
+
+        let latency = plan.max_latency.mul_f64(roll(plan.seed, self.sequence, "latency"));
+        let roll = roll(plan.seed, self.sequence, "fault");
+        let fault = if roll < plan.drop_rate {
+            Some(Fault::Drop)
+        } else if roll < plan.drop_rate + plan.malformed_rate {
+            Some(Fault::Malformed)
+        } else if roll < plan.drop_rate + plan.malformed_rate + plan.partial_batch_rate && batch_len(body) > 1 {
+            Some(Fault::PartialBatch)
+        } else {
+            None
+        };
+
+        if !latency.is_zero() {
+            self.stats.delayed += 1;
+        }
+        match fault {
+            Some(Fault::Drop) => self.stats.dropped += 1,
+            Some(Fault::PartialBatch) => self.stats.partial += 1,
+            // Counted once the response turns out successful.
+            Some(Fault::Malformed) | None => {}
+        }
+        (latency, fault)
+    }
+}
+
+/// Local HTTP proxy injecting faults (latency, dropped responses, malformed JSON, and
+/// partially delivered batches) between this crate's clients and a server, usually
+/// `FakeServices`, to test retries, the circuit breaker, and checkpoints under failure.
+///
+/// Relays one HTTP/1.1 request per connection, each with a Content-Length, as the clients
+/// send them and the fakes answer. Control plane requests are not routed through it.
+///
+/// # Example
+///
+/// ```rust
+/// let fakes = FakeServices::start().await;
+/// let proxy = FaultProxy::start(&fakes.uri()).await?;
+/// proxy.route_clients();
+/// proxy.set_plan(FaultPlan::builder().drop_rate(0.1).build());
+///
+/// let report = IngestionPipeline::builder().build().ingest(&documents).await?;
+/// println!("{} dropped, {} failed", proxy.stats().dropped(), report.failed().len());
+/// ```
+pub struct FaultProxy {
+    address: SocketAddr,
+    state: Arc<Mutex<ProxyState>>,
+}
+
+impl FaultProxy {
+    /// Starts relaying to `upstream`, e.g. "http://127.0.0.1:4000", without faults.
+    pub async fn start(upstream: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let proxy = FaultProxy {
+            address: listener.local_addr()?,
+            state: Arc::new(Mutex::new(ProxyState::default())),
+        };
+
+        let upstream = upstream.trim_start_matches("http://").trim_end_matches('/').to_string();
+        let state = proxy.state.clone();
+        tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                let upstream = upstream.clone();
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = relay(client, &upstream, &state).await {
+                        tracing::warn!("Fault proxy failed to relay a request: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(proxy)
+    }
+
+    /// Points the OpenAI and Pinecone data plane clients at the proxy.
+    pub fn route_clients(&self) {
+        openai_api::set_base_url(&format!("{}/v1", self.uri()));
+        pinecone_api::set_base_url(&self.uri());
+    }
+
+    /// Injects the faults of `plan` from now on, counting them from zero.
+    pub fn set_plan(&self, plan: FaultPlan) {
+        *self.state.lock().unwrap() = ProxyState {
+            plan,
+            ..ProxyState::default()
+        };
+    }
+
+    pub fn stats(&self) -> FaultStats {
+        self.state.lock().unwrap().stats
+    }
+
+    pub fn uri(&self) -> String {
+        format!("http://{}", self.address)
+    }
+}
+
+/// Relays the request on `client` to `upstream` and its response back, injecting a fault.
+async fn relay(mut client: TcpStream, upstream: &str, state: &Mutex<ProxyState>) -> io::Result<()> {
+    let (head, mut body) = read_message(&mut client).await?;
+    let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+    let (latency, fault) = state.lock().unwrap().draw(&path, &body);
+    tokio::time::sleep(latency).await;
+    if fault == Some(Fault::PartialBatch) {
+        body = first_half(&body);
+    }
+
+    let mut server = TcpStream::connect(upstream).await?;
+    write_message(&mut server, &head, &body).await?;
+    let (response_head, mut response_body) = read_message(&mut server).await?;
+
+    let response_head = match fault {
+        // Closing the connection unanswered.
+        Some(Fault::Drop) => return Ok(()),
+        Some(Fault::PartialBatch) => {
+            response_body = json!({ "error": "partially applied batch" }).to_string().into_bytes();
+            "HTTP/1.1 500 Internal Server Error\r\ncontent-type: application/json".to_string()
+        }
+        Some(Fault::Malformed) if status(&response_head).is_some_and(|status| (200..300).contains(&status)) => {
+            state.lock().unwrap().stats.malformed += 1;
+            response_body.truncate(response_body.len() / 2);
+            response_head
+        }
+        _ => response_head,
+    };
+    write_message(&mut client, &response_head, &response_body).await
+}
+
+/// Reads an HTTP message: its start line and headers, and its body of Content-Length bytes.
+async fn read_message(stream: &mut TcpStream) -> io::Result<(String, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 8192];
+    let end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the headers"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..end]).into_owned();
+    let length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0);
+    let mut body = buffer.split_off(end + 4);
+    while body.len() < length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before the body"));
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Ok((head, body))
+}
+
+/// Writes an HTTP message with `head`, its Content-Length and Connection headers replaced,
+/// so that every connection carries a single request.
+async fn write_message(stream: &mut TcpStream, head: &str, body: &[u8]) -> io::Result<()> {
+    let mut lines: Vec<String> = head
+        .lines()
+        .filter(|line| {
+            let name = line.split(':').next().unwrap_or_default().trim();
+            !name.eq_ignore_ascii_case("content-length") && !name.eq_ignore_ascii_case("connection")
+        })
+        .map(str::to_string)
+        .collect();
+    lines.push(format!("content-length: {}", body.len()));
+    lines.push("connection: close".to_string());
+
+    let mut message = format!("{}\r\n\r\n", lines.join("\r\n")).into_bytes();
+    message.extend_from_slice(body);
+    stream.write_all(&message).await?;
+    stream.flush().await
+}
+
+/// Status code of a response `head`.
+fn status(head: &str) -> Option<u16> {
+    head.split_whitespace().nth(1).and_then(|status| status.parse().ok())
+}
+
+/// Number of `vectors` in a JSON request body.
+fn batch_len(body: &[u8]) -> usize {
+    serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|body| body["vectors"].as_array().map(Vec::len))
+        .unwrap_or(0)
+}
+
+/// `body` with only the first half of its `vectors`.
+fn first_half(body: &[u8]) -> Vec<u8> {
+    let Ok(mut body) = serde_json::from_slice::<Value>(body) else {
+        return body.to_vec();
+    };
+    if let Some(vectors) = body["vectors"].as_array_mut() {
+        vectors.truncate(vectors.len() / 2);
+    }
+    body.to_string().into_bytes()
+}
+
+/// Uniform draw in [0, 1) for the `sequence`th request of a plan.
+fn roll(seed: u64, sequence: u64, purpose: &str) -> f64 {
+    let hash = Sha256::digest(format!("{}:{}:{}", seed, sequence, purpose).as_bytes());
+    (u64::from_le_bytes(hash[..8].try_into().unwrap()) >> 11) as f64 / (1u64 << 53) as f64
+}
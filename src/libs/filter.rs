@@ -0,0 +1,104 @@
+use serde_json::Value;
+
+use super::pinecone_data::{Filter, Metadata};
+
+/// Whether `metadata` passes the Pinecone metadata `filter`, for indexes that filter
+/// locally, such as `LocalIndex`.
+///
+/// Supports `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in`, `$nin`, `$exists`, `$and`
+/// and `$or`; a bare value means `$eq`. As in Pinecone, a list value equals anything one
+/// of its items equals, and numbers compare by value, so 3 equals 3.0.
+///
+/// # Example
+///
+/// ```rust
+/// let filter: Filter = serde_json::from_value(json!({
+///     "year": { "$gte": 2020 },
+///     "$or": [{ "source": "faq" }, { "tags": { "$in": ["billing"] } }],
+/// }))?;
+/// assert!(filter::matches(&filter, &metadata));
+/// ```
+pub fn matches(filter: &Filter, metadata: &Metadata) -> bool {
+    matches_all(filter, metadata)
+}
+
+fn matches_all<'a>(filter: impl IntoIterator<Item = (&'a String, &'a Value)>, metadata: &Metadata) -> bool {
+    filter.into_iter().all(|(key, condition)| match key.as_str() {
+        "$and" => clauses(condition).all(|clause| matches_all(clause, metadata)),
+        "$or" => clauses(condition).any(|clause| matches_all(clause, metadata)),
+        _ => field_matches(metadata.get(key), condition),
+    })
+}
+
+/// The filters of an `$and` or `$or`.
+fn clauses(condition: &Value) -> impl Iterator<Item = &serde_json::Map<String, Value>> {
+    condition.as_array().into_iter().flatten().filter_map(Value::as_object)
+}
+
+/// Whether `value`, None if the metadata lacks the key, meets `condition`.
+fn field_matches(value: Option<&Value>, condition: &Value) -> bool {
+    let operators = match condition.as_object() {
+        Some(operators) if operators.keys().all(|key| key.starts_with('$')) => operators,
+        _ => return value.is_some_and(|value| equals(value, condition)),
+    };
+
+    operators.iter().all(|(operator, operand)| match operator.as_str() {
+        "$eq" => value.is_some_and(|value| equals(value, operand)),
+        "$ne" => !value.is_some_and(|value| equals(value, operand)),
+        "$gt" => compare(value, operand).is_some_and(|ordering| ordering.is_gt()),
+        "$gte" => compare(value, operand).is_some_and(|ordering| ordering.is_ge()),
+        "$lt" => compare(value, operand).is_some_and(|ordering| ordering.is_lt()),
+        "$lte" => compare(value, operand).is_some_and(|ordering| ordering.is_le()),
+        "$in" => value.is_some_and(|value| listed(operand).any(|item| equals(value, item))),
+        "$nin" => !value.is_some_and(|value| listed(operand).any(|item| equals(value, item))),
+        "$exists" => operand.as_bool() == Some(value.is_some()),
+        _ => false,
+    })
+}
+
+fn listed(operand: &Value) -> impl Iterator<Item = &Value> {
+    operand.as_array().into_iter().flatten()
+}
+
+fn equals(value: &Value, operand: &Value) -> bool {
+    match (value, value.as_f64(), operand.as_f64()) {
+        (Value::Array(items), _, _) => items.iter().any(|item| equals(item, operand)),
+        (_, Some(value), Some(operand)) => value == operand,
+        _ => value == operand,
+    }
+}
+
+fn compare(value: Option<&Value>, operand: &Value) -> Option<std::cmp::Ordering> {
+    value?.as_f64()?.partial_cmp(&operand.as_f64()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(value: Value) -> Filter {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_matches() {
+        let metadata: Metadata = serde_json::from_value(json!({
+            "source": "faq",
+            "year": 2021,
+            "draft": false,
+            "tags": ["billing", "refunds"],
+        }))
+        .unwrap();
+
+        assert!(matches(&filter(json!({ "source": "faq", "year": 2021.0 })), &metadata));
+        assert!(matches(&filter(json!({ "tags": "refunds", "draft": { "$ne": true } })), &metadata));
+        assert!(matches(&filter(json!({ "year": { "$gte": 2020, "$lt": 2022 } })), &metadata));
+        assert!(!matches(&filter(json!({ "year": { "$gt": 2021 } })), &metadata));
+        assert!(matches(&filter(json!({ "source": { "$in": ["faq", "blog"] } })), &metadata));
+        assert!(!matches(&filter(json!({ "tags": { "$nin": ["billing"] } })), &metadata));
+        assert!(matches(&filter(json!({ "author": { "$exists": false } })), &metadata));
+        assert!(matches(&filter(json!({ "$or": [{ "source": "blog" }, { "year": 2021 }] })), &metadata));
+        assert!(!matches(&filter(json!({ "$and": [{ "source": "faq" }, { "year": "2021" }] })), &metadata));
+    }
+}
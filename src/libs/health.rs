@@ -0,0 +1,237 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::database::{upsert, Database};
+use super::openai_api::{get_tokens, list_models};
+use super::pinecone_api::describe_index_stats;
+use super::search::SemanticSearch;
+
+/// Time each check may take before its backend is reported down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Key the database check writes, reads back, and deletes.
+const PROBE_KEY: &str = "health:probe";
+
+/// Queries in flight at once while `warmup` primes a query cache.
+const PRIME_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+/// Result of checking one backend.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendHealth {
+    name: String,
+    status: HealthStatus,
+    latency_ms: u64,
+
+    /// What was found when up, the error when down.
+    detail: String,
+}
+
+/// Readiness of every configured backend; `ready` only if all of them are up.
+///
+/// # Example
+///
+/// ```rust
+/// let report = health::check_all(Some(&db)).await;
+/// if !report.ready() {
+///     eprintln!("{}", serde_json::to_string_pretty(&report)?);
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    ready: bool,
+    backends: Vec<BackendHealth>,
+}
+
+/// Checks OpenAI, Pinecone, and `db` if given, concurrently.
+pub async fn check_all(db: Option<&dyn Database>) -> HealthReport {
+    let (openai, pinecone, database) = tokio::join!(check_openai(), check_pinecone(), async {
+        match db {
+            Some(db) => Some(check_database(db).await),
+            None => None,
+        }
+    });
+
+    HealthReport::new(vec![Some(openai), Some(pinecone), database].into_iter().flatten().collect())
+}
+
+/// Pays the cold-start costs of a service before its first request: builds the tokenizer,
+/// resolves the Pinecone index host and opens a connection to it, opens `db`'s connection,
+/// and runs `queries` through `search` to prime its `QueryCache`, if it has one. The search
+/// must be configured like the ones serving requests, or their cache keys differ.
+///
+/// The report lists every step like a health check, so a failed step can be logged or
+/// refuse the deploy.
+///
+/// # Example
+///
+/// ```rust
+/// let report = health::warmup(Some(db.as_ref()), Some(&search), &popular_queries).await;
+/// for step in report.backends().iter().filter(|step| step.status() == HealthStatus::Down) {
+///     println!("Warm-up of {} failed: {}", step.name(), step.detail());
+/// }
+/// ```
+pub async fn warmup(db: Option<&dyn Database>, search: Option<&SemanticSearch>, queries: &[String]) -> HealthReport {
+    let (tokenizer, pinecone, database) = tokio::join!(
+        check("tokenizer", async {
+            let tokens = tokio::task::spawn_blocking(|| get_tokens("warm up"))
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|e| e.to_string())?;
+            Ok(format!("encoded {} tokens", tokens.len()))
+        }),
+        check_pinecone(),
+        async {
+            match db {
+                Some(db) => Some(check_database(db).await),
+                None => None,
+            }
+        }
+    );
+
+    // Searches need the index host, so the cache is primed last.
+    let cache = match search {
+        Some(search) if !queries.is_empty() => Some(
+            check("query cache", async {
+                let results = search.query_many(queries, PRIME_CONCURRENCY).await;
+                match results.iter().find_map(|result| result.error().clone()) {
+                    Some(e) => Err(e),
+                    None => Ok(format!("primed {} queries", results.len())),
+                }
+            })
+            .await,
+        ),
+        _ => None,
+    };
+    HealthReport::new(vec![Some(tokenizer), Some(pinecone), database, cache].into_iter().flatten().collect())
+}
+
+/// Verifies the OpenAI API key by listing the models.
+pub async fn check_openai() -> BackendHealth {
+    check("openai", async {
+        let models = list_models().await.map_err(|e| e.to_string())?;
+        Ok(format!("{} models available", models.len()))
+    })
+    .await
+}
+
+/// Verifies the Pinecone index is reachable by describing its stats.
+pub async fn check_pinecone() -> BackendHealth {
+    check("pinecone", async {
+        let stats = describe_index_stats().await.map_err(|e| e.to_string())?;
+        Ok(format!("{} vectors", stats.total_vector_count()))
+    })
+    .await
+}
+
+/// Verifies `db` by writing, reading back, and deleting a probe item.
+pub async fn check_database(db: &dyn Database) -> BackendHealth {
+    check("database", async {
+        upsert(db, PROBE_KEY, "ok").await.map_err(|e| e.to_string())?;
+        let data = db.read(PROBE_KEY).await.map_err(|e| e.to_string())?;
+        db.delete(PROBE_KEY).await.map_err(|e| e.to_string())?;
+        match data.as_str() {
+            "ok" => Ok("read and write succeeded".to_string()),
+            _ => Err("probe read back different data".to_string()),
+        }
+    })
+    .await
+}
+
+async fn check<F>(name: &str, probe: F) -> BackendHealth
+where
+    F: Future<Output = Result<String, String>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())),
+    };
+
+    let (status, detail) = match result {
+        Ok(detail) => (HealthStatus::Up, detail),
+        Err(e) => (HealthStatus::Down, e),
+    };
+    BackendHealth {
+        name: name.to_string(),
+        status,
+        latency_ms: started.elapsed().as_millis() as u64,
+        detail,
+    }
+}
+
+impl HealthReport {
+    fn new(backends: Vec<BackendHealth>) -> Self {
+        HealthReport {
+            ready: backends.iter().all(|backend| backend.status == HealthStatus::Up),
+            backends,
+        }
+    }
+
+    pub fn ready(&self) -> bool {
+        self.ready
+    }
+
+    pub fn backends(&self) -> &Vec<BackendHealth> {
+        &self.backends
+    }
+}
+
+impl BackendHealth {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        self.status
+    }
+
+    pub fn latency_ms(&self) -> u64 {
+        self.latency_ms
+    }
+
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::libs::cache::QueryCache;
+    use crate::libs::sql_lite::SQLiteDB;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::FakeServices;
+
+    #[tokio::test]
+    async fn test_check_database() {
+        let db = SQLiteDB::new(":memory:").unwrap();
+        let health = check_database(&db).await;
+
+        assert_eq!(health.status(), HealthStatus::Up);
+        assert!(db.read(PROBE_KEY).await.is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_warmup_primes_query_cache() {
+        let _fakes = FakeServices::seeded().await;
+        let primed = QueryCache::memory(Duration::from_secs(60));
+        let search = SemanticSearch::builder().top_k(1).build().with_cache(primed.clone());
+        let report = warmup(None, Some(&search), &["refund policy".to_string()]).await;
+        let steps: Vec<&str> = report.backends().iter().map(|step| step.name()).collect();
+        assert_eq!(steps, ["tokenizer", "pinecone", "query cache"]);
+        assert!(report.ready());
+        search.search("Refund policy").await.unwrap();
+        assert_eq!(primed.stats().await.hits(), 1);
+    }
+}
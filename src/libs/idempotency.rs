@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use super::database::{upsert, Database};
+use super::pipeline::Chunk;
+
+/// Prefix of the keys recorded upserts are stored under in the `Database`.
+const IDEMPOTENCY_KEY_PREFIX: &str = "upsert-key:";
+
+/// Database-backed record of the chunks already upserted, so retrying a run does not
+/// re-embed, re-upsert, or re-count them.
+///
+/// Each chunk gets an idempotency key generated on the client from the namespace, the
+/// embedding model, and the chunk's id, text, and metadata. The key is recorded once
+/// Pinecone acknowledges the upsert; a later attempt with the same key inside `window` is
+/// reported as a duplicate instead of being upserted again. Changed chunks get new keys.
+///
+/// Keys outlive the vectors they describe: after deleting vectors, call `forget` for their
+/// chunks, or re-ingesting them inside the window skips them.
+///
+/// # Example
+///
+/// ```rust
+/// let pipeline = IngestionPipeline::builder()
+///     .idempotency(IdempotencyStore::new(Arc::new(SQLiteDB::new("ingest.db")?)))
+///     .build();
+///
+/// let first = pipeline.ingest(&documents).await?;
+/// // The retry only upserts what the first attempt did not.
+/// let retry = pipeline.ingest(&documents).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct IdempotencyStore {
+    db: Arc<dyn Database>,
+    window: Duration,
+}
+
+impl IdempotencyStore {
+    /// A store whose keys are honored for 24 hours.
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        IdempotencyStore {
+            db,
+            window: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Sets how long a recorded key suppresses repeated upserts.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Whether `key` was recorded within the window.
+    pub async fn contains(&self, key: &str) -> bool {
+        let recorded = match self.db.read(&store_key(key)).await {
+            Ok(data) => data.parse::<u64>().ok(),
+            Err(_) => None,
+        };
+        recorded.is_some_and(|recorded| unix_now().saturating_sub(recorded) < self.window.as_secs())
+    }
+
+    /// Records `key` as upserted now.
+    pub async fn record(&self, key: &str) -> Result<(), Box<dyn Error>> {
+        upsert(self.db.as_ref(), &store_key(key), &unix_now().to_string()).await
+    }
+
+    /// Removes the key of `chunk`, so it is upserted again on the next run.
+    pub async fn forget(&self, namespace: &Option<String>, model: &str, chunk: &Chunk) -> Result<(), Box<dyn Error>> {
+        self.db.delete(&store_key(&idempotency_key(namespace, model, chunk))).await
+    }
+}
+
+/// Idempotency key of upserting `chunk` into `namespace` with embeddings from `model`.
+pub fn idempotency_key(namespace: &Option<String>, model: &str, chunk: &Chunk) -> String {
+    let metadata: BTreeMap<&String, &serde_json::Value> = chunk.metadata().iter().collect();
+
+    let mut hasher = Sha256::new();
+    for part in [
+        namespace.as_deref().unwrap_or(""),
+        model,
+        chunk.id(),
+        chunk.text(),
+        &serde_json::to_string(&metadata).unwrap_or_default(),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn store_key(key: &str) -> String {
+    format!("{}{}", IDEMPOTENCY_KEY_PREFIX, key)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[tokio::test]
+    async fn test_idempotency_store() {
+        let chunk = Chunk::builder().id("doc-0".to_string()).text("text".to_string()).build();
+        let changed = Chunk::builder().id("doc-0".to_string()).text("new text".to_string()).build();
+        let key = idempotency_key(&None, "model", &chunk);
+
+        assert_eq!(key, idempotency_key(&None, "model", &chunk));
+        assert_ne!(key, idempotency_key(&Some("docs".to_string()), "model", &chunk));
+        assert_ne!(key, idempotency_key(&None, "model", &changed));
+
+        let store = IdempotencyStore::new(Arc::new(SQLiteDB::new(":memory:").unwrap()));
+        assert!(!store.contains(&key).await);
+        store.record(&key).await.unwrap();
+        assert!(store.contains(&key).await);
+
+        let expired = store.clone().with_window(Duration::ZERO);
+        assert!(!expired.contains(&key).await);
+
+        store.forget(&None, "model", &chunk).await.unwrap();
+        assert!(!store.contains(&key).await);
+    }
+}
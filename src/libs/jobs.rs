@@ -0,0 +1,505 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use typed_builder::TypedBuilder;
+
+use super::database::{upsert, Database};
+use super::pinecone_api::PineconeApiError;
+use super::pinecone_data::{IdList, PineconeRequest, Vector};
+use super::pipeline::{Document, IngestionPipeline, PipelineError};
+
+/// Prefix of the keys jobs are stored under.
+const JOB_KEY_PREFIX: &str = "job:";
+
+/// Vectors moved per fetch/upsert round of a namespace migration.
+const MIGRATION_BATCH_SIZE: usize = 100;
+
+static JOB_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    #[error("StateError: {0}")]
+    StateError(String),
+
+    #[error("NotFound: {0}")]
+    NotFound(String),
+
+    #[error(transparent)]
+    PipelineError(#[from] PipelineError),
+
+    #[error(transparent)]
+    PineconeError(#[from] PineconeApiError),
+}
+
+/// Work a job performs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobKind {
+    /// Ingest every supported file under a directory.
+    IngestDirectory {
+        root: PathBuf,
+        namespace: Option<String>,
+    },
+    /// Ingest documents submitted with the job.
+    IngestDocuments {
+        documents: Vec<Document>,
+        namespace: Option<String>,
+    },
+    /// Delete vectors by id.
+    Delete {
+        ids: Vec<String>,
+        namespace: Option<String>,
+    },
+    /// Move vectors from one namespace to another.
+    MigrateNamespace {
+        ids: Vec<String>,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    /// Remove the least recently used stored embeddings until the rest fit in
+    /// `max_bytes`, see `Database::evict_embeddings`.
+    EvictEmbeddings {
+        max_bytes: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Failed,
+    Done,
+}
+
+/// A unit of work and its progress. Timestamps are unix seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    id: String,
+    kind: JobKind,
+    status: JobStatus,
+    created_at: u64,
+    updated_at: u64,
+
+    #[serde(default)]
+    error: Option<String>,
+
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+}
+
+/// Jobs persisted in a `Database`, so a service can accept work, restart, and report
+/// progress.
+///
+/// Jobs are found by listing the keys of the database, which must be able to list its
+/// ids, rather than in an index every process would rewrite. Job ids end with random bits,
+/// so processes sharing the database do not reuse one another's ids.
+///
+/// # Example
+///
+/// ```rust
+/// let queue = JobQueue::new(Arc::new(SQLiteDB::new("jobs.db")?));
+/// let job = queue
+///     .enqueue(JobKind::IngestDirectory { root: "docs".into(), namespace: None })
+///     .await?;
+///
+/// tokio::spawn(JobWorker::builder().queue(queue.clone()).build().run());
+/// println!("{:?}", queue.get(job.id()).await?.status());
+/// ```
+#[derive(Debug, Clone)]
+pub struct JobQueue {
+    db: Arc<dyn Database>,
+    claiming: Arc<Mutex<()>>,
+}
+
+impl JobQueue {
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        JobQueue {
+            db,
+            claiming: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn enqueue(&self, kind: JobKind) -> Result<Job, JobError> {
+        let now = unix_now();
+        let job = Job {
+            id: job_id(now),
+            kind,
+            status: JobStatus::Queued,
+            created_at: now,
+            updated_at: now,
+            error: None,
+            result: None,
+        };
+
+        self.save(&job).await?;
+        Ok(job)
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Job, JobError> {
+        let data = self
+            .db
+            .read(&job_key(id))
+            .await
+            .map_err(|_| JobError::NotFound(id.to_string()))?;
+        serde_json::from_str(&data).map_err(|e| JobError::StateError(e.to_string()))
+    }
+
+    /// Every job, oldest first; jobs of other processes are ordered to the second.
+    pub async fn list(&self) -> Result<Vec<Job>, JobError> {
+        let mut jobs = Vec::new();
+        for id in self.ids().await? {
+            jobs.push(self.get(&id).await?);
+        }
+        Ok(jobs)
+    }
+
+    pub async fn list_by_status(&self, status: JobStatus) -> Result<Vec<Job>, JobError> {
+        let jobs = self.list().await?;
+        Ok(jobs.into_iter().filter(|job| job.status == status).collect())
+    }
+
+    /// Marks the oldest queued job as running and returns it.
+    pub async fn claim(&self) -> Result<Option<Job>, JobError> {
+        let _claiming = self.claiming.lock().await;
+        for id in self.ids().await? {
+            let mut job = self.get(&id).await?;
+            if job.status == JobStatus::Queued {
+                job.status = JobStatus::Running;
+                job.updated_at = unix_now();
+                self.save(&job).await?;
+                return Ok(Some(job));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Puts jobs left running by a crashed worker back in the queue.
+    pub async fn recover(&self) -> Result<usize, JobError> {
+        let running = self.list_by_status(JobStatus::Running).await?;
+        for mut job in running.iter().cloned() {
+            job.status = JobStatus::Queued;
+            job.updated_at = unix_now();
+            self.save(&job).await?;
+        }
+        Ok(running.len())
+    }
+
+    /// Removes finished (done or failed) jobs. Returns how many were removed.
+    pub async fn prune(&self) -> Result<usize, JobError> {
+        let mut removed = 0;
+        for id in self.ids().await? {
+            let job = self.get(&id).await?;
+            if matches!(job.status, JobStatus::Done | JobStatus::Failed) {
+                self.db
+                    .delete(&job_key(&id))
+                    .await
+                    .map_err(|e| JobError::StateError(e.to_string()))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn finish(&self, mut job: Job, outcome: Result<serde_json::Value, JobError>) -> Result<Job, JobError> {
+        match outcome {
+            Ok(result) => {
+                job.status = JobStatus::Done;
+                job.result = Some(result);
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e.to_string());
+            }
+        }
+        job.updated_at = unix_now();
+        self.save(&job).await?;
+        Ok(job)
+    }
+
+    async fn ids(&self) -> Result<Vec<String>, JobError> {
+        let keys = self
+            .db
+            .ids(JOB_KEY_PREFIX)
+            .await
+            .map_err(|e| JobError::StateError(e.to_string()))?;
+        Ok(keys.into_iter().filter_map(|key| Some(key.strip_prefix(JOB_KEY_PREFIX)?.to_string())).collect())
+    }
+
+    async fn save(&self, job: &Job) -> Result<(), JobError> {
+        self.write(&job_key(&job.id), job).await
+    }
+
+    async fn write<T: Serialize>(&self, key: &str, value: &T) -> Result<(), JobError> {
+        let data = serde_json::to_string(value).map_err(|e| JobError::StateError(e.to_string()))?;
+        upsert(self.db.as_ref(), key, &data)
+            .await
+            .map_err(|e| JobError::StateError(e.to_string()))
+    }
+}
+
+/// Executes queued jobs one at a time.
+///
+/// # Fields
+///
+/// * `queue`: Required. Queue jobs are claimed from.
+/// * `poll_interval`: Optional. Wait between checks of an empty queue. Defaults to 5 seconds.
+/// * `embeddings`: Optional. Database `EvictEmbeddings` jobs evict from. Defaults to the
+///   queue's.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct JobWorker {
+    queue: JobQueue,
+
+    #[builder(default = Duration::from_secs(5))]
+    poll_interval: Duration,
+
+    #[builder(setter(strip_option), default)]
+    embeddings: Option<Arc<dyn Database>>,
+}
+
+impl JobWorker {
+    /// Runs jobs forever. Jobs interrupted by an earlier crash are re-queued first.
+    pub async fn run(self) {
+        if let Err(e) = self.queue.recover().await {
+            tracing::warn!("Failed to recover jobs: {}", e);
+        }
+
+        loop {
+            match self.run_once().await {
+                Ok(Some(_)) => {}
+                Ok(None) => tokio::time::sleep(self.poll_interval).await,
+                Err(e) => {
+                    tracing::warn!("Job worker error: {}", e);
+                    tokio::time::sleep(self.poll_interval).await;
+                }
+            }
+        }
+    }
+
+    /// Runs the oldest queued job, if any, and returns it in its final state.
+    pub async fn run_once(&self) -> Result<Option<Job>, JobError> {
+        let Some(job) = self.queue.claim().await? else {
+            return Ok(None);
+        };
+
+        let embeddings = self.embeddings.as_ref().unwrap_or(&self.queue.db);
+        let outcome = execute(&job.kind, embeddings.as_ref()).await;
+        self.queue.finish(job, outcome).await.map(Some)
+    }
+}
+
+async fn execute(kind: &JobKind, embeddings: &dyn Database) -> Result<serde_json::Value, JobError> {
+    match kind {
+        JobKind::IngestDirectory { root, namespace } => {
+            let report = pipeline(namespace).ingest_directory(root).await?;
+            to_value(&report)
+        }
+        JobKind::IngestDocuments { documents, namespace } => {
+            let report = pipeline(namespace).ingest(documents).await?;
+            to_value(&report)
+        }
+        JobKind::Delete { ids, namespace } => {
+            delete(ids.clone(), namespace.clone()).await?;
+            Ok(serde_json::json!({ "deleted": ids.len() }))
+        }
+        JobKind::MigrateNamespace { ids, from, to } => {
+            let mut migrated = 0;
+            for batch in ids.chunks(MIGRATION_BATCH_SIZE) {
+                migrated += migrate(batch, from, to).await?;
+            }
+            Ok(serde_json::json!({ "migrated": migrated }))
+        }
+        JobKind::EvictEmbeddings { max_bytes } => {
+            let evicted = embeddings
+                .evict_embeddings(*max_bytes)
+                .await
+                .map_err(|e| JobError::StateError(e.to_string()))?;
+            Ok(serde_json::json!({ "evicted": evicted }))
+        }
+    }
+}
+
+fn pipeline(namespace: &Option<String>) -> IngestionPipeline {
+    match namespace {
+        Some(namespace) => IngestionPipeline::builder().namespace(namespace.clone()).build(),
+        None => IngestionPipeline::builder().build(),
+    }
+}
+
+async fn delete(ids: Vec<String>, namespace: Option<String>) -> Result<(), JobError> {
+    let request = match namespace {
+        Some(namespace) => PineconeRequest::builder()
+            .ids(IdList::TextIds(ids))
+            .namespace(namespace)
+            .build(),
+        None => PineconeRequest::builder().ids(IdList::TextIds(ids)).build(),
+    };
+    request.delete().await?;
+    Ok(())
+}
+
+/// Copies `ids` from namespace `from` to `to`, then deletes the originals.
+async fn migrate(ids: &[String], from: &Option<String>, to: &Option<String>) -> Result<usize, JobError> {
+    let request = match from {
+        Some(namespace) => PineconeRequest::builder()
+            .ids(IdList::TextIds(ids.to_vec()))
+            .namespace(namespace.clone())
+            .build(),
+        None => PineconeRequest::builder().ids(IdList::TextIds(ids.to_vec())).build(),
+    };
+    let fetched: HashMap<String, _> = request.fetch().await?.vectors().clone().unwrap_or_default();
+    if fetched.is_empty() {
+        return Ok(0);
+    }
+
+    let vectors: Vec<Vector> = fetched.into_values().map(Vector::from).collect();
+    let moved: Vec<String> = vectors.iter().filter_map(|vector| vector.id().clone()).collect();
+
+    let request = match to {
+        Some(namespace) => PineconeRequest::builder()
+            .vectors(vectors)
+            .namespace(namespace.clone())
+            .build(),
+        None => PineconeRequest::builder().vectors(vectors).build(),
+    };
+    request.upsert().await?;
+
+    delete(moved.clone(), from.clone()).await?;
+    Ok(moved.len())
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<serde_json::Value, JobError> {
+    serde_json::to_value(value).map_err(|e| JobError::StateError(e.to_string()))
+}
+
+/// Id of a job enqueued at `now`: the time and a counter of this process, zero padded so
+/// ids sort by them, then 64 random bits seeded differently in every process.
+fn job_id(now: u64) -> String {
+    let count = JOB_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:08x}-{:08x}-{:016x}", now, count, RandomState::new().hash_one(count))
+}
+
+fn job_key(id: &str) -> String {
+    format!("{}{}", JOB_KEY_PREFIX, id)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl Job {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn kind(&self) -> &JobKind {
+        &self.kind
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.status
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> u64 {
+        self.updated_at
+    }
+
+    pub fn error(&self) -> &Option<String> {
+        &self.error
+    }
+
+    /// Job-specific result, e.g. the `IngestionReport` of an ingestion job.
+    pub fn result(&self) -> &Option<serde_json::Value> {
+        &self.result
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[tokio::test]
+    async fn test_queue_lifecycle() {
+        let queue = JobQueue::new(Arc::new(SQLiteDB::new(":memory:").unwrap()));
+        let first = queue
+            .enqueue(JobKind::Delete {
+                ids: vec!["a".to_string()],
+                namespace: None,
+            })
+            .await
+            .unwrap();
+        let second = queue
+            .enqueue(JobKind::IngestDocuments {
+                documents: Vec::new(),
+                namespace: None,
+            })
+            .await
+            .unwrap();
+
+        let claimed = queue.claim().await.unwrap().unwrap();
+        assert_eq!(claimed.id(), first.id());
+        assert_eq!(queue.get(first.id()).await.unwrap().status(), JobStatus::Running);
+
+        assert_eq!(queue.recover().await.unwrap(), 1);
+        let claimed = queue.claim().await.unwrap().unwrap();
+        assert_eq!(claimed.id(), first.id());
+
+        let failed = queue
+            .finish(claimed, Err(JobError::StateError("boom".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(failed.status(), JobStatus::Failed);
+        assert_eq!(failed.error().as_deref(), Some("StateError: boom"));
+
+        let queued = queue.list_by_status(JobStatus::Queued).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].id(), second.id());
+
+        assert_eq!(queue.prune().await.unwrap(), 1);
+        assert_eq!(queue.list().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_queues_sharing_a_database() {
+        let db: Arc<dyn Database> = Arc::new(SQLiteDB::new(":memory:").unwrap());
+        let (a, b) = (JobQueue::new(db.clone()), JobQueue::new(db));
+        let kind = || JobKind::EvictEmbeddings { max_bytes: 0 };
+        let enqueues = (0..8).map(|n| if n % 2 == 0 { a.enqueue(kind()) } else { b.enqueue(kind()) });
+        let jobs = futures::future::try_join_all(enqueues).await.unwrap();
+        let mut listed: Vec<String> = a.list().await.unwrap().iter().map(|job| job.id().clone()).collect();
+        let mut enqueued: Vec<String> = jobs.iter().map(|job| job.id().clone()).collect();
+        listed.sort();
+        enqueued.sort();
+        enqueued.dedup();
+        assert_eq!(enqueued.len(), 8);
+        assert_eq!(listed, enqueued);
+    }
+
+    #[tokio::test]
+    async fn test_evict_embeddings_job() {
+        let db = Arc::new(SQLiteDB::new(":memory:").unwrap());
+        db.insert_embedding_data("a", "text", &[1.0]).await.unwrap();
+        let queue = JobQueue::new(db.clone());
+        queue.enqueue(JobKind::EvictEmbeddings { max_bytes: 0 }).await.unwrap();
+
+        let job = JobWorker::builder().queue(queue).build().run_once().await.unwrap().unwrap();
+        assert_eq!(job.status(), JobStatus::Done);
+        assert_eq!(job.result().as_ref().unwrap()["evicted"], 1);
+        assert!(db.embeddings().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use csv::{ReaderBuilder, StringRecord};
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use crate::libs::pinecone_data::Metadata;
+use crate::libs::pipeline::Document;
+
+#[derive(Debug, Error)]
+pub enum CsvLoaderError {
+    #[error("CsvError: {0}")]
+    CsvError(#[from] csv::Error),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error("MissingColumn: {0}")]
+    MissingColumn(String),
+}
+
+/// Loads CSV rows as documents.
+///
+/// The text of each row is built from `text_columns` (as `column: value` lines when there
+/// is more than one), and `metadata_columns` are copied into the document metadata. Rows
+/// are grouped `rows_per_document` at a time; grouped documents take their metadata from
+/// the first row and record the covered range in `row_start` / `row_end`.
+///
+/// Whether the first row is a header is inferred unless `has_headers` is set. Without
+/// headers, columns are named `column_0`, `column_1`, ...
+///
+/// # Fields
+///
+/// * `text_columns`: Required. Columns that make up the embedded text.
+/// * `metadata_columns`: Optional. Columns stored as metadata.
+/// * `id_column`: Optional. Column used as document id. Defaults to `{source}:{row}`.
+/// * `rows_per_document`: Optional. Number of rows per document. Defaults to 1.
+/// * `has_headers`: Optional. Overrides header inference.
+/// * `delimiter`: Optional. Field delimiter. Defaults to `,`.
+///
+/// # Example
+///
+/// ```rust
+/// let documents = CsvLoader::builder()
+///     .text_columns(vec!["question".to_string(), "answer".to_string()])
+///     .metadata_columns(vec!["category".to_string()])
+///     .id_column("id".to_string())
+///     .build()
+///     .load("resources/faq.csv")?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct CsvLoader {
+    text_columns: Vec<String>,
+
+    #[builder(default)]
+    metadata_columns: Vec<String>,
+
+    #[builder(setter(strip_option), default)]
+    id_column: Option<String>,
+
+    #[builder(default = 1)]
+    rows_per_document: usize,
+
+    #[builder(setter(strip_option), default)]
+    has_headers: Option<bool>,
+
+    #[builder(default = b',')]
+    delimiter: u8,
+}
+
+impl CsvLoader {
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Document>, CsvLoaderError> {
+        let path = path.as_ref();
+        let source = path.to_string_lossy().to_string();
+        self.load_reader(File::open(path)?, &source)
+    }
+
+    /// Loads CSV data from any reader. `source` is stored as metadata and prefixes default ids.
+    pub fn load_reader<R: Read>(&self, reader: R, source: &str) -> Result<Vec<Document>, CsvLoaderError> {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(self.delimiter)
+            .flexible(true)
+            .from_reader(reader);
+
+        let mut records = reader.records();
+        let Some(first) = records.next().transpose()? else {
+            return Ok(Vec::new());
+        };
+
+        let mut rows = Vec::new();
+        let headers: Vec<String> = if self.has_headers.unwrap_or_else(|| looks_like_header(&first)) {
+            first.iter().map(|h| h.trim().to_string()).collect()
+        } else {
+            let names = (0..first.len()).map(|i| format!("column_{}", i)).collect();
+            rows.push(first);
+            names
+        };
+        for record in records {
+            rows.push(record?);
+        }
+
+        let index = |column: &String| {
+            headers
+                .iter()
+                .position(|h| h == column)
+                .ok_or_else(|| CsvLoaderError::MissingColumn(column.clone()))
+        };
+        let text_columns = self.text_columns.iter().map(index).collect::<Result<Vec<_>, _>>()?;
+        let metadata_columns = self.metadata_columns.iter().map(index).collect::<Result<Vec<_>, _>>()?;
+        let id_column = self.id_column.as_ref().map(index).transpose()?;
+
+        let mut documents = Vec::new();
+        for (group_index, group) in rows.chunks(self.rows_per_document.max(1)).enumerate() {
+            let row_start = group_index * self.rows_per_document.max(1);
+            let row_end = row_start + group.len() - 1;
+
+            let text = group
+                .iter()
+                .map(|row| self.row_text(row, &headers, &text_columns))
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            if text.is_empty() {
+                continue;
+            }
+
+            let mut metadata = Metadata::new();
+            metadata.insert("source".to_string(), source.into());
+            for &column in &metadata_columns {
+                if let Some(value) = group[0].get(column) {
+                    metadata.insert(headers[column].clone(), value.into());
+                }
+            }
+
+            let id = match id_column.and_then(|column| group[0].get(column)) {
+                Some(id) if group.len() == 1 => id.to_string(),
+                _ => format!("{}:{}", source, row_start),
+            };
+            if group.len() > 1 {
+                metadata.insert("row_start".to_string(), row_start.into());
+                metadata.insert("row_end".to_string(), row_end.into());
+            } else {
+                metadata.insert("row".to_string(), row_start.into());
+            }
+
+            documents.push(Document::builder().id(id).text(text).metadata(metadata).build());
+        }
+
+        Ok(documents)
+    }
+
+    fn row_text(&self, row: &StringRecord, headers: &[String], columns: &[usize]) -> String {
+        let values = columns
+            .iter()
+            .filter_map(|&column| row.get(column).map(|value| (column, value.trim())))
+            .filter(|(_, value)| !value.is_empty());
+
+        if columns.len() == 1 {
+            values.map(|(_, value)| value).collect()
+        } else {
+            values
+                .map(|(column, value)| format!("{}: {}", headers[column], value))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    }
+}
+
+/// A first row of distinct, non-empty, non-numeric cells is taken to be a header.
+fn looks_like_header(first: &StringRecord) -> bool {
+    let cells: Vec<&str> = first.iter().map(str::trim).collect();
+    let mut unique = cells.clone();
+    unique.sort_unstable();
+    unique.dedup();
+
+    !cells.is_empty()
+        && unique.len() == cells.len()
+        && cells.iter().all(|cell| !cell.is_empty() && cell.parse::<f64>().is_err())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAQ: &str = "id,question,answer,category
+q1,What is Pinecone?,A vector database.,product
+q2,What is an embedding?,A vector of floats.,ml
+q3,Is it fast?,Yes.,product
+";
+
+    #[test]
+    fn test_load_rows() {
+        let documents = CsvLoader::builder()
+            .text_columns(vec!["question".to_string(), "answer".to_string()])
+            .metadata_columns(vec!["category".to_string()])
+            .id_column("id".to_string())
+            .build()
+            .load_reader(FAQ.as_bytes(), "faq.csv")
+            .unwrap();
+
+        assert_eq!(documents.len(), 3);
+        assert_eq!(documents[0].id(), "q1");
+        assert_eq!(documents[0].text(), "question: What is Pinecone?\nanswer: A vector database.");
+        assert_eq!(documents[1].metadata().get("category").unwrap(), "ml");
+    }
+
+    #[test]
+    fn test_load_batched_rows() {
+        let documents = CsvLoader::builder()
+            .text_columns(vec!["answer".to_string()])
+            .rows_per_document(2)
+            .build()
+            .load_reader(FAQ.as_bytes(), "faq.csv")
+            .unwrap();
+
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].id(), "faq.csv:0");
+        assert_eq!(documents[0].text(), "A vector database.\n\nA vector of floats.");
+        assert_eq!(documents[1].metadata().get("row").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_headerless() {
+        let documents = CsvLoader::builder()
+            .text_columns(vec!["column_1".to_string()])
+            .build()
+            .load_reader("1,first\n2,second\n".as_bytes(), "data")
+            .unwrap();
+
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[1].text(), "second");
+
+        let missing = CsvLoader::builder()
+            .text_columns(vec!["nope".to_string()])
+            .build()
+            .load_reader(FAQ.as_bytes(), "faq.csv");
+        assert!(matches!(missing, Err(CsvLoaderError::MissingColumn(_))));
+    }
+}
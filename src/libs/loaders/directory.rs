@@ -0,0 +1,369 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::libs::pinecone_data::Metadata;
+use crate::libs::pipeline::Document;
+
+/// Number of leading bytes inspected when sniffing a file's content type.
+pub const SNIFF_LEN: usize = 8192;
+
+/// What a file was detected to contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentKind {
+    Text,
+    Pdf,
+    Image,
+    Archive,
+    Executable,
+    Binary,
+}
+
+/// Why a file was left out of a directory load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reason", content = "detail")]
+pub enum SkipReason {
+    Unsupported(ContentKind),
+    Empty,
+    Unreadable(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    path: PathBuf,
+
+    #[serde(flatten)]
+    reason: SkipReason,
+}
+
+/// Documents read from a directory, plus every file that was skipped and why.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DirectoryLoad {
+    documents: Vec<Document>,
+    skipped: Vec<SkippedFile>,
+}
+
+/// Recursively reads every supported file under `root` into a `Document`.
+///
+/// Text files and PDFs are loaded; images, archives, executables, and other binary content
+/// are detected from their leading bytes and reported in `skipped` instead of failing the
+/// whole load. Document ids are paths relative to `root`, and each document carries its
+/// path under the `source` metadata key, plus the title and other metadata `file_metadata`
+/// finds.
+pub fn load_directory<P: AsRef<Path>>(root: P) -> io::Result<DirectoryLoad> {
+    let root = root.as_ref();
+    let mut load = DirectoryLoad::default();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for path in entries {
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+
+            match load_document(root, &path) {
+                Ok(document) => load.documents.push(document),
+                Err(reason) => load.skipped.push(SkippedFile { path, reason }),
+            }
+        }
+    }
+
+    Ok(load)
+}
+
+/// Reads a single file under `root` the same way `load_directory` does.
+pub fn load_document(root: &Path, path: &Path) -> Result<Document, SkipReason> {
+    let (mut metadata, text) = file_metadata(path, load_file(path)?);
+    let id = path.strip_prefix(root).unwrap_or(path).to_string_lossy().to_string();
+    metadata.insert("source".to_string(), path.to_string_lossy().into_owned().into());
+
+    Ok(Document::builder()
+        .id(id)
+        .text(text)
+        .metadata(metadata)
+        .build())
+}
+
+fn load_file(path: &Path) -> Result<String, SkipReason> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    fs::File::open(path)
+        .and_then(|file| file.take(SNIFF_LEN as u64).read_to_end(&mut head))
+        .map_err(|e| SkipReason::Unreadable(e.to_string()))?;
+
+    if head.is_empty() {
+        return Err(SkipReason::Empty);
+    }
+
+    let text = match sniff(&head) {
+        ContentKind::Text => fs::read_to_string(path).map_err(|e| SkipReason::Unreadable(e.to_string()))?,
+        ContentKind::Pdf => pdf_extract::extract_text(path).map_err(|e| SkipReason::Unreadable(e.to_string()))?,
+        kind => return Err(SkipReason::Unsupported(kind)),
+    };
+
+    if text.trim().is_empty() {
+        Err(SkipReason::Empty)
+    } else {
+        Ok(text)
+    }
+}
+
+/// Metadata of a file found in its text and path, and its text without any front matter.
+///
+/// Every key of YAML front matter (between `---` lines at the start of the text) is kept,
+/// e.g. `title`, `author`, `date`, `tags`. Only flat keys with scalars or lists of scalars
+/// are read. Without a `title`, it is taken from a leading Markdown heading (`# Title`, or
+/// a line underlined with `===`), or else from the file name. Without a `date`, it is taken
+/// from a "YYYY-MM-DD-" prefix of the file name, as in "2024-05-01-release.md".
+pub fn file_metadata(path: &Path, text: String) -> (Metadata, String) {
+    let (mut metadata, text) = match front_matter(&text) {
+        Some((metadata, body)) => (metadata, body.to_string()),
+        None => (Metadata::new(), text),
+    };
+
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let date = stem.get(..10).filter(|prefix| is_date(prefix)).map(str::to_string);
+    if !metadata.contains_key("title") {
+        let name = match &date {
+            Some(_) => stem[10..].trim_start_matches('-'),
+            None => &stem,
+        };
+        if let Some(title) = heading_title(&text).or_else(|| name_title(name)) {
+            metadata.insert("title".to_string(), title.into());
+        }
+    }
+    if let (false, Some(date)) = (metadata.contains_key("date"), date) {
+        metadata.insert("date".to_string(), date.into());
+    }
+    (metadata, text)
+}
+
+/// The keys of the front matter of `text`, and the text after it.
+fn front_matter(text: &str) -> Option<(Metadata, &str)> {
+    let rest = text.strip_prefix("---")?;
+    let rest = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut end = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some((parse_front_matter(&rest[..end]), &rest[end + line.len()..]));
+        }
+        end += line.len();
+    }
+    None
+}
+
+fn parse_front_matter(block: &str) -> Metadata {
+    let mut metadata = Metadata::new();
+    let mut list: Option<String> = None;
+    for line in block.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let (Some(key), Some(item)) = (&list, trimmed.strip_prefix("- ")) {
+            if let (Some(Value::Array(items)), Some(item)) = (metadata.get_mut(key), scalar(item)) {
+                items.push(item);
+            }
+            continue;
+        }
+
+        list = None;
+        // Nested mappings are not supported.
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_string(), value.trim());
+        if value.is_empty() {
+            metadata.insert(key.clone(), Value::Array(Vec::new()));
+            list = Some(key);
+        } else if let Some(items) = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+            let items = items.split(',').map(str::trim).filter(|item| !item.is_empty()).filter_map(scalar);
+            metadata.insert(key, Value::Array(items.collect()));
+        } else if let Some(value) = scalar(value) {
+            metadata.insert(key, value);
+        }
+    }
+    // Keys without a value are null, which Pinecone does not store.
+    metadata.retain(|_, value| value.as_array().is_none_or(|items| !items.is_empty()));
+    metadata
+}
+
+/// A YAML scalar as JSON, None for null.
+fn scalar(value: &str) -> Option<Value> {
+    let quoted = ['"', '\''].iter().find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote));
+    if let Some(text) = quoted {
+        return Some(text.into());
+    }
+    Some(match value {
+        "null" | "~" => return None,
+        "true" => true.into(),
+        "false" => false.into(),
+        _ => match (value.parse::<i64>(), value.parse::<f64>()) {
+            (Ok(integer), _) => integer.into(),
+            (_, Ok(float)) if float.is_finite() => float.into(),
+            _ => value.into(),
+        },
+    })
+}
+
+/// The text of a Markdown heading on the first line of `text`.
+fn heading_title(text: &str) -> Option<String> {
+    let mut lines = text.lines().skip_while(|line| line.trim().is_empty());
+    let first = lines.next()?.trim();
+    let title = match first.strip_prefix("# ") {
+        Some(heading) => heading.trim_end_matches('#').trim(),
+        None if lines.next().is_some_and(|line| line.trim().len() >= 2 && line.trim().chars().all(|c| c == '=')) => first,
+        None => return None,
+    };
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+/// A title from a file name such as "refund-policy", i.e. "Refund policy".
+fn name_title(name: &str) -> Option<String> {
+    let words = name.replace(['-', '_'], " ");
+    let mut chars = words.split_whitespace().collect::<Vec<_>>().join(" ").chars().collect::<Vec<_>>();
+    let first = chars.first_mut()?;
+    *first = first.to_ascii_uppercase();
+    Some(chars.into_iter().collect())
+}
+
+fn is_date(text: &str) -> bool {
+    text.bytes()
+        .enumerate()
+        .all(|(i, b)| if i == 4 || i == 7 { b == b'-' } else { b.is_ascii_digit() })
+}
+
+/// Detects the content kind from the first bytes of a file.
+pub fn sniff(head: &[u8]) -> ContentKind {
+    const SIGNATURES: &[(&[u8], ContentKind)] = &[
+        (b"%PDF-", ContentKind::Pdf),
+        (b"\x89PNG\r\n\x1a\n", ContentKind::Image),
+        (b"\xff\xd8\xff", ContentKind::Image),
+        (b"GIF87a", ContentKind::Image),
+        (b"GIF89a", ContentKind::Image),
+        (b"PK\x03\x04", ContentKind::Archive),
+        (b"\x1f\x8b", ContentKind::Archive),
+        (b"7z\xbc\xaf\x27\x1c", ContentKind::Archive),
+        (b"Rar!\x1a\x07", ContentKind::Archive),
+        (b"\xfd7zXZ\x00", ContentKind::Archive),
+        (b"\x7fELF", ContentKind::Executable),
+        (b"\xcf\xfa\xed\xfe", ContentKind::Executable),
+        (b"\xce\xfa\xed\xfe", ContentKind::Executable),
+        (b"\xca\xfe\xba\xbe", ContentKind::Executable),
+        (b"\x00asm", ContentKind::Executable),
+    ];
+
+    if let Some((_, kind)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return *kind;
+    }
+
+    if head.contains(&0) {
+        return ContentKind::Binary;
+    }
+
+    // A multi-byte character may be cut off at the end of the sniffed window.
+    match std::str::from_utf8(head) {
+        Ok(_) => ContentKind::Text,
+        Err(e) if e.error_len().is_none() => ContentKind::Text,
+        Err(_) => ContentKind::Binary,
+    }
+}
+
+impl DirectoryLoad {
+    pub fn documents(&self) -> &Vec<Document> {
+        &self.documents
+    }
+
+    pub fn skipped(&self) -> &Vec<SkippedFile> {
+        &self.skipped
+    }
+
+    pub fn into_parts(self) -> (Vec<Document>, Vec<SkippedFile>) {
+        (self.documents, self.skipped)
+    }
+}
+
+impl SkippedFile {
+    pub fn new(path: PathBuf, reason: SkipReason) -> Self {
+        Self { path, reason }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn reason(&self) -> &SkipReason {
+        &self.reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"hello world\n"), ContentKind::Text);
+        assert_eq!(sniff("héllo".as_bytes()), ContentKind::Text);
+        assert_eq!(sniff(&"é".as_bytes()[..1]), ContentKind::Text);
+        assert_eq!(sniff(b"%PDF-1.7"), ContentKind::Pdf);
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), ContentKind::Image);
+        assert_eq!(sniff(b"PK\x03\x04...."), ContentKind::Archive);
+        assert_eq!(sniff(b"\x7fELF\x02\x01"), ContentKind::Executable);
+        assert_eq!(sniff(b"abc\x00def"), ContentKind::Binary);
+        assert_eq!(sniff(b"\xff\xfe\xfd"), ContentKind::Binary);
+    }
+
+    #[test]
+    fn test_file_metadata() {
+        let post = "---\ntitle: \"Refunds, explained\"\nauthor: Dana\ndraft: false\nversion: 2\ntags: [billing, faq]\nreviewers:\n  - ops\n  - legal\nupdated:\n---\n# Heading\n\nBody.";
+        let (metadata, text) = file_metadata(Path::new("posts/2024-05-01-refunds.md"), post.to_string());
+        assert_eq!(text, "# Heading\n\nBody.");
+        assert_eq!(metadata["title"], "Refunds, explained");
+        assert_eq!(metadata["author"], "Dana");
+        assert_eq!((&metadata["draft"], &metadata["version"]), (&Value::from(false), &Value::from(2)));
+        assert_eq!(metadata["tags"], serde_json::json!(["billing", "faq"]));
+        assert_eq!(metadata["reviewers"], serde_json::json!(["ops", "legal"]));
+        assert_eq!(metadata["date"], "2024-05-01");
+        assert!(!metadata.contains_key("updated"));
+
+        let (metadata, _) = file_metadata(Path::new("docs/guide.md"), "\nShipping Guide\n==============\n".to_string());
+        assert_eq!(metadata["title"], "Shipping Guide");
+        let (metadata, text) = file_metadata(Path::new("notes/2023-01-02-return_policy.txt"), "---\nno end".to_string());
+        assert_eq!(text, "---\nno end");
+        assert_eq!((&metadata["title"], &metadata["date"]), (&Value::from("Return policy"), &Value::from("2023-01-02")));
+    }
+
+    #[test]
+    fn test_load_directory() {
+        let root = std::env::temp_dir().join(format!("load_directory_{}", std::process::id()));
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("a.txt"), "some text").unwrap();
+        fs::write(root.join("nested/b.md"), "# more text").unwrap();
+        fs::write(root.join("image.png"), b"\x89PNG\r\n\x1a\n\x00\x00").unwrap();
+        fs::write(root.join("empty.txt"), "").unwrap();
+
+        let load = load_directory(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let mut ids: Vec<_> = load.documents().iter().map(|doc| doc.id().clone()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["a.txt".to_string(), format!("nested{}b.md", std::path::MAIN_SEPARATOR)]);
+
+        let reasons: Vec<_> = load.skipped().iter().map(|file| file.reason().clone()).collect();
+        assert!(reasons.contains(&SkipReason::Empty));
+        assert!(reasons.contains(&SkipReason::Unsupported(ContentKind::Image)));
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use feed_rs::model::Entry;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use crate::libs::database::{upsert, Database};
+use crate::libs::loaders::web::{html_to_text, CLIENT};
+use crate::libs::pinecone_data::Metadata;
+use crate::libs::pipeline::{Document, IngestionPipeline, PipelineError};
+
+/// Prefix of the state keys watermarks are recorded under in the `Database`.
+const WATERMARK_KEY_PREFIX: &str = "feed-watermark:";
+
+/// Number of entry GUIDs remembered per feed. Feeds only list their latest entries, so
+/// this just has to be comfortably larger than a feed page.
+const SEEN_CAPACITY: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum FeedError {
+    #[error("FetchError: {0}")]
+    FetchError(String),
+
+    #[error("ParseError: {0}")]
+    ParseError(String),
+
+    #[error("StateError: {0}")]
+    StateError(String),
+
+    #[error(transparent)]
+    PipelineError(#[from] PipelineError),
+}
+
+/// What a feed has already been ingested up to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Watermark {
+    published: Option<i64>,
+    seen: VecDeque<String>,
+}
+
+/// Result of polling one feed.
+#[derive(Debug, Clone, Default)]
+pub struct FeedPoll {
+    feed: String,
+    new_entries: usize,
+    upserted: i64,
+}
+
+/// Polls RSS/Atom feeds and ingests entries that have not been seen before.
+///
+/// Entries are deduplicated by GUID (Atom `id`). The GUIDs already ingested and the newest
+/// publish time per feed are stored in the `Database`, so a restarted watcher picks up
+/// where it left off.
+///
+/// # Fields
+///
+/// * `feeds`: Required. URLs of the feeds to poll.
+/// * `pipeline`: Required. Pipeline new entries are ingested with.
+/// * `state`: Required. Database the watermarks are persisted in.
+/// * `interval`: Optional. Time between polls. Defaults to 15 minutes.
+///
+/// # Example
+///
+/// ```rust
+/// let watcher = FeedWatcher::builder()
+///     .feeds(vec!["https://blog.example.com/rss.xml".to_string()])
+///     .pipeline(pipeline)
+///     .state(Arc::new(SQLiteDB::new("state.db")?))
+///     .build();
+///
+/// tokio::spawn(async move { watcher.run().await });
+/// ```
+#[derive(TypedBuilder)]
+pub struct FeedWatcher {
+    feeds: Vec<String>,
+    pipeline: IngestionPipeline,
+    state: Arc<dyn Database>,
+
+    #[builder(default = Duration::from_secs(15 * 60))]
+    interval: Duration,
+}
+
+impl FeedWatcher {
+    /// Polls every feed each `interval`, forever. Failures of a single feed are logged and
+    /// retried on the next tick.
+    pub async fn run(&self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            ticker.tick().await;
+            for feed in &self.feeds {
+                match self.poll(feed).await {
+                    Ok(poll) if poll.new_entries > 0 => {
+                        tracing::info!("{}: ingested {} new entries", feed, poll.new_entries)
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("{}: {}", feed, e),
+                }
+            }
+        }
+    }
+
+    /// Fetches `feed` once, ingests unseen entries, and advances its watermark.
+    pub async fn poll(&self, feed: &str) -> Result<FeedPoll, FeedError> {
+        let body = CLIENT
+            .get(feed)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| FeedError::FetchError(e.to_string()))?
+            .bytes()
+            .await
+            .map_err(|e| FeedError::FetchError(e.to_string()))?;
+
+        let parsed = feed_rs::parser::parse(body.as_ref())
+            .map_err(|e| FeedError::ParseError(e.to_string()))?;
+
+        let mut watermark = self.watermark(feed).await?;
+        let entries: Vec<&Entry> = parsed
+            .entries
+            .iter()
+            .filter(|entry| watermark.is_new(entry))
+            .collect();
+
+        let documents: Vec<Document> = entries.iter().filter_map(|entry| to_document(feed, entry)).collect();
+        let report = self.pipeline.ingest(&documents).await?;
+
+        for entry in &entries {
+            watermark.record(entry);
+        }
+        let data = serde_json::to_string(&watermark).map_err(|e| FeedError::StateError(e.to_string()))?;
+        upsert(self.state.as_ref(), &watermark_key(feed), &data)
+            .await
+            .map_err(|e| FeedError::StateError(e.to_string()))?;
+
+        Ok(FeedPoll {
+            feed: feed.to_string(),
+            new_entries: entries.len(),
+            upserted: report.upserted(),
+        })
+    }
+
+    async fn watermark(&self, feed: &str) -> Result<Watermark, FeedError> {
+        match self.state.read(&watermark_key(feed)).await {
+            Ok(data) => serde_json::from_str(&data).map_err(|e| FeedError::StateError(e.to_string())),
+            Err(_) => Ok(Watermark::default()),
+        }
+    }
+}
+
+fn watermark_key(feed: &str) -> String {
+    format!("{}{}", WATERMARK_KEY_PREFIX, feed)
+}
+
+fn to_document(feed: &str, entry: &Entry) -> Option<Document> {
+    let html = entry
+        .content
+        .as_ref()
+        .and_then(|content| content.body.clone())
+        .or_else(|| entry.summary.as_ref().map(|summary| summary.content.clone()))?;
+
+    let title = entry.title.as_ref().map(|title| title.content.clone());
+    let text = match &title {
+        Some(title) => format!("{}\n{}", title, html_to_text(&html)),
+        None => html_to_text(&html),
+    };
+
+    let mut metadata = Metadata::new();
+    metadata.insert("feed".to_string(), feed.into());
+    metadata.insert("guid".to_string(), entry.id.clone().into());
+    if let Some(title) = title {
+        metadata.insert("title".to_string(), title.into());
+    }
+    if let Some(link) = entry.links.first() {
+        metadata.insert("url".to_string(), link.href.clone().into());
+        metadata.insert("source".to_string(), link.href.clone().into());
+    }
+    if let Some(published) = entry.published.or(entry.updated) {
+        metadata.insert("published".to_string(), published.timestamp().into());
+    }
+
+    Some(
+        Document::builder()
+            .id(entry.id.clone())
+            .text(text)
+            .metadata(metadata)
+            .build(),
+    )
+}
+
+impl Watermark {
+    /// An entry is new if its GUID has not been seen and it is not older than the watermark.
+    pub fn is_new(&self, entry: &Entry) -> bool {
+        let published = entry.published.or(entry.updated).map(|time| time.timestamp());
+        let too_old = matches!((published, self.published), (Some(p), Some(w)) if p < w);
+
+        !too_old && !self.seen.contains(&entry.id)
+    }
+
+    fn record(&mut self, entry: &Entry) {
+        if let Some(time) = entry.published.or(entry.updated) {
+            self.published = self.published.max(Some(time.timestamp()));
+        }
+
+        self.seen.push_back(entry.id.clone());
+        while self.seen.len() > SEEN_CAPACITY {
+            self.seen.pop_front();
+        }
+    }
+
+    pub fn published(&self) -> Option<i64> {
+        self.published
+    }
+}
+
+impl FeedPoll {
+    pub fn feed(&self) -> &String {
+        &self.feed
+    }
+
+    pub fn new_entries(&self) -> usize {
+        self.new_entries
+    }
+
+    pub fn upserted(&self) -> i64 {
+        self.upserted
+    }
+}
@@ -0,0 +1,441 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use crate::libs::loaders::directory::{sniff, ContentKind, SNIFF_LEN};
+use crate::libs::pinecone_data::Metadata;
+use crate::libs::pipeline::{Chunk, IngestionPipeline, IngestionReport, PipelineError};
+
+/// Files larger than this are generated or vendored far more often than hand written.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum GitLoaderError {
+    #[error("GitError: {0}")]
+    GitError(String),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Language {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Go,
+    Java,
+    Kotlin,
+    CSharp,
+    C,
+    Cpp,
+    Ruby,
+    Php,
+    Swift,
+    Scala,
+    Shell,
+    Markdown,
+}
+
+/// A contiguous range of lines of a source file. Lines are 1-based and inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    start_line: usize,
+    end_line: usize,
+    text: String,
+}
+
+/// Loads the source files of a Git repository as chunks split on function/block boundaries.
+///
+/// `repository` may be a local checkout or anything `git clone` accepts. Only files tracked
+/// by Git are read, so ignored build output is never ingested. Each chunk carries `path`,
+/// `language`, `start_line`, `end_line`, and `repository` metadata.
+///
+/// # Fields
+///
+/// * `repository`: Required. Local path or clone URL.
+/// * `branch`: Optional. Branch or tag to clone. Ignored for local paths.
+/// * `max_lines`: Optional. Maximum lines per chunk. Defaults to 80.
+///
+/// # Example
+///
+/// ```rust
+/// let report = GitLoader::builder()
+///     .repository("https://github.com/kodalli/OpenAI-Pinecone.git".to_string())
+///     .build()
+///     .ingest(&pipeline)
+///     .await?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct GitLoader {
+    repository: String,
+
+    #[builder(setter(strip_option), default)]
+    branch: Option<String>,
+
+    #[builder(default = 80)]
+    max_lines: usize,
+}
+
+impl GitLoader {
+    pub fn load(&self) -> Result<Vec<Chunk>, GitLoaderError> {
+        let local = Path::new(&self.repository);
+        if local.is_dir() {
+            return self.load_checkout(local);
+        }
+
+        let checkout = std::env::temp_dir().join(format!(
+            "git-loader-{}-{}",
+            std::process::id(),
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos())
+        ));
+
+        let mut clone = Command::new("git");
+        clone.args(["clone", "--depth", "1", "--quiet"]);
+        if let Some(branch) = &self.branch {
+            clone.args(["--branch", branch]);
+        }
+        git(clone.arg(&self.repository).arg(&checkout))?;
+
+        let chunks = self.load_checkout(&checkout);
+        fs::remove_dir_all(&checkout)?;
+        chunks
+    }
+
+    /// Loads the repository on a blocking thread and ingests its chunks.
+    pub async fn ingest(&self, pipeline: &IngestionPipeline) -> Result<IngestionReport, PipelineError> {
+        let loader = self.clone();
+        let chunks = tokio::task::spawn_blocking(move || loader.load())
+            .await
+            .map_err(|e| PipelineError::LoaderError(e.to_string()))?
+            .map_err(|e| PipelineError::LoaderError(e.to_string()))?;
+
+        pipeline.ingest_chunks(chunks).await
+    }
+
+    fn load_checkout(&self, checkout: &Path) -> Result<Vec<Chunk>, GitLoaderError> {
+        let name = repository_name(&self.repository);
+        let files = git(Command::new("git").arg("-C").arg(checkout).args(["ls-files", "-z"]))?;
+
+        let mut chunks = Vec::new();
+        for relative in files.split('\0').filter(|f| !f.is_empty()) {
+            let path = checkout.join(relative);
+            let Some(language) = Language::from_path(&path) else {
+                continue;
+            };
+            let Some(text) = read_source(&path) else {
+                continue;
+            };
+
+            for block in split_code(&text, language, self.max_lines) {
+                let mut metadata = Metadata::new();
+                metadata.insert("repository".to_string(), name.clone().into());
+                metadata.insert("path".to_string(), relative.into());
+                metadata.insert("source".to_string(), relative.into());
+                metadata.insert("language".to_string(), language.name().into());
+                metadata.insert("start_line".to_string(), block.start_line.into());
+                metadata.insert("end_line".to_string(), block.end_line.into());
+
+                chunks.push(
+                    Chunk::builder()
+                        .id(format!("{}:{}:{}-{}", name, relative, block.start_line, block.end_line))
+                        .text(block.text)
+                        .metadata(metadata)
+                        .build(),
+                );
+            }
+        }
+
+        Ok(chunks)
+    }
+}
+
+fn git(command: &mut Command) -> Result<String, GitLoaderError> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(GitLoaderError::GitError(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn repository_name(repository: &str) -> String {
+    let trimmed = repository.trim_end_matches('/').trim_end_matches(".git");
+    trimmed
+        .rsplit(['/', '\\', ':'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(trimmed)
+        .to_string()
+}
+
+fn read_source(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    if sniff(&bytes[..bytes.len().min(SNIFF_LEN)]) != ContentKind::Text {
+        return None;
+    }
+    String::from_utf8(bytes).ok().filter(|text| !text.trim().is_empty())
+}
+
+/// Splits source code into blocks of at most `max_lines` lines.
+///
+/// Blocks start at top-level declarations (functions, classes, impls, headings, ...),
+/// together with the comments, attributes, and decorators directly above them. Adjacent
+/// small declarations are merged, and declarations longer than `max_lines` are split at
+/// blank lines where possible.
+pub fn split_code(text: &str, language: Language, max_lines: usize) -> Vec<CodeBlock> {
+    let lines: Vec<&str> = text.lines().collect();
+    let max_lines = max_lines.max(1);
+
+    let mut starts = vec![0];
+    for (i, line) in lines.iter().enumerate().skip(1) {
+        if language.is_boundary(line, lines[i - 1]) {
+            let mut start = i;
+            while start > 0 && language.is_preamble(lines[start - 1]) {
+                start -= 1;
+            }
+            if start > *starts.last().unwrap() {
+                starts.push(start);
+            }
+        }
+    }
+    starts.push(lines.len());
+
+    // Units are declarations; split oversized ones, then greedily merge neighbours.
+    let mut units = Vec::new();
+    for window in starts.windows(2) {
+        let (mut start, end) = (window[0], window[1]);
+        while end - start > max_lines {
+            let limit = start + max_lines;
+            let split = (start + max_lines / 2..limit)
+                .rev()
+                .find(|&i| lines[i].trim().is_empty())
+                .map_or(limit, |i| i + 1);
+            units.push((start, split));
+            start = split;
+        }
+        units.push((start, end));
+    }
+
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in units {
+        match blocks.last_mut() {
+            Some(last) if end - last.0 <= max_lines => last.1 = end,
+            _ => blocks.push((start, end)),
+        }
+    }
+
+    blocks
+        .into_iter()
+        .filter_map(|(start, end)| {
+            // Trim blank lines so line ranges point at actual code.
+            let start = (start..end).find(|&i| !lines[i].trim().is_empty())?;
+            let end = (start..end).rev().find(|&i| !lines[i].trim().is_empty())? + 1;
+            Some(CodeBlock {
+                start_line: start + 1,
+                end_line: end,
+                text: lines[start..end].join("\n"),
+            })
+        })
+        .collect()
+}
+
+impl Language {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        let language = match extension.as_str() {
+            "rs" => Language::Rust,
+            "py" | "pyi" => Language::Python,
+            "js" | "jsx" | "mjs" | "cjs" => Language::JavaScript,
+            "ts" | "tsx" => Language::TypeScript,
+            "go" => Language::Go,
+            "java" => Language::Java,
+            "kt" | "kts" => Language::Kotlin,
+            "cs" => Language::CSharp,
+            "c" | "h" => Language::C,
+            "cc" | "cpp" | "cxx" | "hpp" | "hh" => Language::Cpp,
+            "rb" => Language::Ruby,
+            "php" => Language::Php,
+            "swift" => Language::Swift,
+            "scala" => Language::Scala,
+            "sh" | "bash" | "zsh" => Language::Shell,
+            "md" | "markdown" => Language::Markdown,
+            _ => return None,
+        };
+        Some(language)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+            Language::TypeScript => "typescript",
+            Language::Go => "go",
+            Language::Java => "java",
+            Language::Kotlin => "kotlin",
+            Language::CSharp => "csharp",
+            Language::C => "c",
+            Language::Cpp => "cpp",
+            Language::Ruby => "ruby",
+            Language::Php => "php",
+            Language::Swift => "swift",
+            Language::Scala => "scala",
+            Language::Shell => "shell",
+            Language::Markdown => "markdown",
+        }
+    }
+
+    fn keywords(&self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &[
+                "fn ", "pub ", "pub(", "impl", "struct ", "enum ", "trait ", "mod ", "async ",
+                "const ", "static ", "type ", "macro_rules!", "unsafe ", "extern ",
+            ],
+            Language::Python => &["def ", "async def ", "class "],
+            Language::JavaScript | Language::TypeScript => &[
+                "function ", "async function ", "class ", "export ", "const ", "let ", "var ",
+                "interface ", "type ", "enum ", "abstract ",
+            ],
+            Language::Go => &["func ", "type ", "var ", "const "],
+            Language::Java | Language::Kotlin | Language::CSharp | Language::Scala => &[
+                "public ", "private ", "protected ", "internal ", "static ", "abstract ", "final ",
+                "sealed ", "data ", "class ", "interface ", "enum ", "record ", "fun ", "object ",
+                "def ", "trait ", "case class ", "namespace ",
+            ],
+            Language::Ruby => &["def ", "class ", "module "],
+            Language::Php => &["function ", "class ", "interface ", "trait ", "abstract ", "final "],
+            Language::Swift => &[
+                "func ", "class ", "struct ", "enum ", "protocol ", "extension ", "public ",
+                "private ", "internal ", "fileprivate ", "open ", "final ",
+            ],
+            Language::Shell => &["function "],
+            Language::Markdown => &["#"],
+            // C and C++ declarations have no leading keyword; see `is_boundary`.
+            Language::C | Language::Cpp => &["struct ", "class ", "namespace ", "template", "enum "],
+        }
+    }
+
+    fn is_boundary(&self, line: &str, previous: &str) -> bool {
+        if line.is_empty() || line.starts_with(char::is_whitespace) {
+            return false;
+        }
+        if self.keywords().iter().any(|keyword| line.starts_with(keyword)) {
+            return true;
+        }
+
+        match self {
+            // A top-level line after a blank line that is not a brace or preprocessor
+            // directive is almost always the start of a declaration.
+            Language::C | Language::Cpp | Language::Shell => {
+                previous.trim().is_empty()
+                    && !line.starts_with(['{', '}', '#', '/', '*'])
+            }
+            _ => false,
+        }
+    }
+
+    /// Lines directly above a declaration that belong to it.
+    fn is_preamble(&self, line: &str) -> bool {
+        let line = line.trim_start();
+        if line.is_empty() {
+            return false;
+        }
+
+        match self {
+            Language::Python | Language::Ruby | Language::Shell => {
+                line.starts_with('#') || line.starts_with('@')
+            }
+            Language::Markdown => false,
+            Language::Rust => line.starts_with("//") || line.starts_with("#["),
+            Language::CSharp => line.starts_with("//") || line.starts_with('['),
+            _ => {
+                line.starts_with("//")
+                    || line.starts_with("/*")
+                    || line.starts_with('*')
+                    || line.starts_with('@')
+            }
+        }
+    }
+}
+
+impl CodeBlock {
+    pub fn start_line(&self) -> usize {
+        self.start_line
+    }
+
+    pub fn end_line(&self) -> usize {
+        self.end_line
+    }
+
+    pub fn text(&self) -> &String {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUST: &str = "use std::fmt;
+
+/// Adds numbers.
+#[inline]
+pub fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+struct Point {
+    x: i32,
+}
+
+impl Point {
+    fn x(&self) -> i32 {
+        self.x
+    }
+}
+";
+
+    #[test]
+    fn test_split_code_on_declarations() {
+        let blocks = split_code(RUST, Language::Rust, 5);
+        let ranges: Vec<_> = blocks.iter().map(|b| (b.start_line(), b.end_line())).collect();
+
+        assert_eq!(ranges, vec![(1, 1), (3, 7), (9, 11), (13, 17)]);
+        assert!(blocks[1].text().starts_with("/// Adds numbers."));
+    }
+
+    #[test]
+    fn test_split_code_merges_small_blocks() {
+        let blocks = split_code(RUST, Language::Rust, 80);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!((blocks[0].start_line(), blocks[0].end_line()), (1, 17));
+    }
+
+    #[test]
+    fn test_split_code_splits_long_blocks() {
+        let body: Vec<String> = (0..10).map(|i| format!("    x{} = {}\n", i, i)).collect();
+        let text = format!("def long():\n{}\n{}", body[..5].concat(), body[5..].concat());
+
+        let blocks = split_code(&text, Language::Python, 8);
+        let ranges: Vec<_> = blocks.iter().map(|b| (b.start_line(), b.end_line())).collect();
+        assert_eq!(ranges, vec![(1, 6), (8, 12)]);
+    }
+
+    #[test]
+    fn test_repository_name() {
+        assert_eq!(repository_name("https://github.com/kodalli/OpenAI-Pinecone.git"), "OpenAI-Pinecone");
+        assert_eq!(repository_name("git@github.com:kodalli/repo.git"), "repo");
+        assert_eq!(repository_name("/home/me/code/project/"), "project");
+    }
+}
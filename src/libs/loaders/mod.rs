@@ -0,0 +1,8 @@
+pub mod csv;
+pub mod directory;
+pub mod feed;
+pub mod git;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod web;
+pub mod wiki;
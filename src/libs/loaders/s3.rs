@@ -0,0 +1,246 @@
+use std::path::{Path, PathBuf};
+
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::libs::database::{upsert, Database};
+use crate::libs::loaders::directory::{file_metadata, sniff, ContentKind, SkipReason, SkippedFile, SNIFF_LEN};
+use crate::libs::pipeline::{Document, IngestionPipeline, IngestionReport, PipelineError};
+
+/// Prefix of the state keys etags are recorded under in the `Database`.
+const ETAG_KEY_PREFIX: &str = "s3-etag:";
+
+#[derive(Debug, Error)]
+pub enum S3LoaderError {
+    #[error("S3Error: {0}")]
+    S3Error(#[from] S3Error),
+
+    #[error("CredentialsError: {0}")]
+    CredentialsError(String),
+
+    #[error("StateError: {0}")]
+    StateError(String),
+}
+
+/// An object listed in the bucket.
+#[derive(Debug, Clone)]
+pub struct S3Object {
+    key: String,
+    etag: String,
+    size: u64,
+}
+
+/// Objects that changed since the last committed run, read into documents.
+#[derive(Debug, Clone, Default)]
+pub struct S3Load {
+    documents: Vec<Document>,
+    skipped: Vec<SkippedFile>,
+    unchanged: usize,
+}
+
+/// Document source reading objects from an S3-compatible bucket.
+///
+/// Credentials are read from the environment (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`)
+/// or the default AWS profile. Every object's etag is recorded in a `Database` once it has
+/// been ingested, so later runs only load objects whose etag changed. Documents carry the
+/// title and other metadata `file_metadata` finds in the object and its key.
+///
+/// # Example
+///
+/// ```rust
+/// let source = S3Source::new("my-bucket", "us-east-1", None)?.prefix("docs/");
+/// let state = SQLiteDB::new("state.db")?;
+///
+/// let report = source.ingest_changed(&pipeline, &state).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct S3Source {
+    bucket: Bucket,
+    prefix: String,
+}
+
+impl S3Source {
+    /// Connects to `bucket`. Pass an `endpoint` for S3-compatible stores such as MinIO or R2.
+    pub fn new(bucket: &str, region: &str, endpoint: Option<&str>) -> Result<Self, S3LoaderError> {
+        let credentials = Credentials::default()
+            .map_err(|e| S3LoaderError::CredentialsError(e.to_string()))?;
+
+        let bucket = match endpoint {
+            Some(endpoint) => {
+                let region = Region::Custom {
+                    region: region.to_string(),
+                    endpoint: endpoint.to_string(),
+                };
+                Bucket::new(bucket, region, credentials)?.with_path_style()
+            }
+            None => {
+                let region: Region = region.parse().map_err(S3Error::from)?;
+                Bucket::new(bucket, region, credentials)?
+            }
+        };
+
+        Ok(Self {
+            bucket,
+            prefix: String::new(),
+        })
+    }
+
+    /// Only objects whose key starts with `prefix` are listed.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    pub async fn list(&self) -> Result<Vec<S3Object>, S3LoaderError> {
+        let pages = self.bucket.list(self.prefix.clone(), None).await?;
+
+        Ok(pages
+            .into_iter()
+            .flat_map(|page| page.contents)
+            .filter(|object| !object.key.ends_with('/'))
+            .map(|object| S3Object {
+                etag: object.e_tag.unwrap_or_default().trim_matches('"').to_string(),
+                key: object.key,
+                size: object.size,
+            })
+            .collect())
+    }
+
+    /// Reads every object whose etag differs from the one recorded in `state`.
+    ///
+    /// Nothing is recorded here; call `commit` once the documents have been ingested.
+    pub async fn load_changed(&self, state: &dyn Database) -> Result<S3Load, S3LoaderError> {
+        let mut load = S3Load::default();
+
+        for object in self.list().await? {
+            let previous = state.read(&self.state_key(&object.key)).await.ok();
+            if previous.as_deref() == Some(object.etag.as_str()) {
+                load.unchanged += 1;
+                continue;
+            }
+
+            let url = self.url(&object.key);
+            let response = self.bucket.get_object(&object.key).await?;
+
+            match decode(response.as_slice()) {
+                Ok(text) => {
+                    let (mut metadata, text) = file_metadata(Path::new(&object.key), text);
+                    metadata.insert("source".to_string(), url.clone().into());
+                    metadata.insert("etag".to_string(), object.etag.clone().into());
+
+                    load.documents.push(
+                        Document::builder()
+                            .id(url)
+                            .text(text)
+                            .metadata(metadata)
+                            .build(),
+                    );
+                }
+                Err(reason) => load.skipped.push(SkippedFile::new(PathBuf::from(url), reason)),
+            }
+        }
+
+        Ok(load)
+    }
+
+    /// Records the etags of ingested documents so they are skipped until they change.
+    pub async fn commit(&self, state: &dyn Database, documents: &[Document]) -> Result<(), S3LoaderError> {
+        for document in documents {
+            let (Some(etag), Some(key)) = (
+                document.metadata().get("etag").and_then(Value::as_str),
+                document.id().strip_prefix(&self.url("")),
+            ) else {
+                continue;
+            };
+
+            upsert(state, &self.state_key(key), etag)
+                .await
+                .map_err(|e| S3LoaderError::StateError(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads changed objects, ingests them, and commits their etags.
+    pub async fn ingest_changed(
+        &self,
+        pipeline: &IngestionPipeline,
+        state: &dyn Database,
+    ) -> Result<IngestionReport, PipelineError> {
+        let load = self
+            .load_changed(state)
+            .await
+            .map_err(|e| PipelineError::LoaderError(e.to_string()))?;
+
+        let report = pipeline.ingest(&load.documents).await?;
+        self.commit(state, &load.documents)
+            .await
+            .map_err(|e| PipelineError::LoaderError(e.to_string()))?;
+
+        Ok(report.with_skipped(load.skipped))
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("s3://{}/{}", self.bucket.name, key)
+    }
+
+    fn state_key(&self, key: &str) -> String {
+        format!("{}{}/{}", ETAG_KEY_PREFIX, self.bucket.name, key)
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<String, SkipReason> {
+    if bytes.is_empty() {
+        return Err(SkipReason::Empty);
+    }
+
+    let text = match sniff(&bytes[..bytes.len().min(SNIFF_LEN)]) {
+        ContentKind::Text => String::from_utf8(bytes.to_vec())
+            .map_err(|e| SkipReason::Unreadable(e.to_string()))?,
+        ContentKind::Pdf => {
+            let path = std::env::temp_dir().join(format!("s3-object-{}.pdf", std::process::id()));
+            std::fs::write(&path, bytes).map_err(|e| SkipReason::Unreadable(e.to_string()))?;
+            let text = pdf_extract::extract_text(&path);
+            let _ = std::fs::remove_file(&path);
+            text.map_err(|e| SkipReason::Unreadable(e.to_string()))?
+        }
+        kind => return Err(SkipReason::Unsupported(kind)),
+    };
+
+    if text.trim().is_empty() {
+        Err(SkipReason::Empty)
+    } else {
+        Ok(text)
+    }
+}
+
+impl S3Object {
+    pub fn key(&self) -> &String {
+        &self.key
+    }
+
+    pub fn etag(&self) -> &String {
+        &self.etag
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl S3Load {
+    pub fn documents(&self) -> &Vec<Document> {
+        &self.documents
+    }
+
+    pub fn skipped(&self) -> &Vec<SkippedFile> {
+        &self.skipped
+    }
+
+    pub fn unchanged(&self) -> usize {
+        self.unchanged
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::{HashSet, VecDeque};
+
+use lazy_static::lazy_static;
+use reqwest::Client;
+use scraper::{Html, Node, Selector};
+use thiserror::Error;
+use url::Url;
+
+use crate::libs::pinecone_data::Metadata;
+use crate::libs::pipeline::{Document, IngestionPipeline, IngestionReport, PipelineError};
+
+const USER_AGENT: &str = "openai-pinecone-loader";
+
+/// Upper bound on pages fetched by a single crawl, whatever the depth.
+const MAX_PAGES: usize = 200;
+
+/// Elements whose text is navigation or code rather than page content.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "nav", "header", "footer", "aside", "form",
+];
+
+/// Elements that start a new line in the extracted text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p", "div", "section", "article", "main", "br", "li", "tr", "h1", "h2", "h3", "h4", "h5",
+    "h6", "pre", "blockquote", "table",
+];
+
+lazy_static! {
+    pub(crate) static ref CLIENT: Client = Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        .expect("Failed to create client connection.");
+    static ref LINKS: Selector = Selector::parse("a[href]").unwrap();
+    static ref TITLE: Selector = Selector::parse("title").unwrap();
+    static ref BODY: Selector = Selector::parse("body").unwrap();
+}
+
+#[derive(Debug, Error)]
+pub enum WebLoaderError {
+    #[error("InvalidUrl: {0}")]
+    InvalidUrl(String),
+
+    #[error("FetchError: {0}")]
+    FetchError(String),
+
+    #[error("DisallowedByRobots: {0}")]
+    DisallowedByRobots(String),
+}
+
+/// Fetches `url` and every same-domain page linked from it up to `depth` links away,
+/// and ingests them with a default `IngestionPipeline`.
+///
+/// # Example
+///
+/// ```rust
+/// let report = ingest_url("https://docs.example.com/", 2).await?;
+/// ```
+pub async fn ingest_url(url: &str, depth: usize) -> Result<IngestionReport, PipelineError> {
+    ingest_url_with(&IngestionPipeline::builder().build(), url, depth).await
+}
+
+/// Same as `ingest_url`, using the given pipeline.
+pub async fn ingest_url_with(
+    pipeline: &IngestionPipeline,
+    url: &str,
+    depth: usize,
+) -> Result<IngestionReport, PipelineError> {
+    let documents = crawl(url, depth)
+        .await
+        .map_err(|e| PipelineError::LoaderError(e.to_string()))?;
+
+    pipeline.ingest(&documents).await
+}
+
+/// Fetches pages breadth-first from `url`, following same-domain links up to `depth` levels.
+///
+/// Paths disallowed by the site's robots.txt are never fetched. Each page becomes a
+/// `Document` keyed by its URL, with `url`, `source`, and (when present) `title` metadata.
+pub async fn crawl(url: &str, depth: usize) -> Result<Vec<Document>, WebLoaderError> {
+    let start = Url::parse(url).map_err(|e| WebLoaderError::InvalidUrl(e.to_string()))?;
+    let robots = Robots::fetch(&start).await;
+
+    if !robots.allows(start.path()) {
+        return Err(WebLoaderError::DisallowedByRobots(start.to_string()));
+    }
+
+    let mut documents = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(start.to_string());
+    queue.push_back((start, 0));
+
+    while let Some((page_url, level)) = queue.pop_front() {
+        if documents.len() >= MAX_PAGES {
+            break;
+        }
+
+        let html = match fetch_html(&page_url).await {
+            Ok(Some(html)) => html,
+            // Non-HTML responses and broken links are skipped; only the start page must load.
+            Ok(None) if level > 0 => continue,
+            Err(_) if level > 0 => continue,
+            Ok(None) => return Err(WebLoaderError::FetchError(format!("{} is not HTML", page_url))),
+            Err(e) => return Err(e),
+        };
+
+        let page = parse_page(&html, &page_url);
+
+        if level < depth {
+            for link in page.links {
+                if link.host_str() == page_url.host_str()
+                    && robots.allows(link.path())
+                    && seen.insert(link.to_string())
+                {
+                    queue.push_back((link, level + 1));
+                }
+            }
+        }
+
+        if page.text.trim().is_empty() {
+            continue;
+        }
+
+        let mut metadata = Metadata::new();
+        metadata.insert("url".to_string(), page_url.to_string().into());
+        metadata.insert("source".to_string(), page_url.to_string().into());
+        if let Some(title) = page.title {
+            metadata.insert("title".to_string(), title.into());
+        }
+
+        documents.push(
+            Document::builder()
+                .id(page_url.to_string())
+                .text(page.text)
+                .metadata(metadata)
+                .build(),
+        );
+    }
+
+    Ok(documents)
+}
+
+async fn fetch_html(url: &Url) -> Result<Option<String>, WebLoaderError> {
+    let response = CLIENT
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| WebLoaderError::FetchError(e.to_string()))?;
+
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_none_or(|value| value.contains("text/html"));
+
+    if !is_html {
+        return Ok(None);
+    }
+
+    response
+        .text()
+        .await
+        .map(Some)
+        .map_err(|e| WebLoaderError::FetchError(e.to_string()))
+}
+
+struct Page {
+    title: Option<String>,
+    text: String,
+    links: Vec<Url>,
+}
+
+fn parse_page(html: &str, base: &Url) -> Page {
+    let document = Html::parse_document(html);
+
+    let title = document
+        .select(&TITLE)
+        .next()
+        .map(|title| title.text().collect::<String>().trim().to_string())
+        .filter(|title| !title.is_empty());
+
+    let links = document
+        .select(&LINKS)
+        .filter_map(|a| a.value().attr("href"))
+        .filter_map(|href| base.join(href).ok())
+        .filter(|link| link.scheme() == "http" || link.scheme() == "https")
+        .map(|mut link| {
+            link.set_fragment(None);
+            link
+        })
+        .collect();
+
+    let text = readable_text(&document);
+
+    Page { title, text, links }
+}
+
+/// Extracts the readable text of an HTML page or fragment, one line per block element.
+pub fn html_to_text(html: &str) -> String {
+    readable_text(&Html::parse_document(html))
+}
+
+fn readable_text(document: &Html) -> String {
+    let mut raw = String::new();
+    if let Some(body) = document.select(&BODY).next() {
+        for node in body.descendants() {
+            match node.value() {
+                Node::Text(t) => {
+                    let skipped = node.ancestors().any(|ancestor| {
+                        ancestor
+                            .value()
+                            .as_element()
+                            .is_some_and(|e| SKIPPED_ELEMENTS.contains(&e.name()))
+                    });
+                    if !skipped {
+                        raw.push_str(&t.replace('\n', " "));
+                    }
+                }
+                Node::Element(e) if BLOCK_ELEMENTS.contains(&e.name()) => raw.push('\n'),
+                _ => {}
+            }
+        }
+    }
+
+    raw
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The subset of robots.txt rules that applies to this loader.
+#[derive(Debug, Clone, Default)]
+pub struct Robots {
+    allow: Vec<String>,
+    disallow: Vec<String>,
+}
+
+impl Robots {
+    /// Fetches robots.txt for the site of `url`. A missing or unreadable file allows everything.
+    pub async fn fetch(url: &Url) -> Self {
+        let Ok(robots_url) = url.join("/robots.txt") else {
+            return Self::default();
+        };
+
+        match CLIENT.get(robots_url).send().await {
+            Ok(response) if response.status().is_success() => {
+                Self::parse(&response.text().await.unwrap_or_default())
+            }
+            _ => Self::default(),
+        }
+    }
+
+    /// Parses the groups for `*` and this loader's user agent.
+    pub fn parse(robots_txt: &str) -> Self {
+        let mut robots = Self::default();
+        let mut in_group = false;
+        let mut group_applies = false;
+
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match field.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // Consecutive user-agent lines share the rules that follow them.
+                    if in_group {
+                        group_applies = false;
+                        in_group = false;
+                    }
+                    group_applies |= value == "*" || value.eq_ignore_ascii_case(USER_AGENT);
+                }
+                "allow" if group_applies && !value.is_empty() => {
+                    in_group = true;
+                    robots.allow.push(value.to_string());
+                }
+                "disallow" if group_applies && !value.is_empty() => {
+                    in_group = true;
+                    robots.disallow.push(value.to_string());
+                }
+                "allow" | "disallow" => in_group = true,
+                _ => {}
+            }
+        }
+
+        robots
+    }
+
+    /// The longest matching rule wins, with `Allow` winning ties.
+    pub fn allows(&self, path: &str) -> bool {
+        let longest = |rules: &[String]| {
+            rules
+                .iter()
+                .filter(|rule| path.starts_with(rule.as_str()))
+                .map(|rule| rule.len())
+                .max()
+        };
+
+        match (longest(&self.allow), longest(&self.disallow)) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(allow), Some(disallow)) => allow >= disallow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots() {
+        let robots = Robots::parse(
+            "User-agent: googlebot\n\
+             Disallow: /\n\
+             \n\
+             User-agent: *\n\
+             Disallow: /private\n\
+             Allow: /private/public # comment\n",
+        );
+
+        assert!(robots.allows("/docs"));
+        assert!(!robots.allows("/private/notes"));
+        assert!(robots.allows("/private/public/page"));
+    }
+
+    #[test]
+    fn test_parse_page() {
+        let base = Url::parse("https://example.com/docs/").unwrap();
+        let page = parse_page(
+            "<html><head><title> Docs </title><style>p { color: red }</style></head>\
+             <body><nav><a href=\"/home\">Home</a></nav>\
+             <p>First   paragraph.</p><p>Second <a href=\"intro#top\">intro</a>.</p>\
+             <script>var x = 1;</script></body></html>",
+            &base,
+        );
+
+        assert_eq!(page.title.as_deref(), Some("Docs"));
+        assert_eq!(page.text, "First paragraph.\nSecond intro.");
+        assert_eq!(
+            page.links.iter().map(|l| l.as_str()).collect::<Vec<_>>(),
+            vec!["https://example.com/home", "https://example.com/docs/intro"]
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use thiserror::Error;
+
+use crate::libs::loaders::web::html_to_text;
+use crate::libs::pinecone_data::Metadata;
+use crate::libs::pipeline::Document;
+
+lazy_static! {
+    static ref BREADCRUMBS: Selector = Selector::parse("#breadcrumbs li a").unwrap();
+    static ref TITLE_TEXT: Selector = Selector::parse("#title-text").unwrap();
+    static ref TITLE: Selector = Selector::parse("title").unwrap();
+    static ref MAIN_CONTENT: Selector = Selector::parse("#main-content").unwrap();
+}
+
+#[derive(Debug, Error)]
+pub enum WikiImportError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error("XmlError: {0}")]
+    XmlError(#[from] roxmltree::Error),
+}
+
+/// Imports a Notion "Markdown & CSV" export.
+///
+/// Notion names every page `Title <32 hex id>.md` and puts its sub-pages in a folder of the
+/// same name, so the folder structure is the page hierarchy. Each page gets `title`,
+/// `hierarchy` (ancestor titles joined with " / "), `parent`, `notion_id`, and `source`
+/// metadata.
+pub fn load_notion_export<P: AsRef<Path>>(root: P) -> Result<Vec<Document>, WikiImportError> {
+    let root = root.as_ref();
+    let mut documents = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for path in entries {
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if path.extension().and_then(|e| e.to_str()) != Some("md") {
+                continue;
+            }
+
+            let text = fs::read_to_string(&path)?;
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let mut titles: Vec<String> = relative
+                .parent()
+                .into_iter()
+                .flat_map(|parent| parent.iter())
+                .map(|component| split_notion_name(&component.to_string_lossy()).0)
+                .collect();
+
+            let stem = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().to_string());
+            let (title, notion_id) = split_notion_name(&stem);
+            let parent = titles.last().cloned();
+            titles.push(title.clone());
+
+            let source = relative.to_string_lossy().to_string();
+            let mut metadata = Metadata::new();
+            metadata.insert("title".to_string(), title.into());
+            metadata.insert("hierarchy".to_string(), titles.join(" / ").into());
+            metadata.insert("source".to_string(), source.clone().into());
+            if let Some(parent) = parent {
+                metadata.insert("parent".to_string(), parent.into());
+            }
+
+            let id = match notion_id {
+                Some(notion_id) => {
+                    metadata.insert("notion_id".to_string(), notion_id.clone().into());
+                    format!("notion:{}", notion_id)
+                }
+                None => format!("notion:{}", source),
+            };
+
+            documents.push(Document::builder().id(id).text(text).metadata(metadata).build());
+        }
+    }
+
+    Ok(documents)
+}
+
+/// Splits `"Meeting Notes 0123456789abcdef0123456789abcdef"` into the title and Notion id.
+fn split_notion_name(name: &str) -> (String, Option<String>) {
+    match name.rsplit_once(' ') {
+        Some((title, id)) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => {
+            (title.to_string(), Some(id.to_string()))
+        }
+        _ => (name.to_string(), None),
+    }
+}
+
+/// Imports a Confluence space export.
+///
+/// `path` may be an HTML export directory, an XML export directory containing
+/// `entities.xml`, or the `entities.xml` file itself. Pages get `title`, `hierarchy`,
+/// `parent`, `confluence_id` (XML exports only), and `source` metadata.
+pub fn load_confluence_export<P: AsRef<Path>>(path: P) -> Result<Vec<Document>, WikiImportError> {
+    let path = path.as_ref();
+    let entities = if path.is_dir() { path.join("entities.xml") } else { path.to_path_buf() };
+
+    if entities.is_file() {
+        let xml = fs::read_to_string(&entities)?;
+        parse_confluence_entities(&xml, &entities.to_string_lossy())
+    } else {
+        load_confluence_html(path)
+    }
+}
+
+fn load_confluence_html(root: &Path) -> Result<Vec<Document>, WikiImportError> {
+    let mut entries = fs::read_dir(root)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    let mut documents = Vec::new();
+    for path in entries {
+        if path.extension().and_then(|e| e.to_str()) != Some("html") {
+            continue;
+        }
+
+        let html = fs::read_to_string(&path)?;
+        let source = path.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string());
+        if let Some(document) = parse_confluence_page(&html, &source) {
+            documents.push(document);
+        }
+    }
+
+    Ok(documents)
+}
+
+fn parse_confluence_page(html: &str, source: &str) -> Option<Document> {
+    let page = Html::parse_document(html);
+    let content = page.select(&MAIN_CONTENT).next()?;
+    let text = html_to_text(&content.html());
+    if text.is_empty() {
+        return None;
+    }
+
+    let title = page
+        .select(&TITLE_TEXT)
+        .next()
+        .or_else(|| page.select(&TITLE).next())
+        .map(|title| title.text().collect::<String>().trim().to_string())
+        .unwrap_or_else(|| source.to_string());
+    // The <title> fallback is "Space : Page".
+    let title = title.rsplit(" : ").next().unwrap_or(&title).to_string();
+
+    // The first breadcrumb is the space overview, the rest are ancestor pages.
+    let mut titles: Vec<String> = page
+        .select(&BREADCRUMBS)
+        .map(|a| a.text().collect::<String>().trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    let parent = titles.last().cloned();
+    titles.push(title.clone());
+
+    let mut metadata = Metadata::new();
+    metadata.insert("title".to_string(), title.into());
+    metadata.insert("hierarchy".to_string(), titles.join(" / ").into());
+    metadata.insert("source".to_string(), source.into());
+    if let Some(parent) = parent {
+        metadata.insert("parent".to_string(), parent.into());
+    }
+
+    Some(
+        Document::builder()
+            .id(format!("confluence:{}", source))
+            .text(text)
+            .metadata(metadata)
+            .build(),
+    )
+}
+
+struct ConfluencePage {
+    title: String,
+    parent: Option<String>,
+    body_ids: Vec<String>,
+}
+
+fn id_of(node: roxmltree::Node) -> Option<String> {
+    node.children()
+        .find(|child| child.has_tag_name("id"))
+        .and_then(|id| id.text())
+        .map(|id| id.trim().to_string())
+}
+
+fn property<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.has_tag_name("property") && child.attribute("name") == Some(name))
+}
+
+/// Parses the Hibernate object dump of a Confluence XML export. Only current versions of
+/// pages are imported; historical versions and drafts are skipped.
+fn parse_confluence_entities(xml: &str, source: &str) -> Result<Vec<Document>, WikiImportError> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let objects = doc
+        .root_element()
+        .children()
+        .filter(|node| node.has_tag_name("object"));
+
+    let mut pages: Vec<(String, ConfluencePage)> = Vec::new();
+    let mut bodies: HashMap<String, String> = HashMap::new();
+
+    for object in objects {
+        let Some(id) = id_of(object) else {
+            continue;
+        };
+
+        match object.attribute("class") {
+            Some("Page") => {
+                let status = property(object, "contentStatus").and_then(|p| p.text()).unwrap_or("current");
+                if status != "current" || property(object, "originalVersion").is_some() {
+                    continue;
+                }
+
+                let title = property(object, "title").and_then(|p| p.text()).unwrap_or("").to_string();
+                let parent = property(object, "parent").and_then(id_of);
+                let body_ids = object
+                    .children()
+                    .filter(|c| c.has_tag_name("collection") && c.attribute("name") == Some("bodyContents"))
+                    .flat_map(|c| c.children().filter_map(id_of))
+                    .collect();
+
+                pages.push((id, ConfluencePage { title, parent, body_ids }));
+            }
+            Some("BodyContent") => {
+                if let Some(body) = property(object, "body").and_then(|p| p.text()) {
+                    bodies.insert(id, body.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let titles: HashMap<&str, &ConfluencePage> = pages.iter().map(|(id, page)| (id.as_str(), page)).collect();
+
+    let mut documents = Vec::new();
+    for (id, page) in &pages {
+        let text = page
+            .body_ids
+            .iter()
+            .filter_map(|body_id| bodies.get(body_id))
+            .map(|body| html_to_text(body))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if text.is_empty() {
+            continue;
+        }
+
+        let mut hierarchy = vec![page.title.clone()];
+        let mut ancestor = page.parent.as_deref();
+        while let Some(parent) = ancestor.and_then(|id| titles.get(id)) {
+            // Guard against cycles in corrupt exports.
+            if hierarchy.len() > titles.len() {
+                break;
+            }
+            hierarchy.push(parent.title.clone());
+            ancestor = parent.parent.as_deref();
+        }
+        hierarchy.reverse();
+
+        let mut metadata = Metadata::new();
+        metadata.insert("title".to_string(), page.title.clone().into());
+        metadata.insert("hierarchy".to_string(), hierarchy.join(" / ").into());
+        metadata.insert("confluence_id".to_string(), id.clone().into());
+        metadata.insert("source".to_string(), source.into());
+        if hierarchy.len() > 1 {
+            metadata.insert("parent".to_string(), hierarchy[hierarchy.len() - 2].clone().into());
+        }
+
+        documents.push(
+            Document::builder()
+                .id(format!("confluence:{}", id))
+                .text(text)
+                .metadata(metadata)
+                .build(),
+        );
+    }
+
+    Ok(documents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_notion_name() {
+        assert_eq!(
+            split_notion_name("Meeting Notes 0123456789abcdef0123456789abcdef"),
+            ("Meeting Notes".to_string(), Some("0123456789abcdef0123456789abcdef".to_string()))
+        );
+        assert_eq!(split_notion_name("Plain"), ("Plain".to_string(), None));
+    }
+
+    #[test]
+    fn test_notion_hierarchy() {
+        let root = std::env::temp_dir().join(format!("notion_export_{}", std::process::id()));
+        let parent_dir = root.join("Team 0123456789abcdef0123456789abcdef");
+        fs::create_dir_all(&parent_dir).unwrap();
+        fs::write(root.join("Team 0123456789abcdef0123456789abcdef.md"), "# Team").unwrap();
+        fs::write(parent_dir.join("Onboarding fedcba9876543210fedcba9876543210.md"), "# Onboarding").unwrap();
+
+        let documents = load_notion_export(&root).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let child = documents
+            .iter()
+            .find(|doc| doc.id() == "notion:fedcba9876543210fedcba9876543210")
+            .unwrap();
+        assert_eq!(child.metadata().get("hierarchy").unwrap(), "Team / Onboarding");
+        assert_eq!(child.metadata().get("parent").unwrap(), "Team");
+    }
+
+    #[test]
+    fn test_confluence_entities() {
+        let xml = r#"<hibernate-generic>
+            <object class="Page" package="com.atlassian.confluence.pages">
+                <id name="id">1</id>
+                <property name="title"><![CDATA[Home]]></property>
+                <property name="contentStatus"><![CDATA[current]]></property>
+                <collection name="bodyContents"><element class="BodyContent"><id name="id">10</id></element></collection>
+            </object>
+            <object class="Page" package="com.atlassian.confluence.pages">
+                <id name="id">2</id>
+                <property name="title"><![CDATA[Setup]]></property>
+                <property name="parent" class="Page"><id name="id">1</id></property>
+                <collection name="bodyContents"><element class="BodyContent"><id name="id">20</id></element></collection>
+            </object>
+            <object class="Page" package="com.atlassian.confluence.pages">
+                <id name="id">3</id>
+                <property name="title"><![CDATA[Setup]]></property>
+                <property name="originalVersion" class="Page"><id name="id">2</id></property>
+                <collection name="bodyContents"><element class="BodyContent"><id name="id">30</id></element></collection>
+            </object>
+            <object class="BodyContent"><id name="id">10</id><property name="body"><![CDATA[<p>Welcome</p>]]></property></object>
+            <object class="BodyContent"><id name="id">20</id><property name="body"><![CDATA[<p>Install it.</p>]]></property></object>
+            <object class="BodyContent"><id name="id">30</id><property name="body"><![CDATA[<p>Old.</p>]]></property></object>
+        </hibernate-generic>"#;
+
+        let documents = parse_confluence_entities(xml, "entities.xml").unwrap();
+        assert_eq!(documents.len(), 2);
+
+        let setup = documents.iter().find(|doc| doc.id() == "confluence:2").unwrap();
+        assert_eq!(setup.text(), "Install it.");
+        assert_eq!(setup.metadata().get("hierarchy").unwrap(), "Home / Setup");
+    }
+}
@@ -0,0 +1,100 @@
+use super::math::cosine_similarity;
+use super::observer::{DatabaseObserver, ObserverError, VectorRecord};
+use super::filter;
+use super::pinecone_data::{Filter, Match};
+
+/// Brute-force index over a snapshot of the vectors mirrored by a `DatabaseObserver`, to
+/// keep answering queries while Pinecone is unreachable, see `SemanticSearch::with_fallback`.
+///
+/// Scores are exact cosine similarities, as from a Pinecone index with the cosine metric,
+/// and filters are evaluated by `filter::matches`. Every match is tagged `degraded`. Vectors
+/// upserted after `load` are missing until the index is loaded again.
+///
+/// # Example
+///
+/// ```rust
+/// let local = LocalIndex::load(&DatabaseObserver::new(db.clone())).await?;
+/// let search = SemanticSearch::builder().build().with_fallback(Arc::new(local));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LocalIndex {
+    records: Vec<VectorRecord>,
+}
+
+impl LocalIndex {
+    /// Loads every live record of `mirror`.
+    pub async fn load(mirror: &DatabaseObserver) -> Result<Self, ObserverError> {
+        Ok(LocalIndex::new(mirror.records().await?))
+    }
+
+    pub fn new(records: Vec<VectorRecord>) -> Self {
+        LocalIndex { records }
+    }
+
+    /// The `top_k` vectors of `namespace` (None for the default one) closest to `values`
+    /// whose metadata matches `filter`, best first.
+    pub fn query(
+        &self,
+        values: &[f32],
+        top_k: usize,
+        namespace: Option<&str>,
+        filter: Option<&Filter>,
+        include_values: bool,
+    ) -> Vec<Match> {
+        let mut scored: Vec<(f32, &VectorRecord)> = self
+            .records
+            .iter()
+            .filter(|record| record.namespace().as_deref() == namespace)
+            .filter(|record| {
+                filter.is_none_or(|filter| filter::matches(filter, record.metadata()))
+            })
+            .map(|record| (cosine_similarity(values, record.values()), record))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(top_k);
+
+        scored
+            .into_iter()
+            .map(|(score, record)| {
+                let values = if include_values { record.values().clone() } else { Vec::new() };
+                Match::degraded_match(record.id().clone(), score, values, record.metadata().clone())
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_query() {
+        let record = |id: &str, namespace: Option<&str>, values: Vec<f32>, source: &str| {
+            let metadata = HashMap::from([("source".to_string(), source.into())]);
+            VectorRecord::new(id.to_string(), namespace.map(str::to_string), values, metadata)
+        };
+        let index = LocalIndex::new(vec![
+            record("a", None, vec![1.0, 0.0], "faq"),
+            record("b", None, vec![0.6, 0.8], "faq"),
+            record("c", None, vec![0.9, 0.1], "blog"),
+            record("d", Some("other"), vec![1.0, 0.0], "faq"),
+        ]);
+
+        let ids = |matches: Vec<Match>| matches.iter().map(|m| m.id().clone()).collect::<Vec<_>>();
+        assert_eq!(ids(index.query(&[1.0, 0.0], 2, None, None, false)), ["a", "c"]);
+        let faq = HashMap::from([("source".to_string(), "faq".into())]);
+        let matches = index.query(&[1.0, 0.0], 5, None, Some(&faq), false);
+        assert!(matches.iter().all(|m| m.degraded() && m.values().is_empty()));
+        assert_eq!(ids(matches), ["a", "b"]);
+        assert_eq!(ids(index.query(&[1.0, 0.0], 5, Some("other"), None, true)), ["d"]);
+    }
+}
@@ -0,0 +1,101 @@
+/// Sum of the element-wise products of `a` and `b`. Like the other functions taking two
+/// vectors, it expects equal lengths and ignores extra elements of the longer one.
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Euclidean (L2) length of `v`.
+pub fn norm(v: &[f32]) -> f32 {
+    dot(v, v).sqrt()
+}
+
+/// Cosine of the angle between `a` and `b`, in [-1, 1]. Zero if either vector is zero.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot(a, b) / norms
+    }
+}
+
+/// Straight-line distance between `a` and `b`.
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// `v` scaled to unit length. A zero vector is returned unchanged.
+pub fn normalize(v: &[f32]) -> Vec<f32> {
+    let length = norm(v);
+    if length == 0.0 {
+        v.to_vec()
+    } else {
+        v.iter().map(|x| x / length).collect()
+    }
+}
+
+/// Element-wise mean of `vectors`, or `None` if there are none or their lengths differ.
+pub fn mean_pool(vectors: &[Vec<f32>]) -> Option<Vec<f32>> {
+    let dimension = vectors.first()?.len();
+    if vectors.iter().any(|v| v.len() != dimension) {
+        return None;
+    }
+
+    let mut mean = vec![0.0; dimension];
+    for v in vectors {
+        for (sum, x) in mean.iter_mut().zip(v) {
+            *sum += x;
+        }
+    }
+    let count = vectors.len() as f32;
+    mean.iter_mut().for_each(|sum| *sum /= count);
+
+    Some(mean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-6;
+
+    #[test]
+    fn test_dot_and_norm() {
+        assert_eq!(dot(&[1.0, 2.0, 3.0], &[4.0, -5.0, 6.0]), 12.0);
+        assert_eq!(norm(&[3.0, 4.0]), 5.0);
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]) - 1.0).abs() < EPSILON);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < EPSILON);
+        assert!((cosine_similarity(&[1.0, 1.0], &[-1.0, -1.0]) + 1.0).abs() < EPSILON);
+        assert!((cosine_similarity(&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]) - 0.974_631_85).abs() < EPSILON);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+    }
+
+    #[test]
+    fn test_euclidean_distance() {
+        assert_eq!(euclidean_distance(&[0.0, 0.0], &[3.0, 4.0]), 5.0);
+        assert_eq!(euclidean_distance(&[1.0, 2.0], &[1.0, 2.0]), 0.0);
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(&[3.0, 4.0]), vec![0.6, 0.8]);
+        assert!((norm(&normalize(&[1.0, 2.0, 3.0])) - 1.0).abs() < EPSILON);
+        assert_eq!(normalize(&[0.0, 0.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_mean_pool() {
+        let vectors = vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]];
+        assert_eq!(mean_pool(&vectors), Some(vec![3.0, 4.0]));
+        assert_eq!(mean_pool(&[]), None);
+        assert_eq!(mean_pool(&[vec![1.0], vec![1.0, 2.0]]), None);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use super::database::{upsert, Database};
+use super::pinecone_data::Metadata;
+
+/// Prefix of the keys namespace schemas are stored under.
+const SCHEMA_KEY_PREFIX: &str = "metadata_schema:";
+
+/// Type of a metadata value. Filters compare values by type, so a key holding numbers in
+/// some vectors and strings in others matches `$gt` filters only for some of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataType {
+    String,
+    Number,
+    Boolean,
+    List,
+}
+
+impl MetadataType {
+    /// Type of `value`; None for nulls and objects, which Pinecone does not store.
+    pub fn of(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(_) => Some(MetadataType::String),
+            Value::Number(_) => Some(MetadataType::Number),
+            Value::Bool(_) => Some(MetadataType::Boolean),
+            Value::Array(_) => Some(MetadataType::List),
+            Value::Null | Value::Object(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for MetadataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MetadataType::String => "string",
+            MetadataType::Number => "number",
+            MetadataType::Boolean => "boolean",
+            MetadataType::List => "list",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Metadata keys upserted into a namespace, with the type each was first seen with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataSchema {
+    fields: BTreeMap<String, MetadataType>,
+}
+
+/// A metadata value whose type differs from the one its key has in the namespace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaConflict {
+    key: String,
+    expected: MetadataType,
+    found: MetadataType,
+    value: Value,
+}
+
+impl fmt::Display for SchemaConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "metadata {:?} is a {} in the namespace but {} is a {}",
+            self.key, self.expected, self.value, self.found
+        )
+    }
+}
+
+/// Infers the metadata schema of each namespace from what is upserted into it, and checks
+/// new metadata against it.
+///
+/// Schemas are stored in `db` under "metadata_schema:{namespace}", the default namespace
+/// being "". A key keeps the type it was first upserted with; later values of another type
+/// are reported as `SchemaConflict`s, since they would silently fall out of filters written
+/// for the original type. Give it to an `IngestionPipeline` as `metadata_schema` to check
+/// every chunk before it is upserted.
+///
+/// # Example
+///
+/// ```rust
+/// let schemas = MetadataSchemaRegistry::new(Arc::new(SQLiteDB::new("schemas.db")?));
+/// let pipeline = IngestionPipeline::builder().metadata_schema(schemas.clone()).build();
+/// let report = pipeline.ingest(&documents).await?;
+/// for (id, conflict) in report.schema_conflicts() {
+///     println!("{}: {}", id, conflict);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetadataSchemaRegistry {
+    db: Arc<dyn Database>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl MetadataSchemaRegistry {
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        MetadataSchemaRegistry {
+            db,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Schema of `namespace`, empty if nothing was recorded for it.
+    pub async fn schema(&self, namespace: Option<&str>) -> Result<MetadataSchema, Box<dyn Error>> {
+        match self.db.read(&schema_key(namespace)).await {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(_) => Ok(MetadataSchema::default()),
+        }
+    }
+
+    /// Conflicts of `metadata` with the schema of `namespace`, without recording it.
+    pub async fn validate(
+        &self,
+        namespace: Option<&str>,
+        metadata: &Metadata,
+    ) -> Result<Vec<SchemaConflict>, Box<dyn Error>> {
+        Ok(self.schema(namespace).await?.conflicts(metadata))
+    }
+
+    /// Adds the keys of `metadata` new to `namespace` to its schema, and returns the
+    /// conflicts of the others.
+    pub async fn record(
+        &self,
+        namespace: Option<&str>,
+        metadata: &Metadata,
+    ) -> Result<Vec<SchemaConflict>, Box<dyn Error>> {
+        let _guard = self.lock.lock().await;
+        let mut schema = self.schema(namespace).await?;
+        let conflicts = schema.conflicts(metadata);
+
+        let before = schema.fields.len();
+        for (key, value) in metadata {
+            if let Some(found) = MetadataType::of(value) {
+                schema.fields.entry(key.clone()).or_insert(found);
+            }
+        }
+        if schema.fields.len() != before {
+            upsert(self.db.as_ref(), &schema_key(namespace), &serde_json::to_string(&schema)?).await?;
+        }
+        Ok(conflicts)
+    }
+}
+
+impl MetadataSchema {
+    pub fn fields(&self) -> &BTreeMap<String, MetadataType> {
+        &self.fields
+    }
+
+    /// Type of `key`, if it was recorded.
+    pub fn field(&self, key: &str) -> Option<MetadataType> {
+        self.fields.get(key).copied()
+    }
+
+    /// Values of `metadata` whose type differs from their key's, ordered by key.
+    pub fn conflicts(&self, metadata: &Metadata) -> Vec<SchemaConflict> {
+        let mut conflicts: Vec<SchemaConflict> = metadata
+            .iter()
+            .filter_map(|(key, value)| {
+                let expected = self.field(key)?;
+                let found = MetadataType::of(value)?;
+                (found != expected).then(|| SchemaConflict {
+                    key: key.clone(),
+                    expected,
+                    found,
+                    value: value.clone(),
+                })
+            })
+            .collect();
+        conflicts.sort_by(|a, b| a.key.cmp(&b.key));
+        conflicts
+    }
+}
+
+impl SchemaConflict {
+    pub fn key(&self) -> &String {
+        &self.key
+    }
+
+    pub fn expected(&self) -> MetadataType {
+        self.expected
+    }
+
+    pub fn found(&self) -> MetadataType {
+        self.found
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+}
+
+fn schema_key(namespace: Option<&str>) -> String {
+    format!("{}{}", SCHEMA_KEY_PREFIX, namespace.unwrap_or_default())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[tokio::test]
+    async fn test_metadata_schema() {
+        let registry = MetadataSchemaRegistry::new(Arc::new(SQLiteDB::new(":memory:").unwrap()));
+        let metadata = |value: Value| -> Metadata { serde_json::from_value(value).unwrap() };
+
+        let first = metadata(serde_json::json!({ "year": 2023, "draft": false, "source": "a.md" }));
+        assert!(registry.record(Some("docs"), &first).await.unwrap().is_empty());
+        let schema = registry.schema(Some("docs")).await.unwrap();
+        assert_eq!(schema.field("year"), Some(MetadataType::Number));
+        assert_eq!(schema.field("draft"), Some(MetadataType::Boolean));
+
+        let second = metadata(serde_json::json!({ "year": "FY2023", "draft": true, "author": "ada" }));
+        let conflicts = registry.record(Some("docs"), &second).await.unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].key().as_str(), conflicts[0].found()), ("year", MetadataType::String));
+        assert_eq!(registry.schema(Some("docs")).await.unwrap().field("year"), Some(MetadataType::Number));
+        assert_eq!(registry.schema(Some("docs")).await.unwrap().fields().len(), 4);
+
+        assert!(registry.validate(None, &second).await.unwrap().is_empty());
+    }
+}
@@ -1,6 +1,100 @@
+#[cfg(feature = "native")]
+pub mod loaders;
+pub mod math;
 pub mod openai_api;
+pub mod api_keys;
+pub mod budget;
+pub mod usage;
+pub mod models;
+pub mod fallback;
+pub mod profiles;
+pub mod circuit;
 pub mod pinecone_api;
 pub mod pinecone_data;
+pub mod typed_metadata;
+pub mod filter;
+#[cfg(feature = "native")]
+pub mod pipeline;
+#[cfg(feature = "native")]
+pub mod rag;
+#[cfg(feature = "native")]
+pub mod compare;
+#[cfg(feature = "native")]
+pub mod redact;
+pub mod rate_limit;
+#[cfg(feature = "native")]
+pub mod concurrency;
+#[cfg(feature = "native")]
+pub mod spool;
+#[cfg(feature = "native")]
+pub mod search;
+#[cfg(feature = "native")]
+pub mod local_index;
+#[cfg(feature = "native")]
+pub mod splitter;
+#[cfg(feature = "sqlite")]
 pub mod sql_lite;
+#[cfg(feature = "sled")]
+pub mod sled_db;
+#[cfg(feature = "dynamodb")]
+pub mod dynamodb;
+#[cfg(feature = "native")]
 pub mod planetscale;
 pub mod database;
+pub mod audit;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "native")]
+pub mod failures;
+#[cfg(feature = "native")]
+pub mod idempotency;
+#[cfg(feature = "native")]
+pub mod observer;
+#[cfg(feature = "native")]
+pub mod analytics;
+#[cfg(feature = "native")]
+pub mod metadata_schema;
+pub mod cache;
+pub mod context;
+#[cfg(feature = "native")]
+pub mod classify;
+#[cfg(feature = "native")]
+pub mod extract;
+#[cfg(feature = "native")]
+pub mod cluster;
+#[cfg(feature = "native")]
+pub mod projection;
+#[cfg(feature = "native")]
+pub mod watch;
+#[cfg(feature = "native")]
+pub mod jobs;
+#[cfg(feature = "native")]
+pub mod blocking;
+#[cfg(feature = "native")]
+pub mod health;
+#[cfg(feature = "native")]
+pub mod bench;
+#[cfg(feature = "native")]
+pub mod embedding_writer;
+#[cfg(feature = "native")]
+pub mod npy;
+#[cfg(feature = "native")]
+pub mod temp_namespace;
+#[cfg(feature = "native")]
+pub mod versions;
+#[cfg(feature = "native")]
+pub mod namespace_diff;
+#[cfg(feature = "native")]
+pub mod eval;
+#[cfg(feature = "native")]
+pub mod cutover;
+#[cfg(feature = "native")]
+pub mod parents;
+#[cfg(feature = "native")]
+pub mod boilerplate;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+#[cfg(feature = "test-util")]
+pub mod faults;
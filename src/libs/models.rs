@@ -0,0 +1,77 @@
+/// Static information about an OpenAI model.
+///
+/// # Fields
+///
+/// * `name`: The model id as accepted by the API (e.g., "gpt-3.5-turbo").
+/// * `context_window`: Total number of tokens (prompt + completion) the model accepts.
+/// * `max_output_tokens`: Optional upper bound on completion tokens, for models that cap output below the context window.
+/// * `input_price`: List price of prompt tokens, in USD per million.
+/// * `output_price`: List price of completion tokens, in USD per million.
+/// * `embedding_dimension`: Length of the vectors an embedding model returns; None for other models.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelInfo {
+    name: &'static str,
+    context_window: u32,
+    max_output_tokens: Option<u32>,
+    input_price: f64,
+    output_price: f64,
+    embedding_dimension: Option<u32>,
+}
+
+const MODELS: &[ModelInfo] = &[
+    ModelInfo { name: "gpt-3.5-turbo", context_window: 4096, max_output_tokens: None, input_price: 0.5, output_price: 1.5, embedding_dimension: None },
+    ModelInfo { name: "gpt-3.5-turbo-16k", context_window: 16384, max_output_tokens: None, input_price: 3.0, output_price: 4.0, embedding_dimension: None },
+    ModelInfo { name: "gpt-4", context_window: 8192, max_output_tokens: None, input_price: 30.0, output_price: 60.0, embedding_dimension: None },
+    ModelInfo { name: "gpt-4-32k", context_window: 32768, max_output_tokens: None, input_price: 60.0, output_price: 120.0, embedding_dimension: None },
+    ModelInfo { name: "gpt-4-turbo", context_window: 128000, max_output_tokens: Some(4096), input_price: 10.0, output_price: 30.0, embedding_dimension: None },
+    ModelInfo { name: "gpt-4o", context_window: 128000, max_output_tokens: Some(16384), input_price: 2.5, output_price: 10.0, embedding_dimension: None },
+    ModelInfo { name: "gpt-4o-mini", context_window: 128000, max_output_tokens: Some(16384), input_price: 0.15, output_price: 0.6, embedding_dimension: None },
+    ModelInfo { name: "gpt-3.5-turbo-instruct", context_window: 4096, max_output_tokens: None, input_price: 1.5, output_price: 2.0, embedding_dimension: None },
+    ModelInfo { name: "davinci-002", context_window: 16384, max_output_tokens: None, input_price: 2.0, output_price: 2.0, embedding_dimension: None },
+    ModelInfo { name: "babbage-002", context_window: 16384, max_output_tokens: None, input_price: 0.4, output_price: 0.4, embedding_dimension: None },
+    ModelInfo { name: "text-embedding-ada-002", context_window: 8191, max_output_tokens: None, input_price: 0.1, output_price: 0.0, embedding_dimension: Some(1536) },
+    ModelInfo { name: "text-embedding-3-small", context_window: 8191, max_output_tokens: None, input_price: 0.02, output_price: 0.0, embedding_dimension: Some(1536) },
+    ModelInfo { name: "text-embedding-3-large", context_window: 8191, max_output_tokens: None, input_price: 0.13, output_price: 0.0, embedding_dimension: Some(3072) },
+];
+
+/// Looks up a model by id.
+///
+/// Dated snapshots (e.g., "gpt-4-0613") resolve to the longest registered
+/// prefix, so "gpt-4-32k-0613" maps to "gpt-4-32k" rather than "gpt-4".
+pub fn lookup(model: &str) -> Option<&'static ModelInfo> {
+    MODELS
+        .iter()
+        .filter(|info| model == info.name || model.starts_with(&format!("{}-", info.name)))
+        .max_by_key(|info| info.name.len())
+}
+
+impl ModelInfo {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn context_window(&self) -> u32 {
+        self.context_window
+    }
+
+    pub fn max_output_tokens(&self) -> Option<u32> {
+        self.max_output_tokens
+    }
+
+    pub fn input_price(&self) -> f64 {
+        self.input_price
+    }
+
+    pub fn output_price(&self) -> f64 {
+        self.output_price
+    }
+
+    pub fn embedding_dimension(&self) -> Option<u32> {
+        self.embedding_dimension
+    }
+
+    /// List price in USD of a request with these token counts.
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64 * self.input_price + completion_tokens as f64 * self.output_price) / 1_000_000.0
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::pinecone_api::{PineconeApiError, PineconeClient};
+use super::pinecone_data::Metadata;
+
+/// Ids fetched per request when comparing metadata.
+const FETCH_BATCH_SIZE: usize = 100;
+
+/// Values of each differing metadata key in the first and the second namespace, None where
+/// the key is missing.
+pub type MetadataChanges = BTreeMap<String, (Option<Value>, Option<Value>)>;
+
+/// Differences between the vectors of two namespaces, see `diff_namespaces`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct NamespaceDiff {
+    a_count: usize,
+    b_count: usize,
+    only_in_a: Vec<String>,
+    only_in_b: Vec<String>,
+
+    /// Ids in both namespaces whose metadata differs, with the differing keys.
+    metadata_differences: BTreeMap<String, MetadataChanges>,
+}
+
+/// Compares the namespaces `a` and `b` ("" for the default one), e.g. the blue and green
+/// copies of an index migration: the ids only one of them holds, the metadata of the ids
+/// both hold, and their vector counts. Values are not compared.
+///
+/// Ids are listed page by page, which needs a serverless index, and the metadata of the
+/// shared ids is fetched in batches. Vectors upserted or deleted meanwhile may show up as
+/// differences.
+///
+/// # Example
+///
+/// ```rust
+/// let diff = diff_namespaces("docs-blue", "docs-green").await?;
+/// if !diff.is_identical() {
+///     println!("{} missing, {} changed", diff.only_in_a().len(), diff.metadata_differences().len());
+/// }
+/// ```
+pub async fn diff_namespaces(a: &str, b: &str) -> Result<NamespaceDiff, PineconeApiError> {
+    let (a, b) = (PineconeClient::namespace(a), PineconeClient::namespace(b));
+    let a_ids: BTreeSet<String> = a.list_ids("").await?.into_iter().collect();
+    let b_ids: BTreeSet<String> = b.list_ids("").await?.into_iter().collect();
+
+    let shared: Vec<String> = a_ids.intersection(&b_ids).cloned().collect();
+    let mut metadata_differences = BTreeMap::new();
+    for batch in shared.chunks(FETCH_BATCH_SIZE) {
+        let a_metadata = fetch_metadata(&a, batch).await?;
+        let b_metadata = fetch_metadata(&b, batch).await?;
+        for id in batch {
+            let changes = metadata_changes(
+                a_metadata.get(id).unwrap_or(&Metadata::new()),
+                b_metadata.get(id).unwrap_or(&Metadata::new()),
+            );
+            if !changes.is_empty() {
+                metadata_differences.insert(id.clone(), changes);
+            }
+        }
+    }
+
+    Ok(NamespaceDiff {
+        a_count: a_ids.len(),
+        b_count: b_ids.len(),
+        only_in_a: a_ids.difference(&b_ids).cloned().collect(),
+        only_in_b: b_ids.difference(&a_ids).cloned().collect(),
+        metadata_differences,
+    })
+}
+
+async fn fetch_metadata(client: &PineconeClient, ids: &[String]) -> Result<HashMap<String, Metadata>, PineconeApiError> {
+    let response = client.fetch(ids.to_vec()).await?;
+    Ok(response
+        .vectors()
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(id, vector)| (id, vector.metadata().clone()))
+        .collect())
+}
+
+/// Keys whose values differ between `a` and `b`, with both values.
+fn metadata_changes(a: &Metadata, b: &Metadata) -> MetadataChanges {
+    a.keys()
+        .chain(b.keys())
+        .filter(|key| a.get(*key) != b.get(*key))
+        .map(|key| (key.clone(), (a.get(key).cloned(), b.get(key).cloned())))
+        .collect()
+}
+
+impl NamespaceDiff {
+    pub fn a_count(&self) -> usize {
+        self.a_count
+    }
+
+    pub fn b_count(&self) -> usize {
+        self.b_count
+    }
+
+    /// Vectors `b` has more than `a`, negative if it has fewer.
+    pub fn count_delta(&self) -> i64 {
+        self.b_count as i64 - self.a_count as i64
+    }
+
+    pub fn only_in_a(&self) -> &Vec<String> {
+        &self.only_in_a
+    }
+
+    pub fn only_in_b(&self) -> &Vec<String> {
+        &self.only_in_b
+    }
+
+    pub fn metadata_differences(&self) -> &BTreeMap<String, MetadataChanges> {
+        &self.metadata_differences
+    }
+
+    /// Whether both namespaces hold the same ids with the same metadata.
+    pub fn is_identical(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.metadata_differences.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    #[cfg(feature = "test-util")]
+    use crate::libs::pipeline::IngestionPipeline;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::{sample_documents, FakeServices};
+
+    #[test]
+    fn test_metadata_changes() {
+        let a: Metadata = serde_json::from_value(json!({ "source": "faq", "year": 2023, "draft": true })).unwrap();
+        let b: Metadata = serde_json::from_value(json!({ "source": "faq", "year": 2024, "tags": ["new"] })).unwrap();
+
+        let changes = metadata_changes(&a, &b);
+        assert_eq!(changes.keys().collect::<Vec<_>>(), ["draft", "tags", "year"]);
+        assert_eq!(changes["year"], (Some(json!(2023)), Some(json!(2024))));
+        assert_eq!(changes["draft"], (Some(json!(true)), None));
+        assert!(metadata_changes(&a, &a).is_empty());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_diff_namespaces() {
+        let _fakes = FakeServices::start().await;
+        let documents = sample_documents();
+        IngestionPipeline::builder().namespace("blue".to_string()).build().ingest(&documents).await.unwrap();
+        IngestionPipeline::builder()
+            .namespace("green".to_string())
+            .default_metadata(HashMap::from([("green".to_string(), Metadata::from([("env".to_string(), json!("green"))]))]))
+            .build()
+            .ingest(&documents[1..])
+            .await
+            .unwrap();
+        let diff = diff_namespaces("blue", "green").await.unwrap();
+        assert_eq!((diff.a_count(), diff.b_count(), diff.count_delta()), (2, 1, -1));
+        assert_eq!(diff.only_in_a(), &["refunds#chunk0"]);
+        assert!(diff.only_in_b().is_empty());
+        assert_eq!(diff.metadata_differences()["shipping#chunk0"]["env"], (None, Some(json!("green"))));
+        assert!(diff_namespaces("blue", "blue").await.unwrap().is_identical());
+    }
+}
@@ -0,0 +1,273 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::pinecone_api::PineconeClient;
+
+/// Vectors fetched per request by `EmbeddingMatrix::from_index`.
+const FETCH_BATCH_SIZE: usize = 100;
+
+/// Name of the array in `.npz` archives, i.e. `np.load(path)["embeddings"]`.
+const NPZ_ARRAY_NAME: &str = "embeddings.npy";
+
+/// Embeddings as a row-major `float32` matrix with the id of each row, for export to the
+/// NumPy `.npy` and `.npz` formats.
+///
+/// `save` writes the matrix and an `ids.json` sidecar next to it ("embeddings.npy" gets
+/// "embeddings.ids.json"), holding the ids in row order:
+///
+/// ```python
+/// embeddings = np.load("embeddings.npy")
+/// ids = json.load(open("embeddings.ids.json"))
+/// ```
+///
+/// # Example
+///
+/// ```rust
+/// let matrix = EmbeddingMatrix::from_jsonl(BufReader::new(File::open("embeddings.jsonl")?))?;
+/// matrix.save(Path::new("embeddings.npz"))?;
+///
+/// let matrix = EmbeddingMatrix::from_index(&PineconeClient::namespace("docs"), "").await?;
+/// matrix.save(Path::new("docs.npy"))?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmbeddingMatrix {
+    ids: Vec<String>,
+    dimension: usize,
+    values: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingRecord {
+    id: String,
+    embedding: Vec<f32>,
+}
+
+impl EmbeddingMatrix {
+    pub fn new() -> Self {
+        EmbeddingMatrix::default()
+    }
+
+    /// Appends a row. Every row must have the dimension of the first.
+    pub fn push(&mut self, id: &str, embedding: &[f32]) -> io::Result<()> {
+        if self.ids.is_empty() {
+            self.dimension = embedding.len();
+        } else if embedding.len() != self.dimension {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has {} dimensions, expected {}", id, embedding.len(), self.dimension),
+            ));
+        }
+        self.ids.push(id.to_string());
+        self.values.extend_from_slice(embedding);
+        Ok(())
+    }
+
+    /// Reads the JSON Lines written by `EmbeddingWriter`.
+    pub fn from_jsonl<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut matrix = EmbeddingMatrix::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: EmbeddingRecord = serde_json::from_str(&line)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", n + 1, e)))?;
+            matrix.push(&record.id, &record.embedding)?;
+        }
+        Ok(matrix)
+    }
+
+    /// Fetches every vector of the handle's namespace whose id starts with `prefix`, in
+    /// listing order.
+    pub async fn from_index(client: &PineconeClient, prefix: &str) -> Result<Self, Box<dyn Error>> {
+        let ids = client.list_ids(prefix).await?;
+        let mut matrix = EmbeddingMatrix::new();
+        for batch in ids.chunks(FETCH_BATCH_SIZE) {
+            let response = client.fetch(batch.to_vec()).await?;
+            let Some(vectors) = response.vectors() else {
+                continue;
+            };
+            for id in batch {
+                // Listed but not fetched, e.g. deleted meanwhile.
+                if let Some(vector) = vectors.get(id) {
+                    matrix.push(id, vector.values())?;
+                }
+            }
+        }
+        Ok(matrix)
+    }
+
+    pub fn ids(&self) -> &Vec<String> {
+        &self.ids
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Row `n`.
+    pub fn row(&self, n: usize) -> Option<&[f32]> {
+        let start = n.checked_mul(self.dimension)?;
+        self.values.get(start..start + self.dimension).filter(|_| n < self.len())
+    }
+
+    /// Writes the matrix in the `.npy` format, version 1.0.
+    pub fn write_npy<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.npy_header())?;
+        let mut buffer = Vec::with_capacity(self.dimension * 4);
+        for row in self.values.chunks(self.dimension.max(1)) {
+            buffer.clear();
+            for value in row {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+            writer.write_all(&buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an uncompressed `.npz` archive holding the matrix as "embeddings".
+    pub fn write_npz<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut npy = Vec::with_capacity(self.values.len() * 4 + 128);
+        self.write_npy(&mut npy)?;
+        write_stored_zip(writer, NPZ_ARRAY_NAME, &npy)
+    }
+
+    /// Writes the matrix to `path`, as `.npz` if its extension is "npz" and `.npy`
+    /// otherwise, and the ids to the sidecar. Returns the sidecar's path.
+    pub fn save(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("npz") => self.write_npz(&mut writer)?,
+            _ => self.write_npy(&mut writer)?,
+        }
+        writer.flush()?;
+
+        let sidecar = ids_path(path);
+        serde_json::to_writer(BufWriter::new(File::create(&sidecar)?), &self.ids)?;
+        Ok(sidecar)
+    }
+
+    fn npy_header(&self) -> Vec<u8> {
+        let dict = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+            self.len(),
+            self.dimension
+        );
+        // Magic, version, and header length take 10 bytes; the data must start on a
+        // 64-byte boundary, and the header end with a newline.
+        let padding = (64 - (10 + dict.len() + 1) % 64) % 64;
+        let header_len = (dict.len() + padding + 1) as u16;
+
+        let mut header = b"\x93NUMPY\x01\x00".to_vec();
+        header.extend_from_slice(&header_len.to_le_bytes());
+        header.extend_from_slice(dict.as_bytes());
+        header.resize(header.len() + padding, b' ');
+        header.push(b'\n');
+        header
+    }
+}
+
+/// Path of the ids sidecar of the matrix at `path`: the same name with the extension
+/// replaced by "ids.json".
+pub fn ids_path(path: &Path) -> PathBuf {
+    path.with_extension("ids.json")
+}
+
+/// Writes a zip archive of one stored (uncompressed) file.
+fn write_stored_zip<W: Write>(writer: &mut W, name: &str, data: &[u8]) -> io::Result<()> {
+    let size = u32::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "npz archives over 4GB are not supported"))?;
+    let crc = crc32fast::hash(data);
+    let name = name.as_bytes();
+    // 1980-01-01 00:00, the earliest DOS date.
+    let (time, date) = (0u16, 0x21u16);
+
+    let mut local = Vec::with_capacity(30 + name.len());
+    local.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+    local.extend_from_slice(&20u16.to_le_bytes()); // version needed
+    local.extend_from_slice(&0u16.to_le_bytes()); // flags
+    local.extend_from_slice(&0u16.to_le_bytes()); // method: stored
+    local.extend_from_slice(&time.to_le_bytes());
+    local.extend_from_slice(&date.to_le_bytes());
+    local.extend_from_slice(&crc.to_le_bytes());
+    local.extend_from_slice(&size.to_le_bytes());
+    local.extend_from_slice(&size.to_le_bytes());
+    local.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    local.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+    local.extend_from_slice(name);
+    writer.write_all(&local)?;
+    writer.write_all(data)?;
+
+    let mut central = Vec::with_capacity(46 + name.len());
+    central.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+    central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+    central.extend_from_slice(&local[4..30]);
+    central.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    central.extend_from_slice(&0u16.to_le_bytes()); // disk number
+    central.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+    central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+    central.extend_from_slice(&0u32.to_le_bytes()); // offset of the local header
+    central.extend_from_slice(name);
+    writer.write_all(&central)?;
+
+    let central_offset = u32::try_from(local.len() + data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "npz archives over 4GB are not supported"))?;
+    let mut end = Vec::with_capacity(22);
+    end.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // disk number
+    end.extend_from_slice(&0u16.to_le_bytes()); // disk with the central directory
+    end.extend_from_slice(&1u16.to_le_bytes()); // entries on this disk
+    end.extend_from_slice(&1u16.to_le_bytes()); // entries
+    end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    end.extend_from_slice(&central_offset.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    writer.write_all(&end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npy() {
+        let jsonl = "{\"id\":\"a\",\"model\":\"m\",\"embedding\":[1.0,2.0]}\n\n{\"id\":\"b\",\"model\":\"m\",\"embedding\":[3.0,-4.5]}\n";
+        let mut matrix = EmbeddingMatrix::from_jsonl(jsonl.as_bytes()).unwrap();
+        assert_eq!((matrix.len(), matrix.dimension()), (2, 2));
+        assert_eq!(matrix.row(1), Some(&[3.0, -4.5][..]));
+        assert_eq!(matrix.row(2), None);
+        assert!(matrix.push("c", &[1.0]).is_err());
+
+        let mut npy = Vec::new();
+        matrix.write_npy(&mut npy).unwrap();
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        let header = std::str::from_utf8(&npy[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 2), }"));
+        assert!(header.ends_with('\n'));
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(npy.len(), 10 + header_len + 4 * 4);
+        assert_eq!(&npy[npy.len() - 4..], &(-4.5f32).to_le_bytes());
+
+        let mut npz = Vec::new();
+        matrix.write_npz(&mut npz).unwrap();
+        assert_eq!(&npz[..4], b"PK\x03\x04");
+        assert_eq!(&npz[30..30 + NPZ_ARRAY_NAME.len()], NPZ_ARRAY_NAME.as_bytes());
+        assert_eq!(&npz[30 + NPZ_ARRAY_NAME.len()..][..npy.len()], &npy[..]);
+        assert_eq!(&npz[npz.len() - 22..][..4], b"PK\x05\x06");
+
+        assert_eq!(ids_path(Path::new("out/embeddings.npz")), Path::new("out/embeddings.ids.json"));
+    }
+}
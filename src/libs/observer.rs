@@ -0,0 +1,434 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::database::{upsert, Database};
+use super::pinecone_data::{Filter, Metadata, PineconeRequest, Vector};
+
+/// Prefix of the keys `DatabaseObserver` mirrors vectors under.
+const VECTOR_KEY_PREFIX: &str = "vector:";
+
+#[derive(Debug, Error)]
+pub enum ObserverError {
+    #[error("DatabaseError: {0}")]
+    DatabaseError(String),
+
+    #[error("ObserverError: {0}")]
+    Other(String),
+}
+
+/// A vector as it was upserted into Pinecone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorRecord {
+    id: String,
+    namespace: Option<String>,
+    values: Vec<f32>,
+    metadata: Metadata,
+}
+
+/// What a query asked for and what it returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuerySummary {
+    namespace: Option<String>,
+    /// Query text, when the query was embedded from text.
+    query: Option<String>,
+    top_k: i64,
+    filter: Option<Filter>,
+    /// Ids of the returned matches, best first.
+    match_ids: Vec<String>,
+    /// Scores of the returned matches, in the order of `match_ids`.
+    #[serde(default)]
+    match_scores: Vec<f32>,
+    top_score: Option<f32>,
+    elapsed_ms: u64,
+}
+
+/// Hook notified of the vector operations of a pipeline, search, or watcher, for audit
+/// logging, metrics, or mirroring vectors into a database.
+///
+/// Every method defaults to doing nothing, so observers implement only the events they
+/// care about.
+///
+/// # Example
+///
+/// ```rust
+/// #[derive(Debug)]
+/// struct QueryLog;
+///
+/// #[async_trait]
+/// impl Observer for QueryLog {
+///     async fn on_query(&self, summary: &QuerySummary) -> Result<(), ObserverError> {
+///         println!("{:?} -> {} matches", summary.query(), summary.match_ids().len());
+///         Ok(())
+///     }
+/// }
+///
+/// let search = SemanticSearch::builder().observer(Arc::new(QueryLog)).build();
+/// ```
+#[async_trait]
+pub trait Observer: Debug + Send + Sync {
+    /// Called for each vector after Pinecone acknowledged its upsert.
+    async fn on_upsert(&self, _record: &VectorRecord) -> Result<(), ObserverError> {
+        Ok(())
+    }
+
+    /// Called with the ids of deleted vectors. Deletes by metadata filter are not
+    /// reported, as their ids are not known.
+    async fn on_delete(&self, _ids: &[String]) -> Result<(), ObserverError> {
+        Ok(())
+    }
+
+    /// Called after a query returned.
+    async fn on_query(&self, _summary: &QuerySummary) -> Result<(), ObserverError> {
+        Ok(())
+    }
+}
+
+/// Mirrors upserted vectors into a `Database`, as JSON `VectorRecord`s keyed by vector id,
+/// and removes them again when the vectors are deleted.
+///
+/// Over a database in soft-delete mode, deleted vectors are kept as tombstones: they can
+/// be audited with `tombstones`, brought back with `recover`, and hidden from searches
+/// with `SemanticSearch::mirror` until they are purged.
+///
+/// # Example
+///
+/// ```rust
+/// let mirror = DatabaseObserver::new(Arc::new(SQLiteDB::new("mirror.db")?));
+/// let pipeline = IngestionPipeline::builder().observer(Arc::new(mirror)).build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DatabaseObserver {
+    db: Arc<dyn Database>,
+}
+
+impl DatabaseObserver {
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        DatabaseObserver { db }
+    }
+
+    /// The mirrored record of vector `id`.
+    pub async fn get(&self, id: &str) -> Option<VectorRecord> {
+        let data = self.db.read(&vector_key(id)).await.ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    /// Every live record, e.g. to build a `LocalIndex`. The database must be able to list
+    /// its ids.
+    pub async fn records(&self) -> Result<Vec<VectorRecord>, ObserverError> {
+        let keys = self
+            .db
+            .ids(VECTOR_KEY_PREFIX)
+            .await
+            .map_err(|e| ObserverError::DatabaseError(e.to_string()))?;
+        let mut records = Vec::with_capacity(keys.len());
+        for key in keys {
+            let data = self.db.read(&key).await.map_err(|e| ObserverError::DatabaseError(e.to_string()))?;
+            records.push(serde_json::from_str(&data).map_err(|e| ObserverError::DatabaseError(e.to_string()))?);
+        }
+        Ok(records)
+    }
+
+    /// When vector `id` was deleted, if its record is a tombstone.
+    pub async fn deleted_at(&self, id: &str) -> Result<Option<u64>, ObserverError> {
+        self.db
+            .deleted_at(&vector_key(id))
+            .await
+            .map_err(|e| ObserverError::DatabaseError(e.to_string()))
+    }
+
+    /// Ids of the deleted vectors with their deletion times, oldest first.
+    pub async fn tombstones(&self) -> Result<Vec<(String, u64)>, ObserverError> {
+        let tombstones = self
+            .db
+            .tombstones()
+            .await
+            .map_err(|e| ObserverError::DatabaseError(e.to_string()))?;
+        Ok(tombstones
+            .into_iter()
+            .filter_map(|(key, deleted_at)| Some((key.strip_prefix(VECTOR_KEY_PREFIX)?.to_string(), deleted_at)))
+            .collect())
+    }
+
+    /// Restores the record of the deleted vector `id` and upserts the vector into Pinecone
+    /// again from it.
+    pub async fn recover(&self, id: &str) -> Result<VectorRecord, ObserverError> {
+        self.db
+            .restore(&vector_key(id))
+            .await
+            .map_err(|e| ObserverError::DatabaseError(e.to_string()))?;
+        let record = self
+            .get(id)
+            .await
+            .ok_or_else(|| ObserverError::DatabaseError(format!("record of {} is unreadable", id)))?;
+
+        let vector = Vector::builder()
+            .id(record.id.clone())
+            .values(record.values.clone())
+            .metadata(record.metadata.clone())
+            .build();
+        let request = match &record.namespace {
+            Some(namespace) => PineconeRequest::builder().vectors(vec![vector]).namespace(namespace.clone()).build(),
+            None => PineconeRequest::builder().vectors(vec![vector]).build(),
+        };
+        request.upsert().await.map_err(|e| ObserverError::Other(e.to_string()))?;
+        Ok(record)
+    }
+
+    /// Removes the record of vector `id` for good, e.g. once a deletion has been audited.
+    pub async fn purge(&self, id: &str) -> Result<(), ObserverError> {
+        self.db
+            .purge(&vector_key(id))
+            .await
+            .map_err(|e| ObserverError::DatabaseError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Observer for DatabaseObserver {
+    async fn on_upsert(&self, record: &VectorRecord) -> Result<(), ObserverError> {
+        let data = serde_json::to_string(record).map_err(|e| ObserverError::DatabaseError(e.to_string()))?;
+        upsert(self.db.as_ref(), &vector_key(&record.id), &data)
+            .await
+            .map_err(|e| ObserverError::DatabaseError(e.to_string()))
+    }
+
+    async fn on_delete(&self, ids: &[String]) -> Result<(), ObserverError> {
+        for id in ids {
+            self.db
+                .delete(&vector_key(id))
+                .await
+                .map_err(|e| ObserverError::DatabaseError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Fans events out to several observers, e.g. a database mirror, metrics, and an audit log
+/// at once.
+///
+/// Observers are notified concurrently. One failing does not stop the others or the
+/// operation: its error is logged and counted in `failures`, and the set reports success.
+///
+/// # Example
+///
+/// ```rust
+/// let observers = ObserverSet::new()
+///     .with(Arc::new(DatabaseObserver::new(db)))
+///     .with(Arc::new(QueryLog));
+/// let pipeline = IngestionPipeline::builder().observer(Arc::new(observers)).build();
+/// ```
+#[derive(Debug, Default)]
+pub struct ObserverSet {
+    observers: Vec<Arc<dyn Observer>>,
+    failures: AtomicUsize,
+}
+
+impl ObserverSet {
+    pub fn new() -> Self {
+        ObserverSet::default()
+    }
+
+    /// Adds `observer` to the set.
+    pub fn with(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+
+    /// Number of observer calls that failed so far.
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+
+    fn isolate(&self, event: &str, results: Vec<Result<(), ObserverError>>) {
+        for (observer, result) in self.observers.iter().zip(results) {
+            if let Err(e) = result {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Observer {:?} failed on {}: {}", observer, event, e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Observer for ObserverSet {
+    async fn on_upsert(&self, record: &VectorRecord) -> Result<(), ObserverError> {
+        let results = join_all(self.observers.iter().map(|observer| observer.on_upsert(record))).await;
+        self.isolate("upsert", results);
+        Ok(())
+    }
+
+    async fn on_delete(&self, ids: &[String]) -> Result<(), ObserverError> {
+        let results = join_all(self.observers.iter().map(|observer| observer.on_delete(ids))).await;
+        self.isolate("delete", results);
+        Ok(())
+    }
+
+    async fn on_query(&self, summary: &QuerySummary) -> Result<(), ObserverError> {
+        let results = join_all(self.observers.iter().map(|observer| observer.on_query(summary))).await;
+        self.isolate("query", results);
+        Ok(())
+    }
+}
+
+fn vector_key(id: &str) -> String {
+    format!("{}{}", VECTOR_KEY_PREFIX, id)
+}
+
+impl VectorRecord {
+    pub fn new(id: String, namespace: Option<String>, values: Vec<f32>, metadata: Metadata) -> Self {
+        VectorRecord {
+            id,
+            namespace,
+            values,
+            metadata,
+        }
+    }
+
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn namespace(&self) -> &Option<String> {
+        &self.namespace
+    }
+
+    pub fn values(&self) -> &Vec<f32> {
+        &self.values
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl QuerySummary {
+    pub fn new(
+        namespace: Option<String>,
+        query: Option<String>,
+        top_k: i64,
+        filter: Option<Filter>,
+        match_ids: Vec<String>,
+        top_score: Option<f32>,
+        elapsed_ms: u64,
+    ) -> Self {
+        QuerySummary {
+            namespace,
+            query,
+            top_k,
+            filter,
+            match_ids,
+            match_scores: Vec::new(),
+            top_score,
+            elapsed_ms,
+        }
+    }
+
+    /// Adds the scores of the matches, in the order of their ids.
+    pub fn with_scores(mut self, match_scores: Vec<f32>) -> Self {
+        self.match_scores = match_scores;
+        self
+    }
+
+    pub fn namespace(&self) -> &Option<String> {
+        &self.namespace
+    }
+
+    pub fn query(&self) -> &Option<String> {
+        &self.query
+    }
+
+    pub fn top_k(&self) -> i64 {
+        self.top_k
+    }
+
+    pub fn filter(&self) -> &Option<Filter> {
+        &self.filter
+    }
+
+    pub fn match_ids(&self) -> &Vec<String> {
+        &self.match_ids
+    }
+
+    pub fn match_scores(&self) -> &Vec<f32> {
+        &self.match_scores
+    }
+
+    pub fn top_score(&self) -> Option<f32> {
+        self.top_score
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        self.elapsed_ms
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[tokio::test]
+    async fn test_database_observer() {
+        let observer = DatabaseObserver::new(Arc::new(SQLiteDB::new(":memory:").unwrap()));
+        let record = VectorRecord::new("doc-0".to_string(), None, vec![0.5, 0.5], Metadata::new());
+
+        observer.on_upsert(&record).await.unwrap();
+        observer.on_upsert(&record).await.unwrap();
+        assert_eq!(observer.get("doc-0").await.unwrap().values(), &vec![0.5, 0.5]);
+
+        observer.on_delete(&["doc-0".to_string()]).await.unwrap();
+        assert!(observer.get("doc-0").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_soft_deleted_records() {
+        let observer = DatabaseObserver::new(Arc::new(SQLiteDB::new(":memory:").unwrap().with_soft_delete()));
+        let record = VectorRecord::new("doc-0".to_string(), None, vec![0.5, 0.5], Metadata::new());
+
+        observer.on_upsert(&record).await.unwrap();
+        observer.on_delete(&["doc-0".to_string()]).await.unwrap();
+        assert!(observer.get("doc-0").await.is_none());
+        assert!(observer.deleted_at("doc-0").await.unwrap().is_some());
+        assert_eq!(observer.tombstones().await.unwrap()[0].0, "doc-0");
+
+        // Upserting the vector again revives its record.
+        observer.on_upsert(&record).await.unwrap();
+        assert!(observer.get("doc-0").await.is_some());
+        assert!(observer.tombstones().await.unwrap().is_empty());
+    }
+
+    #[derive(Debug)]
+    struct Failing;
+
+    #[async_trait]
+    impl Observer for Failing {
+        async fn on_upsert(&self, _record: &VectorRecord) -> Result<(), ObserverError> {
+            Err(ObserverError::Other("unavailable".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observer_set_isolates_failures() {
+        let mirror = Arc::new(DatabaseObserver::new(Arc::new(SQLiteDB::new(":memory:").unwrap())));
+        let observers = ObserverSet::new().with(Arc::new(Failing)).with(mirror.clone());
+        let record = VectorRecord::new("doc-0".to_string(), None, vec![1.0], Metadata::new());
+
+        assert!(observers.on_upsert(&record).await.is_ok());
+        assert_eq!(observers.failures(), 1);
+        assert!(mirror.get("doc-0").await.is_some());
+    }
+}
@@ -1,31 +1,148 @@
 use lazy_static::lazy_static;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::{env, error::Error, sync::Arc};
+use std::{env, error::Error, sync::{Arc, OnceLock, RwLock}};
 use reqwest::header::{HeaderMap, HeaderValue};
-use tiktoken_rs::cl100k_base;
+use reqwest::StatusCode;
+use tiktoken_rs::{cl100k_base, CoreBPE};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use tracing::Instrument;
 use typed_builder::TypedBuilder;
 
+use super::api_keys::KeyPool;
+use super::budget::{BudgetError, TokenBudget};
+use super::cache::ChatCache;
+use super::context;
+use super::fallback::{should_fall_back, ModelChain};
+use super::models;
+use super::profiles;
+use super::usage::UsageLedger;
+use super::rate_limit::{rate_limiter, Priority};
+
+static API_KEY: OnceLock<String> = OnceLock::new();
+static KEY_POOL: OnceLock<Arc<KeyPool>> = OnceLock::new();
+static BUDGET: OnceLock<Arc<TokenBudget>> = OnceLock::new();
+static USAGE_LEDGER: OnceLock<Arc<UsageLedger>> = OnceLock::new();
+static MODEL_CHAIN: OnceLock<Arc<ModelChain>> = OnceLock::new();
+static BASE_URL: RwLock<Option<String>> = RwLock::new(None);
+
 lazy_static! {
     static ref CLIENT: Arc<Client> = {
-        dotenv::dotenv().ok();
-        let api_key = env::var("OPENAI_API_KEY").expect("Failed to locate api key.");
-
         let client = Client::builder()
-            .default_headers(headers(api_key))
+            .default_headers(headers())
             .build()
             .expect("Failed to create client connection.");
 
         Arc::new(client)
     };
+    static ref DEFAULT_API_KEY: String = API_KEY.get().cloned().unwrap_or_else(|| {
+        dotenv::dotenv().ok();
+        env::var("OPENAI_API_KEY").expect("Failed to locate api key.")
+    });
+    static ref BPE: CoreBPE = cl100k_base().expect("Failed to load cl100k_base encoder.");
+}
+
+/// Sets the OpenAI API key instead of reading `OPENAI_API_KEY` from the environment, e.g. on
+/// wasm32 where there is no process environment. Takes effect only if called before the
+/// first request; returns false if a key was already set.
+pub fn set_api_key(api_key: String) -> bool {
+    API_KEY.set(api_key).is_ok()
+}
+
+/// Sends every request with a key from `pool` instead of the single API key, and reports
+/// rate-limited requests back to it. Returns false if a pool was already set.
+pub fn set_key_pool(pool: Arc<KeyPool>) -> bool {
+    KEY_POOL.set(pool).is_ok()
+}
+
+/// Enforces `budget` on every request from now on. Returns false if a budget was already
+/// set.
+pub fn set_budget(budget: Arc<TokenBudget>) -> bool {
+    BUDGET.set(budget).is_ok()
+}
+
+/// Adds the usage of every request from now on to `ledger`. Returns false if a ledger was
+/// already set.
+pub fn set_usage_ledger(ledger: Arc<UsageLedger>) -> bool {
+    USAGE_LEDGER.set(ledger).is_ok()
+}
+
+/// Falls back through `chain` when a chat completion of one of its models fails, see
+/// `ModelChain`. Returns false if a chain was already set.
+pub fn set_model_chain(chain: Arc<ModelChain>) -> bool {
+    MODEL_CHAIN.set(chain).is_ok()
+}
+
+/// Waits for, or refuses, a request of `tokens` estimated tokens sent with `api_key`.
+async fn reserve_budget(api_key: &str, tokens: u32) -> Result<(), BudgetError> {
+    match BUDGET.get() {
+        Some(budget) => budget.reserve(api_key, tokens).await,
+        None => Ok(()),
+    }
+}
+
+/// Counts the tokens a request to `model` sent with `api_key` used against its budget and
+/// in the usage ledger.
+async fn record_usage(api_key: &str, model: &str, usage: &Usage) {
+    if let Some(budget) = BUDGET.get() {
+        if let Err(e) = budget.record(api_key, usage.total_tokens).await {
+            tracing::warn!("Failed to record token usage: {}", e);
+        }
+    }
+    if let Some(ledger) = USAGE_LEDGER.get() {
+        let completion_tokens = usage.completion_tokens.unwrap_or_default();
+        if let Err(e) = ledger.record(model, u64::from(usage.prompt_tokens), u64::from(completion_tokens)).await {
+            tracing::warn!("Failed to record token usage: {}", e);
+        }
+    }
+}
+
+/// Sends requests to `base_url` (e.g. "http://localhost:8080/v1") instead of
+/// "https://api.openai.com/v1", such as a proxy or the fakes in `test_util`.
+pub fn set_base_url(base_url: &str) {
+    *BASE_URL.write().unwrap_or_else(|e| e.into_inner()) = Some(base_url.trim_end_matches('/').to_string());
+}
+
+fn url(path: &str) -> String {
+    match BASE_URL.read().unwrap_or_else(|e| e.into_inner()).as_deref() {
+        Some(base_url) => format!("{}/{}", base_url, path),
+        None => format!("https://api.openai.com/v1/{}", path),
+    }
+}
+
+/// Key for the next request: from the key pool if one is set, else the single API key.
+fn api_key() -> String {
+    match KEY_POOL.get() {
+        Some(pool) => pool.select(),
+        None => DEFAULT_API_KEY.clone(),
+    }
 }
 
-fn headers(api_key: String) -> HeaderMap {
+/// Tells the key pool when OpenAI rate limited a request sent with `api_key`.
+fn note_throttling(api_key: &str, response: &reqwest::Response) {
+    if let (StatusCode::TOO_MANY_REQUESTS, Some(pool)) = (response.status(), KEY_POOL.get()) {
+        pool.mark_throttled(api_key);
+    }
+}
+
+/// Tokens reserved on top of the counted prompt when deriving `max_tokens`, covering
+/// the per-message framing the chat format adds around each message.
+const AUTO_MAX_TOKENS_MARGIN: u32 = 64;
+
+/// JSON body of `request`, with the current `RequestContext`'s user, or else the current
+/// trace, if it sets none.
+fn body<T: Serialize>(request: &T, user: &Option<String>) -> Result<serde_json::Value, serde_json::Error> {
+    let mut body = serde_json::to_value(request)?;
+    if let (None, Some(user)) = (user, context::current_user().or_else(context::trace_user)) {
+        body["user"] = user.into();
+    }
+    Ok(body)
+}
+
+fn headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
-    headers.insert(
-        reqwest::header::AUTHORIZATION,
-        HeaderValue::from_str(format!("Bearer {}", api_key).as_str()).unwrap(),
-    );
     headers.insert(
         reqwest::header::CONTENT_TYPE,
         HeaderValue::from_str("application/json").unwrap(),
@@ -33,6 +150,31 @@ fn headers(api_key: String) -> HeaderMap {
     headers
 }
 
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+/// Ids of the models the API key has access to.
+pub async fn list_models() -> Result<Vec<String>, Box<dyn Error>> {
+    let api_key = api_key();
+    let response = CLIENT
+        .get(url("models"))
+        .bearer_auth(&api_key)
+        .send()
+        .instrument(context::span("openai.models"))
+        .await?;
+    note_throttling(&api_key, &response);
+
+    let models: ModelList = response.error_for_status()?.json().await?;
+    Ok(models.data.into_iter().map(|model| model.id).collect())
+}
+
 /// Represents a request body for OpenAI's Embedding API.
 ///
 /// # Fields
@@ -40,6 +182,7 @@ fn headers(api_key: String) -> HeaderMap {
 /// * `input`: Required. Input text to get embeddings for, encoded as a `String` or array of tokens.
 /// * `model`: Required. ID of the model to use. Use the List models API to see available models or refer to the Model overview for descriptions.
 /// * `user`: Optional. A unique identifier representing your end-user, which can help OpenAI monitor and detect abuse.
+/// * `priority`: Optional. Rate-limit priority; not sent to the API. Defaults to `Priority::Interactive`.
 ///
 /// # Example
 ///
@@ -58,6 +201,10 @@ pub struct OpenAIEmbeddingRequest {
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     user: Option<String>,
+
+    #[builder(default)]
+    #[serde(skip)]
+    priority: Priority,
 }
 
 impl OpenAIEmbeddingRequest {
@@ -69,16 +216,30 @@ impl OpenAIEmbeddingRequest {
 
     pub async fn send(&self) -> Result<OpenAIEmbeddingResponse, Box<dyn Error>> {
         self.validate()?;
+        let tokens = get_tokens(&self.input)?.len() as u32;
+        rate_limiter().acquire(&self.model, tokens, self.priority).await;
 
-        let response: OpenAIEmbeddingResponse = CLIENT
-            .post("https://api.openai.com/v1/embeddings")
-            .json(self)
+        let span = context::span("openai.embeddings");
+        let api_key = api_key();
+        reserve_budget(&api_key, tokens).await?;
+        let response = CLIENT
+            .post(url("embeddings"))
+            .bearer_auth(&api_key)
+            .json(&body(self, &self.user)?)
             .send()
+            .instrument(span.clone())
             .await
-            .map_err(|_| "Failed to send request.")?
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+        note_throttling(&api_key, &response);
+
+        // Statuses are kept in the error, so callers can tell rate limiting apart.
+        let response: OpenAIEmbeddingResponse = response
+            .error_for_status()?
             .json()
+            .instrument(span)
             .await
             .map_err(|_| "Failed to deserialize response.")?;
+        record_usage(&api_key, &response.model, &response.usage).await;
 
         Ok(response)
     }
@@ -114,7 +275,13 @@ pub struct Embedding {
 /// * `presence_penalty`: Optional. A number between -2.0 and 2.0. Positive values penalize new tokens based on whether they appear in the text so far.
 /// * `frequency_penalty`: Optional. A number between -2.0 and 2.0. Positive values penalize new tokens based on their existing frequency in the text so far.
 /// * `logit_bias`: Optional. A map to modify the likelihood of specified tokens appearing in the completion. Maps tokens to associated bias values from -100 to 100.
+/// * `logprobs`: Optional. If set, the log probability of each output token is returned, see `OpenAIResponse::best_choice_by_logprob`.
+/// * `top_logprobs`: Optional. Number of most likely alternatives (0 to 20) returned per token; requires `logprobs`.
 /// * `user`: Optional. A unique identifier representing the end-user, which can help OpenAI monitor and detect abuse.
+/// * `response_format`: Optional. Set to `ResponseFormat::json_object()` to enable JSON mode.
+/// * `priority`: Optional. Rate-limit priority; not sent to the API. Defaults to `Priority::Interactive`.
+/// * `profile`: Optional. Name of a `GenerationProfile` supplying the settings this request
+///   leaves unset, e.g. "deterministic"; not sent to the API.
 ///
 /// # Example
 ///
@@ -130,7 +297,7 @@ pub struct Embedding {
 ///     .build()
 ///     .unwrap();
 /// ```
-#[derive(Debug, Serialize, Deserialize, TypedBuilder)]
+#[derive(Debug, Clone, Serialize, Deserialize, TypedBuilder)]
 pub struct OpenAIRequest {
     model: String,
     messages: Vec<Message>,
@@ -171,54 +338,540 @@ pub struct OpenAIRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     logit_bias: Option<std::collections::HashMap<String, f64>>,
 
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<u32>,
+
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     user: Option<String>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+
+    #[builder(default)]
+    #[serde(skip)]
+    priority: Priority,
+
+    #[builder(setter(into, strip_option), default)]
+    #[serde(skip)]
+    profile: Option<String>,
+}
+
+/// Output format of a chat completion. `json_object` enables JSON mode, in which the model
+/// is constrained to emit valid JSON (the prompt must still ask for JSON). `json_schema`
+/// constrains it to JSON matching a schema.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    format_type: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json_schema: Option<serde_json::Value>,
+}
+
+impl ResponseFormat {
+    pub fn json_object() -> Self {
+        ResponseFormat {
+            format_type: "json_object".to_string(),
+            json_schema: None,
+        }
+    }
+
+    /// Structured outputs: the reply is JSON valid against `schema`, in the strict subset of
+    /// JSON Schema the API supports (every property required, no additional properties).
+    pub fn json_schema(name: &str, schema: serde_json::Value) -> Self {
+        ResponseFormat {
+            format_type: "json_schema".to_string(),
+            json_schema: Some(serde_json::json!({ "name": name, "schema": schema, "strict": true })),
+        }
+    }
+
+    pub fn text() -> Self {
+        ResponseFormat {
+            format_type: "text".to_string(),
+            json_schema: None,
+        }
+    }
 }
 
 impl OpenAIRequest {
+    /// This request with the settings of its profile filled in, or itself if it has none.
+    fn with_profile(&self) -> Result<Cow<'_, OpenAIRequest>, OpenAIApiError> {
+        let Some(name) = &self.profile else {
+            return Ok(Cow::Borrowed(self));
+        };
+        let profile = profiles::lookup(name).ok_or(OpenAIApiError::UnknownProfile)?;
+
+        let mut request = self.clone();
+        request.profile = None;
+        request.temperature = request.temperature.or(profile.temperature());
+        request.top_p = request.top_p.or(profile.top_p());
+        request.presence_penalty = request.presence_penalty.or(profile.presence_penalty());
+        request.frequency_penalty = request.frequency_penalty.or(profile.frequency_penalty());
+        request.max_tokens = request.max_tokens.or(profile.max_tokens());
+        request.response_format = request.response_format.or_else(|| profile.response_format().clone());
+        Ok(Cow::Owned(request))
+    }
+
     pub fn validate(&self) -> Result<(), OpenAIApiError> {
-        match (
-            self.temperature.unwrap_or(0.0),
-            self.top_p.unwrap_or(0.0),
-            self.presence_penalty.unwrap_or(0.0),
-            self.frequency_penalty.unwrap_or(0.0),
-        ) {
-            (temp, _, _, _) if temp < 0.0 || temp > 2.0 => Err(OpenAIApiError::InvalidTemperature),
-            (_, p, _, _) if p < 0.0 || p > 1.0 => Err(OpenAIApiError::InvalidTopP),
-            (_, _, presence_penalty, _) if presence_penalty < -2.0 || presence_penalty > 2.0 => {
-                Err(OpenAIApiError::InvalidPresencePenalty)
+        let request = self.with_profile()?;
+        let request = request.as_ref();
+        validate_sampling(
+            request.temperature,
+            request.top_p,
+            request.presence_penalty,
+            request.frequency_penalty,
+            &request.stop,
+        )
+    }
+
+    /// Sets `max_tokens` to whatever is left of `model`'s context window after the prompt.
+    ///
+    /// The prompt is counted with the same tokenizer as `Message::get_tokens`, and a small
+    /// safety margin is kept back. Returns `OpenAIApiError::UnknownModel` if the model is not
+    /// in the registry and `OpenAIApiError::ContextLengthExceeded` if the prompt alone does
+    /// not fit.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let request = OpenAIRequest::builder()
+    ///     .model("gpt-3.5-turbo".to_string())
+    ///     .messages(messages)
+    ///     .build()
+    ///     .with_auto_max_tokens("gpt-3.5-turbo")?;
+    /// ```
+    pub fn with_auto_max_tokens(mut self, model: &str) -> Result<Self, Box<dyn Error>> {
+        let info = models::lookup(model).ok_or(OpenAIApiError::UnknownModel)?;
+        let prompt_tokens = self.prompt_tokens()? as u32;
+
+        let available = info
+            .context_window()
+            .checked_sub(prompt_tokens + AUTO_MAX_TOKENS_MARGIN)
+            .filter(|tokens| *tokens > 0)
+            .ok_or(OpenAIApiError::ContextLengthExceeded)?;
+
+        self.max_tokens = Some(
+            info.max_output_tokens()
+                .map_or(available, |cap| available.min(cap)),
+        );
+        Ok(self)
+    }
+
+    /// Number of tokens the messages of this request take up.
+    pub fn prompt_tokens(&self) -> Result<usize, serde_json::Error> {
+        self.messages
+            .iter()
+            .map(|msg| msg.get_tokens().map(|tokens| tokens.len()))
+            .sum()
+    }
+
+    /// Sends the request, falling back to later models of the `ModelChain` if one is set
+    /// and the model fails. `OpenAIResponse::model` tells which model answered.
+    pub async fn send(&self) -> Result<OpenAIResponse, Box<dyn Error>> {
+        let Some(chain) = MODEL_CHAIN.get() else {
+            return self.send_to(&self.model).await;
+        };
+        let candidates = chain.candidates(&self.model);
+        for (model, next) in candidates.iter().zip(candidates.iter().skip(1)) {
+            match self.send_to(model).await {
+                Ok(response) => {
+                    chain.record(model);
+                    return Ok(response);
+                }
+                Err(e) if should_fall_back(e.as_ref()) => {
+                    tracing::warn!("{} failed, falling back to {}: {}", model, next, e);
+                }
+                Err(e) => return Err(e),
             }
-            (_, _, _, frequency_penalty) if frequency_penalty < -2.0 || frequency_penalty > 2.0 => {
-                Err(OpenAIApiError::InvalidFrequencyPenalty)
+        }
+
+        let last = candidates.last().unwrap_or(&self.model);
+        let response = self.send_to(last).await?;
+        chain.record(last);
+        Ok(response)
+    }
+
+    /// Sends the request to `model` instead of its own model.
+    async fn send_to(&self, model: &str) -> Result<OpenAIResponse, Box<dyn Error>> {
+        let mut request = self.with_profile()?;
+        if request.model != model {
+            request.to_mut().model = model.to_string();
+        }
+        let request = request.as_ref();
+        request.validate()?;
+        // OpenAI counts `max_tokens` against the token budget up front, so reserve it too.
+        let tokens = request.prompt_tokens()? as u32 + request.max_tokens.unwrap_or(0);
+        rate_limiter().acquire(&request.model, tokens, request.priority).await;
+
+        let span = context::span("openai.chat");
+        let api_key = api_key();
+        reserve_budget(&api_key, tokens).await?;
+        let send = CLIENT
+            .post(url("chat/completions"))
+            .bearer_auth(&api_key)
+            .json(&body(request, &request.user)?)
+            .send()
+            .instrument(span.clone());
+        let response = context::traced("openai.chat", send)
+            .await
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+        note_throttling(&api_key, &response);
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(OpenAIApiError::Status(status.as_u16(), message).into());
+        }
+
+        let response: OpenAIResponse = response
+            .json()
+            .instrument(span)
+            .await
+            .map_err(|_| "Failed to deserialize response.")?;
+        record_usage(&api_key, &response.model, &response.usage).await;
+
+        Ok(response)
+    }
+
+    /// Like `send`, but when the answer is cut off by the token limit, asks the model to
+    /// continue it, up to `max_continuations` times, and joins the parts into one message.
+    ///
+    /// Each continuation re-sends the conversation with the partial answer as an assistant
+    /// message, so its prompt grows by the answer so far. The returned response has the
+    /// joined message as its only choice, the finish reason of the last part (still `Length`
+    /// if the budget ran out), and the usage of all parts. Only the first choice is
+    /// continued, so leave `n` unset.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let response = request.send_with_continuation(3).await?;
+    /// if response.choices()[0].finish_reason().is_truncated() {
+    ///     println!("summary still incomplete after 3 continuations");
+    /// }
+    /// ```
+    pub async fn send_with_continuation(&self, max_continuations: usize) -> Result<OpenAIResponse, Box<dyn Error>> {
+        let mut response = self.send().await?;
+        response.choices.truncate(1);
+
+        for _ in 0..max_continuations {
+            let Some(choice) = response.choices.first() else {
+                break;
+            };
+            if !choice.finish_reason.is_truncated() {
+                break;
             }
-            _ => Ok(()),
+
+            let mut request = self.clone();
+            request.n = None;
+            request.messages.push(Message {
+                role: "assistant".to_string(),
+                content: choice.message.content.clone(),
+            });
+            request.messages.push(Message {
+                role: "user".to_string(),
+                content: CONTINUATION_PROMPT.to_string(),
+            });
+
+            let next = request.send().await?;
+            response = response.continued_by(next);
         }
+
+        Ok(response)
     }
 
-    pub async fn send(&self) -> Result<OpenAIResponse, Box<dyn Error>> {
+    /// Like `send`, but answers deterministic requests (temperature 0) from `cache` when
+    /// an identical request was sent before, and stores fresh responses in it.
+    pub async fn send_cached(&self, cache: &ChatCache) -> Result<OpenAIResponse, Box<dyn Error>> {
+        if let Some(response) = cache.get(self).await {
+            return Ok(response);
+        }
+
+        let response = self.send().await?;
+        cache.put(self, &response).await?;
+        Ok(response)
+    }
+
+    /// Hash identifying this request for the response cache, or `None` if its output is not
+    /// deterministic (temperature is not 0, or the response is streamed).
+    ///
+    /// Message roles and surrounding whitespace are normalized and `user` is ignored, so
+    /// requests that only differ in those share a key. Profiles are resolved first, so a
+    /// request shares its key with one spelling out its profile's settings.
+    pub fn cache_key(&self) -> Option<String> {
+        let request = self.with_profile().ok()?;
+        let request = request.as_ref();
+        if request.temperature != Some(0.0) || request.stream == Some(true) {
+            return None;
+        }
+
+        Some(format!("{}:{}", request.model, hex_digest(&request.normalized().to_string())))
+    }
+
+    /// Stable hash of everything that determines the response: the model, the normalized
+    /// messages, and every sampling parameter including temperature. Record it with a
+    /// response's `content_hash` to later tell whether replaying the prompt still gives the
+    /// same answer, see `ChatCache::verify`.
+    pub fn prompt_hash(&self) -> Result<String, OpenAIApiError> {
+        let request = self.with_profile()?;
+        let mut normalized = request.normalized();
+        normalized["temperature"] = serde_json::json!(request.temperature);
+        Ok(hex_digest(&normalized.to_string()))
+    }
+
+    /// The request's settings as JSON, with message roles and whitespace normalized,
+    /// without temperature, streaming, and `user`.
+    fn normalized(&self) -> serde_json::Value {
+        let messages: Vec<(String, &str)> = self
+            .messages
+            .iter()
+            .map(|msg| (msg.role.trim().to_lowercase(), msg.content.trim()))
+            .collect();
+        let logit_bias: Option<BTreeMap<&String, &f64>> =
+            self.logit_bias.as_ref().map(|bias| bias.iter().collect());
+
+        serde_json::json!({
+            "model": self.model,
+            "messages": messages,
+            "top_p": self.top_p,
+            "n": self.n,
+            "stop": self.stop,
+            "max_tokens": self.max_tokens,
+            "presence_penalty": self.presence_penalty,
+            "frequency_penalty": self.frequency_penalty,
+            "logit_bias": logit_bias,
+            "logprobs": self.logprobs,
+            "top_logprobs": self.top_logprobs,
+            "response_format": self.response_format,
+        })
+    }
+}
+
+/// Lowercase hex SHA-256 of `data`.
+fn hex_digest(data: &str) -> String {
+    let digest = Sha256::digest(data.as_bytes());
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Instruction sent after a truncated answer by `send_with_continuation`.
+const CONTINUATION_PROMPT: &str = "Continue exactly where your last message stopped. \
+Do not repeat any of it or add an introduction.";
+
+/// Checks the sampling parameters shared by chat and legacy completion requests.
+fn validate_sampling(
+    temperature: Option<f64>,
+    top_p: Option<f64>,
+    presence_penalty: Option<f64>,
+    frequency_penalty: Option<f64>,
+    stop: &Option<Vec<String>>,
+) -> Result<(), OpenAIApiError> {
+    match (
+        temperature.unwrap_or(0.0),
+        top_p.unwrap_or(0.0),
+        presence_penalty.unwrap_or(0.0),
+        frequency_penalty.unwrap_or(0.0),
+    ) {
+        (temp, _, _, _) if !(0.0..=2.0).contains(&temp) => Err(OpenAIApiError::InvalidTemperature),
+        (_, p, _, _) if !(0.0..=1.0).contains(&p) => Err(OpenAIApiError::InvalidTopP),
+        (_, _, presence_penalty, _) if !(-2.0..=2.0).contains(&presence_penalty) => {
+            Err(OpenAIApiError::InvalidPresencePenalty)
+        }
+        (_, _, _, frequency_penalty) if !(-2.0..=2.0).contains(&frequency_penalty) => {
+            Err(OpenAIApiError::InvalidFrequencyPenalty)
+        }
+        _ if stop.as_ref().is_some_and(|stop| stop.len() > 4) => Err(OpenAIApiError::InvalidStop),
+        _ => Ok(()),
+    }
+}
+
+/// Represents a request body for OpenAI's legacy Completions API (`/v1/completions`), for
+/// instruct and fine-tuned base models that do not accept chat messages.
+///
+/// # Fields
+///
+/// * `model`: Required. ID of the model to use (e.g., "gpt-3.5-turbo-instruct", "davinci-002").
+/// * `prompt`: Required. Text to complete.
+/// * `suffix`: Optional. Text that comes after the completion, for inserting text.
+/// * `max_tokens`: Optional. The maximum number of tokens to generate. The API defaults to 16.
+/// * `temperature`: Optional. A number between 0 and 2 controlling output randomness.
+/// * `top_p`: Optional. A number between 0 and 1 for nucleus sampling.
+/// * `n`: Optional. The number of completions to generate for the prompt.
+/// * `echo`: Optional. If set, the prompt is returned in front of each completion.
+/// * `stop`: Optional. Up to 4 sequences where the API will stop generating further tokens.
+/// * `presence_penalty`: Optional. A number between -2.0 and 2.0 penalizing tokens that already appear.
+/// * `frequency_penalty`: Optional. A number between -2.0 and 2.0 penalizing frequent tokens.
+/// * `logit_bias`: Optional. A map from tokens to bias values from -100 to 100.
+/// * `logprobs`: Optional. Number of most likely tokens (0 to 5) whose log probabilities are returned per position.
+/// * `user`: Optional. A unique identifier representing the end-user, which can help OpenAI monitor and detect abuse.
+/// * `priority`: Optional. Rate-limit priority; not sent to the API. Defaults to `Priority::Interactive`.
+///
+/// # Example
+///
+/// ```rust
+/// let request = OpenAICompletionRequest::builder()
+///     .model("gpt-3.5-turbo-instruct".to_string())
+///     .prompt("Write a tagline for an ice cream shop.".to_string())
+///     .max_tokens(32)
+///     .n(3)
+///     .build();
+/// let taglines: Vec<&str> = request.send().await?.choices().iter().map(|c| c.text()).collect();
+/// ```
+#[derive(Debug, Serialize, Deserialize, TypedBuilder)]
+pub struct OpenAICompletionRequest {
+    model: String,
+    prompt: String,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suffix: Option<String>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    echo: Option<bool>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logit_bias: Option<std::collections::HashMap<String, f64>>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<u32>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+
+    #[builder(default)]
+    #[serde(skip)]
+    priority: Priority,
+}
+
+impl OpenAICompletionRequest {
+    pub fn validate(&self) -> Result<(), OpenAIApiError> {
+        validate_sampling(
+            self.temperature,
+            self.top_p,
+            self.presence_penalty,
+            self.frequency_penalty,
+            &self.stop,
+        )
+    }
+
+    pub async fn send(&self) -> Result<OpenAICompletionResponse, Box<dyn Error>> {
         self.validate()?;
+        // Every one of the `n` completions may use up to `max_tokens`; the API defaults to 16.
+        let completion_tokens = self.max_tokens.unwrap_or(16) * self.n.unwrap_or(1);
+        let tokens = get_tokens(&self.prompt)?.len() as u32 + completion_tokens;
+        rate_limiter().acquire(&self.model, tokens, self.priority).await;
 
-        let response: OpenAIResponse = CLIENT
-            .post("https://api.openai.com/v1/chat/completions")
-            .json(self)
+        let span = context::span("openai.completions");
+        let api_key = api_key();
+        reserve_budget(&api_key, tokens).await?;
+        let response = CLIENT
+            .post(url("completions"))
+            .bearer_auth(&api_key)
+            .json(&body(self, &self.user)?)
             .send()
+            .instrument(span.clone())
             .await
-            .map_err(|_| "Failed to send request.")?
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+        note_throttling(&api_key, &response);
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(OpenAIApiError::Status(status.as_u16(), message).into());
+        }
+
+        let response: OpenAICompletionResponse = response
             .json()
+            .instrument(span)
             .await
             .map_err(|_| "Failed to deserialize response.")?;
+        record_usage(&api_key, &response.model, &response.usage).await;
 
         Ok(response)
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenAICompletionResponse {
+    id: String,
+    object: String,
+    created: u64,
+    model: String,
+    choices: Vec<CompletionChoice>,
+    usage: Usage,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CompletionChoice {
+    text: String,
+    index: u32,
+
+    #[serde(default)]
+    finish_reason: Option<FinishReason>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    logprobs: Option<CompletionLogprobs>,
+}
+
+/// Log probabilities of a legacy completion's tokens.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CompletionLogprobs {
+    #[serde(default)]
+    tokens: Vec<String>,
+
+    /// `None` for the first token of an echoed prompt, which has no probability.
+    #[serde(default)]
+    token_logprobs: Vec<Option<f64>>,
+}
+
 #[derive(Debug)]
 pub enum OpenAIApiError {
     InvalidTemperature,
     InvalidTopP,
     InvalidPresencePenalty,
     InvalidFrequencyPenalty,
+    InvalidStop,
+    UnknownModel,
+    ContextLengthExceeded,
+    UnknownProfile,
+    /// The API answered with this HTTP status and body.
+    Status(u16, String),
 }
 
 // Implement the std::error::Error trait for the ValidationError enum
@@ -238,6 +891,13 @@ impl std::fmt::Display for OpenAIApiError {
             OpenAIApiError::InvalidFrequencyPenalty => {
                 write!(f, "Frequency_penalty must be between -2.0 and 2.0.")
             }
+            OpenAIApiError::InvalidStop => write!(f, "Stop may contain at most 4 sequences."),
+            OpenAIApiError::UnknownModel => write!(f, "Model is not in the model registry."),
+            OpenAIApiError::UnknownProfile => write!(f, "Profile is not registered."),
+            OpenAIApiError::ContextLengthExceeded => {
+                write!(f, "Prompt does not fit in the model's context window.")
+            }
+            OpenAIApiError::Status(status, message) => write!(f, "Error status: {}: {}", status, message),
         }
     }
 }
@@ -261,16 +921,135 @@ impl Message {
 }
 
 pub fn get_tokens(msg: &str) -> Result<Vec<usize>, serde_json::Error> {
-    let bpe = cl100k_base().unwrap();
-    let tokens = bpe.encode_with_special_tokens(msg);
+    let tokens = BPE.encode_with_special_tokens(msg);
     Ok(tokens)
 }
 
+/// Truncates `text` to at most `max_tokens` tokens.
+///
+/// The cut never falls inside a multi-byte character. When the kept text holds a sentence
+/// (or failing that, a word) boundary no earlier than its middle, it is cut there instead, so the
+/// result does not end mid-sentence.
+///
+/// # Example
+///
+/// ```rust
+/// let text = truncate_to_tokens("The first sentence is here. The second one is longer.", 9);
+/// assert_eq!(text, "The first sentence is here.");
+/// ```
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> &str {
+    let tokens = BPE.encode_with_special_tokens(text);
+    if tokens.len() <= max_tokens {
+        return text;
+    }
+
+    // A token can end partway through a character; drop tokens until the prefix decodes.
+    let mut end = max_tokens;
+    let prefix = loop {
+        if end == 0 {
+            return "";
+        }
+        match BPE.decode(tokens[..end].to_vec()) {
+            Ok(decoded) => break &text[..decoded.len()],
+            Err(_) => end -= 1,
+        }
+    };
+
+    let half = prefix.len() / 2;
+    let sentence_end = prefix
+        .char_indices()
+        .rev()
+        .find(|&(i, c)| {
+            matches!(c, '.' | '!' | '?' | '\n') && text[i + c.len_utf8()..].starts_with(char::is_whitespace)
+        })
+        .map(|(i, c)| i + c.len_utf8());
+    if let Some(end) = sentence_end.filter(|&end| end >= half) {
+        return prefix[..end].trim_end();
+    }
+
+    match prefix.rfind(char::is_whitespace).filter(|&end| end >= half) {
+        Some(end) => prefix[..end].trim_end(),
+        None => prefix,
+    }
+}
+
+/// Why the model stopped generating a choice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum FinishReason {
+    /// A natural stop or a stop sequence.
+    Stop,
+    /// The `max_tokens` limit or the context window: the output is cut off.
+    Length,
+    ToolCalls,
+    /// Content was omitted by OpenAI's content filters.
+    ContentFilter,
+    /// A reason this crate does not know, e.g. the deprecated "function_call".
+    Other(String),
+}
+
+impl FinishReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::Other(reason) => reason,
+        }
+    }
+
+    /// Whether the output was cut off by the token limit.
+    pub fn is_truncated(&self) -> bool {
+        *self == FinishReason::Length
+    }
+}
+
+impl From<String> for FinishReason {
+    fn from(reason: String) -> Self {
+        match reason.as_str() {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "tool_calls" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            _ => FinishReason::Other(reason),
+        }
+    }
+}
+
+impl From<FinishReason> for String {
+    fn from(reason: FinishReason) -> Self {
+        reason.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for FinishReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Choice {
     message: Message,
-    finish_reason: String,
+    finish_reason: FinishReason,
     index: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    logprobs: Option<ChoiceLogprobs>,
+}
+
+/// Log probabilities of a chat choice's tokens, returned when the request sets `logprobs`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChoiceLogprobs {
+    #[serde(default)]
+    content: Vec<TokenLogprob>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenLogprob {
+    token: String,
+    logprob: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -308,13 +1087,54 @@ impl Choice {
         &self.message
     }
 
-    pub fn finish_reason(&self) -> &str {
+    pub fn finish_reason(&self) -> &FinishReason {
         &self.finish_reason
     }
 
     pub fn index(&self) -> u32 {
         self.index
     }
+
+    pub fn logprobs(&self) -> &Option<ChoiceLogprobs> {
+        &self.logprobs
+    }
+
+    /// Average log probability of the choice's tokens, if the request asked for `logprobs`.
+    pub fn mean_logprob(&self) -> Option<f64> {
+        let content = &self.logprobs.as_ref()?.content;
+        mean(content.iter().map(|token| token.logprob))
+    }
+}
+
+impl ChoiceLogprobs {
+    pub fn content(&self) -> &[TokenLogprob] {
+        &self.content
+    }
+}
+
+impl TokenLogprob {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn logprob(&self) -> f64 {
+        self.logprob
+    }
+}
+
+/// Mean of `values`, or `None` if there are none.
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// The item `score` rates highest; the earliest of equally rated items.
+fn best_by<T>(items: &[T], score: impl Fn(&T) -> f64) -> Option<&T> {
+    items
+        .iter()
+        .map(|item| (score(item), item))
+        .reduce(|best, next| if next.0.total_cmp(&best.0).is_gt() { next } else { best })
+        .map(|(_, item)| item)
 }
 
 impl Usage {
@@ -355,6 +1175,137 @@ impl OpenAIResponse {
     pub fn choices(&self) -> &[Choice] {
         &self.choices
     }
+
+    /// Stable hash of the choices' messages, in order. Ids, timestamps, and usage are left
+    /// out, so replays that answer the same way hash the same.
+    pub fn content_hash(&self) -> String {
+        let contents: Vec<(&str, &str)> = self
+            .choices
+            .iter()
+            .map(|choice| (choice.message.role.as_str(), choice.message.content.as_str()))
+            .collect();
+        hex_digest(&serde_json::json!(contents).to_string())
+    }
+
+    /// This response with `next`, the continuation of its first choice, appended to it.
+    fn continued_by(mut self, next: OpenAIResponse) -> OpenAIResponse {
+        let Some(continuation) = next.choices.into_iter().next() else {
+            return self;
+        };
+        if let Some(choice) = self.choices.first_mut() {
+            choice.message.content.push_str(&continuation.message.content);
+            choice.finish_reason = continuation.finish_reason;
+            choice.logprobs = None;
+        }
+
+        self.usage.prompt_tokens += next.usage.prompt_tokens;
+        self.usage.total_tokens += next.usage.total_tokens;
+        self.usage.completion_tokens = match (self.usage.completion_tokens, next.usage.completion_tokens) {
+            (None, None) => None,
+            (first, second) => Some(first.unwrap_or(0) + second.unwrap_or(0)),
+        };
+        self
+    }
+
+    /// Choices that ended for `finish_reason`, e.g. `Stop` to skip those cut off by length.
+    pub fn choices_with_finish_reason(&self, finish_reason: FinishReason) -> impl Iterator<Item = &Choice> + '_ {
+        self.choices.iter().filter(move |choice| choice.finish_reason == finish_reason)
+    }
+
+    /// The choice `score` rates highest, for requests with `n` > 1.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// // The shortest answer.
+    /// let best = response.best_choice_by(|choice| -(choice.message().content().len() as f64));
+    /// ```
+    pub fn best_choice_by(&self, score: impl Fn(&Choice) -> f64) -> Option<&Choice> {
+        best_by(&self.choices, score)
+    }
+
+    /// The choice the model was most confident in: the highest mean token log probability.
+    /// Requires the request to set `logprobs`; choices without them rank last.
+    pub fn best_choice_by_logprob(&self) -> Option<&Choice> {
+        self.best_choice_by(|choice| choice.mean_logprob().unwrap_or(f64::NEG_INFINITY))
+    }
+}
+
+impl OpenAICompletionResponse {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn object(&self) -> &str {
+        &self.object
+    }
+
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn choices(&self) -> &[CompletionChoice] {
+        &self.choices
+    }
+
+    /// Choices that ended for `finish_reason`, e.g. `Stop` to skip those cut off by length.
+    pub fn choices_with_finish_reason(&self, finish_reason: FinishReason) -> impl Iterator<Item = &CompletionChoice> + '_ {
+        self.choices
+            .iter()
+            .filter(move |choice| choice.finish_reason.as_ref() == Some(&finish_reason))
+    }
+
+    /// The choice `score` rates highest, for requests with `n` > 1.
+    pub fn best_choice_by(&self, score: impl Fn(&CompletionChoice) -> f64) -> Option<&CompletionChoice> {
+        best_by(&self.choices, score)
+    }
+
+    /// The choice with the highest mean token log probability. Requires the request to set
+    /// `logprobs`; choices without them rank last.
+    pub fn best_choice_by_logprob(&self) -> Option<&CompletionChoice> {
+        self.best_choice_by(|choice| choice.mean_logprob().unwrap_or(f64::NEG_INFINITY))
+    }
+
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+}
+
+impl CompletionChoice {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn finish_reason(&self) -> Option<&FinishReason> {
+        self.finish_reason.as_ref()
+    }
+
+    pub fn logprobs(&self) -> &Option<CompletionLogprobs> {
+        &self.logprobs
+    }
+
+    /// Average log probability of the choice's tokens, if the request asked for `logprobs`.
+    pub fn mean_logprob(&self) -> Option<f64> {
+        mean(self.logprobs.as_ref()?.token_logprobs.iter().flatten().copied())
+    }
+}
+
+impl CompletionLogprobs {
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    pub fn token_logprobs(&self) -> &[Option<f64>] {
+        &self.token_logprobs
+    }
 }
 
 impl OpenAIEmbeddingResponse {
@@ -388,3 +1339,275 @@ impl Embedding {
         &self.object
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::context::RequestContext;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::FakeServices;
+
+    fn request(content: &str) -> OpenAIRequest {
+        let msg = Message::builder()
+            .role("user".to_string())
+            .content(content.to_string())
+            .build();
+
+        OpenAIRequest::builder()
+            .model("gpt-3.5-turbo".to_string())
+            .messages(vec![msg])
+            .build()
+    }
+
+    #[test]
+    fn test_auto_max_tokens() {
+        let request = request("How do you upload your mind to skynet?");
+        let prompt_tokens = request.prompt_tokens().unwrap() as u32;
+
+        let request = request.with_auto_max_tokens("gpt-3.5-turbo").unwrap();
+        assert_eq!(
+            request.max_tokens,
+            Some(4096 - prompt_tokens - AUTO_MAX_TOKENS_MARGIN)
+        );
+
+        let request = request.with_auto_max_tokens("gpt-4o-2024-05-13").unwrap();
+        assert_eq!(request.max_tokens, Some(16384));
+    }
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        let text = "The first sentence is here. The second one is quite a bit longer.";
+        assert_eq!(truncate_to_tokens(text, 100), text);
+        assert_eq!(truncate_to_tokens(text, 9), "The first sentence is here.");
+        assert_eq!(truncate_to_tokens(text, 0), "");
+
+        let accents = "é".repeat(50);
+        let truncated = truncate_to_tokens(&accents, 7);
+        assert!(!truncated.is_empty() && get_tokens(truncated).unwrap().len() <= 7);
+        assert!(accents.starts_with(truncated));
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let deterministic = |content: &str| {
+            OpenAIRequest::builder()
+                .model("gpt-3.5-turbo".to_string())
+                .messages(vec![Message::builder()
+                    .role("user".to_string())
+                    .content(content.to_string())
+                    .build()])
+                .temperature(0.0)
+                .build()
+        };
+
+        let key = deterministic("Classify: great product").cache_key().unwrap();
+        assert!(key.starts_with("gpt-3.5-turbo:"));
+        assert_eq!(deterministic("  Classify: great product\n").cache_key().unwrap(), key);
+        assert_ne!(deterministic("Classify: bad product").cache_key().unwrap(), key);
+        assert_eq!(request("Classify: great product").cache_key(), None);
+    }
+
+    #[test]
+    fn test_profile() {
+        let profiled = |profile: &str| {
+            OpenAIRequest::builder()
+                .model("gpt-3.5-turbo".to_string())
+                .messages(vec![Message::builder()
+                    .role("user".to_string())
+                    .content("Extract the names".to_string())
+                    .build()])
+                .profile(profile)
+                .build()
+        };
+
+        let extractor = profiled("json-extractor");
+        let resolved = extractor.with_profile().unwrap();
+        assert_eq!(resolved.temperature, Some(0.0));
+        assert_eq!(resolved.response_format, Some(ResponseFormat::json_object()));
+
+        let mut overridden = profiled("deterministic");
+        overridden.temperature = Some(0.7);
+        assert_eq!(overridden.with_profile().unwrap().temperature, Some(0.7));
+        assert_eq!(overridden.cache_key(), None);
+
+        let mut explicit = request("Extract the names");
+        explicit.temperature = Some(0.0);
+        assert_eq!(profiled("deterministic").cache_key(), explicit.cache_key());
+
+        assert!(matches!(profiled("missing").validate(), Err(OpenAIApiError::UnknownProfile)));
+    }
+
+    #[test]
+    fn test_hashes() {
+        let mut warm = request("Name a color");
+        warm.temperature = Some(0.7);
+        assert_eq!(request("  Name a color ").prompt_hash().unwrap(), request("Name a color").prompt_hash().unwrap());
+        assert_ne!(warm.prompt_hash().unwrap(), request("Name a color").prompt_hash().unwrap());
+
+        let response = |id: &str, content: &str| -> OpenAIResponse {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-3.5-turbo",
+                "usage": { "prompt_tokens": 3, "total_tokens": 4 },
+                "choices": [{ "index": 0, "message": { "role": "assistant", "content": content }, "finish_reason": "stop" }],
+            }))
+            .unwrap()
+        };
+        assert_eq!(response("a", "Blue").content_hash(), response("b", "Blue").content_hash());
+        assert_ne!(response("a", "Blue").content_hash(), response("a", "Red").content_hash());
+    }
+
+    #[tokio::test]
+    async fn test_context_user() {
+        let request = request("hi");
+        assert!(body(&request, &request.user).unwrap().get("user").is_none());
+
+        let context = RequestContext::builder().user("user-42".to_string()).build();
+        let body = context.scope(async { body(&request, &request.user).unwrap() }).await;
+        assert_eq!(body["user"], "user-42");
+    }
+
+    #[test]
+    fn test_completion_request() {
+        let request = OpenAICompletionRequest::builder()
+            .model("gpt-3.5-turbo-instruct".to_string())
+            .prompt("Once upon a time".to_string())
+            .suffix("happily ever after.".to_string())
+            .echo(true)
+            .n(2)
+            .build();
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "model": "gpt-3.5-turbo-instruct",
+                "prompt": "Once upon a time",
+                "suffix": "happily ever after.",
+                "n": 2,
+                "echo": true,
+            })
+        );
+
+        let invalid = OpenAICompletionRequest::builder()
+            .model("davinci-002".to_string())
+            .prompt("hi".to_string())
+            .top_p(1.5)
+            .build();
+        assert!(matches!(invalid.validate(), Err(OpenAIApiError::InvalidTopP)));
+    }
+
+    #[test]
+    fn test_best_choice() {
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-3.5-turbo",
+            "usage": { "prompt_tokens": 5, "completion_tokens": 6, "total_tokens": 11 },
+            "choices": [
+                {
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Paris, I think" },
+                    "finish_reason": "stop",
+                    "logprobs": { "content": [{ "token": "Paris", "logprob": -0.5 }, { "token": ",", "logprob": -1.5 }] },
+                },
+                {
+                    "index": 1,
+                    "message": { "role": "assistant", "content": "Paris" },
+                    "finish_reason": "stop",
+                    "logprobs": { "content": [{ "token": "Paris", "logprob": -0.25 }] },
+                },
+                {
+                    "index": 2,
+                    "message": { "role": "assistant", "content": "The capital of" },
+                    "finish_reason": "length",
+                },
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(response.choices_with_finish_reason(FinishReason::Stop).count(), 2);
+        assert!(response.choices()[2].finish_reason().is_truncated());
+        assert_eq!(response.choices()[0].mean_logprob(), Some(-1.0));
+        assert_eq!(response.best_choice_by_logprob().unwrap().index(), 1);
+        assert_eq!(
+            response.best_choice_by(|choice| choice.message().content().len() as f64).unwrap().index(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_auto_max_tokens_errors() {
+        assert!(request("hi").with_auto_max_tokens("unknown-model").is_err());
+        assert!(request(&"word ".repeat(5000))
+            .with_auto_max_tokens("gpt-3.5-turbo")
+            .is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_completion_echo() {
+        let fakes = FakeServices::start().await;
+        fakes.set_chat_reply(" ever after.");
+        let completion = OpenAICompletionRequest::builder()
+            .model("gpt-3.5-turbo-instruct".to_string())
+            .prompt("Happily".to_string())
+            .echo(true)
+            .build();
+        assert_eq!(completion.send().await.unwrap().choices()[0].text(), "Happily ever after.");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_completion_error_status() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let fakes = FakeServices::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/completions"))
+            .respond_with(ResponseTemplate::new(429).set_body_string("Rate limit reached"))
+            .with_priority(1)
+            .mount(fakes.server())
+            .await;
+        let completion = OpenAICompletionRequest::builder()
+            .model("gpt-3.5-turbo-instruct".to_string())
+            .prompt("Happily".to_string())
+            .build();
+        let error = completion.send().await.unwrap_err();
+        let status = error.downcast_ref::<OpenAIApiError>();
+        assert!(matches!(status, Some(OpenAIApiError::Status(429, message)) if message == "Rate limit reached"), "{}", error);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_multiple_choices() {
+        let fakes = FakeServices::start().await;
+        fakes.set_chat_reply("Two business days.");
+        let response = OpenAIRequest::builder()
+            .model("gpt-3.5-turbo".to_string())
+            .messages(vec![Message::builder().role("user".to_string()).content("How fast?".to_string()).build()])
+            .n(3)
+            .build()
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.choices().len(), 3);
+        assert_eq!(response.choices_with_finish_reason(FinishReason::Stop).count(), 3);
+        assert_eq!(response.usage().completion_tokens(), Some(9));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_send_with_continuation() {
+        let fakes = FakeServices::start().await;
+        fakes.set_chat_reply("part ");
+        fakes.set_finish_reason(FinishReason::Length);
+        let response = request("Go on").send_with_continuation(2).await.unwrap();
+        assert_eq!(response.choices()[0].message().content(), "part part part ");
+        assert!(response.choices()[0].finish_reason().is_truncated());
+        assert_eq!(response.usage().completion_tokens(), Some(3));
+    }
+}
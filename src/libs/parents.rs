@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use serde_json::Value;
+use typed_builder::TypedBuilder;
+
+use super::database::{upsert, Database};
+use super::pinecone_data::{chunk_id, Match};
+use super::pipeline::{Chunk, Document, PipelineError};
+use super::splitter::{Splitter, TokenSplitter};
+
+/// Metadata key of the id of the parent passage a chunk was cut from.
+pub const PARENT_ID_KEY: &str = "parent_id";
+
+/// Metadata key a hydrated match keeps its own chunk text under.
+pub const CHILD_TEXT_KEY: &str = "child_text";
+
+/// Prefix of the keys parent passages are stored under in the `Database`.
+const PARENT_KEY_PREFIX: &str = "parent:";
+
+/// Separates a document's id from the parent number in parent ids, e.g. "refunds#parent0".
+const PARENT_ID_SEPARATOR: &str = "#parent";
+
+/// Small-to-big chunking: embeds small chunks, which match queries precisely, and answers
+/// with the larger passages they were cut from, which give the chat model enough context.
+///
+/// Each document is split into parent passages, kept in `db`, and each parent into the
+/// chunks that are embedded, with the parent's id in their `parent_id` metadata. Chunk ids
+/// are numbered across the document as usual. Set on an `IngestionPipeline` to chunk with
+/// it, and on a `SemanticSearch` to return the parents of the matching chunks.
+///
+/// # Fields
+///
+/// * `db`: Required. Database the parent passages are kept in.
+/// * `parent_splitter`: Optional. Splits documents into parents. Defaults to 1024 tokens.
+/// * `child_splitter`: Optional. Splits parents into the embedded chunks. Defaults to 128 tokens.
+///
+/// # Example
+///
+/// ```rust
+/// let parents = ParentChunks::builder().db(Arc::new(SQLiteDB::new("parents.db")?)).build();
+/// IngestionPipeline::builder().parents(parents.clone()).build().ingest(&documents).await?;
+///
+/// let matches = SemanticSearch::builder().parents(parents).build().search("refunds").await?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ParentChunks {
+    db: Arc<dyn Database>,
+
+    #[builder(default = Arc::new(TokenSplitter::builder().max_tokens(1024).build()))]
+    parent_splitter: Arc<dyn Splitter>,
+
+    #[builder(default = Arc::new(TokenSplitter::builder().max_tokens(128).build()))]
+    child_splitter: Arc<dyn Splitter>,
+}
+
+impl ParentChunks {
+    /// Splits `document` into parents, stores them, and returns the chunks to embed.
+    pub async fn chunk(&self, document: &Document) -> Result<Vec<Chunk>, PipelineError> {
+        let mut chunks = Vec::new();
+        for (n, parent) in self.parent_splitter.split(document.text()).await?.into_iter().enumerate() {
+            let parent_id = format!("{}{}{}", document.id(), PARENT_ID_SEPARATOR, n);
+            upsert(self.db.as_ref(), &parent_key(&parent_id), &parent)
+                .await
+                .map_err(|e| PipelineError::DatabaseError(e.to_string()))?;
+
+            for text in self.child_splitter.split(&parent).await? {
+                let mut metadata = document.metadata().clone();
+                metadata.insert("document_id".to_string(), document.id().clone().into());
+                metadata.insert(PARENT_ID_KEY.to_string(), parent_id.clone().into());
+                chunks.push(
+                    Chunk::builder()
+                        .id(chunk_id(document.id(), chunks.len()))
+                        .text(text)
+                        .metadata(metadata)
+                        .build(),
+                );
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Text of the parent `parent_id`, if it is stored.
+    pub async fn parent(&self, parent_id: &str) -> Option<String> {
+        self.db.read(&parent_key(parent_id)).await.ok()
+    }
+
+    /// Replaces the `text` metadata of every match with its parent's, keeping its own
+    /// under `child_text`, and drops matches whose parent an earlier match already brought.
+    /// Matches without a stored parent are kept as they are.
+    pub async fn hydrate(&self, matches: Vec<Match>) -> Vec<Match> {
+        let mut seen = HashSet::new();
+        let mut hydrated = Vec::with_capacity(matches.len());
+        for mut m in matches {
+            let Some(parent_id) = m.metadata_str(PARENT_ID_KEY).map(str::to_string) else {
+                hydrated.push(m);
+                continue;
+            };
+            if !seen.insert(parent_id.clone()) {
+                continue;
+            }
+            if let Some(parent) = self.parent(&parent_id).await {
+                let metadata = m.metadata_mut();
+                if let Some(text) = metadata.insert("text".to_string(), Value::String(parent)) {
+                    metadata.insert(CHILD_TEXT_KEY.to_string(), text);
+                }
+            }
+            hydrated.push(m);
+        }
+        hydrated
+    }
+}
+
+fn parent_key(parent_id: &str) -> String {
+    format!("{}{}", PARENT_KEY_PREFIX, parent_id)
+}
+
+#[cfg(all(test, feature = "test-util", feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::pipeline::IngestionPipeline;
+    use crate::libs::search::SemanticSearch;
+    use crate::libs::splitter::ParagraphSplitter;
+    use crate::libs::sql_lite::SQLiteDB;
+    use crate::libs::test_util::FakeServices;
+
+    #[tokio::test]
+    async fn test_hydrate_parents() {
+        let _fakes = FakeServices::start().await;
+        let parents = ParentChunks::builder()
+            .db(Arc::new(SQLiteDB::new(":memory:").unwrap()))
+            .parent_splitter(Arc::new(ParagraphSplitter::builder().build()))
+            .child_splitter(Arc::new(TokenSplitter::builder().max_tokens(4).build()))
+            .build();
+        let handbook = Document::builder()
+            .id("handbook".to_string())
+            .text("Refunds are issued within 30 days of purchase.\n\nOrders ship within two business days.".to_string())
+            .build();
+        let report = IngestionPipeline::builder()
+            .namespace("children".to_string())
+            .parents(parents.clone())
+            .build()
+            .ingest(&[handbook])
+            .await
+            .unwrap();
+        assert!(report.chunks() > 2);
+        let hydrated = SemanticSearch::builder()
+            .namespace("children".to_string())
+            .parents(parents)
+            .build()
+            .search("when are refunds issued")
+            .await
+            .unwrap();
+        assert_eq!(hydrated.len(), 2);
+        let texts: Vec<&str> = hydrated.iter().filter_map(|m| m.metadata_str("text")).collect();
+        assert!(texts.contains(&"Refunds are issued within 30 days of purchase."));
+        assert!(hydrated.iter().all(|m| m.metadata_str(CHILD_TEXT_KEY).is_some()));
+    }
+}
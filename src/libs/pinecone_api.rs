@@ -1,17 +1,39 @@
 use lazy_static::lazy_static;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, Response};
 use serde::de::DeserializeOwned;
-use std::{env, sync::Arc};
+use std::{collections::HashMap, env, sync::{Arc, OnceLock, RwLock}};
 use reqwest::header::{HeaderMap, HeaderValue};
 use thiserror::Error;
-use crate::libs::database::DatabaseOperation;
+use tracing::Instrument;
 
-use super::pinecone_data::{IdList, PineconeRequest, PineconeResponse};
+use super::audit::{AuditEntry, AuditLog};
+use super::circuit::{CircuitBreaker, CircuitState};
+use super::context;
+use super::pinecone_data::{
+    chunk_prefix, write_json, write_vectors_json, AdditionalProp, Filter, IdList, IndexDescription, IndexStats,
+    ListResponse, Match, Metric, NamespaceStats, PineconeRequest, PineconeResponse, Vector,
+};
+
+static API_KEY: OnceLock<String> = OnceLock::new();
+static BASE_URL: RwLock<Option<String>> = RwLock::new(None);
+static INDEX_NAME: RwLock<Option<String>> = RwLock::new(None);
+static CONTROL_PLANE_URL: RwLock<Option<String>> = RwLock::new(None);
+static API_VERSION: RwLock<Option<String>> = RwLock::new(None);
+static SERVER_API_VERSION: RwLock<Option<String>> = RwLock::new(None);
+static CIRCUIT_BREAKER: OnceLock<Arc<CircuitBreaker>> = OnceLock::new();
+static AUDIT_LOG: OnceLock<Arc<AuditLog>> = OnceLock::new();
+
+/// Data plane URL resolved for the index of that name.
+static RESOLVED_HOST: RwLock<Option<(String, String)>> = RwLock::new(None);
+/// Index name and metric of the last `index_metric` lookup.
+static RESOLVED_METRIC: RwLock<Option<(String, Metric)>> = RwLock::new(None);
 
 lazy_static! {
     static ref CLIENT: Arc<Client> = {
-        dotenv::dotenv().ok();
-        let api_key = env::var("PINECONE_API_KEY").expect("Failed to locate api key.");
+        let api_key = API_KEY.get().cloned().unwrap_or_else(|| {
+            dotenv::dotenv().ok();
+            env::var("PINECONE_API_KEY").expect("Failed to locate api key.")
+        });
 
         let client = Client::builder()
             .default_headers(headers(api_key))
@@ -20,6 +42,191 @@ lazy_static! {
 
         Arc::new(client)
     };
+    /// `PINECONE_API_VERSION` from the environment, read once.
+    static ref ENV_API_VERSION: Option<String> = {
+        dotenv::dotenv().ok();
+        env::var("PINECONE_API_VERSION").ok()
+    };
+    /// `PINECONE_INDEX` from the environment, read once.
+    static ref ENV_INDEX_NAME: Option<String> = {
+        dotenv::dotenv().ok();
+        env::var("PINECONE_INDEX").ok()
+    };
+}
+
+/// Sets the Pinecone API key instead of reading `PINECONE_API_KEY` from the environment,
+/// e.g. on wasm32 where there is no process environment. Takes effect only if called before the
+/// first request; returns false if a key was already set.
+pub fn set_api_key(api_key: String) -> bool {
+    API_KEY.set(api_key).is_ok()
+}
+
+/// Guards the requests posted to the index (upserts, queries, updates and deletes) with
+/// `breaker`: once it opens they fail at once. Failures to reach the index and server
+/// errors count against it. Returns false if a breaker was already set.
+pub fn set_circuit_breaker(breaker: Arc<CircuitBreaker>) -> bool {
+    CIRCUIT_BREAKER.set(breaker).is_ok()
+}
+
+/// Records every delete sent to the index (by id, by filter, and `delete_all`) in `log`.
+/// Returns false if a log was already set.
+pub fn set_audit_log(log: Arc<AuditLog>) -> bool {
+    AUDIT_LOG.set(log).is_ok()
+}
+
+/// Whether the circuit breaker refuses requests to the index, or only lets a trial through.
+pub fn circuit_open() -> bool {
+    CIRCUIT_BREAKER.get().is_some_and(|breaker| breaker.state() != CircuitState::Closed)
+}
+
+/// Sends requests to the index at `base_url` instead of the default index, e.g. through a
+/// local proxy.
+pub fn set_base_url(base_url: &str) {
+    *BASE_URL.write().unwrap_or_else(|e| e.into_inner()) = Some(format!("{}/", base_url.trim_end_matches('/')));
+}
+
+/// Sends requests to the index `name` instead of the default index, with its host looked
+/// up from the control plane (`describe_index`) on first use. Works with serverless and
+/// pod-based indexes. The index can also be named by the `PINECONE_INDEX` environment
+/// variable; `set_base_url` takes precedence over both.
+pub fn set_index(name: &str) {
+    *INDEX_NAME.write().unwrap_or_else(|e| e.into_inner()) = Some(name.to_string());
+    *RESOLVED_HOST.write().unwrap_or_else(|e| e.into_inner()) = None;
+    *RESOLVED_METRIC.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Sends control plane requests to `url` instead of "https://api.pinecone.io", such as the
+/// fakes in `test_util`.
+pub fn set_control_plane_url(url: &str) {
+    *CONTROL_PLANE_URL.write().unwrap_or_else(|e| e.into_inner()) = Some(url.trim_end_matches('/').to_string());
+    *RESOLVED_HOST.write().unwrap_or_else(|e| e.into_inner()) = None;
+    *RESOLVED_METRIC.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Pins requests to the Pinecone API `version` (e.g. "2024-07") with the
+/// `X-Pinecone-API-Version` header, instead of `PINECONE_API_VERSION` from the environment.
+///
+/// Without a version, Pinecone picks one and request bodies keep this client's original
+/// shapes. With one, bodies follow the versioned schema: query vectors and ids are sent as
+/// plain arrays, and responses report `usage`.
+pub fn set_api_version(version: &str) {
+    *API_VERSION.write().unwrap_or_else(|e| e.into_inner()) = Some(version.to_string());
+}
+
+/// The API version requests are pinned to, if any.
+pub fn api_version() -> Option<String> {
+    API_VERSION.read().unwrap_or_else(|e| e.into_inner()).clone().or_else(|| ENV_API_VERSION.clone())
+}
+
+/// The API version Pinecone reported it answered the latest request with, which may
+/// differ from the requested one if that version is no longer supported.
+pub fn server_api_version() -> Option<String> {
+    SERVER_API_VERSION.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// `builder` with the version header, if a version is pinned.
+fn versioned(builder: RequestBuilder) -> RequestBuilder {
+    match api_version() {
+        Some(version) => builder.header(API_VERSION_HEADER, version),
+        None => builder,
+    }
+}
+
+/// Remembers the API version `response` was served with.
+fn note_api_version(response: &Response) {
+    if let Some(version) = response.headers().get(API_VERSION_HEADER).and_then(|v| v.to_str().ok()) {
+        *SERVER_API_VERSION.write().unwrap_or_else(|e| e.into_inner()) = Some(version.to_string());
+    }
+}
+
+fn index_name() -> Option<String> {
+    INDEX_NAME.read().unwrap_or_else(|e| e.into_inner()).clone().or_else(|| ENV_INDEX_NAME.clone())
+}
+
+/// URL of `describe_index` for the index `name`. Indexes in a classic environment
+/// (`PINECONE_ENVIRONMENT`) are described by that environment's controller.
+fn describe_index_url(name: &str) -> String {
+    if let Some(url) = CONTROL_PLANE_URL.read().unwrap_or_else(|e| e.into_inner()).as_deref() {
+        return format!("{}/indexes/{}", url, name);
+    }
+    match env::var("PINECONE_ENVIRONMENT") {
+        Ok(environment) => format!("https://controller.{}.pinecone.io/databases/{}", environment, name),
+        Err(_) => format!("{}/indexes/{}", DEFAULT_CONTROL_PLANE_URL, name),
+    }
+}
+
+/// Name, dimension, and data plane host of the index `name`.
+pub async fn describe_index(name: &str) -> Result<IndexDescription, PineconeApiError> {
+    let error = |e: reqwest::Error| PineconeApiError::DescribeError(e.to_string());
+    let response = versioned(CLIENT.get(describe_index_url(name)))
+        .send()
+        .instrument(context::span(DESCRIBE_INDEX))
+        .await
+        .map_err(error)?;
+    note_api_version(&response);
+    response
+        .error_for_status()
+        .map_err(error)?
+        .json()
+        .await
+        .map_err(error)
+}
+
+/// Data plane URL of the index `name`, with a trailing '/', looked up with `describe_index`.
+pub async fn index_host(name: &str) -> Result<String, PineconeApiError> {
+    let description = describe_index(name).await?;
+    let host = description
+        .host()
+        .ok_or_else(|| PineconeApiError::DescribeError(format!("index {} has no host yet", name)))?;
+    Ok(match host.contains("://") {
+        true => format!("{}/", host.trim_end_matches('/')),
+        false => format!("https://{}/", host.trim_end_matches('/')),
+    })
+}
+
+/// Metric of the configured index (`set_index` or `PINECONE_INDEX`), looked up with
+/// `describe_index` on first use.
+pub async fn index_metric() -> Result<Metric, PineconeApiError> {
+    let name = index_name().ok_or_else(|| {
+        PineconeApiError::DescribeError("no index to describe; set one with set_index or PINECONE_INDEX".to_string())
+    })?;
+    if let Some((resolved, metric)) = RESOLVED_METRIC.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        if *resolved == name {
+            return Ok(*metric);
+        }
+    }
+
+    let description = describe_index(&name).await?;
+    let metric = description.parsed_metric().ok_or_else(|| {
+        PineconeApiError::DescribeError(format!("index {} has an unknown metric {:?}", name, description.metric()))
+    })?;
+    *RESOLVED_METRIC.write().unwrap_or_else(|e| e.into_inner()) = Some((name, metric));
+    Ok(metric)
+}
+
+/// Base URL of the data plane: the one set by `set_base_url`, the resolved host of the
+/// configured index, or the default index.
+async fn base_url() -> Result<String, PineconeApiError> {
+    if let Some(base_url) = BASE_URL.read().unwrap_or_else(|e| e.into_inner()).clone() {
+        return Ok(base_url);
+    }
+    let Some(name) = index_name() else {
+        return Ok(DEFAULT_BASE_URL.to_string());
+    };
+
+    if let Some((resolved, host)) = RESOLVED_HOST.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        if *resolved == name {
+            return Ok(host.clone());
+        }
+    }
+
+    let host = index_host(&name).await?;
+    *RESOLVED_HOST.write().unwrap_or_else(|e| e.into_inner()) = Some((name, host.clone()));
+    Ok(host)
+}
+
+async fn endpoint_url(endpoint: &str) -> Result<String, PineconeApiError> {
+    Ok(format!("{}{}", base_url().await?, endpoint))
 }
 
 fn headers(api_key: String) -> HeaderMap {
@@ -34,12 +241,23 @@ fn headers(api_key: String) -> HeaderMap {
     headers
 }
 
-const BASE_URL: &str = "https://test-index-1a567db.svc.us-west4-gcp.pinecone.io/";
+const DEFAULT_BASE_URL: &str = "https://test-index-1a567db.svc.us-west4-gcp.pinecone.io/";
+const DEFAULT_CONTROL_PLANE_URL: &str = "https://api.pinecone.io";
+const DESCRIBE_INDEX: &str = "describe_index";
+const API_VERSION_HEADER: &str = "X-Pinecone-API-Version";
+
+/// Largest upsert body Pinecone accepts.
+const MAX_UPSERT_BYTES: usize = 2 * 1024 * 1024;
 const UPSERT: &str = "vectors/upsert";
 const QUERY: &str = "query";
 const UPDATE: &str = "vectors/update";
 const FETCH: &str = "vectors/fetch";
 const DELETE: &str = "vectors/delete";
+const LIST: &str = "vectors/list";
+
+/// Ids Pinecone deletes per request at most.
+const MAX_DELETE_IDS: usize = 1000;
+const DESCRIBE_INDEX_STATS: &str = "describe_index_stats";
 
 // Error handling
 #[derive(Debug, Error)]
@@ -58,9 +276,87 @@ pub enum PineconeApiError {
 
     #[error("DeleteError: {0}")]
     DeleteError(String),
+
+    #[error("DescribeError: {0}")]
+    DescribeError(String),
+
+    #[error("ListError: {0}")]
+    ListError(String),
 }
 // Error handling
 
+/// Vector counts and dimension of the index, per namespace.
+pub async fn describe_index_stats() -> Result<IndexStats, PineconeApiError> {
+    let error = |e: reqwest::Error| PineconeApiError::DescribeError(e.to_string());
+    let response = versioned(CLIENT.post(endpoint_url(DESCRIBE_INDEX_STATS).await?))
+        .json(&serde_json::json!({}))
+        .send()
+        .instrument(context::span(DESCRIBE_INDEX_STATS))
+        .await
+        .map_err(error)?;
+    note_api_version(&response);
+    response
+        .error_for_status()
+        .map_err(error)?
+        .json()
+        .await
+        .map_err(error)
+}
+
+/// Posts the JSON `body` to `endpoint` of the index.
+async fn post<T, E>(endpoint: &str, body: Vec<u8>, error: E) -> Result<T, PineconeApiError>
+    where
+        T: DeserializeOwned,
+        E: Fn(String) -> PineconeApiError,
+{
+    let breaker = CIRCUIT_BREAKER.get().map(Arc::as_ref);
+    if breaker.is_some_and(|breaker| !breaker.allow()) {
+        return Err(error("circuit breaker open after repeated failures".to_string()));
+    }
+    let url = endpoint_url(endpoint).await.map_err(|e| error(e.to_string()))?;
+    post_to(url, endpoint, body, breaker, error).await
+}
+
+/// Posts the JSON `body` to `url`, an `endpoint` of some index, reporting to `breaker`
+/// whether the index was reachable.
+async fn post_to<T, E>(
+    url: String,
+    endpoint: &str,
+    body: Vec<u8>,
+    breaker: Option<&CircuitBreaker>,
+    error: E,
+) -> Result<T, PineconeApiError>
+    where
+        T: DeserializeOwned,
+        E: Fn(String) -> PineconeApiError,
+{
+    let mut request = versioned(CLIENT.post(url)).body(body);
+    if let Some(traceparent) = context::traceparent() {
+        request = request.header("traceparent", traceparent);
+    }
+    let response = request.send().instrument(context::span(endpoint)).await;
+
+    if let Some(breaker) = breaker {
+        breaker.record(response.as_ref().is_ok_and(|response| !response.status().is_server_error()));
+    }
+
+    let result = match response {
+        Ok(response) => {
+            note_api_version(&response);
+            let status = response.status();
+
+            if status.is_success() {
+                response.json().await.map_err(|e| e.to_string())
+            } else {
+                Err(format!("Error status: {}", status))
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    result.map_err(error)
+}
+
 // Request Functions
 impl PineconeRequest {
     async fn send<T, E>(&self, endpoint: &str, error: E) -> Result<T, PineconeApiError>
@@ -68,33 +364,35 @@ impl PineconeRequest {
             T: DeserializeOwned,
             E: Fn(String) -> PineconeApiError,
     {
-        let response = CLIENT
-            .post(format!("{}{}", BASE_URL, endpoint))
-            .json(self)
-            .send()
-            .await;
-
-        println!("{:?}", response);
-
-        let result = match response {
-            Ok(response) => {
-                let status = response.status();
+        let body = self.body().and_then(|body| serde_json::to_vec(&body)).map_err(|e| error(e.to_string()))?;
+        post(endpoint, body, error).await
+    }
 
-                if status.is_success() {
-                    println!("success");
-                    response.json().await.map_err(|e| e.to_string())
-                } else {
-                    println!("{}", status);
-                    Err(format!("Error status: {}", status))
-                }
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                Err(e.to_string())
-            }
-        };
+    /// JSON body of an upsert, written directly into a buffer sized from
+    /// `estimated_payload_bytes`. Upserts have no versioned shapes to rewrite.
+    fn upsert_body(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(self.estimated_payload_bytes());
+        body.extend_from_slice(b"{\"vectors\":");
+        write_vectors_json(self.vectors().as_deref().unwrap_or_default(), &mut body);
+        if let Some(namespace) = self.namespace().clone().or_else(context::current_namespace) {
+            body.extend_from_slice(b",\"namespace\":");
+            write_json(&namespace, &mut body);
+        }
+        body.push(b'}');
+        body
+    }
 
-        result.map_err(error)
+    /// JSON body of the request, in the current `RequestContext`'s tenant namespace if it
+    /// sets none, and in the versioned schema if an API version is pinned.
+    fn body(&self) -> Result<serde_json::Value, serde_json::Error> {
+        let mut body = serde_json::to_value(self)?;
+        if let (None, Some(namespace)) = (self.namespace(), context::current_namespace()) {
+            body["namespace"] = namespace.into();
+        }
+        if api_version().is_some() {
+            versioned_body(&mut body);
+        }
+        Ok(body)
     }
 
     ///
@@ -102,15 +400,28 @@ impl PineconeRequest {
     ///
     pub async fn upsert(&self) -> Result<PineconeResponse, PineconeApiError> {
         // vectors must not be empty
-        if self.vectors().as_ref().map_or(true, |v| v.len() == 0) {
+        if self.vectors().as_ref().is_none_or(|v| v.is_empty()) {
             return Err(PineconeApiError::UpsertError(
                 "vectors cannot be empty".to_string(),
             ));
         }
 
-        self.send(UPSERT, |error_message| {
-                PineconeApiError::UpsertError(error_message)
-            }).await
+        if self.estimated_payload_bytes() <= MAX_UPSERT_BYTES {
+            return post(UPSERT, self.upsert_body(), PineconeApiError::UpsertError).await;
+        }
+
+        // Too large for one request: upsert in batches that each fit.
+        let mut upserted = 0;
+        for vectors in split_by_size(self.vectors().clone().unwrap_or_default(), MAX_UPSERT_BYTES)? {
+            let count = vectors.len() as i64;
+            let batch = match self.namespace() {
+                Some(namespace) => PineconeRequest::builder().vectors(vectors).namespace(namespace.clone()).build(),
+                None => PineconeRequest::builder().vectors(vectors).build(),
+            };
+            let response: PineconeResponse = post(UPSERT, batch.upsert_body(), PineconeApiError::UpsertError).await?;
+            upserted += response.upserted_count().unwrap_or(count);
+        }
+        Ok(PineconeResponse::upserted(upserted))
     }
 
     ///
@@ -126,8 +437,38 @@ impl PineconeRequest {
         }).await
     }
 
+    /// Runs the query, parsing the metadata of every match into `T`, see
+    /// `Match::parse_metadata`. Set `include_metadata`, or only optional fields can be parsed.
+    pub async fn query_as<T: DeserializeOwned>(&self) -> Result<Vec<Match<T>>, PineconeApiError> {
+        let response = self.query().await?;
+        response
+            .matches()
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|found| {
+                let id = found.id().clone();
+                found
+                    .parse_metadata()
+                    .map_err(|e| PineconeApiError::QueryError(format!("match {}: {}", id, e)))
+            })
+            .collect()
+    }
+
+    /// Runs the query against the index at `host`, a data plane URL, instead of the
+    /// configured index, e.g. a replica in another region (see `index_host`).
+    pub async fn query_at(&self, host: &str) -> Result<PineconeResponse, PineconeApiError> {
+        if let Some(value) = self.validate_query_request() {
+            return value;
+        }
+
+        let error = PineconeApiError::QueryError;
+        let body = self.body().and_then(|body| serde_json::to_vec(&body)).map_err(|e| error(e.to_string()))?;
+        post_to(format!("{}/{}", host.trim_end_matches('/'), QUERY), QUERY, body, None, error).await
+    }
+
     fn validate_query_request(&self) -> Option<Result<PineconeResponse, PineconeApiError>> {
-        if self.id().as_ref().map_or(false, |id| id.len() > 512) {
+        if self.id().as_ref().is_some_and(|id| id.len() > 512) {
             return Some(Err(PineconeApiError::QueryError(
                 "id length must be 512 or less".to_string(),
             )));
@@ -143,21 +484,18 @@ impl PineconeRequest {
             return Some(Err(PineconeApiError::QueryError(
                 "top_k cannot be empty".to_string(),
             )));
-        } else if self.top_k().as_ref().map_or(false, |k| k < &1) {
+        } else if self.top_k().is_some_and(|k| k < 1) {
             return Some(Err(PineconeApiError::QueryError(
                 "top_k must be at least 1".to_string(),
             )));
         }
 
         if let Some(sparse) = &self.sparse_vector() {
-            let indices_len = sparse.indices().as_ref().map_or(0, |indices| indices.len());
-            let values_len = sparse.values().len();
-
-            if indices_len == 0 {
+            if sparse.indices().is_empty() {
                 return Some(Err(PineconeApiError::QueryError(
                     "indices cannot be empty when providing a sparse_vector".to_string(),
                 )));
-            } else if indices_len != values_len {
+            } else if !sparse.is_valid() {
                 return Some(Err(PineconeApiError::QueryError(
                     "indices and values must have the same length when providing a sparse_vector"
                         .to_string(),
@@ -197,10 +535,7 @@ impl PineconeRequest {
         }
 
         if let Some(sparse) = &self.sparse_values() {
-            let indices_len = sparse.indices().as_ref().map_or(0, |indices| indices.len());
-            let values_len = sparse.values().len();
-
-            if values_len == 0 || indices_len == 0 || (values_len != indices_len) {
+            if !sparse.is_valid() {
                 return Some(Err(PineconeApiError::UpdateError(
                     "sparse indices and values cannot be empty and must have the same length."
                         .to_string(),
@@ -215,28 +550,27 @@ impl PineconeRequest {
     /// Fields: ids, namespace
     ///
     pub async fn fetch(&self) -> Result<PineconeResponse, PineconeApiError> {
-        let url: String;
-        if let Some(IdList::TextIds(ids)) = &self.ids() {
-            let url_temp = ids
-                .iter()
-                .fold(BASE_URL.to_owned(), |url, id| format!("{}&ids={}", url, id));
-
-            if let Some(namespace) = self.namespace() {
-                url = format!("{}&namespace={}", url_temp, namespace);
-            } else {
-                url = url_temp;
-            }
+        // Ids are sent as query pairs, escaped, since chunk ids contain '#'.
+        let mut query: Vec<(&str, String)> = if let Some(IdList::TextIds(ids)) = &self.ids() {
+            ids.iter().map(|id| ("ids", id.clone())).collect()
         } else {
             return Err(PineconeApiError::FetchError(
                 "ids cannot be empty".to_string(),
             ));
+        };
+
+        if let Some(namespace) = self.namespace().clone().or_else(context::current_namespace) {
+            query.push(("namespace", namespace));
         }
 
-        let response = CLIENT
-            .get(url)
+        let response = versioned(CLIENT.get(endpoint_url(FETCH).await?))
+            .query(&query)
             .send()
+            .instrument(context::span(FETCH))
             .await
-            .map_err(|e| PineconeApiError::FetchError(e.to_string()))?
+            .map_err(|e| PineconeApiError::FetchError(e.to_string()))?;
+        note_api_version(&response);
+        let response = response
             .json()
             .await
             .map_err(|e| PineconeApiError::FetchError(e.to_string()))?;
@@ -244,24 +578,71 @@ impl PineconeRequest {
         Ok(response)
     }
 
+    /// Fetches the vectors, parsing their metadata into `T`, see
+    /// `AdditionalProp::parse_metadata`. Keyed by id.
+    pub async fn fetch_as<T: DeserializeOwned>(&self) -> Result<HashMap<String, AdditionalProp<T>>, PineconeApiError> {
+        let response = self.fetch().await?;
+        response
+            .vectors()
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(id, vector)| match vector.parse_metadata() {
+                Ok(vector) => Ok((id, vector)),
+                Err(e) => Err(PineconeApiError::FetchError(format!("vector {}: {}", id, e))),
+            })
+            .collect()
+    }
+
     ///
     /// Fields: ids, delete_all, filter, namespace
     ///
     pub async fn delete(&self) -> Result<PineconeResponse, PineconeApiError> {
+        let affected = match AUDIT_LOG.get() {
+            Some(_) if self.validate_delete_request().is_none() => self.delete_count().await,
+            _ => None,
+        };
+        self.delete_counted(affected).await
+    }
+
+    /// Deletes as `delete`, recording `affected` as the vectors targeted in the audit log
+    /// rather than counting them again.
+    pub async fn delete_counted(&self, affected: Option<u64>) -> Result<PineconeResponse, PineconeApiError> {
         if let Some(value) = self.validate_delete_request() {
             return value;
         }
 
-        self.send(DELETE, |error_message| {
-            PineconeApiError::DeleteError(error_message)
-        })
-            .await
+        let Some(log) = AUDIT_LOG.get() else {
+            return self.send(DELETE, PineconeApiError::DeleteError).await;
+        };
+        let result = self.send(DELETE, PineconeApiError::DeleteError).await;
+        let entry = AuditEntry::new(self, affected, result.as_ref().err().map(ToString::to_string));
+        // The delete already happened, so its result stands; `AuditLog::failures` counts
+        // the entries missing.
+        if let Err(e) = log.append(&entry).await {
+            tracing::error!("Failed to record the delete in the audit log: {}", e);
+        }
+        result
+    }
+
+    /// Vectors the delete targets, as far as known before it is sent: the ids, or the
+    /// namespace's vector count for `delete_all`. Pinecone does not count deletes by filter.
+    async fn delete_count(&self) -> Option<u64> {
+        if *self.delete_all() == Some(true) {
+            let namespace = self.namespace().clone().or_else(context::current_namespace).unwrap_or_default();
+            let stats = describe_index_stats().await.ok()?;
+            return Some(stats.namespaces().get(&namespace).map_or(0, NamespaceStats::vector_count));
+        }
+        match self.ids() {
+            Some(IdList::TextIds(ids)) if self.filter().is_none() => Some(ids.len() as u64),
+            _ => None,
+        }
     }
 
     fn validate_delete_request(&self) -> Option<Result<PineconeResponse, PineconeApiError>> {
-        if self.ids().is_none() && self.delete_all().is_none() {
+        if self.ids().is_none() && self.delete_all().is_none() && self.filter().is_none() {
             return Some(Err(PineconeApiError::DeleteError(
-                "You must provide either delete_all or ids to delete".to_string(),
+                "You must provide either delete_all, ids, or filter to delete".to_string(),
             )));
         }
 
@@ -272,7 +653,7 @@ impl PineconeRequest {
                 )));
             }
             Some(IdList::TextIds(val)) => {
-                if val.len() == 0 {
+                if val.is_empty() {
                     return Some(Err(PineconeApiError::DeleteError(
                         "ids cannot be empty".to_string(),
                     )));
@@ -287,15 +668,314 @@ impl PineconeRequest {
     // validation functions
 }
 
+/// Handle on the index that runs requests in one namespace, see `PineconeClient::namespace`.
+/// The default handle leaves requests in the namespace they name, or the current
+/// `RequestContext`'s.
+#[derive(Debug, Clone, Default)]
+pub struct PineconeClient {
+    namespace: Option<String>,
+}
+
+impl PineconeClient {
+    /// Handle whose requests all run in `namespace`, so callers name it once.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let docs = PineconeClient::namespace("docs");
+    /// docs.upsert(vectors).await?;
+    /// let response = docs.query(PineconeRequest::builder().vector(vector).top_k(5).build()).await?;
+    /// docs.delete_document("refunds").await?;
+    /// ```
+    pub fn namespace(namespace: &str) -> Self {
+        PineconeClient {
+            namespace: Some(namespace.to_string()),
+        }
+    }
+
+    pub fn namespace_name(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// `request` in this handle's namespace. A request naming another namespace is refused
+    /// rather than silently moved.
+    fn scoped<E>(&self, mut request: PineconeRequest, error: E) -> Result<PineconeRequest, PineconeApiError>
+        where
+            E: Fn(String) -> PineconeApiError,
+    {
+        let namespace = match &self.namespace {
+            Some(namespace) => namespace,
+            None => return Ok(request),
+        };
+        match request.namespace() {
+            Some(other) if other != namespace => Err(error(format!(
+                "request is for namespace {:?} but the handle is scoped to {:?}",
+                other, namespace
+            ))),
+            Some(_) => Ok(request),
+            None => {
+                request.set_namespace(namespace.clone());
+                Ok(request)
+            }
+        }
+    }
+
+    pub async fn upsert(&self, vectors: Vec<Vector>) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(PineconeRequest::builder().vectors(vectors).build(), PineconeApiError::UpsertError)?
+            .upsert()
+            .await
+    }
+
+    /// Runs the query `request`, which needs no namespace of its own.
+    pub async fn query(&self, request: PineconeRequest) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(request, PineconeApiError::QueryError)?.query().await
+    }
+
+    /// Runs the query `request`, parsing the metadata of every match into `T`, see
+    /// `PineconeRequest::query_as`.
+    pub async fn query_as<T: DeserializeOwned>(&self, request: PineconeRequest) -> Result<Vec<Match<T>>, PineconeApiError> {
+        self.scoped(request, PineconeApiError::QueryError)?.query_as().await
+    }
+
+    /// Runs the update `request`, which needs no namespace of its own.
+    pub async fn update(&self, request: PineconeRequest) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(request, PineconeApiError::UpdateError)?.update().await
+    }
+
+    pub async fn fetch(&self, ids: Vec<String>) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(PineconeRequest::builder().ids(IdList::TextIds(ids)).build(), PineconeApiError::FetchError)?
+            .fetch()
+            .await
+    }
+
+    /// Fetches the vectors `ids`, parsing their metadata into `T`.
+    pub async fn fetch_as<T: DeserializeOwned>(
+        &self,
+        ids: Vec<String>,
+    ) -> Result<HashMap<String, AdditionalProp<T>>, PineconeApiError> {
+        self.scoped(PineconeRequest::builder().ids(IdList::TextIds(ids)).build(), PineconeApiError::FetchError)?
+            .fetch_as()
+            .await
+    }
+
+    pub async fn delete(&self, ids: Vec<String>) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(PineconeRequest::builder().ids(IdList::TextIds(ids)).build(), PineconeApiError::DeleteError)?
+            .delete()
+            .await
+    }
+
+    /// Deletes the vectors whose metadata matches `filter`.
+    pub async fn delete_by_filter(&self, filter: Filter) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(PineconeRequest::builder().filter(filter).build(), PineconeApiError::DeleteError)?
+            .delete()
+            .await
+    }
+
+    /// Deletes every vector of the namespace.
+    pub async fn delete_all(&self) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(PineconeRequest::builder().delete_all(true).build(), PineconeApiError::DeleteError)?
+            .delete()
+            .await
+    }
+
+    /// Deletes every vector of the namespace, already counted at `vector_count`, which the
+    /// audit log records without describing the index again.
+    pub async fn delete_all_counted(&self, vector_count: u64) -> Result<PineconeResponse, PineconeApiError> {
+        self.scoped(PineconeRequest::builder().delete_all(true).build(), PineconeApiError::DeleteError)?
+            .delete_counted(Some(vector_count))
+            .await
+    }
+
+    /// One page of the ids starting with `prefix`, continuing from `pagination_token`.
+    /// Listing needs a serverless index.
+    pub async fn list(&self, prefix: &str, pagination_token: Option<&str>) -> Result<ListResponse, PineconeApiError> {
+        let error = |e: reqwest::Error| PineconeApiError::ListError(e.to_string());
+        let mut query = vec![("prefix", prefix.to_string())];
+        if let Some(namespace) = self.namespace.clone().or_else(context::current_namespace) {
+            query.push(("namespace", namespace));
+        }
+        if let Some(token) = pagination_token {
+            query.push(("paginationToken", token.to_string()));
+        }
+
+        let response = versioned(CLIENT.get(endpoint_url(LIST).await?))
+            .query(&query)
+            .send()
+            .instrument(context::span(LIST))
+            .await
+            .map_err(error)?;
+        note_api_version(&response);
+        response
+            .error_for_status()
+            .map_err(error)?
+            .json()
+            .await
+            .map_err(error)
+    }
+
+    /// Every id starting with `prefix`, following the pages of `list`.
+    pub async fn list_ids(&self, prefix: &str) -> Result<Vec<String>, PineconeApiError> {
+        let mut ids = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let page = self.list(prefix, token.as_deref()).await?;
+            ids.extend(page.ids().map(str::to_string));
+            match page.next() {
+                Some(next) => token = Some(next.to_string()),
+                None => return Ok(ids),
+            }
+        }
+    }
+
+    /// Ids of the chunks of the document `document_id`, as named by `chunk_id`.
+    pub async fn list_chunks(&self, document_id: &str) -> Result<Vec<String>, PineconeApiError> {
+        self.list_ids(&chunk_prefix(document_id)).await
+    }
+
+    /// Deletes every chunk of the document `document_id` and returns how many there were.
+    ///
+    /// All chunk ids are listed before any is deleted, so that paging is not disturbed;
+    /// chunks upserted for the document meanwhile are left alone. To replace a document,
+    /// delete it before ingesting the new version, so no chunks of a longer old version
+    /// linger.
+    pub async fn delete_document(&self, document_id: &str) -> Result<usize, PineconeApiError> {
+        let ids = self.list_chunks(document_id).await?;
+        for batch in ids.chunks(MAX_DELETE_IDS) {
+            self.delete(batch.to_vec()).await?;
+        }
+        Ok(ids.len())
+    }
+}
+
+/// Splits `vectors` into batches whose upsert bodies stay under `max_bytes`, keeping their
+/// order. Fails if a single vector does not fit.
+fn split_by_size(vectors: Vec<Vector>, max_bytes: usize) -> Result<Vec<Vec<Vector>>, PineconeApiError> {
+    // Room for the request's namespace and framing.
+    let budget = max_bytes - 1024;
+    let mut batches: Vec<Vec<Vector>> = Vec::new();
+    let mut batch_bytes = 0;
+
+    for vector in vectors {
+        let bytes = vector.estimated_bytes() + 1;
+        if bytes > budget {
+            return Err(PineconeApiError::UpsertError(format!(
+                "vector {} is about {} bytes, over the {} byte request limit",
+                vector.id().as_deref().unwrap_or_default(),
+                bytes,
+                max_bytes
+            )));
+        }
+
+        match batches.last_mut() {
+            Some(batch) if batch_bytes + bytes <= budget => {
+                batch.push(vector);
+                batch_bytes += bytes;
+            }
+            _ => {
+                batches.push(vec![vector]);
+                batch_bytes = bytes;
+            }
+        }
+    }
+    Ok(batches)
+}
+
+/// Rewrites a body from this client's original shapes to the versioned schema: the query
+/// vector `{"values": [...]}` becomes a plain array.
+fn versioned_body(body: &mut serde_json::Value) {
+    if let Some(values) = body.get_mut("vector").and_then(|vector| vector.get_mut("values")).map(|values| values.take()) {
+        body["vector"] = values;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::libs::{
-        openai_api::OpenAIEmbeddingResponse, pinecone_data::Vector,
-    };
+    use crate::libs::openai_api::OpenAIEmbeddingResponse;
+    use crate::libs::pinecone_data::SparseValues;
     use serde_json::from_reader;
     use std::{fs::File, io::BufReader};
     use tokio::test;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::{FakeServices, FAKE_INDEX_NAME};
+
+    #[test]
+    async fn test_versioned_body() {
+        let request = PineconeRequest::builder()
+            .vector(Vector::builder().values(vec![0.5, 0.25]).build())
+            .ids(IdList::TextIds(vec!["a".to_string()]))
+            .top_k(1)
+            .build();
+
+        let mut body = serde_json::to_value(&request).unwrap();
+        versioned_body(&mut body);
+        assert_eq!(body["vector"], serde_json::json!([0.5, 0.25]));
+        assert_eq!(body["ids"], serde_json::json!(["a"]));
+        assert_eq!(body["topK"], 1);
+    }
+
+    #[test]
+    async fn test_id_list() {
+        let request = PineconeRequest::builder()
+            .ids(IdList::TextIds(vec!["a".to_string(), "b".to_string()]))
+            .namespace("docs".to_string())
+            .build();
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({ "ids": ["a", "b"], "namespace": "docs" })
+        );
+
+        let read = |json: &str| serde_json::from_str::<IdList>(json).unwrap();
+        assert!(matches!(read(r#"["a", "b"]"#), IdList::TextIds(ids) if ids == ["a", "b"]));
+        assert!(matches!(read(r#"{"TextIds": ["a"]}"#), IdList::TextIds(ids) if ids == ["a"]));
+        assert!(matches!(read("[1, 2]"), IdList::IntegerIds(ids) if ids == [1, 2]));
+        assert!(matches!(read(r#"{"IntegerIds": [3]}"#), IdList::IntegerIds(ids) if ids == [3]));
+        assert!(matches!(read("[]"), IdList::TextIds(ids) if ids.is_empty()));
+        assert!(serde_json::from_str::<IdList>(r#"{"ids": ["a"]}"#).is_err());
+    }
+
+    #[test]
+    async fn test_upsert_body() {
+        let metadata = [("text".to_string(), "tab\tquote\" é".into())].iter().cloned().collect();
+        let vectors = vec![
+            Vector::builder().id("a".to_string()).values(vec![0.1, -2.5e-8, 3.0, f32::NAN]).metadata(metadata).build(),
+            Vector::builder()
+                .values(vec![1.0])
+                .sparse_values(SparseValues::builder().indices(vec![7, 9]).values(vec![0.5, -1e-9]).build())
+                .build(),
+        ];
+        let request = PineconeRequest::builder().vectors(vectors).namespace("docs".to_string()).build();
+
+        assert_eq!(
+            String::from_utf8(request.upsert_body()).unwrap(),
+            serde_json::to_string(&request).unwrap()
+        );
+    }
+
+    #[test]
+    async fn test_payload_size() {
+        let vector = |id: &str| {
+            let metadata = [("text".to_string(), "\"quoted\"\n".repeat(20).into())].iter().cloned().collect();
+            Vector::builder().id(id.to_string()).values(vec![-1.1754944e-38; 1536]).metadata(metadata).build()
+        };
+        let vectors: Vec<Vector> = (0..200).map(|i| vector(&i.to_string())).collect();
+        let request = PineconeRequest::builder().vectors(vectors.clone()).namespace("docs".to_string()).build();
+
+        let actual = serde_json::to_vec(&request).unwrap().len();
+        let estimated = request.estimated_payload_bytes();
+        assert!(estimated >= actual && estimated < actual + actual / 10, "{} vs {}", estimated, actual);
+
+        let batches = split_by_size(vectors, MAX_UPSERT_BYTES).unwrap();
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 200);
+        assert!(batches.len() > 1);
+        for batch in batches {
+            let request = PineconeRequest::builder().vectors(batch).namespace("docs".to_string()).build();
+            assert!(serde_json::to_vec(&request).unwrap().len() <= MAX_UPSERT_BYTES);
+        }
+
+        let huge = Vector::builder().values(vec![0.0; 200_000]).build();
+        assert!(split_by_size(vec![huge], MAX_UPSERT_BYTES).is_err());
+    }
 
     #[ignore]
     #[test]
@@ -321,6 +1001,7 @@ mod tests {
 
     #[ignore]
     #[test]
+    #[allow(unused_variables, clippy::useless_vec)]
     async fn test_query() {
         let embedding = read_openai_response_from_file("resources/embedding_example.json");
 
@@ -354,6 +1035,112 @@ mod tests {
         let file = File::open(path).unwrap();
         let reader = BufReader::new(file);
         let response: OpenAIEmbeddingResponse = from_reader(reader).unwrap();
-        response.data().get(0).unwrap().embedding().to_owned()
+        response.data().first().unwrap().embedding().to_owned()
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    async fn test_describe_index() {
+        let fakes = FakeServices::start().await;
+        assert_eq!(describe_index(FAKE_INDEX_NAME).await.unwrap().host(), Some(fakes.uri().as_str()));
+        assert!(describe_index("missing").await.is_err());
+        // Requests to the index go to the host described by the control plane.
+        assert_eq!(describe_index_stats().await.unwrap().total_vector_count(), 0);
+        let requests = fakes.server().received_requests().await.unwrap();
+        assert!(requests.iter().any(|request| request.url.path() == "/describe_index_stats"));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    async fn test_namespace_client() {
+        let fakes = FakeServices::start().await;
+        let docs = PineconeClient::namespace("docs");
+        let vector = Vector::builder().id("a".to_string()).values(vec![0.5; 8]).build();
+        docs.upsert(vec![vector]).await.unwrap();
+        assert_eq!((fakes.vector_count(Some("docs")), fakes.vector_count(None)), (1, 0));
+        let typo = PineconeRequest::builder()
+            .namespace("doc".to_string())
+            .vector(Vector::builder().values(vec![0.5; 8]).build())
+            .top_k(1)
+            .build();
+        assert!(docs.query(typo).await.is_err());
+        docs.delete(vec!["a".to_string()]).await.unwrap();
+        assert_eq!(fakes.vector_count(Some("docs")), 0);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    async fn test_document_chunks() {
+        let fakes = FakeServices::start().await;
+        let index = PineconeClient::default();
+        let vectors = ["refunds#chunk0", "refunds#chunk1", "refunds-old#chunk0", "shipping#chunk0"]
+            .iter()
+            .map(|id| Vector::builder().id(id.to_string()).values(vec![0.5; 8]).build())
+            .collect();
+        index.upsert(vectors).await.unwrap();
+        assert_eq!(index.list_chunks("refunds").await.unwrap(), vec!["refunds#chunk0", "refunds#chunk1"]);
+        assert_eq!(index.delete_document("refunds").await.unwrap(), 2);
+        assert!(index.list_chunks("refunds").await.unwrap().is_empty());
+        assert_eq!(fakes.vector_count(None), 2);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    async fn test_typed_metadata() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Article {
+            title: String,
+            year: u16,
+        }
+
+        let _fakes = FakeServices::start().await;
+        let docs = PineconeClient::namespace("docs");
+        let article = Article { title: "Refunds".to_string(), year: 2023 };
+        let values: Vec<f32> = (0..8).map(|n| n as f32).collect();
+        let vector = Vector::builder().id("b".to_string()).values(values.clone()).build();
+        docs.upsert(vec![vector.with_metadata(&article).unwrap()]).await.unwrap();
+        let query = PineconeRequest::builder()
+            .vector(Vector::builder().values(values).build())
+            .top_k(1)
+            .include_metadata(true)
+            .build();
+        assert_eq!(*docs.query_as::<Article>(query).await.unwrap()[0].metadata(), article);
+        assert_eq!(*docs.fetch_as::<Article>(vec!["b".to_string()]).await.unwrap()["b"].metadata(), article);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    async fn test_numeric_filter() {
+        let fakes = FakeServices::start().await;
+        let docs = PineconeClient::namespace("docs");
+        let vectors = [("old", 2019), ("new", 2023)]
+            .iter()
+            .map(|(id, year)| {
+                let vector = Vector::builder().id(id.to_string()).values(vec![0.5; 8]).build();
+                vector.with_metadata(&serde_json::json!({ "year": year })).unwrap()
+            })
+            .collect();
+        docs.upsert(vectors).await.unwrap();
+        assert_eq!(fakes.vector_count(Some("docs")), 2);
+
+        let since = |year: u16| {
+            PineconeRequest::builder()
+                .vector(Vector::builder().values(vec![0.5; 8]).build())
+                .top_k(2)
+                .filter(serde_json::from_value(serde_json::json!({ "year": { "$gte": year } })).unwrap())
+                .build()
+        };
+        let ids = |response: PineconeResponse| -> Vec<String> {
+            response.matches().iter().flatten().map(|m| m.id().to_string()).collect()
+        };
+        assert_eq!(ids(docs.query(since(2020)).await.unwrap()), ["new"]);
+        assert!(ids(docs.query(since(2024)).await.unwrap()).is_empty());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    async fn test_index_metric() {
+        let _fakes = FakeServices::start().await;
+        assert_eq!(index_metric().await.unwrap(), Metric::Cosine);
     }
 }
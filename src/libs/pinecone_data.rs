@@ -1,8 +1,50 @@
 use std::collections::HashMap;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use typed_builder::TypedBuilder;
 
+use super::typed_metadata::{from_metadata, to_metadata, MetadataError};
+
+/// Upper bound of the JSON length of an `f32` and its separating comma; ryu prints at most
+/// 14 characters, e.g. "-1.1754944e-38".
+const MAX_FLOAT_JSON_BYTES: usize = 15;
+
+/// Upper bound of the JSON length of an `i64` and its separating comma.
+const MAX_INTEGER_JSON_BYTES: usize = 21;
+
+/// Metadata of a vector. Pinecone stores strings, numbers, booleans, and lists of strings,
+/// and filters compare them by type, so numbers must not be stored as text.
+pub type Metadata = HashMap<String, Value>;
+
+/// A Pinecone metadata filter, e.g. `{"year": {"$gte": 2020}, "source": "faq"}`, see
+/// `filter::matches`.
+pub type Filter = HashMap<String, Value>;
+
+/// Separates a document's id from the chunk number in vector ids.
+const CHUNK_ID_SEPARATOR: &str = "#chunk";
+
+/// Id of the vector of chunk `n` of the document `document_id`, e.g. "refunds#chunk0". All
+/// chunks of a document share the prefix `chunk_prefix(document_id)`.
+pub fn chunk_id(document_id: &str, n: usize) -> String {
+    format!("{}{}{}", document_id, CHUNK_ID_SEPARATOR, n)
+}
+
+/// Prefix of the ids of every chunk of `document_id`, and of no other document's.
+pub fn chunk_prefix(document_id: &str) -> String {
+    format!("{}{}", document_id, CHUNK_ID_SEPARATOR)
+}
+
+/// Id of the document the chunk `vector_id` belongs to, or `vector_id` itself if it is not
+/// the id of a chunk.
+pub fn document_id(vector_id: &str) -> &str {
+    match vector_id.rsplit_once(CHUNK_ID_SEPARATOR) {
+        Some((document_id, n)) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => document_id,
+        _ => vector_id,
+    }
+}
+
 /// PineconeRequest represents a request to the Pinecone API.
 ///
 /// # Fields
@@ -14,8 +56,8 @@ use typed_builder::TypedBuilder;
 /// * `include_metadata`: Optional flag to include metadata in the response.
 /// * `include_values`: Optional flag to include values in the response.
 /// * `set_metadata`: Optional metadata to set for the specified vector.
-/// * `sparse_vector`: Optional sparse vector representation.
-/// * `sparse_values`: Optional sparse vector values.
+/// * `sparse_vector`: Optional sparse values to query with, next to `vector`.
+/// * `sparse_values`: Optional sparse values to set for the specified vector.
 /// * `ids`: Optional list of integer or text IDs for fetching vectors.
 /// * `id`: Optional single ID for fetching a vector.
 /// * `filter`: Optional filter for the request.
@@ -53,17 +95,17 @@ pub struct PineconeRequest {
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "setMetadata")]
-    metadata: Option<HashMap<String, String>>,
+    metadata: Option<Metadata>,
 
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "sparseVector")]
-    sparse_vector: Option<Vector>,
+    sparse_vector: Option<SparseValues>,
 
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "sparseValues")]
-    sparse_values: Option<Vector>,
+    sparse_values: Option<SparseValues>,
 
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -75,7 +117,7 @@ pub struct PineconeRequest {
 
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
-    filter: Option<HashMap<String, String>>,
+    filter: Option<Filter>,
 
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -83,12 +125,40 @@ pub struct PineconeRequest {
     delete_all: Option<bool>,
 }
 
+/// Ids of a fetch or delete, serialized as the plain JSON array Pinecone takes. The
+/// externally tagged `{"TextIds": [...]}` of older versions is still read, so stored
+/// requests keep loading.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged, from = "IdListRepr")]
 pub enum IdList {
     IntegerIds(Vec<i64>),
     TextIds(Vec<String>),
 }
 
+/// Shapes `IdList` is read from; an empty array is read as text ids.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IdListRepr {
+    TextIds(Vec<String>),
+    IntegerIds(Vec<i64>),
+    Tagged(TaggedIdList),
+}
+
+#[derive(Deserialize)]
+enum TaggedIdList {
+    IntegerIds(Vec<i64>),
+    TextIds(Vec<String>),
+}
+
+impl From<IdListRepr> for IdList {
+    fn from(repr: IdListRepr) -> Self {
+        match repr {
+            IdListRepr::TextIds(ids) | IdListRepr::Tagged(TaggedIdList::TextIds(ids)) => IdList::TextIds(ids),
+            IdListRepr::IntegerIds(ids) | IdListRepr::Tagged(TaggedIdList::IntegerIds(ids)) => IdList::IntegerIds(ids),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, TypedBuilder, Clone)]
 pub struct Vector {
     #[builder(setter(strip_option), default)]
@@ -98,18 +168,33 @@ pub struct Vector {
     values: Vec<f32>,
 
     #[builder(setter(strip_option), default)]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    indices: Option<Vec<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "sparseValues")]
+    sparse_values: Option<SparseValues>,
 
     #[builder(setter(strip_option), default)]
     #[serde(skip_serializing_if = "Option::is_none")]
-    metadata: Option<HashMap<String, String>>,
+    metadata: Option<Metadata>,
+}
+
+/// Sparse part of a vector, for hybrid search: the nonzero `values` and their `indices`,
+/// which Pinecone requires to be as many and not empty.
+///
+/// # Example
+///
+/// ```rust
+/// let sparse = SparseValues::builder().indices(vec![7, 42]).values(vec![0.5, 0.25]).build();
+/// let vector = Vector::builder().id(id).values(values).sparse_values(sparse).build();
+/// ```
+#[derive(Debug, Serialize, Deserialize, TypedBuilder, Clone, PartialEq)]
+pub struct SparseValues {
+    indices: Vec<u32>,
+    values: Vec<f32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PineconeResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
-    vectors: Option<Vec<AdditionalProp>>,
+    vectors: Option<HashMap<String, AdditionalProp>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     namespace: Option<String>,
@@ -117,29 +202,172 @@ pub struct PineconeResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     matches: Option<Vec<Match>>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "upsertedCount")]
     upserted_count: Option<i64>,
+
+    /// Read units consumed, reported by versioned APIs (see `pinecone_api::set_api_version`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usage: Option<PineconeUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PineconeUsage {
+    #[serde(default)]
+    read_units: u64,
+}
+
+/// Response of Pinecone's `describe_index_stats`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    #[serde(default)]
+    namespaces: HashMap<String, NamespaceStats>,
+
+    #[serde(default)]
+    dimension: u32,
+
+    #[serde(default)]
+    index_fullness: f32,
+
+    #[serde(default)]
+    total_vector_count: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceStats {
+    #[serde(default)]
+    vector_count: u64,
+}
+
+/// Response of Pinecone's control plane `describe_index`.
+///
+/// The current API returns the data plane host at the top level; the classic,
+/// environment-based controller nests it under `status`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexDescription {
+    #[serde(default)]
+    name: String,
+
+    #[serde(default)]
+    dimension: u32,
+
+    #[serde(default)]
+    metric: Option<String>,
+
+    #[serde(default)]
+    host: Option<String>,
+
+    #[serde(default)]
+    status: Option<IndexStatus>,
+}
+
+/// Similarity metric of an index, which decides what its query scores mean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    Cosine,
+    DotProduct,
+    Euclidean,
+}
+
+impl Metric {
+    /// The metric named `name` as `describe_index` reports it, e.g. "dotproduct".
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "cosine" => Some(Metric::Cosine),
+            "dotproduct" => Some(Metric::DotProduct),
+            "euclidean" => Some(Metric::Euclidean),
+            _ => None,
+        }
+    }
+
+    /// `score` of a query on an index with this metric as a cosine similarity, so one
+    /// `min_score` applies to any index: higher is closer, and 1 is identical.
+    ///
+    /// Assumes unit-length embeddings, as OpenAI's are: their dot product is their cosine,
+    /// and the squared Euclidean distance Pinecone scores with is 2 - 2 * cosine. Results
+    /// are clamped to [-1, 1].
+    pub fn normalize(self, score: f32) -> f32 {
+        let cosine = match self {
+            Metric::Cosine | Metric::DotProduct => score,
+            Metric::Euclidean => 1.0 - score / 2.0,
+        };
+        cosine.clamp(-1.0, 1.0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexStatus {
+    #[serde(default)]
+    ready: bool,
+
+    #[serde(default)]
+    host: Option<String>,
+}
+
+/// One page of the ids `list` found with a prefix.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListResponse {
+    #[serde(default)]
+    vectors: Vec<ListedId>,
+
+    #[serde(default)]
+    pagination: Option<Pagination>,
+
+    #[serde(default)]
+    namespace: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ListedId {
+    id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Pagination {
+    #[serde(default)]
+    next: Option<String>,
 }
 
+/// A fetched vector. Its metadata can be parsed into a struct of the caller's, see
+/// `parse_metadata`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct AdditionalProp {
+#[serde(bound(deserialize = "M: Deserialize<'de> + Default"))]
+pub struct AdditionalProp<M = Metadata> {
     id: String,
+
+    #[serde(default)]
     values: Vec<f32>,
-    metadata: HashMap<String, String>,
 
-    #[serde(rename = "sparseValues")]
-    sparse_values: Vector,
+    #[serde(default)]
+    metadata: M,
+
+    #[serde(default, rename = "sparseValues")]
+    sparse_values: Option<SparseValues>,
 }
 
+/// A query match. Its metadata can be parsed into a struct of the caller's, see
+/// `parse_metadata`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Match {
+#[serde(bound(deserialize = "M: Deserialize<'de> + Default"))]
+pub struct Match<M = Metadata> {
     id: String,
     score: f32,
+
+    #[serde(default)]
     values: Vec<f32>,
-    metadata: HashMap<String, String>,
 
-    #[serde(rename = "sparseValues")]
-    sparse_values: Vector,
+    #[serde(default)]
+    metadata: M,
+
+    #[serde(default, rename = "sparseValues")]
+    sparse_values: Option<SparseValues>,
+
+    /// Served from a local fallback index instead of Pinecone, see `LocalIndex`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    degraded: bool,
 }
 
 impl PineconeRequest {
@@ -167,15 +395,15 @@ impl PineconeRequest {
         &self.include_values
     }
 
-    pub fn metadata(&self) -> &Option<HashMap<String, String>> {
+    pub fn metadata(&self) -> &Option<Metadata> {
         &self.metadata
     }
 
-    pub fn sparse_vector(&self) -> &Option<Vector> {
+    pub fn sparse_vector(&self) -> &Option<SparseValues> {
         &self.sparse_vector
     }
 
-    pub fn sparse_values(&self) -> &Option<Vector> {
+    pub fn sparse_values(&self) -> &Option<SparseValues> {
         &self.sparse_values
     }
 
@@ -187,7 +415,7 @@ impl PineconeRequest {
         &self.id
     }
 
-    pub fn filter(&self) -> &Option<HashMap<String, String>> {
+    pub fn filter(&self) -> &Option<Filter> {
         &self.filter
     }
 
@@ -195,6 +423,22 @@ impl PineconeRequest {
         &self.delete_all
     }
 
+    pub(crate) fn set_namespace(&mut self, namespace: String) {
+        self.namespace = Some(namespace);
+    }
+
+    /// Size the JSON body of this request will at most have, from its vectors and
+    /// namespace, without serializing it. Pinecone rejects upserts larger than 2MB.
+    pub fn estimated_payload_bytes(&self) -> usize {
+        let vectors: usize = self
+            .vectors
+            .iter()
+            .flatten()
+            .map(|vector| vector.estimated_bytes() + 1)
+            .sum();
+        let namespace = self.namespace.as_deref().map_or(0, |namespace| json_string_bytes(namespace) + 14);
+        vectors + namespace + 16
+    }
 }
 
 impl Vector {
@@ -206,16 +450,142 @@ impl Vector {
         &self.values
     }
 
-    pub fn indices(&self) -> &Option<Vec<i64>> {
-        &self.indices
+    pub fn sparse_values(&self) -> &Option<SparseValues> {
+        &self.sparse_values
     }
 
-    pub fn metadata(&self) -> &Option<HashMap<String, String>> {
+    pub fn metadata(&self) -> &Option<Metadata> {
         &self.metadata
     }
+
+    /// This vector with `metadata`, a struct of the caller's, as its metadata, see
+    /// `typed_metadata::to_metadata`.
+    pub fn with_metadata<T: Serialize>(mut self, metadata: &T) -> Result<Self, MetadataError> {
+        self.metadata = Some(to_metadata(metadata)?);
+        Ok(self)
+    }
+
+    /// Upper bound of the JSON size of this vector, see `PineconeRequest::estimated_payload_bytes`.
+    pub fn estimated_bytes(&self) -> usize {
+        let id = self.id.as_deref().map_or(0, |id| json_string_bytes(id) + 6);
+        let values = self.values.len() * MAX_FLOAT_JSON_BYTES + 11;
+        let sparse = self.sparse_values.as_ref().map_or(0, SparseValues::estimated_bytes);
+        let metadata = self.metadata.as_ref().map_or(0, |metadata| {
+            metadata
+                .iter()
+                .map(|(key, value)| json_string_bytes(key) + json_value_bytes(value) + 2)
+                .sum::<usize>()
+                + 13
+        });
+        id + values + sparse + metadata + 2
+    }
 }
 
-impl AdditionalProp {
+impl SparseValues {
+    pub fn indices(&self) -> &Vec<u32> {
+        &self.indices
+    }
+
+    pub fn values(&self) -> &Vec<f32> {
+        &self.values
+    }
+
+    /// Whether Pinecone accepts these values: indices and values as many and not empty.
+    pub fn is_valid(&self) -> bool {
+        !self.indices.is_empty() && self.indices.len() == self.values.len()
+    }
+
+    /// Upper bound of the JSON size of these values and their `sparseValues` key.
+    fn estimated_bytes(&self) -> usize {
+        self.indices.len() * MAX_INTEGER_JSON_BYTES + self.values.len() * MAX_FLOAT_JSON_BYTES + 40
+    }
+}
+
+/// Appends `vectors` to `out` as a JSON array, byte for byte as serde_json would, with the
+/// values formatted by `ryu` straight into `out` instead of through the serde data model.
+/// Reuse `out` across batches to avoid reallocating it; see `benches/vector_serialization.rs`.
+pub fn write_vectors_json(vectors: &[Vector], out: &mut Vec<u8>) {
+    out.push(b'[');
+    for (n, vector) in vectors.iter().enumerate() {
+        if n > 0 {
+            out.push(b',');
+        }
+        vector.write_json(out);
+    }
+    out.push(b']');
+}
+
+/// Appends `values` to `out` as a JSON array. Non-finite values are written as `null`, as
+/// serde_json does.
+pub fn write_floats_json(values: &[f32], out: &mut Vec<u8>) {
+    let mut buffer = ryu::Buffer::new();
+    out.push(b'[');
+    for (n, value) in values.iter().enumerate() {
+        if n > 0 {
+            out.push(b',');
+        }
+        match value.is_finite() {
+            true => out.extend_from_slice(buffer.format_finite(*value).as_bytes()),
+            false => out.extend_from_slice(b"null"),
+        }
+    }
+    out.push(b']');
+}
+
+impl Vector {
+    /// Appends this vector to `out` as a JSON object, see `write_vectors_json`.
+    pub fn write_json(&self, out: &mut Vec<u8>) {
+        out.push(b'{');
+        if let Some(id) = &self.id {
+            out.extend_from_slice(b"\"id\":");
+            write_json(id, out);
+            out.push(b',');
+        }
+        out.extend_from_slice(b"\"values\":");
+        write_floats_json(&self.values, out);
+        if let Some(sparse) = &self.sparse_values {
+            out.extend_from_slice(b",\"sparseValues\":{\"indices\":");
+            write_json(&sparse.indices, out);
+            out.extend_from_slice(b",\"values\":");
+            write_floats_json(&sparse.values, out);
+            out.push(b'}');
+        }
+        if let Some(metadata) = &self.metadata {
+            out.extend_from_slice(b",\"metadata\":");
+            write_json(metadata, out);
+        }
+        out.push(b'}');
+    }
+}
+
+/// Appends `value` to `out` as JSON.
+pub(crate) fn write_json<T: Serialize + ?Sized>(value: &T, out: &mut Vec<u8>) {
+    serde_json::to_writer(&mut *out, value).expect("strings, integers, and JSON maps serialize");
+}
+
+/// Length of `value` as JSON.
+fn json_value_bytes(value: &Value) -> usize {
+    match value {
+        Value::String(text) => json_string_bytes(text),
+        Value::Array(items) => items.iter().map(|item| json_value_bytes(item) + 1).sum::<usize>() + 2,
+        other => other.to_string().len(),
+    }
+}
+
+/// Length of `text` as a JSON string, quotes and escapes included.
+fn json_string_bytes(text: &str) -> usize {
+    let escaped: usize = text
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+            c if c < ' ' => 6,
+            c => c.len_utf8(),
+        })
+        .sum();
+    escaped + 2
+}
+
+impl<M> AdditionalProp<M> {
     pub fn id(&self) -> &String {
         &self.id
     }
@@ -224,16 +594,47 @@ impl AdditionalProp {
         &self.values
     }
 
-    pub fn sparse_values(&self) -> &Vector {
+    pub fn sparse_values(&self) -> &Option<SparseValues> {
         &self.sparse_values
     }
 
-    pub fn metadata(&self) -> &HashMap<String, String> {
+    pub fn metadata(&self) -> &M {
         &self.metadata
     }
 }
 
-impl Match {
+impl AdditionalProp {
+    /// This vector with its metadata parsed into `T`, see `typed_metadata::from_metadata`.
+    pub fn parse_metadata<T: DeserializeOwned>(self) -> Result<AdditionalProp<T>, MetadataError> {
+        Ok(AdditionalProp {
+            metadata: from_metadata(&self.metadata)?,
+            id: self.id,
+            values: self.values,
+            sparse_values: self.sparse_values,
+        })
+    }
+}
+
+/// A fetched vector as it is upserted again, e.g. into another namespace. Every field is
+/// carried over, the sparse values of a hybrid index included.
+impl From<AdditionalProp> for Vector {
+    fn from(vector: AdditionalProp) -> Self {
+        let AdditionalProp {
+            id,
+            values,
+            metadata,
+            sparse_values,
+        } = vector;
+        Vector {
+            id: Some(id),
+            values,
+            sparse_values,
+            metadata: Some(metadata),
+        }
+    }
+}
+
+impl<M> Match<M> {
     pub fn id(&self) -> &String {
         &self.id
     }
@@ -246,17 +647,90 @@ impl Match {
         &self.values
     }
 
-    pub fn sparse_values(&self) -> &Vector {
+    pub fn sparse_values(&self) -> &Option<SparseValues> {
         &self.sparse_values
     }
 
-    pub fn metadata(&self) -> &HashMap<String, String> {
+    pub fn metadata(&self) -> &M {
         &self.metadata
     }
+
+    /// Whether the match came from a local fallback index rather than Pinecone, so it may
+    /// miss recent vectors.
+    pub fn degraded(&self) -> bool {
+        self.degraded
+    }
+}
+
+impl Match {
+    /// This match with its metadata parsed into `T`, see `typed_metadata::from_metadata`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// #[derive(Deserialize)]
+    /// struct Article {
+    ///     title: String,
+    ///     year: u16,
+    /// }
+    ///
+    /// let article: Match<Article> = matches.remove(0).parse_metadata()?;
+    /// println!("{} ({})", article.metadata().title, article.metadata().year);
+    /// ```
+    pub fn parse_metadata<T: DeserializeOwned>(self) -> Result<Match<T>, MetadataError> {
+        Ok(Match {
+            metadata: from_metadata(&self.metadata)?,
+            id: self.id,
+            score: self.score,
+            values: self.values,
+            sparse_values: self.sparse_values,
+            degraded: self.degraded,
+        })
+    }
+
+    /// The metadata value of `key`, if it is a string.
+    pub fn metadata_str(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).and_then(Value::as_str)
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn set_score(&mut self, score: f32) {
+        self.score = score;
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// A match found by a local fallback index.
+    #[cfg(feature = "native")]
+    pub(crate) fn degraded_match(id: String, score: f32, values: Vec<f32>, metadata: Metadata) -> Self {
+        Match {
+            id,
+            score,
+            values,
+            metadata,
+            sparse_values: None,
+            degraded: true,
+        }
+    }
 }
 
 impl PineconeResponse {
-    pub fn vectors(&self) -> &Option<Vec<AdditionalProp>> {
+    /// Response of an upsert sent in several batches.
+    pub(crate) fn upserted(count: i64) -> Self {
+        PineconeResponse {
+            vectors: None,
+            namespace: None,
+            matches: None,
+            upserted_count: Some(count),
+            usage: None,
+        }
+    }
+
+    /// Fetched vectors, keyed by id.
+    pub fn vectors(&self) -> &Option<HashMap<String, AdditionalProp>> {
         &self.vectors
     }
 
@@ -271,4 +745,83 @@ impl PineconeResponse {
     pub fn upserted_count(&self) -> &Option<i64> {
         &self.upserted_count
     }
+
+    pub fn usage(&self) -> &Option<PineconeUsage> {
+        &self.usage
+    }
+}
+
+impl PineconeUsage {
+    pub fn read_units(&self) -> u64 {
+        self.read_units
+    }
+}
+
+impl IndexStats {
+    pub fn namespaces(&self) -> &HashMap<String, NamespaceStats> {
+        &self.namespaces
+    }
+
+    pub fn dimension(&self) -> u32 {
+        self.dimension
+    }
+
+    pub fn index_fullness(&self) -> f32 {
+        self.index_fullness
+    }
+
+    pub fn total_vector_count(&self) -> u64 {
+        self.total_vector_count
+    }
+}
+
+impl IndexDescription {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn dimension(&self) -> u32 {
+        self.dimension
+    }
+
+    pub fn metric(&self) -> &Option<String> {
+        &self.metric
+    }
+
+    /// The metric, if Pinecone reported one this client knows.
+    pub fn parsed_metric(&self) -> Option<Metric> {
+        self.metric.as_deref().and_then(Metric::parse)
+    }
+
+    /// Host of the index's data plane, e.g. "docs-a1b2c3d.svc.us-east-1-aws.pinecone.io".
+    pub fn host(&self) -> Option<&str> {
+        self.host
+            .as_deref()
+            .or_else(|| self.status.as_ref().and_then(|status| status.host.as_deref()))
+    }
+
+    pub fn ready(&self) -> bool {
+        self.status.as_ref().is_some_and(|status| status.ready)
+    }
+}
+
+impl ListResponse {
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.vectors.iter().map(|vector| vector.id.as_str())
+    }
+
+    /// Token of the next page, if there is one.
+    pub fn next(&self) -> Option<&str> {
+        self.pagination.as_ref().and_then(|pagination| pagination.next.as_deref())
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+}
+
+impl NamespaceStats {
+    pub fn vector_count(&self) -> u64 {
+        self.vector_count
+    }
 }
@@ -0,0 +1,1281 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::future::{BoxFuture, Shared};
+use futures::{stream, FutureExt, StreamExt};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use typed_builder::TypedBuilder;
+
+use super::boilerplate::BoilerplateFilter;
+use super::concurrency::AdaptiveConcurrency;
+use super::context;
+use super::database::{upsert, Database};
+use super::failures::{FailedItem, FailureReport, FailureStage};
+use super::idempotency::{idempotency_key, IdempotencyStore};
+use super::observer::{Observer, ObserverError, VectorRecord};
+use super::parents::ParentChunks;
+use super::loaders::directory::{load_directory, SkippedFile};
+use super::metadata_schema::{MetadataSchemaRegistry, SchemaConflict};
+use super::openai_api::{truncate_to_tokens, Message, OpenAIEmbeddingRequest, OpenAIRequest};
+use super::pinecone_api::{PineconeApiError, PineconeClient};
+use super::pinecone_data::{chunk_id, Metadata, PineconeRequest, Vector};
+use super::redact::Redactor;
+use super::rate_limit::Priority;
+use super::splitter::{Splitter, TokenSplitter};
+use super::spool::UpsertSpool;
+
+const UPSERT_BATCH_SIZE: usize = 100;
+
+/// Ids fetched or deleted per request when updating a document's stored chunks.
+const FETCH_BATCH_SIZE: usize = 100;
+
+/// Prefix of the keys the time of the last ingestion into each namespace is stored under.
+const LAST_INGESTION_KEY_PREFIX: &str = "last-ingestion:";
+
+/// Metadata field holding the hash a chunk was upserted with, see `update_document`.
+const CHUNK_HASH_KEY: &str = "chunk_hash";
+
+/// Metadata field holding the time a chunk was embedded, in seconds since the Unix epoch,
+/// unless the chunk brings its own, see `SemanticSearch`'s `recency_half_life`.
+pub const INGESTED_AT_KEY: &str = "ingested_at";
+
+/// Tokens of chunk text kept in the `text` metadata field, well below Pinecone's 40KB
+/// metadata limit per vector.
+const METADATA_TEXT_TOKENS: usize = 4096;
+
+/// Tokens of chunk text sent to the chat model for enrichment.
+const ENRICHMENT_INPUT_TOKENS: usize = 3000;
+
+/// An embedding request shared by every caller waiting for it.
+type PendingEmbedding = Shared<BoxFuture<'static, Result<Vec<f32>, String>>>;
+
+lazy_static! {
+    /// Embedding requests in flight by model and text, see `embed`.
+    static ref IN_FLIGHT_EMBEDDINGS: Mutex<HashMap<(String, String), PendingEmbedding>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("EmbeddingError: {0}")]
+    EmbeddingError(String),
+
+    #[error("EnrichmentError: {0}")]
+    EnrichmentError(String),
+
+    #[error(transparent)]
+    PineconeError(#[from] PineconeApiError),
+
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+
+    #[error("LoaderError: {0}")]
+    LoaderError(String),
+
+    #[error(transparent)]
+    ObserverError(#[from] ObserverError),
+
+    #[error("SchemaError: {0}")]
+    SchemaError(String),
+
+    #[error("SpoolError: {0}")]
+    SpoolError(String),
+
+    #[error("DatabaseError: {0}")]
+    DatabaseError(String),
+}
+
+/// A source document to be chunked, embedded, and upserted.
+///
+/// # Fields
+///
+/// * `id`: Required. Stable identifier of the document, used to derive chunk ids.
+/// * `text`: Required. Full text of the document.
+/// * `metadata`: Optional. Metadata copied onto every chunk of the document.
+#[derive(Debug, Clone, Serialize, Deserialize, TypedBuilder)]
+pub struct Document {
+    id: String,
+    text: String,
+
+    #[builder(default)]
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+/// A piece of a `Document` small enough to embed.
+#[derive(Debug, Clone, Serialize, Deserialize, TypedBuilder)]
+pub struct Chunk {
+    id: String,
+    text: String,
+
+    #[builder(default)]
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+/// Optional stage asking the chat model for a title, keywords, and entities of each chunk.
+///
+/// The results are stored in the chunk metadata under `title`, `keywords`, and `entities`
+/// (lists of strings), so queries can filter on them, e.g. with `{"keywords": "refunds"}`.
+///
+/// # Fields
+///
+/// * `model`: Required. Chat model used for extraction (e.g., "gpt-3.5-turbo").
+/// * `concurrency`: Optional. Number of chunks enriched in parallel. Defaults to 4.
+/// * `on_truncation`: Optional. What to do when the model's answer is cut off by the token
+///   limit. Defaults to `TruncationPolicy::Warn`.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct EnrichmentStage {
+    model: String,
+
+    #[builder(default = 4)]
+    concurrency: usize,
+
+    #[builder(default)]
+    on_truncation: TruncationPolicy,
+}
+
+/// Handling of chat completions that stopped at the token limit (`FinishReason::Length`),
+/// whose output is incomplete.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncationPolicy {
+    /// Use the output as is.
+    Ignore,
+    /// Use the output as is, listing the chunk in the report's `truncated`.
+    #[default]
+    Warn,
+    /// Fail the chunk: a pipeline lists it in the report's `failed`, and `enrich` returns
+    /// `PipelineError::EnrichmentError`.
+    Fail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkSummary {
+    title: String,
+
+    #[serde(default)]
+    keywords: Vec<String>,
+
+    #[serde(default)]
+    entities: Vec<String>,
+}
+
+const ENRICHMENT_PROMPT: &str = "Extract a short title, up to 5 keywords, and the named entities \
+from the text the user sends. Respond only with JSON of the form \
+{\"title\": string, \"keywords\": [string], \"entities\": [string]}.";
+
+impl EnrichmentStage {
+    /// Enriches every chunk in place. Fails on the first chunk the model could not summarize.
+    /// Returns the ids of the chunks whose summary was truncated, under `TruncationPolicy::Warn`.
+    pub async fn enrich(&self, chunks: &mut [Chunk]) -> Result<Vec<String>, PipelineError> {
+        let mut truncated = Vec::new();
+        for (index, outcome) in self.enrich_each(chunks).await {
+            match outcome {
+                Ok(true) => truncated.push(chunks[index].id.clone()),
+                Ok(false) => {}
+                Err(e) => return Err(PipelineError::EnrichmentError(format!("{}: {}", chunks[index].id, e))),
+            }
+        }
+        Ok(truncated)
+    }
+
+    /// Enriches in place the chunks the model could summarize. Returns, by chunk index,
+    /// whether the summary was truncated, or why the chunk could not be summarized.
+    async fn enrich_each(&self, chunks: &mut [Chunk]) -> Vec<(usize, Result<bool, String>)> {
+        let texts: Vec<(usize, Chunk)> = chunks.iter().cloned().enumerate().collect();
+        let summaries: Vec<_> = stream::iter(texts)
+            .map(|(index, chunk)| self.summarize(index, chunk))
+            .buffer_unordered(self.concurrency.max(1))
+            .collect()
+            .await;
+
+        summaries
+            .into_iter()
+            .map(|(index, summary)| {
+                let outcome = summary.map(|(summary, truncated)| {
+                    let metadata = &mut chunks[index].metadata;
+                    metadata.insert("title".to_string(), summary.title.into());
+                    metadata.insert("keywords".to_string(), summary.keywords.into());
+                    metadata.insert("entities".to_string(), summary.entities.into());
+                    truncated
+                });
+                (index, outcome)
+            })
+            .collect()
+    }
+
+    async fn summarize(&self, index: usize, chunk: Chunk) -> (usize, Result<(ChunkSummary, bool), String>) {
+        (index, self.request_summary(chunk).await)
+    }
+
+    /// The summary of `chunk`, and whether it was truncated under `TruncationPolicy::Warn`.
+    async fn request_summary(&self, chunk: Chunk) -> Result<(ChunkSummary, bool), String> {
+        let messages = vec![
+            Message::builder()
+                .role("system".to_string())
+                .content(ENRICHMENT_PROMPT.to_string())
+                .build(),
+            Message::builder()
+                .role("user".to_string())
+                .content(truncate_to_tokens(&chunk.text, ENRICHMENT_INPUT_TOKENS).to_string())
+                .build(),
+        ];
+
+        let response = OpenAIRequest::builder()
+            .model(self.model.clone())
+            .messages(messages)
+            .temperature(0.0)
+            .priority(Priority::Batch)
+            .build()
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let choice = response.choices().first().ok_or("response has no choices")?;
+        let truncated = match (choice.finish_reason().is_truncated(), self.on_truncation) {
+            (false, _) | (true, TruncationPolicy::Ignore) => false,
+            (true, TruncationPolicy::Warn) => {
+                tracing::warn!("{}: enrichment output truncated by length", chunk.id);
+                true
+            }
+            (true, TruncationPolicy::Fail) => return Err("output truncated by length".to_string()),
+        };
+
+        let summary = serde_json::from_str(choice.message().content()).map_err(|e| e.to_string())?;
+        Ok((summary, truncated))
+    }
+}
+
+/// Chunks documents, embeds each chunk, and upserts the vectors into Pinecone.
+///
+/// # Fields
+///
+/// * `embedding_model`: Optional. Embedding model id. Defaults to "text-embedding-ada-002".
+/// * `namespace`: Optional. Pinecone namespace the vectors are upserted into.
+/// * `chunk_tokens`: Optional. Maximum number of tokens per chunk. Defaults to 512.
+/// * `splitter`: Optional. Splitting strategy. Defaults to a `TokenSplitter` of `chunk_tokens`.
+/// * `enrichment`: Optional. Metadata enrichment stage run before embedding. Chunks it fails
+///   on are listed in the report's `failed`.
+/// * `idempotency`: Optional. Record of upserted chunks; chunks it already holds are skipped and
+///   reported as duplicates.
+/// * `observer`: Optional. Notified of every upserted vector.
+/// * `redactor`: Optional. Masks PII and secrets in chunk text and metadata before anything
+///   is sent to OpenAI or Pinecone; what it masked is counted in the report's `redactions`.
+/// * `metadata_schema`: Optional. Records the metadata keys upserted into the namespace and
+///   warns about values whose type differs from earlier ones, see the report's `schema_conflicts`.
+/// * `state`: Optional. Database the time of the last ingestion into each namespace is kept
+///   in, see `last_ingestions`.
+/// * `stage_buffer`: Optional. Chunks, or vectors, held between the chunking, embedding, and
+///   upserting stages. A stage waits for the next one once this many are pending. Defaults
+///   to 256.
+/// * `embedding_concurrency`: Optional. Limit of concurrent embedding requests, adapting to
+///   OpenAI's 429s. Pipelines sharing one share the limit. Defaults to 4, growing up to 32.
+/// * `upsert_concurrency`: Optional. Limit of concurrent upserts, adapting to Pinecone's
+///   429s and timeouts. Defaults to 2, growing up to 8.
+/// * `default_metadata`: Optional. Metadata added to every vector upserted into a namespace,
+///   keyed by namespace ("" for the default one), e.g. `{"env": "prod"}`. Keys of the
+///   chunk's own metadata take precedence. Chunks the idempotency store already holds are
+///   not upserted again when these change.
+/// * `boilerplate`: Optional. Strips lines repeated across the documents of a run, such as
+///   headers and footers, and stop words from document text before it is chunked; what it
+///   removed is counted in the report's `boilerplate`. See `BoilerplateFilter`.
+/// * `parents`: Optional. Embeds small chunks of larger parent passages, which it stores
+///   for searches to return instead, see `ParentChunks`. Replaces `splitter`.
+///
+/// Every vector is stamped with its `ingested_at` time unless its chunk's metadata has one.
+///
+/// With `with_spool`, upserts keep being accepted through a Pinecone outage and are sent
+/// once it is over.
+///
+/// # Example
+///
+/// ```rust
+/// let pipeline = IngestionPipeline::builder()
+///     .namespace("docs".to_string())
+///     .enrichment(EnrichmentStage::builder().model("gpt-3.5-turbo".to_string()).build())
+///     .build();
+///
+/// let report = pipeline.ingest(&documents).await?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct IngestionPipeline {
+    #[builder(default = "text-embedding-ada-002".to_string())]
+    embedding_model: String,
+
+    #[builder(setter(strip_option), default)]
+    namespace: Option<String>,
+
+    #[builder(default = 512)]
+    chunk_tokens: usize,
+
+    #[builder(setter(strip_option), default)]
+    splitter: Option<Arc<dyn Splitter>>,
+
+    #[builder(setter(strip_option), default)]
+    enrichment: Option<EnrichmentStage>,
+
+    #[builder(setter(strip_option), default)]
+    idempotency: Option<IdempotencyStore>,
+
+    #[builder(setter(strip_option), default)]
+    observer: Option<Arc<dyn Observer>>,
+
+    #[builder(setter(strip_option), default)]
+    redactor: Option<Redactor>,
+
+    #[builder(setter(strip_option), default)]
+    metadata_schema: Option<MetadataSchemaRegistry>,
+
+    #[builder(setter(strip_option), default)]
+    state: Option<Arc<dyn Database>>,
+
+    #[builder(default = 256)]
+    stage_buffer: usize,
+
+    #[builder(default = Arc::new(AdaptiveConcurrency::builder().build()))]
+    embedding_concurrency: Arc<AdaptiveConcurrency>,
+
+    #[builder(default = Arc::new(AdaptiveConcurrency::builder().initial(2).max(8).build()))]
+    upsert_concurrency: Arc<AdaptiveConcurrency>,
+
+    #[builder(default)]
+    default_metadata: HashMap<String, Metadata>,
+
+    #[builder(setter(strip_option), default)]
+    boilerplate: Option<BoilerplateFilter>,
+
+    #[builder(setter(strip_option), default)]
+    parents: Option<ParentChunks>,
+
+    #[builder(setter(skip), default)]
+    spool: Option<Arc<UpsertSpool>>,
+}
+
+/// A chunk with its retry count, idempotency key, and embedded vector.
+type Embedded = (Chunk, u32, String, Vector);
+
+/// Chunks an ingestion run starts from.
+enum ChunkSource<'a> {
+    Documents(&'a [Document]),
+
+    /// Chunks with the number of times each was retried.
+    Chunks(Vec<(Chunk, u32)>),
+}
+
+/// Summary of an ingestion run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestionReport {
+    documents: usize,
+    chunks: usize,
+    upserted: i64,
+
+    #[serde(default)]
+    duplicates: usize,
+
+    #[serde(default)]
+    unchanged: usize,
+
+    #[serde(default)]
+    deleted: usize,
+
+    #[serde(default)]
+    vector_ids: Vec<String>,
+
+    #[serde(default)]
+    skipped: Vec<SkippedFile>,
+
+    #[serde(default)]
+    failed: Vec<FailedItem>,
+
+    #[serde(default)]
+    redactions: BTreeMap<String, BTreeMap<String, usize>>,
+
+    #[serde(default)]
+    boilerplate: BTreeMap<String, usize>,
+
+    #[serde(default)]
+    schema_conflicts: Vec<(String, SchemaConflict)>,
+
+    #[serde(default)]
+    truncated: Vec<String>,
+
+    #[serde(default)]
+    spooled: usize,
+}
+
+impl IngestionPipeline {
+    pub async fn ingest(&self, documents: &[Document]) -> Result<IngestionReport, PipelineError> {
+        let report = self.ingest_attempt(ChunkSource::Documents(documents)).await?;
+
+        Ok(IngestionReport {
+            documents: documents.len(),
+            ..report
+        })
+    }
+
+    /// Embeds and upserts chunks produced outside the pipeline, e.g. by a loader that
+    /// splits its documents itself.
+    ///
+    /// Chunks that fail to enrich, embed or upsert do not abort the run; they are listed in the
+    /// report's `failed`, see `failure_report` and `retry_failures`.
+    pub async fn ingest_chunks(&self, chunks: Vec<Chunk>) -> Result<IngestionReport, PipelineError> {
+        self.ingest_attempt(ChunkSource::Chunks(chunks.into_iter().map(|chunk| (chunk, 0)).collect()))
+            .await
+    }
+
+    /// Reprocesses the chunks of a failure report. Chunks failing again are reported with
+    /// their retry count incremented.
+    pub async fn retry_failures(&self, report: &FailureReport) -> Result<IngestionReport, PipelineError> {
+        let chunks = report
+            .items()
+            .iter()
+            .filter(|item| item.stage() != FailureStage::Classification)
+            .map(|item| (item.chunk().clone(), item.retries() + 1))
+            .collect();
+        self.ingest_attempt(ChunkSource::Chunks(chunks)).await
+    }
+
+    /// Runs chunks through three stages connected by channels of `stage_buffer` items:
+    /// preparation (redaction and the idempotency check), embedding (enrichment included),
+    /// and upserting. A full channel pauses the stage feeding it, so a slow upsert holds back
+    /// the embedding requests instead of every embedding of a large corpus piling up in memory.
+    ///
+    /// The stages run concurrently on the calling task, so they see its `RequestContext`.
+    async fn ingest_attempt(&self, chunks: ChunkSource<'_>) -> Result<IngestionReport, PipelineError> {
+        let (prepared_sender, prepared) = mpsc::channel(self.stage_buffer.max(1));
+        let (embedded_sender, embedded) = mpsc::channel(self.stage_buffer.max(1));
+
+        // A stage that fails drops its end of the channels, which winds down the others;
+        // the first stage's error wins.
+        let (prepared_report, embedded_report, upserted_report) = futures::join!(
+            self.prepare_stage(chunks, prepared_sender),
+            self.embed_stage(prepared, embedded_sender),
+            self.upsert_stage(embedded),
+        );
+        let report = prepared_report?.merge(embedded_report?).merge(upserted_report?);
+
+        if let Some(state) = self.state.as_ref().filter(|_| report.upserted > 0) {
+            let namespace = self.target_namespace().unwrap_or_default();
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let key = format!("{}{}", LAST_INGESTION_KEY_PREFIX, namespace);
+            if let Err(e) = upsert(state.as_ref(), &key, &now.to_string()).await {
+                tracing::warn!("Failed to record the ingestion time of {:?}: {}", namespace, e);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Redacts each chunk and passes on those the idempotency store does not hold yet, with
+    /// their idempotency key. Documents are chunked one at a time, as the embedding stage
+    /// catches up.
+    async fn prepare_stage(
+        &self,
+        chunks: ChunkSource<'_>,
+        prepared: Sender<(Chunk, u32, String)>,
+    ) -> Result<IngestionReport, PipelineError> {
+        let mut report = IngestionReport::default();
+        match chunks {
+            ChunkSource::Documents(documents) => {
+                if let Some(filter) = &self.boilerplate {
+                    filter.learn(documents.iter().map(|document| document.text.as_str()));
+                }
+                for document in documents {
+                    let document = self.strip_boilerplate(document, &mut report.boilerplate);
+                    for chunk in self.chunk(&document).await? {
+                        if !self.prepare(chunk, 0, &prepared, &mut report).await {
+                            return Ok(report);
+                        }
+                    }
+                }
+            }
+            ChunkSource::Chunks(chunks) => {
+                for (chunk, retries) in chunks {
+                    if !self.prepare(chunk, retries, &prepared, &mut report).await {
+                        return Ok(report);
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Returns false once the embedding stage has stopped.
+    async fn prepare(
+        &self,
+        mut chunk: Chunk,
+        retries: u32,
+        prepared: &Sender<(Chunk, u32, String)>,
+        report: &mut IngestionReport,
+    ) -> bool {
+        report.chunks += 1;
+        if let Some(redactor) = &self.redactor {
+            redact(redactor, &mut chunk, &mut report.redactions);
+        }
+
+        // Keys are taken before enrichment, whose output can differ between attempts.
+        let key = idempotency_key(&self.target_namespace(), &self.embedding_model, &chunk);
+        match &self.idempotency {
+            Some(store) if store.contains(&key).await => {
+                report.duplicates += 1;
+                report.vector_ids.push(chunk.id);
+                true
+            }
+            _ => prepared.send((chunk, retries, key)).await.is_ok(),
+        }
+    }
+
+    /// Enriches and embeds the prepared chunks, passing on their vectors. Chunks are
+    /// embedded concurrently, up to the limit of `embedding_concurrency`.
+    async fn embed_stage(
+        &self,
+        mut prepared: Receiver<(Chunk, u32, String)>,
+        embedded: Sender<Embedded>,
+    ) -> Result<IngestionReport, PipelineError> {
+        let mut report = IngestionReport::default();
+        let defaults = self.default_metadata.get(&self.target_namespace().unwrap_or_default());
+        let mut batch = Vec::with_capacity(UPSERT_BATCH_SIZE);
+        // Takes every chunk waiting, so enrichment and embedding run on several at once.
+        while prepared.recv_many(&mut batch, UPSERT_BATCH_SIZE).await > 0 {
+            let (chunks, rest): (Vec<Chunk>, Vec<(u32, String)>) =
+                batch.drain(..).map(|(chunk, retries, key)| (chunk, (retries, key))).unzip();
+            let (chunks, rest) = match &self.enrichment {
+                Some(enrichment) => enrich_batch(enrichment, chunks, rest, &mut report).await,
+                None => (chunks, rest),
+            };
+
+            let texts: Vec<String> = chunks.iter().map(|chunk| chunk.text.clone()).collect();
+            let embeddings: Vec<Result<Vec<f32>, PipelineError>> = stream::iter(texts)
+                .map(|text| self.embed_limited(text))
+                .buffered(self.embedding_concurrency.max())
+                .collect()
+                .await;
+
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            for ((chunk, (retries, key)), embedding) in chunks.into_iter().zip(rest).zip(embeddings) {
+                match embedding {
+                    Ok(values) => {
+                        let mut metadata = defaults.cloned().unwrap_or_default();
+                        metadata.extend(chunk.metadata.clone());
+                        metadata.entry(INGESTED_AT_KEY.to_string()).or_insert_with(|| now.into());
+                        metadata.insert("text".to_string(), truncate_to_tokens(&chunk.text, METADATA_TEXT_TOKENS).into());
+                        metadata.insert(CHUNK_HASH_KEY.to_string(), key.clone().into());
+                        let vector = Vector::builder()
+                            .id(chunk.id.clone())
+                            .values(values)
+                            .metadata(metadata)
+                            .build();
+                        if embedded.send((chunk, retries, key, vector)).await.is_err() {
+                            return Ok(report);
+                        }
+                    }
+                    Err(e) => report
+                        .failed
+                        .push(FailedItem::new(FailureStage::Embedding, e.to_string(), retries, chunk)),
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    async fn embed_limited(&self, text: String) -> Result<Vec<f32>, PipelineError> {
+        let permit = self.embedding_concurrency.acquire().await;
+        let result = embed(&self.embedding_model, &text, Priority::Batch).await;
+        permit.finish(&result);
+        result
+    }
+
+    /// Records the metadata schema of the embedded vectors and upserts them in batches of
+    /// `UPSERT_BATCH_SIZE`, concurrently up to the limit of `upsert_concurrency`.
+    async fn upsert_stage(&self, embedded: Receiver<Embedded>) -> Result<IngestionReport, PipelineError> {
+        // One full batch waits while the upserts are at the limit.
+        let (batch_sender, batches) = mpsc::channel(1);
+        let (batched, upserted) = futures::join!(
+            self.batch_stage(embedded, batch_sender),
+            self.upsert_batches(batches),
+        );
+        Ok(batched?.merge(upserted?))
+    }
+
+    async fn batch_stage(
+        &self,
+        mut embedded: Receiver<Embedded>,
+        batches: Sender<Vec<Embedded>>,
+    ) -> Result<IngestionReport, PipelineError> {
+        let mut report = IngestionReport::default();
+        let namespace = self.target_namespace();
+        let mut batch = Vec::with_capacity(UPSERT_BATCH_SIZE);
+        while let Some((chunk, retries, key, vector)) = embedded.recv().await {
+            if let Some(registry) = &self.metadata_schema {
+                let metadata = vector.metadata().clone().unwrap_or_default();
+                let conflicts = registry
+                    .record(namespace.as_deref(), &metadata)
+                    .await
+                    .map_err(|e| PipelineError::SchemaError(e.to_string()))?;
+                for conflict in conflicts {
+                    tracing::warn!("{}: {}", chunk.id, conflict);
+                    report.schema_conflicts.push((chunk.id.clone(), conflict));
+                }
+            }
+
+            batch.push((chunk, retries, key, vector));
+            if batch.len() == UPSERT_BATCH_SIZE && batches.send(std::mem::take(&mut batch)).await.is_err() {
+                return Ok(report);
+            }
+        }
+        if !batch.is_empty() {
+            batches.send(batch).await.ok();
+        }
+        Ok(report)
+    }
+
+    async fn upsert_batches(&self, batches: Receiver<Vec<Embedded>>) -> Result<IngestionReport, PipelineError> {
+        let mut report = IngestionReport::default();
+        let mut upserts = pin!(stream::unfold(batches, |mut batches| async move {
+            batches.recv().await.map(|batch| (batch, batches))
+        })
+        .map(|batch| self.upsert_limited(batch))
+        .buffered(self.upsert_concurrency.max()));
+
+        while let Some((batch, result)) = upserts.next().await {
+            match result {
+                Ok(upserted) => {
+                    match upserted {
+                        Some(upserted) => report.upserted += upserted,
+                        None => report.spooled += batch.len(),
+                    }
+                    report.vector_ids.extend(batch.iter().map(|(chunk, _, _, _)| chunk.id.clone()));
+                    if let Some(store) = &self.idempotency {
+                        for (_, _, key, _) in &batch {
+                            // A lost record only costs a redundant upsert on the next attempt.
+                            store.record(key).await.ok();
+                        }
+                    }
+                    if let Some(observer) = &self.observer {
+                        for (_, _, _, vector) in &batch {
+                            observer.on_upsert(&self.record(vector)).await?;
+                        }
+                    }
+                }
+                Err(e) => {
+                    for (chunk, retries, _, _) in batch {
+                        report
+                            .failed
+                            .push(FailedItem::new(FailureStage::Upsert, e.to_string(), retries, chunk));
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Upserts a batch within the concurrency limit, or spools it during an outage. The
+    /// count is None if the batch was spooled.
+    async fn upsert_limited(&self, batch: Vec<Embedded>) -> (Vec<Embedded>, Result<Option<i64>, PipelineError>) {
+        let vectors: Vec<Vector> = batch.iter().map(|(_, _, _, vector)| vector.clone()).collect();
+        let result = match &self.spool {
+            Some(spool) if spool.is_spooling() => self.spool_vectors(spool, vectors).await,
+            Some(spool) => {
+                let result = self.upsert_with_permit(vectors.clone()).await;
+                let error = result.as_ref().err().map(|e| e.to_string());
+                match spool.record(error.as_deref()) {
+                    true => self.spool_vectors(spool, vectors).await,
+                    false => result.map(Some),
+                }
+            }
+            None => self.upsert_with_permit(vectors).await.map(Some),
+        };
+        (batch, result)
+    }
+
+    async fn upsert_with_permit(&self, vectors: Vec<Vector>) -> Result<i64, PipelineError> {
+        let permit = self.upsert_concurrency.acquire().await;
+        let result = self.upsert(vectors).await;
+        permit.finish(&result);
+        result
+    }
+
+    async fn spool_vectors(&self, spool: &UpsertSpool, vectors: Vec<Vector>) -> Result<Option<i64>, PipelineError> {
+        spool
+            .push(self.target_namespace(), vectors)
+            .await
+            .map_err(|e| PipelineError::SpoolError(e.to_string()))?;
+        Ok(None)
+    }
+
+    /// Writes batches to `spool` instead of failing them once upserts keep failing because
+    /// Pinecone is unreachable, see `UpsertSpool`. Spooled chunks are counted in the
+    /// report's `spooled` and recorded as upserted by the `idempotency` store and
+    /// `observer`, as the spool will deliver them.
+    pub fn with_spool(mut self, spool: Arc<UpsertSpool>) -> Self {
+        self.spool = Some(spool);
+        self
+    }
+
+    /// Loads every supported file under `root` and ingests it.
+    ///
+    /// Binary and unsupported files are not ingested; they are listed in the report's `skipped`.
+    pub async fn ingest_directory<P: AsRef<Path>>(&self, root: P) -> Result<IngestionReport, PipelineError> {
+        let (documents, skipped) = load_directory(root)?.into_parts();
+        let report = self.ingest(&documents).await?;
+        Ok(report.with_skipped(skipped))
+    }
+
+    /// Brings the stored chunks of `document` up to date with its current text: only
+    /// chunks whose hash differs from the stored one are embedded and upserted, and chunks
+    /// the document no longer has are deleted. A document with no stored chunks is
+    /// ingested in full.
+    ///
+    /// Chunk hashes cover the chunk's id, text, and metadata before enrichment, plus the
+    /// namespace and embedding model, so changing either re-embeds every chunk. Vectors
+    /// upserted before hashes were stored count as changed.
+    pub async fn update_document(&self, document: &Document) -> Result<IngestionReport, PipelineError> {
+        let client = match &self.namespace {
+            Some(namespace) => PineconeClient::namespace(namespace),
+            None => PineconeClient::default(),
+        };
+        let stored = self.stored_hashes(&client, document.id()).await?;
+
+        let chunks = self.chunk(&self.strip_boilerplate(document, &mut BTreeMap::new())).await?;
+        let removed: Vec<String> = stored
+            .keys()
+            .filter(|id| !chunks.iter().any(|chunk| chunk.id == **id))
+            .cloned()
+            .collect();
+
+        let mut changed = Vec::new();
+        let mut unchanged = 0;
+        for chunk in chunks {
+            // Hashed as ingestion will see it, i.e. redacted.
+            let mut hashed = chunk.clone();
+            if let Some(redactor) = &self.redactor {
+                redact(redactor, &mut hashed, &mut BTreeMap::new());
+            }
+            let hash = idempotency_key(&self.target_namespace(), &self.embedding_model, &hashed);
+            match stored.get(&chunk.id) {
+                Some(Some(stored)) if *stored == hash => unchanged += 1,
+                _ => changed.push(chunk),
+            }
+        }
+
+        let mut report = self.ingest_chunks(changed).await?;
+        for batch in removed.chunks(FETCH_BATCH_SIZE) {
+            client.delete(batch.to_vec()).await?;
+            if let Some(observer) = &self.observer {
+                observer.on_delete(batch).await?;
+            }
+        }
+
+        report.documents = 1;
+        report.chunks += unchanged;
+        report.unchanged = unchanged;
+        report.deleted = removed.len();
+        Ok(report)
+    }
+
+    /// Ids of the stored chunks of `document_id`, with the hash each was upserted with.
+    async fn stored_hashes(
+        &self,
+        client: &PineconeClient,
+        document_id: &str,
+    ) -> Result<HashMap<String, Option<String>>, PipelineError> {
+        let ids = client.list_chunks(document_id).await?;
+        let mut hashes = HashMap::with_capacity(ids.len());
+        for batch in ids.chunks(FETCH_BATCH_SIZE) {
+            let response = client.fetch(batch.to_vec()).await?;
+            for (id, vector) in response.vectors().iter().flatten() {
+                let hash = vector.metadata().get(CHUNK_HASH_KEY).and_then(Value::as_str);
+                hashes.insert(id.clone(), hash.map(str::to_string));
+            }
+        }
+        // Listed but not fetched, e.g. deleted meanwhile: treat as stored without a hash.
+        for id in ids {
+            hashes.entry(id).or_insert(None);
+        }
+        Ok(hashes)
+    }
+
+    /// `document` with its text stripped by the `boilerplate` filter, if any.
+    fn strip_boilerplate(&self, document: &Document, removed: &mut BTreeMap<String, usize>) -> Document {
+        match &self.boilerplate {
+            Some(filter) => Document {
+                text: filter.strip(&document.text, removed),
+                ..document.clone()
+            },
+            None => document.clone(),
+        }
+    }
+
+    /// Splits a document into chunks with the configured `splitter`, or `parents`.
+    pub async fn chunk(&self, document: &Document) -> Result<Vec<Chunk>, PipelineError> {
+        if let Some(parents) = &self.parents {
+            return parents.chunk(document).await;
+        }
+        let texts = match &self.splitter {
+            Some(splitter) => splitter.split(&document.text).await?,
+            None => {
+                let splitter = TokenSplitter::builder().max_tokens(self.chunk_tokens).build();
+                splitter.split(&document.text).await?
+            }
+        };
+
+        Ok(texts
+            .into_iter()
+            .enumerate()
+            .map(|(n, text)| {
+                let mut metadata = document.metadata.clone();
+                metadata.insert("document_id".to_string(), document.id.clone().into());
+
+                Chunk::builder()
+                    .id(chunk_id(&document.id, n))
+                    .text(text)
+                    .metadata(metadata)
+                    .build()
+            })
+            .collect())
+    }
+
+    pub fn namespace(&self) -> &Option<String> {
+        &self.namespace
+    }
+
+    pub fn observer(&self) -> &Option<Arc<dyn Observer>> {
+        &self.observer
+    }
+
+    /// The namespace vectors are upserted into: `namespace`, or else that of the current
+    /// `RequestContext`'s tenant.
+    fn target_namespace(&self) -> Option<String> {
+        self.namespace.clone().or_else(context::current_namespace)
+    }
+
+    fn record(&self, vector: &Vector) -> VectorRecord {
+        VectorRecord::new(
+            vector.id().clone().unwrap_or_default(),
+            self.target_namespace(),
+            vector.values().clone(),
+            vector.metadata().clone().unwrap_or_default(),
+        )
+    }
+
+    async fn upsert(&self, vectors: Vec<Vector>) -> Result<i64, PipelineError> {
+        let count = vectors.len() as i64;
+        let request = match &self.namespace {
+            Some(namespace) => PineconeRequest::builder()
+                .vectors(vectors)
+                .namespace(namespace.clone())
+                .build(),
+            None => PineconeRequest::builder().vectors(vectors).build(),
+        };
+
+        let response = request.upsert().await?;
+        Ok(response.upserted_count().unwrap_or(count))
+    }
+}
+
+/// Masks the text and metadata of `chunk`, counting the matches under its document's id.
+/// `document_id` is kept, since deleting a document's vectors filters on it.
+fn redact(redactor: &Redactor, chunk: &mut Chunk, redactions: &mut BTreeMap<String, BTreeMap<String, usize>>) {
+    let mut counts = BTreeMap::new();
+    chunk.text = redactor.redact(&chunk.text, &mut counts);
+    for (key, value) in chunk.metadata.iter_mut() {
+        if key != "document_id" {
+            redact_value(redactor, value, &mut counts);
+        }
+    }
+
+    if !counts.is_empty() {
+        let document_id = chunk.metadata.get("document_id").and_then(Value::as_str).unwrap_or(&chunk.id).to_string();
+        add_counts(redactions.entry(document_id).or_default(), counts);
+    }
+}
+
+/// Masks a metadata value: a string, or the strings of a list.
+fn redact_value(redactor: &Redactor, value: &mut Value, counts: &mut BTreeMap<String, usize>) {
+    match value {
+        Value::String(text) => *text = redactor.redact(text, counts),
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_value(redactor, item, counts)),
+        _ => {}
+    }
+}
+
+fn add_counts(total: &mut BTreeMap<String, usize>, counts: BTreeMap<String, usize>) {
+    for (name, count) in counts {
+        *total.entry(name).or_default() += count;
+    }
+}
+
+/// Time of the last ingestion into each namespace recorded in `db` by a pipeline's
+/// `state`, in seconds since the Unix epoch, sorted by namespace. The default namespace is "".
+pub async fn last_ingestions(db: &dyn Database) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+    let mut ingestions = Vec::new();
+    for key in db.ids(LAST_INGESTION_KEY_PREFIX).await? {
+        let at = db.read(&key).await?.parse()?;
+        ingestions.push((key[LAST_INGESTION_KEY_PREFIX.len()..].to_string(), at));
+    }
+    Ok(ingestions)
+}
+
+/// Enriches `chunks`, listing those the model could not summarize in the report's `failed`
+/// like chunks that fail to embed, and returns the others with their retry counts and keys.
+async fn enrich_batch(
+    enrichment: &EnrichmentStage,
+    mut chunks: Vec<Chunk>,
+    rest: Vec<(u32, String)>,
+    report: &mut IngestionReport,
+) -> (Vec<Chunk>, Vec<(u32, String)>) {
+    let mut outcomes = enrichment.enrich_each(&mut chunks).await;
+    outcomes.sort_by_key(|(index, _)| *index);
+
+    let mut enriched = (Vec::with_capacity(chunks.len()), Vec::with_capacity(chunks.len()));
+    for ((chunk, (retries, key)), (_, outcome)) in chunks.into_iter().zip(rest).zip(outcomes) {
+        match outcome {
+            Ok(truncated) => {
+                if truncated {
+                    report.truncated.push(chunk.id.clone());
+                }
+                enriched.0.push(chunk);
+                enriched.1.push((retries, key));
+            }
+            Err(e) => report.failed.push(FailedItem::new(FailureStage::Enrichment, e, retries, chunk)),
+        }
+    }
+    enriched
+}
+
+/// Embeds `text` with `model`, returning the embedding vector.
+///
+/// Concurrent calls for the same model and text share one request (singleflight), so
+/// boilerplate repeated across documents is sent once while it is in flight. The shared
+/// request waits for the rate limiter with the priority of the first caller.
+pub(crate) async fn embed(model: &str, text: &str, priority: Priority) -> Result<Vec<f32>, PipelineError> {
+    let key = (model.to_string(), text.to_string());
+    let pending = IN_FLIGHT_EMBEDDINGS
+        .lock()
+        .unwrap()
+        .entry(key.clone())
+        .or_insert_with(|| request_embedding(key, priority).boxed().shared())
+        .clone();
+    context::traced("embed", pending).await.map_err(PipelineError::EmbeddingError)
+}
+
+/// Sends the embedding request for `key` and removes it from `IN_FLIGHT_EMBEDDINGS`.
+async fn request_embedding(key: (String, String), priority: Priority) -> Result<Vec<f32>, String> {
+    let (model, text) = &key;
+    let result = OpenAIEmbeddingRequest::builder()
+        .model(model.clone())
+        .input(text.clone())
+        .priority(priority)
+        .build()
+        .send()
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|response| {
+            response
+                .data()
+                .first()
+                .map(|embedding| embedding.embedding().to_vec())
+                .ok_or_else(|| "response has no embeddings".to_string())
+        });
+
+    IN_FLIGHT_EMBEDDINGS.lock().unwrap().remove(&key);
+    result
+}
+
+impl Document {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn text(&self) -> &String {
+        &self.text
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl Chunk {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn text(&self) -> &String {
+        &self.text
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl IngestionReport {
+    /// Adds files a loader skipped before ingestion to the report.
+    pub fn with_skipped(mut self, skipped: Vec<SkippedFile>) -> Self {
+        self.skipped.extend(skipped);
+        self
+    }
+
+    /// Combines the reports of two runs.
+    pub fn merge(mut self, other: IngestionReport) -> Self {
+        self.documents += other.documents;
+        self.chunks += other.chunks;
+        self.upserted += other.upserted;
+        self.duplicates += other.duplicates;
+        self.unchanged += other.unchanged;
+        self.deleted += other.deleted;
+        self.vector_ids.extend(other.vector_ids);
+        self.skipped.extend(other.skipped);
+        self.failed.extend(other.failed);
+        self.schema_conflicts.extend(other.schema_conflicts);
+        self.truncated.extend(other.truncated);
+        self.spooled += other.spooled;
+        for (document, counts) in other.redactions {
+            add_counts(self.redactions.entry(document).or_default(), counts);
+        }
+        add_counts(&mut self.boilerplate, other.boilerplate);
+        self
+    }
+
+    pub fn documents(&self) -> usize {
+        self.documents
+    }
+
+    pub fn chunks(&self) -> usize {
+        self.chunks
+    }
+
+    pub fn upserted(&self) -> i64 {
+        self.upserted
+    }
+
+    /// Chunks skipped because an earlier attempt already upserted them, see
+    /// `IdempotencyStore`. They are not counted in `upserted`.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Chunks `update_document` left alone because their hash was unchanged. They are
+    /// counted in `chunks` but not in `upserted`.
+    pub fn unchanged(&self) -> usize {
+        self.unchanged
+    }
+
+    /// Vectors `update_document` deleted because the document no longer has their chunk.
+    pub fn deleted(&self) -> usize {
+        self.deleted
+    }
+
+    /// Ids of every vector the run upserted successfully, including duplicates and spooled
+    /// vectors.
+    pub fn vector_ids(&self) -> &Vec<String> {
+        &self.vector_ids
+    }
+
+    pub fn skipped(&self) -> &Vec<SkippedFile> {
+        &self.skipped
+    }
+
+    /// Chunks written to the `UpsertSpool` during a Pinecone outage, to be upserted when it
+    /// is flushed. They are not counted in `upserted`.
+    pub fn spooled(&self) -> usize {
+        self.spooled
+    }
+
+    /// Chunks that could not be embedded or upserted.
+    pub fn failed(&self) -> &Vec<FailedItem> {
+        &self.failed
+    }
+
+    /// Matches masked by the `redactor`, by document id and detector name.
+    pub fn redactions(&self) -> &BTreeMap<String, BTreeMap<String, usize>> {
+        &self.redactions
+    }
+
+    /// Lines and stop words removed by the `boilerplate` filter, with the number of times
+    /// each was removed.
+    pub fn boilerplate(&self) -> &BTreeMap<String, usize> {
+        &self.boilerplate
+    }
+
+    /// Metadata values whose type differs from earlier upserts into the namespace, by chunk
+    /// id. The chunks were upserted regardless.
+    pub fn schema_conflicts(&self) -> &Vec<(String, SchemaConflict)> {
+        &self.schema_conflicts
+    }
+
+    /// Ids of the chunks whose enrichment output was cut off by the token limit, under
+    /// `TruncationPolicy::Warn`. The chunks were enriched with the output as is.
+    pub fn truncated(&self) -> &Vec<String> {
+        &self.truncated
+    }
+
+    /// The failed chunks as a report that can be written out and retried later.
+    pub fn failure_report(&self) -> FailureReport {
+        FailureReport::new(self.failed.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::libs::openai_api::FinishReason;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::{sample_documents, FakeServices};
+
+    #[tokio::test]
+    async fn test_chunk() {
+        let document = Document::builder()
+            .id("doc".to_string())
+            .text("one two three four five six seven".to_string())
+            .build();
+
+        let chunks = IngestionPipeline::builder().chunk_tokens(3).build().chunk(&document).await.unwrap();
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].id(), "doc#chunk0");
+        assert_eq!(chunks[0].text(), "one two three");
+        assert_eq!(chunks[2].text(), "seven");
+        assert_eq!(chunks[2].metadata().get("document_id").unwrap(), "doc");
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut chunk = Chunk::builder()
+            .id("doc-0".to_string())
+            .text("mail jane@example.com".to_string())
+            .metadata(HashMap::from([
+                ("document_id".to_string(), "doc".into()),
+                ("author".to_string(), "jane@example.com".into()),
+            ]))
+            .build();
+        let mut redactions = BTreeMap::new();
+
+        redact(&Redactor::new(), &mut chunk, &mut redactions);
+
+        assert_eq!(chunk.text(), "mail [REDACTED:EMAIL]");
+        assert_eq!(chunk.metadata().get("author").unwrap(), "[REDACTED:EMAIL]");
+        assert_eq!(redactions["doc"]["EMAIL"], 2);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_enrichment_truncation_warns() {
+        let fakes = FakeServices::start().await;
+        fakes.set_chat_reply(r#"{"title": "Refunds", "keywords": ["refund"]}"#);
+        fakes.set_finish_reason(FinishReason::Length);
+        let enrichment = EnrichmentStage::builder().model("gpt-3.5-turbo".to_string()).build();
+        let report = IngestionPipeline::builder()
+            .enrichment(enrichment)
+            .build()
+            .ingest(&sample_documents())
+            .await
+            .unwrap();
+        assert_eq!(report.truncated().len(), report.chunks());
+        assert_eq!(report.upserted() as usize, report.chunks());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_enrichment_truncation_fails_chunks() {
+        let fakes = FakeServices::start().await;
+        fakes.set_chat_reply(r#"{"title": "Refunds", "keywords": ["refund""#);
+        fakes.set_finish_reason(FinishReason::Length);
+        let enrichment = EnrichmentStage::builder()
+            .model("gpt-3.5-turbo".to_string())
+            .on_truncation(TruncationPolicy::Fail)
+            .build();
+        let report = IngestionPipeline::builder()
+            .enrichment(enrichment)
+            .build()
+            .ingest(&sample_documents())
+            .await
+            .unwrap();
+        assert_eq!((report.upserted(), report.failed().len()), (0, report.chunks()));
+        assert!(report.failed().iter().all(|item| item.stage() == FailureStage::Enrichment));
+        assert!(report.failed()[0].reason().contains("truncated"), "{}", report.failed()[0].reason());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_update_document() {
+        let fakes = FakeServices::start().await;
+        let shipping = &sample_documents()[1];
+        let pipeline = IngestionPipeline::builder().chunk_tokens(3).build();
+        let report = pipeline.update_document(shipping).await.unwrap();
+        assert_eq!((report.upserted(), report.deleted()), (3, 0));
+        let report = pipeline.update_document(shipping).await.unwrap();
+        assert_eq!((report.unchanged(), report.upserted()), (3, 0));
+        let shorter = Document::builder().id("shipping".to_string()).text("Orders ship within".to_string()).build();
+        let report = pipeline.update_document(&shorter).await.unwrap();
+        assert_eq!((report.unchanged(), report.upserted(), report.deleted()), (1, 0, 2));
+        assert_eq!(fakes.vector_count(None), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_stage_buffer() {
+        let fakes = FakeServices::start().await;
+        let documents = sample_documents();
+        // One item between stages, so every stage keeps waiting on the next one.
+        let staged = IngestionPipeline::builder()
+            .namespace("staged".to_string())
+            .chunk_tokens(3)
+            .stage_buffer(1)
+            .build();
+        let report = staged.ingest(&documents).await.unwrap();
+        assert!(report.chunks() > documents.len());
+        assert_eq!(report.upserted() as usize, report.chunks());
+        assert_eq!(fakes.vector_count(Some("staged")), report.chunks());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_concurrent_embeddings() {
+        let fakes = FakeServices::start().await;
+        let boilerplate = "This document is confidential.";
+        let (first, second) = futures::join!(
+            embed("text-embedding-ada-002", boilerplate, Priority::Batch),
+            embed("text-embedding-ada-002", boilerplate, Priority::Batch),
+        );
+        assert_eq!(first.unwrap(), second.unwrap());
+        let requests = fakes.server().received_requests().await.unwrap();
+        assert_eq!(requests.iter().filter(|request| request.url.path() == "/v1/embeddings").count(), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_default_metadata() {
+        let fakes = FakeServices::start().await;
+        let pipeline = IngestionPipeline::builder()
+            .namespace("staged".to_string())
+            .default_metadata(HashMap::from([
+                ("staged".to_string(), Metadata::from([("env".to_string(), Value::from("prod"))])),
+                ("other".to_string(), Metadata::from([("env".to_string(), Value::from("dev"))])),
+            ]))
+            .build();
+        let report = pipeline.ingest(&sample_documents()).await.unwrap();
+        let tagged = fakes.metadata(Some("staged"), &report.vector_ids()[0]).unwrap();
+        assert_eq!(tagged["env"], "prod");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_ingested_at() {
+        let fakes = FakeServices::start().await;
+        let pipeline = IngestionPipeline::builder().namespace("stamped".to_string()).build();
+        let report = pipeline.ingest(&sample_documents()).await.unwrap();
+        let tagged = fakes.metadata(Some("stamped"), &report.vector_ids()[0]).unwrap();
+        assert!(tagged["ingested_at"].is_u64());
+    }
+}
@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::error::Error;
 use mysql_async::{
     Pool,
@@ -8,17 +7,18 @@ use mysql_async::{
 };
 use std::sync::Arc;
 use async_trait::async_trait;
-use crate::libs::database::{convert_binary_to_embeddings, convert_embeddings_to_binary, Database};
+use crate::libs::database::{convert_binary_to_embeddings, convert_embeddings_to_binary, eviction_victims, Database};
 
 #[derive(Debug)]
 pub struct PlanetScaleDB {
     pool: Arc<Pool>,
+    soft_delete: bool,
 }
 
 impl PlanetScaleDB {
     pub async fn new(connection_string: &str) -> Result<Self, Box<dyn Error>> {
         let pool = Pool::new(connection_string);
-        let observer = PlanetScaleDB { pool: Arc::new(pool) };
+        let observer = PlanetScaleDB { pool: Arc::new(pool), soft_delete: false };
         observer.init().await?;
         Ok(observer)
     }
@@ -36,17 +36,38 @@ impl PlanetScaleDB {
                 data TEXT NOT NULL,
                 embedding BLOB,
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                last_accessed TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                last_accessed TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                deleted_at TIMESTAMP NULL
             )
         "#;
-        self.execute_query(create_table_query).await
+        self.execute_query(create_table_query).await?;
+
+        // Tables created before soft deletes lack the column.
+        let mut conn = self.pool.get_conn().await?;
+        let has_deleted_at: Option<bool> = conn
+            .query_first(
+                r"SELECT COUNT(*) > 0 FROM information_schema.COLUMNS
+                  WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'items' AND COLUMN_NAME = 'deleted_at'",
+            )
+            .await?;
+        if has_deleted_at != Some(true) {
+            conn.query_drop("ALTER TABLE items ADD COLUMN deleted_at TIMESTAMP NULL").await?;
+        }
+        Ok(())
+    }
+
+    /// Makes `delete` stamp items' `deleted_at` instead of removing the rows, see
+    /// `SQLiteDB::with_soft_delete`.
+    pub fn with_soft_delete(mut self) -> Self {
+        self.soft_delete = true;
+        self
     }
 
-    async fn insert_embedding_data(&self, id: &str, data: &str, embeddings: &[f32]) {
+    pub async fn insert_embedding_data(&self, id: &str, data: &str, embeddings: &[f32]) {
         let mut conn = self.pool.get_conn().await.unwrap();
         let binary_embeddings = convert_embeddings_to_binary(embeddings);
 
-        let query = r"INSERT INTO text_embeddings (id, data, embedding) VALUES (:id, :data, :embedding)";
+        let query = r"INSERT INTO items (id, data, embedding) VALUES (:id, :data, :embedding)";
         let params = params! {
             "id" => id,
             "data" => data,
@@ -56,15 +77,21 @@ impl PlanetScaleDB {
         conn.exec_drop(query, params).await.unwrap();
     }
 
-    async fn get_embedding_data(&self, id: &str) -> Option<(String, String, Vec<f32>)> {
+    pub async fn get_embedding_data(&self, id: &str) -> Option<(String, String, Vec<f32>)> {
         let mut conn = self.pool.get_conn().await.unwrap();
 
-        let query = r"SELECT id, data, embedding FROM text_embeddings WHERE id = :id";
+        let query = r"SELECT id, data, embedding FROM items WHERE id = :id";
         let params = params! {
             "id" => id,
         };
 
         let row : Option<(String, String, Vec<u8>)> = conn.exec_first(query, params).await.unwrap();
+        if row.is_some() {
+            let touch = r"UPDATE items SET last_accessed = CURRENT_TIMESTAMP WHERE id = :id";
+            if let Err(e) = conn.exec_drop(touch, params! { "id" => id }).await {
+                tracing::warn!("Failed to record the read of {}: {}", id, e);
+            }
+        }
         if let Some((id, data, binary_data)) = row {
             let id: String = id;
             let data: String = data;
@@ -79,18 +106,23 @@ impl PlanetScaleDB {
 #[async_trait]
 impl Database for PlanetScaleDB {
     async fn create(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>> {
-        let query = format!("INSERT INTO data_table (id, data) VALUES ('{}', '{}')", id, data);
+        let query = format!("INSERT INTO items (id, data) VALUES ('{}', '{}')", id, data);
         self.execute_query(&query).await
     }
 
     async fn read(&self, id: &str) -> Result<String, Box<dyn Error>> {
         let mut conn = self.pool.get_conn().await?;
-        let query = format!("SELECT data FROM data_table WHERE id = '{}'", id);
+        let query = format!("SELECT data FROM items WHERE id = '{}' AND deleted_at IS NULL", id);
         let row: Option<Row> = conn.query_first(query).await?;
 
         match row {
             Some(row) => {
                 let data: String = row.get("data").unwrap();
+                let touch = r"UPDATE items SET last_accessed = CURRENT_TIMESTAMP WHERE id = :id";
+                // A stale access time only makes the item an earlier eviction candidate.
+                if let Err(e) = conn.exec_drop(touch, params! { "id" => id }).await {
+                    tracing::warn!("Failed to record the read of {}: {}", id, e);
+                }
                 Ok(data)
             }
             None => Err(Box::new(std::io::Error::new(std::io::ErrorKind::NotFound, "Data not found"))),
@@ -98,12 +130,63 @@ impl Database for PlanetScaleDB {
     }
 
     async fn update(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>> {
-        let query = format!("UPDATE data_table SET data = '{}' WHERE id = '{}'", data, id);
+        let query = format!("UPDATE items SET data = '{}' WHERE id = '{}' AND deleted_at IS NULL", data, id);
         self.execute_query(&query).await
     }
 
     async fn delete(&self, id: &str) -> Result<(), Box<dyn Error>> {
-        let query = format!("DELETE FROM data_table WHERE id = '{}'", id);
+        if !self.soft_delete {
+            return self.purge(id).await;
+        }
+        let mut conn = self.pool.get_conn().await?;
+        let query = r"UPDATE items SET deleted_at = CURRENT_TIMESTAMP WHERE id = :id AND deleted_at IS NULL";
+        conn.exec_drop(query, params! { "id" => id }).await?;
+        Ok(())
+    }
+
+    async fn restore(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.pool.get_conn().await?;
+        let query = r"UPDATE items SET deleted_at = NULL WHERE id = :id AND deleted_at IS NOT NULL";
+        conn.exec_drop(query, params! { "id" => id }).await?;
+        if conn.affected_rows() == 0 {
+            return Err(format!("item {} is not deleted", id).into());
+        }
+        Ok(())
+    }
+
+    async fn deleted_at(&self, id: &str) -> Result<Option<u64>, Box<dyn Error>> {
+        let mut conn = self.pool.get_conn().await?;
+        let query = r"SELECT UNIX_TIMESTAMP(deleted_at) FROM items WHERE id = :id AND deleted_at IS NOT NULL";
+        let deleted_at: Option<u64> = conn.exec_first(query, params! { "id" => id }).await?;
+        Ok(deleted_at)
+    }
+
+    async fn tombstones(&self) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+        let mut conn = self.pool.get_conn().await?;
+        let query = r"SELECT id, UNIX_TIMESTAMP(deleted_at) FROM items WHERE deleted_at IS NOT NULL ORDER BY deleted_at, id";
+        Ok(conn.query(query).await?)
+    }
+
+    async fn purge(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        let query = format!("DELETE FROM items WHERE id = '{}'", id);
         self.execute_query(&query).await
     }
+
+    async fn evict_embeddings(&self, max_bytes: u64) -> Result<usize, Box<dyn Error>> {
+        let mut conn = self.pool.get_conn().await?;
+        let query = r"SELECT id, LENGTH(data) + LENGTH(embedding) FROM items
+                      WHERE embedding IS NOT NULL ORDER BY last_accessed, id";
+        let items: Vec<(String, u64)> = conn.query(query).await?;
+
+        let victims = eviction_victims(items, max_bytes);
+        let delete = r"DELETE FROM items WHERE id = :id";
+        conn.exec_batch(delete, victims.iter().map(|id| params! { "id" => id })).await?;
+        Ok(victims.len())
+    }
+
+    async fn ids(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut conn = self.pool.get_conn().await?;
+        let query = r"SELECT id FROM items WHERE LEFT(id, CHAR_LENGTH(:prefix)) = :prefix AND deleted_at IS NULL ORDER BY id";
+        Ok(conn.exec(query, params! { "prefix" => prefix }).await?)
+    }
 }
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::openai_api::ResponseFormat;
+
+lazy_static! {
+    static ref PROFILES: RwLock<HashMap<String, GenerationProfile>> = RwLock::new(built_in());
+}
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+
+    #[error("ParseError: {0}")]
+    ParseError(String),
+}
+
+/// Named generation settings applied by `OpenAIRequest::builder().profile(name)`.
+///
+/// Settings the request sets itself take precedence over the profile's. Built-in profiles:
+/// * `deterministic`: temperature 0, for reproducible (and cacheable) answers.
+/// * `creative`: temperature 1.1 with a presence penalty, for varied writing.
+/// * `json-extractor`: temperature 0 in JSON mode, for structured extraction.
+///
+/// # Fields
+///
+/// * `temperature`: Optional. Sampling temperature between 0 and 2.
+/// * `top_p`: Optional. Nucleus sampling mass between 0 and 1.
+/// * `presence_penalty`: Optional. Between -2.0 and 2.0.
+/// * `frequency_penalty`: Optional. Between -2.0 and 2.0.
+/// * `max_tokens`: Optional. Maximum number of tokens to generate.
+/// * `response_format`: Optional. `ResponseFormat::json_object()` for JSON mode.
+///
+/// # Example
+///
+/// ```rust
+/// // profiles.json: {"summarizer": {"temperature": 0.3, "max_tokens": 400}}
+/// profiles::load_profiles("profiles.json")?;
+///
+/// let response = OpenAIRequest::builder()
+///     .model("gpt-3.5-turbo".to_string())
+///     .messages(messages)
+///     .profile("summarizer")
+///     .build()
+///     .send()
+///     .await?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, TypedBuilder)]
+pub struct GenerationProfile {
+    #[builder(setter(strip_option), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f64>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+
+    #[builder(setter(strip_option), default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+impl GenerationProfile {
+    pub fn temperature(&self) -> Option<f64> {
+        self.temperature
+    }
+
+    pub fn top_p(&self) -> Option<f64> {
+        self.top_p
+    }
+
+    pub fn presence_penalty(&self) -> Option<f64> {
+        self.presence_penalty
+    }
+
+    pub fn frequency_penalty(&self) -> Option<f64> {
+        self.frequency_penalty
+    }
+
+    pub fn max_tokens(&self) -> Option<u32> {
+        self.max_tokens
+    }
+
+    pub fn response_format(&self) -> &Option<ResponseFormat> {
+        &self.response_format
+    }
+}
+
+fn built_in() -> HashMap<String, GenerationProfile> {
+    let mut profiles = HashMap::new();
+    profiles.insert(
+        "deterministic".to_string(),
+        GenerationProfile::builder().temperature(0.0).build(),
+    );
+    profiles.insert(
+        "creative".to_string(),
+        GenerationProfile::builder().temperature(1.1).top_p(0.95).presence_penalty(0.6).build(),
+    );
+    profiles.insert(
+        "json-extractor".to_string(),
+        GenerationProfile::builder()
+            .temperature(0.0)
+            .response_format(ResponseFormat::json_object())
+            .build(),
+    );
+    profiles
+}
+
+/// Adds `profile` as `name`, replacing any profile of that name, including built-in ones.
+pub fn register_profile(name: &str, profile: GenerationProfile) {
+    let mut profiles = PROFILES.write().unwrap_or_else(|e| e.into_inner());
+    profiles.insert(name.to_string(), profile);
+}
+
+/// Registers every profile of the JSON file at `path`, an object from profile names to
+/// settings, and returns how many there were.
+pub fn load_profiles<P: AsRef<Path>>(path: P) -> Result<usize, ProfileError> {
+    let data = fs::read_to_string(path)?;
+    let loaded: HashMap<String, GenerationProfile> =
+        serde_json::from_str(&data).map_err(|e| ProfileError::ParseError(e.to_string()))?;
+
+    let count = loaded.len();
+    for (name, profile) in loaded {
+        register_profile(&name, profile);
+    }
+    Ok(count)
+}
+
+/// The profile registered as `name`.
+pub fn lookup(name: &str) -> Option<GenerationProfile> {
+    let profiles = PROFILES.read().unwrap_or_else(|e| e.into_inner());
+    profiles.get(name).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_load_profiles() {
+        assert_eq!(lookup("deterministic").unwrap().temperature(), Some(0.0));
+        assert_eq!(lookup("summarizer"), None);
+
+        let path = env::temp_dir().join(format!("profiles-{}.json", std::process::id()));
+        fs::write(&path, r#"{"summarizer": {"temperature": 0.3, "max_tokens": 400}}"#).unwrap();
+        assert_eq!(load_profiles(&path).unwrap(), 1);
+        assert_eq!(lookup("summarizer").unwrap().max_tokens(), Some(400));
+
+        fs::write(&path, r#"{"summarizer": {"temperature": "low"}}"#).unwrap();
+        assert!(load_profiles(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
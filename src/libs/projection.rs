@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::cluster::{Clustering, XorShift};
+use super::math::{dot, mean_pool, normalize};
+
+#[derive(Debug, Error)]
+pub enum ProjectionError {
+    #[error("InvalidInput: {0}")]
+    InvalidInput(String),
+
+    #[error(transparent)]
+    CsvError(#[from] csv::Error),
+
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// How embeddings are mapped to the plane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProjectionMethod {
+    /// The two principal components, found by power iteration. Keeps as much of the spread
+    /// of the embeddings as two dimensions can.
+    #[default]
+    Pca,
+    /// Two random directions. Cheaper than PCA and good enough to see well separated
+    /// clusters, but distances are more distorted.
+    Random,
+}
+
+/// Maps embeddings to 2D coordinates for plotting, e.g. to sanity-check that documents on
+/// the same topic end up close together.
+///
+/// Embeddings are centered on their mean before projecting. Both methods start from
+/// random vectors drawn from a fixed `seed`, so the same input always gives the same
+/// coordinates.
+///
+/// # Fields
+///
+/// * `method`: Optional. Defaults to `ProjectionMethod::Pca`.
+/// * `max_iterations`: Optional. Upper bound on power iterations per PCA component. Defaults to 100.
+/// * `seed`: Optional. Seed of the random vectors. Defaults to 42.
+///
+/// # Example
+///
+/// ```rust
+/// let embeddings = fetch_embeddings(&ids, None).await?;
+/// let clustering = KMeans::builder().k(8).build().fit(&embeddings)?;
+/// let projection = Projector::builder().build().project(&embeddings)?.with_clusters(&clustering);
+/// fs::write("corpus.csv", projection.to_csv()?)?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Projector {
+    #[builder(default)]
+    method: ProjectionMethod,
+
+    #[builder(default = 100)]
+    max_iterations: usize,
+
+    #[builder(default = 42)]
+    seed: u64,
+}
+
+/// A projected embedding.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProjectedPoint {
+    id: String,
+    x: f32,
+    y: f32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster: Option<usize>,
+}
+
+/// Result of a `Projector`, in the order of its input.
+#[derive(Debug, Clone, Serialize)]
+pub struct Projection {
+    points: Vec<ProjectedPoint>,
+
+    /// Share of the total variance along each axis. Only known for PCA.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explained_variance: Option<[f32; 2]>,
+}
+
+impl Projector {
+    /// Projects `(id, embedding)` pairs. All embeddings must have the same dimension.
+    pub fn project(&self, embeddings: &[(String, Vec<f32>)]) -> Result<Projection, ProjectionError> {
+        let Some((_, first)) = embeddings.first() else {
+            return Err(ProjectionError::InvalidInput("no embeddings to project".to_string()));
+        };
+        let dimension = first.len();
+        if let Some((id, _)) = embeddings.iter().find(|(_, v)| v.len() != dimension) {
+            return Err(ProjectionError::InvalidInput(format!("{} has a different dimension", id)));
+        }
+
+        let vectors: Vec<Vec<f32>> = embeddings.iter().map(|(_, v)| v.clone()).collect();
+        let mean = mean_pool(&vectors).unwrap_or_default();
+        let centered: Vec<Vec<f32>> = vectors
+            .iter()
+            .map(|v| v.iter().zip(&mean).map(|(x, m)| x - m).collect())
+            .collect();
+
+        let mut rng = XorShift::new(self.seed);
+        let (axes, explained_variance) = match self.method {
+            ProjectionMethod::Pca => {
+                let first = self.principal_component(&centered, &[], &mut rng);
+                let second = self.principal_component(&centered, std::slice::from_ref(&first), &mut rng);
+                let total: f32 = centered.iter().map(|v| dot(v, v)).sum();
+                let variance = |axis: &[f32]| -> f32 {
+                    let explained: f32 = centered.iter().map(|v| dot(v, axis).powi(2)).sum();
+                    if total > 0.0 { explained / total } else { 0.0 }
+                };
+                let explained = [variance(&first), variance(&second)];
+                ([first, second], Some(explained))
+            }
+            ProjectionMethod::Random => {
+                let first = random_unit(dimension, &[], &mut rng);
+                let second = random_unit(dimension, std::slice::from_ref(&first), &mut rng);
+                ([first, second], None)
+            }
+        };
+
+        let points = embeddings
+            .iter()
+            .zip(&centered)
+            .map(|((id, _), v)| ProjectedPoint {
+                id: id.clone(),
+                x: dot(v, &axes[0]),
+                y: dot(v, &axes[1]),
+                cluster: None,
+            })
+            .collect();
+
+        Ok(Projection {
+            points,
+            explained_variance,
+        })
+    }
+
+    /// The direction of greatest variance of `centered` orthogonal to `found`, by power
+    /// iteration on the covariance matrix, which is applied as `Xᵀ(Xv)` without forming it.
+    fn principal_component(&self, centered: &[Vec<f32>], found: &[Vec<f32>], rng: &mut XorShift) -> Vec<f32> {
+        let dimension = centered[0].len();
+        let mut axis = random_unit(dimension, found, rng);
+        for _ in 0..self.max_iterations {
+            let mut next = vec![0.0; dimension];
+            for v in centered {
+                let weight = dot(v, &axis);
+                for (n, x) in next.iter_mut().zip(v) {
+                    *n += weight * x;
+                }
+            }
+            orthogonalize(&mut next, found);
+            let next = normalize(&next);
+            // Converged: the direction no longer changes (up to sign).
+            let converged = dot(&next, &axis).abs() > 1.0 - 1e-6;
+            axis = next;
+            if converged {
+                break;
+            }
+        }
+        axis
+    }
+}
+
+impl Projection {
+    /// Adds the cluster of each point, for coloring the plot.
+    pub fn with_clusters(mut self, clustering: &Clustering) -> Self {
+        let assignments: &HashMap<String, usize> = clustering.assignments();
+        for point in &mut self.points {
+            point.cluster = assignments.get(&point.id).copied();
+        }
+        self
+    }
+
+    pub fn points(&self) -> &Vec<ProjectedPoint> {
+        &self.points
+    }
+
+    pub fn explained_variance(&self) -> Option<[f32; 2]> {
+        self.explained_variance
+    }
+
+    /// CSV with an `id,x,y` header, plus a `cluster` column after `with_clusters`.
+    pub fn to_csv(&self) -> Result<String, ProjectionError> {
+        let clustered = self.points.iter().any(|point| point.cluster.is_some());
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        if clustered {
+            writer.write_record(["id", "x", "y", "cluster"])?;
+        } else {
+            writer.write_record(["id", "x", "y"])?;
+        }
+        for point in &self.points {
+            let mut record = vec![point.id.clone(), point.x.to_string(), point.y.to_string()];
+            if clustered {
+                record.push(point.cluster.map(|c| c.to_string()).unwrap_or_default());
+            }
+            writer.write_record(&record)?;
+        }
+        let bytes = writer.into_inner().map_err(|e| csv::Error::from(e.into_error()))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// JSON of the form `{"points": [{"id", "x", "y", "cluster"}], "explained_variance": [x, y]}`.
+    pub fn to_json(&self) -> Result<String, ProjectionError> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+impl ProjectedPoint {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn x(&self) -> f32 {
+        self.x
+    }
+
+    pub fn y(&self) -> f32 {
+        self.y
+    }
+
+    pub fn cluster(&self) -> Option<usize> {
+        self.cluster
+    }
+}
+
+/// Random unit vector orthogonal to `found`.
+fn random_unit(dimension: usize, found: &[Vec<f32>], rng: &mut XorShift) -> Vec<f32> {
+    let mut v: Vec<f32> = (0..dimension).map(|_| rng.unit() * 2.0 - 1.0).collect();
+    orthogonalize(&mut v, found);
+    normalize(&v)
+}
+
+/// Removes the components of `v` along the unit vectors `found` (Gram-Schmidt).
+fn orthogonalize(v: &mut [f32], found: &[Vec<f32>]) {
+    for axis in found {
+        let projection = dot(v, axis);
+        for (x, a) in v.iter_mut().zip(axis) {
+            *x -= projection * a;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project() {
+        // Spread mostly along the first axis, a little along the second, none on the third.
+        let embeddings: Vec<(String, Vec<f32>)> = [(-3.0, 0.5), (-1.0, -0.5), (1.0, -0.5), (3.0, 0.5)]
+            .iter()
+            .enumerate()
+            .map(|(n, (a, b))| (format!("v{}", n), vec![*a, *b, 1.0]))
+            .collect();
+
+        let projection = Projector::builder().build().project(&embeddings).unwrap();
+        let xs: Vec<f32> = projection.points().iter().map(|p| p.x().abs()).collect();
+        assert!((xs[0] - 3.0).abs() < 1e-3 && (xs[1] - 1.0).abs() < 1e-3, "{:?}", xs);
+        assert!(projection.points().iter().all(|p| (p.y().abs() - 0.5).abs() < 1e-3));
+        let [first, second] = projection.explained_variance().unwrap();
+        assert!((first + second - 1.0).abs() < 1e-3 && first > second);
+
+        let random = Projector::builder().method(ProjectionMethod::Random).build().project(&embeddings).unwrap();
+        assert_eq!(random.points().len(), 4);
+        assert_eq!(random.explained_variance(), None);
+
+        let csv = projection.to_csv().unwrap();
+        assert!(csv.starts_with("id,x,y\nv0,"));
+        assert_eq!(csv.lines().count(), 5);
+
+        assert!(Projector::builder().build().project(&[]).is_err());
+    }
+}
@@ -0,0 +1,706 @@
+use futures::{stream, StreamExt};
+use serde::Serialize;
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use std::collections::HashMap;
+
+use super::models;
+use super::openai_api::{get_tokens, truncate_to_tokens, Message, OpenAIRequest};
+use super::pinecone_data::Match;
+use super::search::{SearchError, SemanticSearch};
+
+/// Answer the model is told to give when the context does not contain the answer.
+pub const DEFAULT_REFUSAL: &str = "I don't know based on the provided documents.";
+
+/// Context budget used when the chat model is not in the registry and no cap is set.
+const DEFAULT_CONTEXT_TOKENS: usize = 3000;
+
+/// Reply of the compression model for chunks with nothing relevant to the question.
+const NOTHING_RELEVANT: &str = "NONE";
+
+const COMPRESSION_PROMPT: &str = "The user sends a question and a document excerpt. Copy, word \
+for word, only the sentences of the excerpt that help answer the question, and nothing else. If \
+no sentence helps, respond with exactly NONE.";
+
+const DEFAULT_PREAMBLE: &str = "You are a helpful assistant answering questions about a \
+collection of documents. Relevant excerpts are provided as numbered context.";
+
+#[derive(Debug, Error)]
+pub enum RagError {
+    #[error("ChatError: {0}")]
+    ChatError(String),
+
+    #[error(transparent)]
+    SearchError(#[from] SearchError),
+}
+
+/// A rule appended to the system prompt of a `RagChat`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Guardrail {
+    /// Answer only from the provided context, never from prior knowledge.
+    ContextOnly,
+    /// Reply with exactly this message when the context does not contain the answer.
+    Refusal(String),
+    /// Cite the numbered context excerpts an answer is based on, e.g. `[1]`.
+    Citations,
+    /// Any other instruction.
+    Custom(String),
+}
+
+impl Guardrail {
+    pub fn prompt(&self) -> String {
+        match self {
+            Guardrail::ContextOnly => "Answer only using the context below. Do not use prior knowledge, \
+                and do not guess."
+                .to_string(),
+            Guardrail::Refusal(message) => format!(
+                "If the context does not contain the answer, reply with exactly: \"{}\"",
+                message
+            ),
+            Guardrail::Citations => "Cite the context excerpts your answer is based on by their number \
+                in square brackets, e.g. [1] or [2][3], directly after the statement they support."
+                .to_string(),
+            Guardrail::Custom(prompt) => prompt.clone(),
+        }
+    }
+}
+
+/// System prompt of a `RagChat`, composed of a preamble and guardrails.
+///
+/// # Fields
+///
+/// * `preamble`: Optional. Opening instruction describing the assistant. Defaults to a generic document assistant.
+/// * `guardrails`: Optional. Rules appended in order, one per line.
+///
+/// # Example
+///
+/// ```rust
+/// let prompt = SystemPrompt::builder()
+///     .guardrails(vec![
+///         Guardrail::ContextOnly,
+///         Guardrail::Refusal("Not covered by the handbook.".to_string()),
+///     ])
+///     .build();
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct SystemPrompt {
+    #[builder(default = DEFAULT_PREAMBLE.to_string())]
+    preamble: String,
+
+    #[builder(default)]
+    guardrails: Vec<Guardrail>,
+}
+
+impl SystemPrompt {
+    /// Context-only answers, the default refusal, and citations.
+    pub fn guarded() -> Self {
+        SystemPrompt::builder()
+            .guardrails(vec![
+                Guardrail::ContextOnly,
+                Guardrail::Refusal(DEFAULT_REFUSAL.to_string()),
+                Guardrail::Citations,
+            ])
+            .build()
+    }
+
+    pub fn render(&self) -> String {
+        let mut prompt = self.preamble.clone();
+        for guardrail in &self.guardrails {
+            prompt.push('\n');
+            prompt.push_str(&guardrail.prompt());
+        }
+        prompt
+    }
+
+    /// The refusal message, if the prompt has a refusal guardrail.
+    pub fn refusal(&self) -> Option<&str> {
+        self.guardrails.iter().find_map(|guardrail| match guardrail {
+            Guardrail::Refusal(message) => Some(message.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether `answer` is the refusal message, ignoring case, whitespace, and quotes.
+    pub fn is_refusal(&self, answer: &str) -> bool {
+        let normalize = |text: &str| {
+            text.trim()
+                .trim_matches('"')
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        };
+        self.refusal()
+            .is_some_and(|refusal| normalize(answer).contains(&normalize(refusal)))
+    }
+
+    pub fn guardrails(&self) -> &Vec<Guardrail> {
+        &self.guardrails
+    }
+}
+
+impl Default for SystemPrompt {
+    fn default() -> Self {
+        SystemPrompt::guarded()
+    }
+}
+
+/// Post-retrieval step that asks the chat model to keep only the sentences of each retrieved
+/// chunk that are relevant to the question, so more chunks fit the context budget.
+///
+/// The `text` metadata of each match is replaced with the extracted sentences; matches
+/// with none are dropped. A chunk whose extraction fails is kept whole.
+///
+/// # Fields
+///
+/// * `model`: Optional. Chat model used for extraction. Defaults to "gpt-3.5-turbo".
+/// * `concurrency`: Optional. Number of chunks compressed in parallel. Defaults to 4.
+///
+/// # Example
+///
+/// ```rust
+/// let chat = RagChat::builder()
+///     .search(SemanticSearch::builder().top_k(12).build())
+///     .compressor(ContextCompressor::builder().build())
+///     .build();
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ContextCompressor {
+    #[builder(default = "gpt-3.5-turbo".to_string())]
+    model: String,
+
+    #[builder(default = 4)]
+    concurrency: usize,
+}
+
+impl ContextCompressor {
+    /// Compresses the `text` of every match for `question`, keeping their order.
+    pub async fn compress(&self, question: &str, matches: Vec<Match>) -> Vec<Match> {
+        let compressed: Vec<Option<Match>> = stream::iter(matches)
+            .map(|m| self.compress_match(question, m))
+            .buffered(self.concurrency.max(1))
+            .collect()
+            .await;
+        compressed.into_iter().flatten().collect()
+    }
+
+    async fn compress_match(&self, question: &str, mut m: Match) -> Option<Match> {
+        let Some(text) = m.metadata_str("text") else {
+            return Some(m);
+        };
+        match self.extract(question, text).await {
+            Ok(extract) if extract == NOTHING_RELEVANT => None,
+            Ok(extract) => {
+                m.metadata_mut().insert("text".to_string(), extract.into());
+                Some(m)
+            }
+            Err(e) => {
+                tracing::warn!("{}: compression failed, keeping the whole chunk: {}", m.id(), e);
+                Some(m)
+            }
+        }
+    }
+
+    async fn extract(&self, question: &str, text: &str) -> Result<String, String> {
+        let messages = vec![
+            Message::builder()
+                .role("system".to_string())
+                .content(COMPRESSION_PROMPT.to_string())
+                .build(),
+            Message::builder()
+                .role("user".to_string())
+                .content(format!("Question: {}\n\nExcerpt:\n{}", question, text))
+                .build(),
+        ];
+
+        let response = OpenAIRequest::builder()
+            .model(self.model.clone())
+            .messages(messages)
+            .temperature(0.0)
+            .build()
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let extract = response
+            .choices()
+            .first()
+            .map(|choice| choice.message().content().trim().to_string())
+            .ok_or("response has no choices")?;
+
+        if extract.is_empty() {
+            return Ok(NOTHING_RELEVANT.to_string());
+        }
+        Ok(extract)
+    }
+}
+
+/// Order in which packed chunks are placed in the prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interleave {
+    /// Best match first.
+    #[default]
+    ByScore,
+    /// One chunk per source in turn, each source's chunks best first, so no single
+    /// document fills the start of the context.
+    RoundRobin,
+    /// Best matches at both ends and the weakest in the middle, where models attend least.
+    EdgesFirst,
+}
+
+/// Packs retrieved chunks into the prompt of a `RagChat`.
+///
+/// Chunks are taken best score first and added whole until the next one no longer fits
+/// the token budget. The budget is what the model's context window, from the model
+/// registry, leaves after the rest of the prompt and `reserve_tokens` for the answer.
+///
+/// # Fields
+///
+/// * `max_tokens`: Optional. Upper bound on the budget. Used alone for models missing from the registry, which otherwise get 3000.
+/// * `reserve_tokens`: Optional. Tokens kept free for the answer. Defaults to 1024.
+/// * `max_per_source`: Optional. Maximum number of chunks from the same `source` metadata.
+/// * `interleave`: Optional. Order of the packed chunks in the prompt. Defaults to `Interleave::ByScore`.
+///
+/// # Example
+///
+/// ```rust
+/// let chat = RagChat::builder()
+///     .model("gpt-4".to_string())
+///     .search(SemanticSearch::builder().top_k(20).build())
+///     .packer(ContextPacker::builder().max_per_source(3).interleave(Interleave::RoundRobin).build())
+///     .build();
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ContextPacker {
+    #[builder(setter(strip_option), default)]
+    max_tokens: Option<usize>,
+
+    #[builder(default = 1024)]
+    reserve_tokens: usize,
+
+    #[builder(setter(strip_option), default)]
+    max_per_source: Option<usize>,
+
+    #[builder(default)]
+    interleave: Interleave,
+}
+
+impl ContextPacker {
+    /// Tokens available for context when `prompt_tokens` of the prompt to `model` are
+    /// taken by everything else.
+    pub fn budget(&self, model: &str, prompt_tokens: usize) -> usize {
+        match models::lookup(model) {
+            Some(info) => {
+                let available = (info.context_window() as usize)
+                    .saturating_sub(self.reserve_tokens)
+                    .saturating_sub(prompt_tokens);
+                self.max_tokens.map_or(available, |max| max.min(available))
+            }
+            None => self.max_tokens.unwrap_or(DEFAULT_CONTEXT_TOKENS),
+        }
+    }
+
+    /// Selects the chunks of `matches` that fit `budget` tokens, in prompt order.
+    /// Matches without `text` metadata are skipped.
+    pub fn pack(&self, mut matches: Vec<Match>, budget: usize) -> Vec<Match> {
+        matches.sort_by(|a, b| b.score().total_cmp(&a.score()));
+
+        let mut per_source: HashMap<String, usize> = HashMap::new();
+        let mut remaining = budget;
+        let mut packed = Vec::new();
+        for m in matches {
+            let Some(text) = m.metadata_str("text") else {
+                continue;
+            };
+            if let (Some(max), Some(source)) = (self.max_per_source, m.metadata_str("source")) {
+                let count = per_source.entry(source.to_string()).or_default();
+                if *count >= max {
+                    continue;
+                }
+                *count += 1;
+            }
+            let tokens = excerpt_tokens(packed.len() + 1, text);
+            if tokens > remaining {
+                break;
+            }
+            remaining -= tokens;
+            packed.push(m);
+        }
+        self.arrange(packed)
+    }
+
+    fn arrange(&self, packed: Vec<Match>) -> Vec<Match> {
+        match self.interleave {
+            Interleave::ByScore => packed,
+            Interleave::RoundRobin => {
+                let mut sources: Vec<(Option<String>, Vec<Match>)> = Vec::new();
+                for m in packed {
+                    let source = m.metadata_str("source").map(str::to_string);
+                    match sources.iter_mut().find(|(s, _)| *s == source) {
+                        Some((_, chunks)) => chunks.push(m),
+                        None => sources.push((source, vec![m])),
+                    }
+                }
+                let mut queues: Vec<_> = sources.into_iter().map(|(_, chunks)| chunks.into_iter()).collect();
+                let mut arranged = Vec::new();
+                loop {
+                    let before = arranged.len();
+                    arranged.extend(queues.iter_mut().filter_map(|queue| queue.next()));
+                    if arranged.len() == before {
+                        return arranged;
+                    }
+                }
+            }
+            Interleave::EdgesFirst => {
+                let mut front = Vec::new();
+                let mut back = Vec::new();
+                for (n, m) in packed.into_iter().enumerate() {
+                    if n % 2 == 0 {
+                        front.push(m);
+                    } else {
+                        back.push(m);
+                    }
+                }
+                front.extend(back.into_iter().rev());
+                front
+            }
+        }
+    }
+}
+
+fn excerpt(n: usize, text: &str) -> String {
+    format!("[{}] {}\n", n, text)
+}
+
+fn excerpt_tokens(n: usize, text: &str) -> usize {
+    get_tokens(&excerpt(n, text)).map_or(0, |t| t.len()) + 1
+}
+
+/// Answers questions from the documents in a Pinecone index.
+///
+/// The closest chunks are retrieved with `search`, and their `text` metadata is passed to
+/// the chat model as numbered context under the `system_prompt`. When `search` reports
+/// `InsufficientResults`, the chat declines with the refusal message without calling the
+/// model.
+///
+/// # Fields
+///
+/// * `model`: Optional. Chat model id. Defaults to "gpt-3.5-turbo".
+/// * `search`: Optional. Retrieval settings, including score policies. Defaults to the 4 closest chunks.
+/// * `context_tokens`: Optional. Token budget for the retrieved context. Defaults to 3000. Ignored with a `packer`.
+/// * `system_prompt`: Optional. Preamble and guardrails. Defaults to `SystemPrompt::guarded()`.
+/// * `compressor`: Optional. Cuts each retrieved chunk down to its sentences relevant to the
+///   question before the context is built.
+/// * `packer`: Optional. Packs the context into what the model's context window allows,
+///   instead of the `context_tokens` budget.
+///
+/// # Example
+///
+/// ```rust
+/// let search = SemanticSearch::builder()
+///     .namespace("docs".to_string())
+///     .top_k(4)
+///     .min_score(0.78)
+///     .require_at_least(1)
+///     .build();
+/// let chat = RagChat::builder().search(search).build();
+/// let answer = chat.ask("How do I rotate my API key?").await?;
+/// println!("{}", answer.answer());
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct RagChat {
+    #[builder(default = "gpt-3.5-turbo".to_string())]
+    model: String,
+
+    #[builder(default = SemanticSearch::builder().top_k(4).build())]
+    search: SemanticSearch,
+
+    #[builder(default = 3000)]
+    context_tokens: usize,
+
+    #[builder(default)]
+    system_prompt: SystemPrompt,
+
+    #[builder(setter(strip_option), default)]
+    compressor: Option<ContextCompressor>,
+
+    #[builder(setter(strip_option), default)]
+    packer: Option<ContextPacker>,
+}
+
+/// A context excerpt an answer was generated from.
+#[derive(Debug, Clone, Serialize)]
+pub struct RagSource {
+    id: String,
+    score: f32,
+    source: Option<String>,
+}
+
+/// Answer of a `RagChat`, with the context it was given.
+#[derive(Debug, Clone, Serialize)]
+pub struct RagAnswer {
+    answer: String,
+    sources: Vec<RagSource>,
+    refused: bool,
+}
+
+/// Outcome of checking the refusal policy against out-of-corpus questions.
+#[derive(Debug, Clone, Default)]
+pub struct RefusalCheck {
+    passed: Vec<String>,
+    failed: Vec<(String, String)>,
+}
+
+impl RagChat {
+    pub async fn ask(&self, question: &str) -> Result<RagAnswer, RagError> {
+        let matches = match self.retrieve(question).await {
+            Err(RagError::SearchError(SearchError::InsufficientResults { .. })) => {
+                return Ok(RagAnswer {
+                    answer: self.system_prompt.refusal().unwrap_or(DEFAULT_REFUSAL).to_string(),
+                    sources: Vec::new(),
+                    refused: true,
+                })
+            }
+            matches => matches?,
+        };
+        let matches = match &self.compressor {
+            Some(compressor) => compressor.compress(question, matches).await,
+            None => matches,
+        };
+        let matches = self.pack(question, matches);
+        let messages = self.messages(question, &matches);
+
+        let response = OpenAIRequest::builder()
+            .model(self.model.clone())
+            .messages(messages)
+            .temperature(0.0)
+            .build()
+            .send()
+            .await
+            .map_err(|e| RagError::ChatError(e.to_string()))?;
+
+        let answer = response
+            .choices()
+            .first()
+            .map(|choice| choice.message().content().to_string())
+            .ok_or_else(|| RagError::ChatError("response has no choices".to_string()))?;
+
+        let sources = matches.iter().map(RagSource::from).collect();
+
+        Ok(RagAnswer {
+            refused: self.system_prompt.is_refusal(&answer),
+            answer,
+            sources,
+        })
+    }
+
+    /// Retrieves the chunks closest to `question`.
+    pub async fn retrieve(&self, question: &str) -> Result<Vec<Match>, RagError> {
+        Ok(self.search.search(question).await?)
+    }
+
+    /// Selects the matches that fit the context window with the `packer`, if any.
+    pub fn pack(&self, question: &str, matches: Vec<Match>) -> Vec<Match> {
+        let Some(packer) = &self.packer else {
+            return matches;
+        };
+        let prompt = format!("{}\n\nContext:\n{}", self.system_prompt.render(), question);
+        let budget = packer.budget(&self.model, get_tokens(&prompt).map_or(0, |t| t.len()));
+        packer.pack(matches, budget)
+    }
+
+    /// Builds the chat messages: the system prompt with numbered context, then the question.
+    ///
+    /// Context is added best match first until `context_tokens` is used up. With a
+    /// `packer`, `matches` are expected to be packed already by `pack` and are added whole.
+    pub fn messages(&self, question: &str, matches: &[Match]) -> Vec<Message> {
+        let mut context = String::new();
+        let mut remaining = if self.packer.is_some() { usize::MAX } else { self.context_tokens };
+        for (n, m) in matches.iter().enumerate() {
+            let Some(text) = m.metadata_str("text") else {
+                continue;
+            };
+            let excerpt = excerpt(n + 1, text);
+            let truncated = truncate_to_tokens(&excerpt, remaining);
+            if truncated.is_empty() {
+                break;
+            }
+            remaining = remaining.saturating_sub(get_tokens(truncated).map_or(0, |t| t.len()));
+            context.push_str(truncated);
+            context.push('\n');
+        }
+
+        let system = format!("{}\n\nContext:\n{}", self.system_prompt.render(), context.trim_end());
+        vec![
+            Message::builder()
+                .role("system".to_string())
+                .content(system)
+                .build(),
+            Message::builder()
+                .role("user".to_string())
+                .content(question.to_string())
+                .build(),
+        ]
+    }
+
+    /// Test mode: asks questions the corpus cannot answer and checks the model refuses each.
+    ///
+    /// Requires a `Guardrail::Refusal` in the system prompt; without one every question fails.
+    pub async fn check_refusals(&self, questions: &[&str]) -> Result<RefusalCheck, RagError> {
+        let mut check = RefusalCheck::default();
+        for question in questions {
+            let answer = self.ask(question).await?;
+            if answer.refused {
+                check.passed.push(question.to_string());
+            } else {
+                check.failed.push((question.to_string(), answer.answer));
+            }
+        }
+        Ok(check)
+    }
+
+    pub fn system_prompt(&self) -> &SystemPrompt {
+        &self.system_prompt
+    }
+}
+
+impl From<&Match> for RagSource {
+    fn from(m: &Match) -> Self {
+        RagSource {
+            id: m.id().clone(),
+            score: m.score(),
+            source: m.metadata_str("source").map(str::to_string),
+        }
+    }
+}
+
+impl RagSource {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    pub fn source(&self) -> &Option<String> {
+        &self.source
+    }
+}
+
+impl RagAnswer {
+    pub fn answer(&self) -> &String {
+        &self.answer
+    }
+
+    pub fn sources(&self) -> &Vec<RagSource> {
+        &self.sources
+    }
+
+    pub fn refused(&self) -> bool {
+        self.refused
+    }
+}
+
+impl RefusalCheck {
+    pub fn passed(&self) -> &Vec<String> {
+        &self.passed
+    }
+
+    /// Questions the model answered anyway, with its answer.
+    pub fn failed(&self) -> &Vec<(String, String)> {
+        &self.failed
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::FakeServices;
+
+    #[test]
+    fn test_system_prompt() {
+        let prompt = SystemPrompt::builder()
+            .preamble("Preamble.".to_string())
+            .guardrails(vec![
+                Guardrail::Refusal("Not in the handbook.".to_string()),
+                Guardrail::Custom("Be brief.".to_string()),
+            ])
+            .build();
+
+        assert_eq!(
+            prompt.render(),
+            "Preamble.\nIf the context does not contain the answer, reply with exactly: \"Not in the handbook.\"\nBe brief."
+        );
+        assert!(prompt.is_refusal("\"not in the  handbook.\""));
+        assert!(!prompt.is_refusal("The handbook says 42."));
+        assert!(!SystemPrompt::builder().build().is_refusal(DEFAULT_REFUSAL));
+    }
+
+    #[test]
+    fn test_context_packer() {
+        let chunk = |id: &str, score: f32, source: &str| -> Match {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "score": score,
+                "metadata": { "text": "Some retrieved text.", "source": source },
+            }))
+            .unwrap()
+        };
+        let matches = vec![
+            chunk("a1", 0.9, "a"),
+            chunk("a2", 0.8, "a"),
+            chunk("b1", 0.85, "b"),
+            chunk("a3", 0.7, "a"),
+            chunk("c1", 0.6, "c"),
+        ];
+        let ids = |packed: Vec<Match>| packed.iter().map(|m| m.id().clone()).collect::<Vec<_>>();
+
+        let packer = ContextPacker::builder().build();
+        assert_eq!(ids(packer.pack(matches.clone(), 1000)), ["a1", "b1", "a2", "a3", "c1"]);
+        assert_eq!(ids(packer.pack(matches.clone(), 20)), ["a1", "b1"]);
+
+        let capped = ContextPacker::builder().max_per_source(1).build();
+        assert_eq!(ids(capped.pack(matches.clone(), 1000)), ["a1", "b1", "c1"]);
+
+        let round_robin = ContextPacker::builder().interleave(Interleave::RoundRobin).build();
+        assert_eq!(ids(round_robin.pack(matches.clone(), 1000)), ["a1", "b1", "c1", "a2", "a3"]);
+
+        let edges = ContextPacker::builder().interleave(Interleave::EdgesFirst).build();
+        assert_eq!(ids(edges.pack(matches, 1000)), ["a1", "a2", "c1", "a3", "b1"]);
+
+        assert_eq!(packer.budget("gpt-4", 1000), 8192 - 1024 - 1000);
+        assert_eq!(ContextPacker::builder().max_tokens(500).build().budget("gpt-4", 1000), 500);
+        assert_eq!(packer.budget("unknown-model", 1000), DEFAULT_CONTEXT_TOKENS);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_refusal_policy() {
+        let chat = RagChat::builder().build();
+        let check = chat
+            .check_refusals(&["What was the winning lottery number in Ohio on 3 March 1987?"])
+            .await
+            .unwrap();
+
+        assert!(check.is_ok(), "{:?}", check.failed());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_compress() {
+        let fakes = FakeServices::seeded().await;
+        let matches = SemanticSearch::builder().top_k(1).build().search("when are refunds issued").await.unwrap();
+        let compressor = ContextCompressor::builder().build();
+        fakes.set_chat_reply("Refunds are issued within 30 days.");
+        let compressed = compressor.compress("when are refunds issued", matches.clone()).await;
+        assert_eq!(compressed[0].metadata()["text"], "Refunds are issued within 30 days.");
+        fakes.set_chat_reply("NONE");
+        assert!(compressor.compress("when are refunds issued", matches).await.is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(not(feature = "wasm"))]
+use std::time::Instant;
+
+use lazy_static::lazy_static;
+#[cfg(feature = "wasm")]
+use web_time::Instant;
+
+lazy_static! {
+    static ref RATE_LIMITER: RateLimiter = RateLimiter::new();
+}
+
+/// The process-wide limiter every OpenAI request waits on.
+///
+/// # Example
+///
+/// ```rust
+/// rate_limiter().set_limits("text-embedding-ada-002", Limits::new(3_000, 1_000_000));
+/// rate_limiter().set_limits("gpt-3.5-turbo", Limits::new(3_500, 90_000));
+/// rate_limiter().acquire("gpt-3.5-turbo", 1_200, Priority::Interactive).await;
+/// ```
+pub fn rate_limiter() -> &'static RateLimiter {
+    &RATE_LIMITER
+}
+
+/// How urgently a request needs the rate-limit budget.
+///
+/// While an interactive request is waiting for a model's budget, batch requests for that
+/// model hold back, so a long ingestion cannot starve chat and search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// A user is waiting on the result.
+    #[default]
+    Interactive,
+    /// Background work such as ingestion or classification jobs.
+    Batch,
+}
+
+/// How often held-back batch requests check whether interactive requests are done.
+const BATCH_YIELD_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Requests-per-minute and tokens-per-minute budget of one model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    requests_per_minute: u32,
+    tokens_per_minute: u32,
+}
+
+impl Limits {
+    pub fn new(requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        Limits {
+            requests_per_minute,
+            tokens_per_minute,
+        }
+    }
+
+    pub fn requests_per_minute(&self) -> u32 {
+        self.requests_per_minute
+    }
+
+    pub fn tokens_per_minute(&self) -> u32 {
+        self.tokens_per_minute
+    }
+}
+
+/// Continuously refilling budget: `capacity` units per minute, at most `capacity` banked.
+#[derive(Debug, Clone)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: u32, now: Instant) -> Self {
+        Bucket {
+            capacity: capacity as f64,
+            available: capacity as f64,
+            refilled: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.refilled = now;
+    }
+
+    /// Time until `amount` can be taken. Amounts larger than the whole budget only wait for
+    /// a full bucket and then leave it in debt, so they slow later requests instead of
+    /// blocking forever.
+    fn wait(&self, amount: f64) -> Duration {
+        let needed = amount.min(self.capacity) - self.available;
+        if needed <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(needed * 60.0 / self.capacity)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ModelBudget {
+    requests: Bucket,
+    tokens: Bucket,
+    interactive_waiting: usize,
+}
+
+impl ModelBudget {
+    fn new(limits: Limits, now: Instant) -> Self {
+        ModelBudget {
+            requests: Bucket::new(limits.requests_per_minute.max(1), now),
+            tokens: Bucket::new(limits.tokens_per_minute.max(1), now),
+            interactive_waiting: 0,
+        }
+    }
+
+    /// Takes one request and `tokens` tokens if both budgets allow, otherwise returns how
+    /// long to wait before trying again.
+    fn try_acquire(&mut self, tokens: u32, priority: Priority, now: Instant) -> Result<(), Duration> {
+        if priority == Priority::Batch && self.interactive_waiting > 0 {
+            return Err(BATCH_YIELD_INTERVAL);
+        }
+
+        self.requests.refill(now);
+        self.tokens.refill(now);
+
+        let wait = self.requests.wait(1.0).max(self.tokens.wait(tokens as f64));
+        if wait > Duration::ZERO {
+            return Err(wait);
+        }
+
+        self.requests.available -= 1.0;
+        self.tokens.available -= tokens as f64;
+        Ok(())
+    }
+}
+
+/// Client-side rate limiter with per-model request and token budgets.
+///
+/// Budgets refill continuously rather than resetting each minute, so a batch job is
+/// smoothed to the configured rate instead of bursting into 429s. Interactive requests
+/// are served before waiting batch requests. Models without limits are not throttled.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    budgets: Mutex<HashMap<String, ModelBudget>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter::default()
+    }
+
+    /// Sets the limits of `model`, replacing any previous ones.
+    pub fn set_limits(&self, model: &str, limits: Limits) {
+        let budget = ModelBudget::new(limits, Instant::now());
+        self.budgets.lock().unwrap().insert(model.to_string(), budget);
+    }
+
+    pub fn remove_limits(&self, model: &str) {
+        self.budgets.lock().unwrap().remove(model);
+    }
+
+    /// Waits until `model` has budget for one request of `tokens` tokens, then takes it.
+    pub async fn acquire(&self, model: &str, tokens: u32, priority: Priority) {
+        let _waiting = match priority {
+            Priority::Interactive => Some(InteractiveWaiter::new(self, model)),
+            Priority::Batch => None,
+        };
+
+        while let Err(wait) = self.try_acquire(model, tokens, priority) {
+            sleep(wait).await;
+        }
+    }
+
+    /// Takes budget for one request of `tokens` tokens without waiting, or returns how long
+    /// to wait before trying again.
+    pub fn try_acquire(&self, model: &str, tokens: u32, priority: Priority) -> Result<(), Duration> {
+        let mut budgets = self.budgets.lock().unwrap();
+        match budgets.get_mut(model) {
+            Some(budget) => budget.try_acquire(tokens, priority, Instant::now()),
+            None => Ok(()),
+        }
+    }
+
+    fn update_waiting(&self, model: &str, update: impl FnOnce(&mut usize)) {
+        if let Some(budget) = self.budgets.lock().unwrap().get_mut(model) {
+            update(&mut budget.interactive_waiting);
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+/// wasm32 has no tokio timer; wait on the host's `setTimeout` instead.
+#[cfg(feature = "wasm")]
+pub(crate) async fn sleep(duration: Duration) {
+    gloo_timers::future::sleep(duration).await
+}
+
+/// Counts an interactive request as waiting for as long as it lives, including when the
+/// waiting future is dropped.
+struct InteractiveWaiter<'a> {
+    limiter: &'a RateLimiter,
+    model: &'a str,
+}
+
+impl<'a> InteractiveWaiter<'a> {
+    fn new(limiter: &'a RateLimiter, model: &'a str) -> Self {
+        limiter.update_waiting(model, |waiting| *waiting += 1);
+        InteractiveWaiter { limiter, model }
+    }
+}
+
+impl Drop for InteractiveWaiter<'_> {
+    fn drop(&mut self) {
+        self.limiter
+            .update_waiting(self.model, |waiting| *waiting = waiting.saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_budget() {
+        let start = Instant::now();
+        let mut budget = ModelBudget::new(Limits::new(60, 600), start);
+
+        assert!(budget.try_acquire(500, Priority::Batch, start).is_ok());
+        // 100 tokens left; the missing 100 refill in 10 seconds.
+        assert_eq!(budget.try_acquire(200, Priority::Batch, start), Err(Duration::from_secs(10)));
+        assert!(budget.try_acquire(200, Priority::Batch, start + Duration::from_secs(10)).is_ok());
+
+        // Oversized requests wait for a full bucket, then go into debt.
+        let later = start + Duration::from_secs(120);
+        assert!(budget.try_acquire(1000, Priority::Batch, later).is_ok());
+        assert_eq!(budget.try_acquire(1, Priority::Batch, later), Err(Duration::from_secs_f64(40.1)));
+    }
+
+    #[test]
+    fn test_unlimited_model() {
+        let limiter = RateLimiter::new();
+        limiter.set_limits("limited", Limits::new(1, 1000));
+
+        assert!(limiter.try_acquire("limited", 10, Priority::Interactive).is_ok());
+        assert!(limiter.try_acquire("limited", 10, Priority::Interactive).is_err());
+        assert!(limiter.try_acquire("other", 10, Priority::Interactive).is_ok());
+    }
+
+    #[test]
+    fn test_interactive_priority() {
+        let limiter = RateLimiter::new();
+        limiter.set_limits("model", Limits::new(1000, 100_000));
+
+        let waiter = InteractiveWaiter::new(&limiter, "model");
+        assert_eq!(limiter.try_acquire("model", 10, Priority::Batch), Err(BATCH_YIELD_INTERVAL));
+        assert!(limiter.try_acquire("model", 10, Priority::Interactive).is_ok());
+
+        drop(waiter);
+        assert!(limiter.try_acquire("model", 10, Priority::Batch).is_ok());
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RedactError {
+    #[error("PatternError: {0}")]
+    PatternError(String),
+}
+
+/// Built-in kinds of sensitive data a `Redactor` can detect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detector {
+    Email,
+    Phone,
+    /// Secret keys of common services (OpenAI, AWS, GitHub, Slack, Stripe).
+    ApiKey,
+    /// US social security numbers.
+    Ssn,
+}
+
+impl Detector {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Detector::Email => "EMAIL",
+            Detector::Phone => "PHONE",
+            Detector::ApiKey => "API_KEY",
+            Detector::Ssn => "SSN",
+        }
+    }
+
+    fn pattern(&self) -> &'static str {
+        match self {
+            Detector::Email => r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+            Detector::Phone => r"(?:\+\d{1,3}[-. ]?)?(?:\(\d{3}\)|\b\d{3})[-. ]?\d{3}[-. ]?\d{4}\b",
+            Detector::ApiKey => {
+                r"\b(?:sk-[A-Za-z0-9_-]{20,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{36,}|xox[abprs]-[A-Za-z0-9-]{10,}|[rs]k_live_[A-Za-z0-9]{16,})\b"
+            }
+            Detector::Ssn => r"\b\d{3}-\d{2}-\d{4}\b",
+        }
+    }
+}
+
+/// Masks emails, phone numbers, API keys, SSNs, and custom patterns in text.
+///
+/// Each match is replaced by `[REDACTED:<NAME>]`, e.g. `[REDACTED:EMAIL]`. Detectors run in
+/// the order they were added; the built-in ones are ordered so SSNs and keys are not
+/// mistaken for phone numbers.
+///
+/// # Example
+///
+/// ```rust
+/// let redactor = Redactor::new().with_pattern("TICKET", r"\bINC-\d+\b")?;
+/// let pipeline = IngestionPipeline::builder().redactor(redactor).build();
+///
+/// let report = pipeline.ingest(&documents).await?;
+/// for (document, counts) in report.redactions() {
+///     println!("{}: {:?}", document, counts);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    patterns: Vec<(String, Regex)>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    /// A redactor with every built-in detector.
+    pub fn new() -> Self {
+        [Detector::ApiKey, Detector::Email, Detector::Ssn, Detector::Phone]
+            .iter()
+            .fold(Self::empty(), |redactor, detector| redactor.with(*detector))
+    }
+
+    /// A redactor without detectors, to pick them with `with` and `with_pattern`.
+    pub fn empty() -> Self {
+        Redactor { patterns: Vec::new() }
+    }
+
+    pub fn with(mut self, detector: Detector) -> Self {
+        let regex = Regex::new(detector.pattern()).expect("built-in patterns are valid");
+        self.patterns.push((detector.name().to_string(), regex));
+        self
+    }
+
+    /// Adds a detector masking matches of `pattern` as `[REDACTED:<name>]`.
+    pub fn with_pattern(mut self, name: &str, pattern: &str) -> Result<Self, RedactError> {
+        let regex = Regex::new(pattern).map_err(|e| RedactError::PatternError(e.to_string()))?;
+        self.patterns.push((name.to_string(), regex));
+        Ok(self)
+    }
+
+    /// Masks `text`, adding the number of matches of each detector to `counts`.
+    pub fn redact(&self, text: &str, counts: &mut BTreeMap<String, usize>) -> String {
+        let mut redacted = text.to_string();
+        for (name, regex) in &self.patterns {
+            let matches = regex.find_iter(&redacted).count();
+            if matches == 0 {
+                continue;
+            }
+            redacted = regex
+                .replace_all(&redacted, format!("[REDACTED:{}]", name).as_str())
+                .into_owned();
+            *counts.entry(name.clone()).or_default() += matches;
+        }
+        redacted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let redactor = Redactor::new().with_pattern("TICKET", r"\bINC-\d+\b").unwrap();
+        let mut counts = BTreeMap::new();
+
+        let text = "Mail jane.doe@example.com or call (555) 123-4567 / +1 555.987.6543 about INC-42. \
+                    SSN 123-45-6789, key sk-abcdefghijklmnopqrstuvwx, build 2023-01-15.";
+        assert_eq!(
+            redactor.redact(text, &mut counts),
+            "Mail [REDACTED:EMAIL] or call [REDACTED:PHONE] / [REDACTED:PHONE] about [REDACTED:TICKET]. \
+             SSN [REDACTED:SSN], key [REDACTED:API_KEY], build 2023-01-15."
+        );
+        assert_eq!(counts.get("PHONE"), Some(&2));
+        assert_eq!(counts.values().sum::<usize>(), 6);
+
+        assert!(Redactor::empty().with_pattern("BAD", "(").is_err());
+    }
+}
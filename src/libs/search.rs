@@ -0,0 +1,1090 @@
+use std::collections::{BTreeMap, HashMap};
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::future::{join_all, select, try_join_all, Either};
+use futures::{stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::cache::{query_key, QueryCache};
+use super::context;
+use super::local_index::LocalIndex;
+use super::math::cosine_similarity;
+use super::openai_api::{Message, OpenAIRequest};
+use super::parents::ParentChunks;
+use super::observer::{DatabaseObserver, Observer, ObserverError, QuerySummary};
+use super::pinecone_api::{circuit_open, index_metric, PineconeApiError};
+use super::pinecone_data::{document_id, Filter, Match, PineconeRequest, PineconeResponse, Vector};
+use super::pipeline::{embed, PipelineError, INGESTED_AT_KEY};
+use super::rate_limit::Priority;
+use super::typed_metadata::MetadataError;
+
+#[derive(Debug, Error)]
+pub enum SearchError {
+    #[error(transparent)]
+    PipelineError(#[from] PipelineError),
+
+    #[error(transparent)]
+    PineconeError(#[from] PineconeApiError),
+
+    #[error("InsufficientResults: {found} matches passed the filters, {required} required")]
+    InsufficientResults { found: usize, required: usize },
+
+    #[error(transparent)]
+    ObserverError(#[from] ObserverError),
+
+    #[error("ExpansionError: {0}")]
+    ExpansionError(String),
+
+    #[error(transparent)]
+    MetadataError(#[from] MetadataError),
+}
+
+/// Result of one query of `SemanticSearch::query_many`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    query: String,
+
+    /// Time from sending the query to receiving its matches, embedding included.
+    latency_ms: f64,
+    matches: Vec<Match>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Latency distribution of a batch of requests, in milliseconds. Percentiles are
+/// nearest-rank, over the requests that succeeded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LatencySummary {
+    requests: usize,
+    errors: usize,
+    mean: f64,
+    min: f64,
+    p50: f64,
+    p90: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+/// How the scores of a document's matching chunks make up the document's score, see
+/// `SemanticSearch::search_documents`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScoreAggregation {
+    /// The best chunk's score: a document matches as well as its most relevant passage.
+    #[default]
+    Max,
+    /// The mean of the matching chunks' scores, favoring documents relevant throughout.
+    Mean,
+}
+
+/// A document and the chunks of it that matched a query, see
+/// `SemanticSearch::search_documents`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentMatch {
+    id: String,
+
+    /// Aggregated score of `chunks`.
+    score: f32,
+
+    /// Matching chunks, best first.
+    chunks: Vec<Match>,
+}
+
+/// How often the index or its replica answered the queries of a `SemanticSearch` raced
+/// against both first, see `SemanticSearch::with_replica`.
+#[derive(Debug, Default)]
+pub struct RaceStats {
+    primary_wins: AtomicU64,
+    replica_wins: AtomicU64,
+
+    /// Queries neither answered.
+    failures: AtomicU64,
+}
+
+/// Rank constant of reciprocal rank fusion; larger values flatten the weight of top ranks.
+const RRF_K: f32 = 60.0;
+
+const MULTI_QUERY_PROMPT: &str = "Rewrite the search query the user sends in {n} different ways, \
+using different wording and perspectives, to help find relevant documents. Respond with one \
+query per line and nothing else.";
+
+const HYDE_PROMPT: &str = "Write a short passage that answers the question the user sends, as it \
+could appear in a document. Respond only with the passage.";
+
+/// Queries a `SemanticSearch` derives from the user's query with the chat model, to find
+/// documents worded differently from the query. Each derived query is embedded and
+/// searched alongside the original, and the results are fused by reciprocal rank.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryExpansion {
+    /// `n` reformulations of the query from the chat model `model`.
+    MultiQuery { model: String, n: usize },
+    /// A hypothetical answer to the query from the chat model `model`, which tends to
+    /// embed closer to real answers than the question does (HyDE).
+    Hyde { model: String },
+}
+
+impl QueryExpansion {
+    /// The texts to search for besides `query`.
+    pub async fn expand(&self, query: &str) -> Result<Vec<String>, SearchError> {
+        let (model, prompt) = match self {
+            QueryExpansion::MultiQuery { model, n } => (model, MULTI_QUERY_PROMPT.replace("{n}", &n.to_string())),
+            QueryExpansion::Hyde { model } => (model, HYDE_PROMPT.to_string()),
+        };
+        let messages = vec![
+            Message::builder().role("system".to_string()).content(prompt).build(),
+            Message::builder().role("user".to_string()).content(query.to_string()).build(),
+        ];
+
+        let response = OpenAIRequest::builder()
+            .model(model.clone())
+            .messages(messages)
+            .temperature(0.0)
+            .priority(Priority::Interactive)
+            .build()
+            .send()
+            .await
+            .map_err(|e| SearchError::ExpansionError(e.to_string()))?;
+        let content = response
+            .choices()
+            .first()
+            .map(|choice| choice.message().content().trim().to_string())
+            .unwrap_or_default();
+
+        Ok(match self {
+            QueryExpansion::MultiQuery { n, .. } => content
+                .lines()
+                .map(|line| line.trim_start_matches(|c: char| c.is_ascii_digit() || "-*.) ".contains(c)).trim())
+                .filter(|line| !line.is_empty())
+                .take(*n)
+                .map(str::to_string)
+                .collect(),
+            QueryExpansion::Hyde { .. } if content.is_empty() => Vec::new(),
+            QueryExpansion::Hyde { .. } => vec![content],
+        })
+    }
+}
+
+/// Embeds a text query and returns the closest vectors in Pinecone.
+///
+/// With `mmr_lambda` set, `fetch_k` candidates are retrieved with their values and
+/// re-ranked with Maximal Marginal Relevance, so the `top_k` results are relevant but not
+/// near-duplicates of each other.
+///
+/// Matches scoring below `min_score` are dropped. If fewer than `require_at_least` remain,
+/// the search fails with `SearchError::InsufficientResults`, so callers can decline to
+/// answer instead of working from weak context.
+///
+/// # Fields
+///
+/// * `embedding_model`: Optional. Embedding model id; must match the one used at ingestion. Defaults to "text-embedding-ada-002".
+/// * `namespace`: Optional. Pinecone namespace to search.
+/// * `top_k`: Optional. Number of results. Defaults to 10.
+/// * `filter`: Optional. Pinecone metadata filter.
+/// * `mmr_lambda`: Optional. Balance between relevance (1.0) and diversity (0.0). Disables MMR when unset.
+/// * `fetch_k`: Optional. Candidates retrieved for MMR, recency weighting or `parents`. Defaults to 4 times `top_k`.
+/// * `min_score`: Optional. Lowest similarity score kept.
+/// * `normalize_scores`: Optional. Rescales scores to cosine similarities by the metric of
+///   the index, from `describe_index`, so `min_score` means the same on a dotproduct or
+///   euclidean index, see `Metric::normalize`. Defaults to false.
+/// * `recency_half_life`: Optional. Weights the score of every match by its age, from its
+///   `ingested_at` metadata, halving it every `recency_half_life`, and re-ranks the
+///   candidates, so fresh content wins over slightly closer stale content. See `recency_decay`.
+/// * `require_at_least`: Optional. Fewest matches accepted after `min_score` is applied.
+/// * `observer`: Optional. Notified of every query with a `QuerySummary`.
+/// * `mirror`: Optional. Vector mirror whose soft-deleted vectors are dropped from the
+///   matches, so they stop showing up before Pinecone's copies are purged.
+/// * `expansion`: Optional. Also searches for queries derived from the text query by the
+///   chat model, concurrently, and fuses the results. Does not apply to `search_vector`.
+/// * `parents`: Optional. Returns the parent passage of every matching chunk in its `text`
+///   metadata, once per parent, see `ParentChunks::hydrate`.
+///
+/// For latency-sensitive searches across regions, `with_replica` races every query against
+/// a replica of the index. `with_cache` serves repeated text queries from a `QueryCache`,
+/// and `with_fallback` keeps searches answering from a `LocalIndex` during outages.
+///
+/// # Example
+///
+/// ```rust
+/// let matches = SemanticSearch::builder()
+///     .top_k(5)
+///     .mmr_lambda(0.5)
+///     .min_score(0.75)
+///     .require_at_least(2)
+///     .build()
+///     .search("How are refunds processed?")
+///     .await?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct SemanticSearch {
+    #[builder(default = "text-embedding-ada-002".to_string())]
+    embedding_model: String,
+
+    #[builder(setter(strip_option), default)]
+    namespace: Option<String>,
+
+    #[builder(default = 10)]
+    top_k: i64,
+
+    #[builder(setter(strip_option), default)]
+    filter: Option<Filter>,
+
+    #[builder(setter(strip_option), default)]
+    mmr_lambda: Option<f32>,
+
+    #[builder(setter(strip_option), default)]
+    fetch_k: Option<i64>,
+
+    #[builder(setter(strip_option), default)]
+    min_score: Option<f32>,
+
+    #[builder(default)]
+    normalize_scores: bool,
+
+    #[builder(setter(strip_option), default)]
+    recency_half_life: Option<Duration>,
+
+    #[builder(setter(strip_option), default)]
+    require_at_least: Option<usize>,
+
+    #[builder(setter(strip_option), default)]
+    observer: Option<Arc<dyn Observer>>,
+
+    #[builder(setter(strip_option), default)]
+    mirror: Option<DatabaseObserver>,
+
+    #[builder(setter(strip_option), default)]
+    expansion: Option<QueryExpansion>,
+
+    #[builder(setter(strip_option), default)]
+    parents: Option<ParentChunks>,
+
+    #[builder(setter(skip), default)]
+    replica: Option<String>,
+
+    #[builder(setter(skip), default)]
+    race_stats: Arc<RaceStats>,
+
+    #[builder(setter(skip), default)]
+    cache: Option<QueryCache>,
+
+    #[builder(setter(skip), default)]
+    fallback: Option<Arc<LocalIndex>>,
+}
+
+impl SemanticSearch {
+    pub async fn search(&self, query: &str) -> Result<Vec<Match>, SearchError> {
+        let started = Instant::now();
+        let key = self.cache.as_ref().map(|_| self.cache_key(query));
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            if let Some(matches) = cache.get(key).await {
+                // Vectors may have been deleted in the mirror since the results were cached.
+                let matches = self.apply_policies(self.drop_deleted(matches).await?)?;
+                self.notify(Some(query), &matches, started).await?;
+                return Ok(matches);
+            }
+        }
+
+        let expanded = match &self.expansion {
+            Some(expansion) => expansion.expand(query).await?,
+            None => Vec::new(),
+        };
+
+        let texts = std::iter::once(query).chain(expanded.iter().map(String::as_str));
+        let mut embeddings =
+            try_join_all(texts.map(|text| embed(&self.embedding_model, text, Priority::Interactive))).await?;
+        let values = embeddings.remove(0);
+        let matches = self.run(values, embeddings, Some(query), started).await?;
+
+        // Degraded matches would outlive the outage in the cache.
+        if let (Some(cache), Some(key), false) = (&self.cache, &key, matches.iter().any(Match::degraded)) {
+            // A failed write only costs the next search a miss.
+            cache.put(key, &matches).await.ok();
+        }
+        Ok(matches)
+    }
+
+    /// Searches like `search`, parsing the metadata of every match into `T`, see
+    /// `Match::parse_metadata`.
+    pub async fn search_as<T: DeserializeOwned>(&self, query: &str) -> Result<Vec<Match<T>>, SearchError> {
+        let matches = self.search(query).await?;
+        Ok(matches.into_iter().map(Match::parse_metadata).collect::<Result<_, _>>()?)
+    }
+
+    /// Key of `query`'s results in the cache, covering every setting that changes them.
+    fn cache_key(&self, query: &str) -> String {
+        let namespace = self.namespace.clone().or_else(context::current_namespace);
+        let filter: Option<BTreeMap<&String, &Value>> = self.filter.as_ref().map(|filter| filter.iter().collect());
+        let settings = format!(
+            "{}|{:?}|{:?}|{:?}|{:?}|{}|{:?}|{:?}|{:?}|{}|{}",
+            self.embedding_model,
+            filter,
+            self.mmr_lambda,
+            self.fetch_k,
+            self.min_score,
+            self.normalize_scores,
+            self.recency_half_life,
+            self.require_at_least,
+            self.expansion,
+            self.parents.is_some(),
+            self.mirror.is_some()
+        );
+        query_key(query, namespace.as_deref(), self.top_k, &settings)
+    }
+
+    /// Searches with an already embedded query.
+    pub async fn search_vector(&self, values: Vec<f32>) -> Result<Vec<Match>, SearchError> {
+        self.run(values, Vec::new(), None, Instant::now()).await
+    }
+
+    fn candidates(&self) -> i64 {
+        match (self.mmr_lambda, self.recency_half_life, &self.parents) {
+            (None, None, None) => self.top_k,
+            _ => self.fetch_k.unwrap_or(self.top_k * 4).max(self.top_k),
+        }
+    }
+
+    /// The closest `candidates` matches of one embedded query.
+    async fn retrieve(&self, values: Vec<f32>) -> Result<Vec<Match>, SearchError> {
+        let fallback_values = self.fallback.as_ref().map(|_| values.clone());
+        let request = PineconeRequest::builder()
+            .vector(Vector::builder().values(values).build())
+            .top_k(self.candidates())
+            .include_metadata(true)
+            .include_values(self.mmr_lambda.is_some());
+        let request = match (&self.namespace, &self.filter) {
+            (Some(namespace), Some(filter)) => request.namespace(namespace.clone()).filter(filter.clone()).build(),
+            (Some(namespace), None) => request.namespace(namespace.clone()).build(),
+            (None, Some(filter)) => request.filter(filter.clone()).build(),
+            (None, None) => request.build(),
+        };
+
+        let query = async {
+            match &self.replica {
+                Some(replica) => self.race(&request, replica).await,
+                None => request.query().await,
+            }
+        };
+        let response = context::traced("pinecone.query", query).await;
+        match (response, &self.fallback, fallback_values) {
+            (Ok(response), _, _) => self.normalize(response.matches().clone().unwrap_or_default()).await,
+            (Err(_), Some(local), Some(values)) if circuit_open() => {
+                let namespace = self.namespace.clone().or_else(context::current_namespace);
+                Ok(local.query(
+                    &values,
+                    self.candidates() as usize,
+                    namespace.as_deref(),
+                    self.filter.as_ref(),
+                    self.mmr_lambda.is_some(),
+                ))
+            }
+            (Err(e), _, _) => Err(e.into()),
+        }
+    }
+
+    /// Rescales the scores of `matches` from Pinecone by the metric of the index, if
+    /// `normalize_scores` is set. Matches of the local fallback are cosine already.
+    async fn normalize(&self, mut matches: Vec<Match>) -> Result<Vec<Match>, SearchError> {
+        if self.normalize_scores {
+            let metric = index_metric().await?;
+            for m in &mut matches {
+                m.set_score(metric.normalize(m.score()));
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Sends `request` to the index and to `replica`, returning the first successful
+    /// response. The slower request is dropped.
+    async fn race(&self, request: &PineconeRequest, replica: &str) -> Result<PineconeResponse, PineconeApiError> {
+        let primary = pin!(request.query());
+        let secondary = pin!(request.query_at(replica));
+        let (winner, result) = match select(primary, secondary).await {
+            Either::Left((Ok(response), _)) => (Some(&self.race_stats.primary_wins), Ok(response)),
+            Either::Right((Ok(response), _)) => (Some(&self.race_stats.replica_wins), Ok(response)),
+            Either::Left((Err(e), secondary)) => match secondary.await {
+                Ok(response) => (Some(&self.race_stats.replica_wins), Ok(response)),
+                Err(_) => (None, Err(e)),
+            },
+            Either::Right((Err(_), primary)) => match primary.await {
+                Ok(response) => (Some(&self.race_stats.primary_wins), Ok(response)),
+                Err(e) => (None, Err(e)),
+            },
+        };
+        winner.unwrap_or(&self.race_stats.failures).fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Searches for `values` and the `expanded` queries, fusing the results of all of them.
+    async fn run(
+        &self,
+        values: Vec<f32>,
+        expanded: Vec<Vec<f32>>,
+        query: Option<&str>,
+        started: Instant,
+    ) -> Result<Vec<Match>, SearchError> {
+        let matches = if expanded.is_empty() {
+            self.retrieve(values.clone()).await?
+        } else {
+            let queries = std::iter::once(values.clone()).chain(expanded);
+            let lists = join_all(queries.map(|values| self.retrieve(values)))
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut fused = reciprocal_rank_fusion(lists);
+            fused.truncate(self.candidates() as usize);
+            fused
+        };
+        let matches = self.drop_deleted(matches).await?;
+        let matches = self.apply_policies(matches)?;
+        let rerank = async {
+            let matches = match self.recency_half_life {
+                Some(half_life) => {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                    recency_decay(matches, half_life, now)
+                }
+                None => matches,
+            };
+            match self.mmr_lambda {
+                Some(lambda) => mmr(&values, matches, self.top_k as usize, lambda),
+                None => matches,
+            }
+        };
+        let matches = context::traced("rerank", rerank).await;
+        let mut matches = match &self.parents {
+            Some(parents) => context::traced("hydrate", parents.hydrate(matches)).await,
+            None => matches,
+        };
+        matches.truncate(self.top_k as usize);
+
+        self.notify(query, &matches, started).await?;
+        Ok(matches)
+    }
+
+    /// Reports the matches of a query to the `observer`.
+    async fn notify(&self, query: Option<&str>, matches: &[Match], started: Instant) -> Result<(), SearchError> {
+        if let Some(observer) = &self.observer {
+            let summary = QuerySummary::new(
+                self.namespace.clone().or_else(context::current_namespace),
+                query.map(str::to_string),
+                self.top_k,
+                self.filter.clone(),
+                matches.iter().map(|m| m.id().clone()).collect(),
+                matches.iter().map(|m| m.score()).reduce(f32::max),
+                started.elapsed().as_millis() as u64,
+            )
+            .with_scores(matches.iter().map(|m| m.score()).collect());
+            observer.on_query(&summary).await?;
+        }
+        Ok(())
+    }
+
+    /// Drops matches whose record in the `mirror` is a tombstone.
+    async fn drop_deleted(&self, matches: Vec<Match>) -> Result<Vec<Match>, SearchError> {
+        let Some(mirror) = &self.mirror else {
+            return Ok(matches);
+        };
+        let mut kept = Vec::with_capacity(matches.len());
+        for m in matches {
+            if mirror.deleted_at(m.id()).await?.is_none() {
+                kept.push(m);
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Drops matches below `min_score` and enforces `require_at_least`.
+    pub fn apply_policies(&self, mut matches: Vec<Match>) -> Result<Vec<Match>, SearchError> {
+        if let Some(min_score) = self.min_score {
+            matches.retain(|m| m.score() >= min_score);
+        }
+
+        match self.require_at_least {
+            Some(required) if matches.len() < required => Err(SearchError::InsufficientResults {
+                found: matches.len(),
+                required,
+            }),
+            _ => Ok(matches),
+        }
+    }
+
+    /// Searches like `search`, but returns the `top_k` best documents instead of chunks,
+    /// each with its matching chunks and their scores aggregated by `aggregation`. The
+    /// `fetch_k` best chunks are retrieved (4 times `top_k` by default), so a document can
+    /// only be scored by its chunks among those.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let documents = SemanticSearch::builder().top_k(3).build()
+    ///     .search_documents("How are refunds processed?", ScoreAggregation::Max)
+    ///     .await?;
+    /// ```
+    pub async fn search_documents(
+        &self,
+        query: &str,
+        aggregation: ScoreAggregation,
+    ) -> Result<Vec<DocumentMatch>, SearchError> {
+        // The chunks searched for are the candidates, so `candidates` does not widen them again.
+        let mut chunks = self.clone();
+        chunks.top_k = self.fetch_k.unwrap_or(self.top_k * 4).max(self.top_k);
+        chunks.fetch_k = Some(chunks.top_k);
+        let mut documents = group_by_document(chunks.search(query).await?, aggregation);
+        documents.truncate(self.top_k as usize);
+        Ok(documents)
+    }
+
+    /// Runs every query with up to `concurrency` in flight, e.g. to load test the index or
+    /// score an evaluation set. Results are in the order of `queries`; a failed query is
+    /// reported in its result rather than failing the batch.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let results = SemanticSearch::builder().build().query_many(&queries, 16).await;
+    /// if let Some(latency) = LatencySummary::from_results(&results) {
+    ///     println!("p95: {:.0}ms", latency.p95());
+    /// }
+    /// ```
+    pub async fn query_many(&self, queries: &[String], concurrency: usize) -> Vec<BatchResult> {
+        stream::iter(queries)
+            .map(|query| async move {
+                let started = Instant::now();
+                let result = self.search(query).await;
+                let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+                let (matches, error) = match result {
+                    Ok(matches) => (matches, None),
+                    Err(e) => (Vec::new(), Some(e.to_string())),
+                };
+                BatchResult {
+                    query: query.clone(),
+                    latency_ms,
+                    matches,
+                    error,
+                }
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Races every query against the index at `replica` too, a data plane URL such as a
+    /// replica in another region (see `index_host`), and uses whichever answers first. A query
+    /// fails only if both fail, with the index's error. See `race_stats` for the winners.
+    pub fn with_replica(mut self, replica: String) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Serves repeated text queries from `cache` while their results are fresh, skipping the
+    /// embedding and the Pinecone query. `search_vector` is not cached.
+    pub fn with_cache(mut self, cache: QueryCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Answers from `local` instead of failing while the circuit breaker of the index is
+    /// open (see `pinecone_api::set_circuit_breaker`). Its matches are tagged `degraded`.
+    pub fn with_fallback(mut self, local: Arc<LocalIndex>) -> Self {
+        self.fallback = Some(local);
+        self
+    }
+
+    /// Searches `namespace` instead, "" for the default one, keeping every other setting,
+    /// e.g. to evaluate the same search against two namespaces.
+    pub fn with_namespace(mut self, namespace: String) -> Self {
+        self.namespace = (!namespace.is_empty()).then_some(namespace);
+        self
+    }
+
+    pub fn namespace(&self) -> &Option<String> {
+        &self.namespace
+    }
+
+    pub fn top_k(&self) -> i64 {
+        self.top_k
+    }
+
+    /// Winners of the queries raced against the replica, shared by clones of this search.
+    pub fn race_stats(&self) -> &RaceStats {
+        &self.race_stats
+    }
+}
+
+impl RaceStats {
+    pub fn primary_wins(&self) -> u64 {
+        self.primary_wins.load(Ordering::Relaxed)
+    }
+
+    pub fn replica_wins(&self) -> u64 {
+        self.replica_wins.load(Ordering::Relaxed)
+    }
+
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+impl BatchResult {
+    pub fn query(&self) -> &String {
+        &self.query
+    }
+
+    pub fn latency_ms(&self) -> f64 {
+        self.latency_ms
+    }
+
+    pub fn matches(&self) -> &Vec<Match> {
+        &self.matches
+    }
+
+    pub fn error(&self) -> &Option<String> {
+        &self.error
+    }
+}
+
+impl DocumentMatch {
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    pub fn chunks(&self) -> &Vec<Match> {
+        &self.chunks
+    }
+}
+
+impl LatencySummary {
+    /// Summary of `results`, None if none of them succeeded.
+    pub fn from_results(results: &[BatchResult]) -> Option<Self> {
+        let latencies: Vec<f64> = results
+            .iter()
+            .filter(|result| result.error.is_none())
+            .map(|result| result.latency_ms)
+            .collect();
+        let errors = results.len() - latencies.len();
+        LatencySummary::from_latencies(latencies, errors)
+    }
+
+    /// Summary of the latencies of successful requests, plus the count of failed ones.
+    /// None if there are no latencies.
+    pub fn from_latencies(mut latencies: Vec<f64>, errors: usize) -> Option<Self> {
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_by(f64::total_cmp);
+
+        let percentile = |p: f64| -> f64 {
+            let rank = (p / 100.0 * latencies.len() as f64).ceil() as usize;
+            latencies[rank.clamp(1, latencies.len()) - 1]
+        };
+        Some(LatencySummary {
+            requests: latencies.len() + errors,
+            errors,
+            mean: latencies.iter().sum::<f64>() / latencies.len() as f64,
+            min: latencies[0],
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            max: latencies[latencies.len() - 1],
+        })
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    pub fn p50(&self) -> f64 {
+        self.p50
+    }
+
+    pub fn p90(&self) -> f64 {
+        self.p90
+    }
+
+    pub fn p95(&self) -> f64 {
+        self.p95
+    }
+
+    pub fn p99(&self) -> f64 {
+        self.p99
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+}
+
+/// Fuses the ranked match lists of several queries with reciprocal rank fusion: matches
+/// are ordered by the sum of `1 / (60 + rank)` over the lists they appear in, so matches
+/// several queries agree on come first. Each match keeps its best score of any list.
+pub fn reciprocal_rank_fusion(lists: Vec<Vec<Match>>) -> Vec<Match> {
+    let mut fused: Vec<(f32, Match)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for list in lists {
+        for (rank, m) in list.into_iter().enumerate() {
+            let weight = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match positions.get(m.id()) {
+                Some(&position) => {
+                    let (total, best) = &mut fused[position];
+                    *total += weight;
+                    if m.score() > best.score() {
+                        *best = m;
+                    }
+                }
+                None => {
+                    positions.insert(m.id().clone(), fused.len());
+                    fused.push((weight, m));
+                }
+            }
+        }
+    }
+
+    // Stable, so ties keep the order the matches were first seen in.
+    fused.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    fused.into_iter().map(|(_, m)| m).collect()
+}
+
+/// Groups `matches` by the document their ids belong to (see `pinecone_data::document_id`),
+/// scores each document by `aggregation` of its matches' scores, and sorts the documents
+/// best first.
+pub fn group_by_document(matches: Vec<Match>, aggregation: ScoreAggregation) -> Vec<DocumentMatch> {
+    let mut documents: Vec<DocumentMatch> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for m in matches {
+        let id = document_id(m.id()).to_string();
+        let position = *positions.entry(id.clone()).or_insert_with(|| {
+            documents.push(DocumentMatch { id, score: 0.0, chunks: Vec::new() });
+            documents.len() - 1
+        });
+        documents[position].chunks.push(m);
+    }
+
+    for document in &mut documents {
+        document.chunks.sort_by(|a, b| b.score().total_cmp(&a.score()));
+        let scores = document.chunks.iter().map(Match::score);
+        document.score = match aggregation {
+            ScoreAggregation::Max => scores.fold(f32::MIN, f32::max),
+            ScoreAggregation::Mean => scores.sum::<f32>() / document.chunks.len() as f32,
+        };
+    }
+    // Stable, so ties keep the order the documents were first seen in.
+    documents.sort_by(|a, b| b.score.total_cmp(&a.score));
+    documents
+}
+
+/// Weights the score of every match by `exp(-ln 2 * age / half_life)`, so it halves every
+/// `half_life`, where `age` is the time from its `ingested_at` metadata to `now`, in seconds
+/// since the Unix epoch, and sorts the matches by the weighted score. Matches without an
+/// `ingested_at` time keep their score.
+pub fn recency_decay(mut matches: Vec<Match>, half_life: Duration, now: u64) -> Vec<Match> {
+    let half_life = half_life.as_secs_f64().max(1.0);
+    for m in &mut matches {
+        if let Some(ingested_at) = m.metadata().get(INGESTED_AT_KEY).and_then(Value::as_u64) {
+            let age = now.saturating_sub(ingested_at) as f64;
+            m.set_score(m.score() * (-std::f64::consts::LN_2 * age / half_life).exp() as f32);
+        }
+    }
+    // Stable, so ties keep the order of the index.
+    matches.sort_by(|a, b| b.score().total_cmp(&a.score()));
+    matches
+}
+
+/// Maximal Marginal Relevance: repeatedly picks the match maximizing
+/// `lambda * sim(query, m) - (1 - lambda) * max sim(m, already picked)`, until `k` are picked.
+///
+/// Similarities are cosine similarities of the match values, so the matches must have been
+/// queried with `include_values`. Matches without values are treated as unrelated to
+/// everything.
+pub fn mmr(query: &[f32], matches: Vec<Match>, k: usize, lambda: f32) -> Vec<Match> {
+    let mut selected: Vec<Match> = Vec::with_capacity(k.min(matches.len()));
+    let relevance: Vec<f32> = matches.iter().map(|m| cosine_similarity(query, m.values())).collect();
+    let mut relevance: Vec<(usize, f32)> = relevance.into_iter().enumerate().collect();
+
+    while selected.len() < k && !relevance.is_empty() {
+        let (position, _) = relevance
+            .iter()
+            .enumerate()
+            .map(|(position, &(index, relevance))| {
+                let redundancy = selected
+                    .iter()
+                    .map(|s| cosine_similarity(matches[index].values(), s.values()))
+                    .fold(0.0_f32, f32::max);
+                (position, lambda * relevance - (1.0 - lambda) * redundancy)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .expect("candidates are not empty");
+
+        let (index, _) = relevance.remove(position);
+        selected.push(matches[index].clone());
+    }
+
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::pinecone_data::Metric;
+    #[cfg(feature = "test-util")]
+    use crate::libs::pipeline::{Document, IngestionPipeline};
+    #[cfg(feature = "test-util")]
+    use crate::libs::splitter::ParagraphSplitter;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::{sample_documents, FakeServices};
+
+    fn matches() -> Vec<Match> {
+        serde_json::from_str(
+            r#"[
+                {"id": "a", "score": 0.99, "values": [1.0, 0.0, 0.0]},
+                {"id": "a-copy", "score": 0.98, "values": [0.99, 0.01, 0.0]},
+                {"id": "b", "score": 0.7, "values": [0.7, 0.7, 0.0]}
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        let result = |latency_ms: f64, error: Option<&str>| BatchResult {
+            query: "q".to_string(),
+            latency_ms,
+            matches: Vec::new(),
+            error: error.map(str::to_string),
+        };
+        let mut results: Vec<BatchResult> = (1..=100).rev().map(|n| result(n as f64, None)).collect();
+        results.push(result(5000.0, Some("timed out")));
+
+        let summary = LatencySummary::from_results(&results).unwrap();
+        assert_eq!((summary.requests(), summary.errors()), (101, 1));
+        assert_eq!((summary.min(), summary.max()), (1.0, 100.0));
+        assert_eq!((summary.p50(), summary.p95(), summary.p99()), (50.0, 95.0, 99.0));
+        assert_eq!(summary.mean(), 50.5);
+        assert_eq!(LatencySummary::from_results(&results[100..]), None);
+    }
+
+    #[test]
+    fn test_mmr() {
+        let query = [1.0, 0.0, 0.0];
+
+        let relevant: Vec<String> = mmr(&query, matches(), 2, 1.0).iter().map(|m| m.id().clone()).collect();
+        assert_eq!(relevant, vec!["a", "a-copy"]);
+
+        let diverse: Vec<String> = mmr(&query, matches(), 2, 0.3).iter().map(|m| m.id().clone()).collect();
+        assert_eq!(diverse, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let list = |ids: &[(&str, f32)]| -> Vec<Match> {
+            ids.iter()
+                .map(|(id, score)| serde_json::from_value(serde_json::json!({ "id": id, "score": score })).unwrap())
+                .collect()
+        };
+        let fused = reciprocal_rank_fusion(vec![
+            list(&[("a", 0.9), ("b", 0.8), ("c", 0.7)]),
+            list(&[("c", 0.95), ("b", 0.85)]),
+        ]);
+
+        let ids: Vec<&str> = fused.iter().map(|m| m.id().as_str()).collect();
+        assert_eq!(ids, vec!["c", "b", "a"]);
+        assert_eq!(fused[0].score(), 0.95);
+    }
+
+    #[test]
+    fn test_apply_policies() {
+        let search = SemanticSearch::builder().min_score(0.9).require_at_least(2).build();
+        let kept = search.apply_policies(matches()).unwrap();
+        assert_eq!(kept.len(), 2);
+
+        let strict = SemanticSearch::builder().min_score(0.985).require_at_least(2).build();
+        assert!(matches!(
+            strict.apply_policies(matches()),
+            Err(SearchError::InsufficientResults { found: 1, required: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_normalize_scores() {
+        let (a, b) = ([0.6, 0.8, 0.0], [0.0, 0.6, 0.8]);
+        let cosine = cosine_similarity(&a, &b);
+        let dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        let squared_distance: f32 = a.iter().zip(&b).map(|(x, y)| (x - y).powi(2)).sum();
+
+        assert!((Metric::Cosine.normalize(cosine) - cosine).abs() < 1e-6);
+        assert!((Metric::DotProduct.normalize(dot) - cosine).abs() < 1e-6);
+        assert!((Metric::Euclidean.normalize(squared_distance) - cosine).abs() < 1e-6);
+        assert_eq!(Metric::Euclidean.normalize(0.0), 1.0);
+        assert_eq!(Metric::DotProduct.normalize(1.5), 1.0);
+        assert_eq!(Metric::parse("dotproduct"), Some(Metric::DotProduct));
+    }
+
+    #[test]
+    fn test_group_by_document() {
+        let matches: Vec<Match> = serde_json::from_value(serde_json::json!([
+            {"id": "refunds#chunk2", "score": 0.9},
+            {"id": "shipping#chunk0", "score": 0.85},
+            {"id": "refunds#chunk0", "score": 0.5},
+            {"id": "faq#chunks", "score": 0.8},
+        ]))
+        .unwrap();
+
+        let by_max = group_by_document(matches.clone(), ScoreAggregation::Max);
+        let ids: Vec<&str> = by_max.iter().map(|d| d.id().as_str()).collect();
+        assert_eq!(ids, ["refunds", "shipping", "faq#chunks"]);
+        assert_eq!(by_max[0].chunks().len(), 2);
+
+        let by_mean = group_by_document(matches, ScoreAggregation::Mean);
+        let ids: Vec<&str> = by_mean.iter().map(|d| d.id().as_str()).collect();
+        assert_eq!(ids, ["shipping", "faq#chunks", "refunds"]);
+        assert!((by_mean[2].score() - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_recency_decay() {
+        let day = 24 * 60 * 60;
+        let now = 100 * day;
+        let matches: Vec<Match> = serde_json::from_value(serde_json::json!([
+            {"id": "stale", "score": 0.9, "metadata": {"ingested_at": now - 7 * day}},
+            {"id": "fresh", "score": 0.8, "metadata": {"ingested_at": now - day / 10}},
+            {"id": "undated", "score": 0.85},
+        ]))
+        .unwrap();
+
+        let decayed = recency_decay(matches, Duration::from_secs(7 * day), now);
+        let ids: Vec<&str> = decayed.iter().map(|m| m.id().as_str()).collect();
+        assert_eq!(ids, ["undated", "fresh", "stale"]);
+        assert!((decayed[2].score() - 0.45).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_multi_query_expansion() {
+        let fakes = FakeServices::seeded().await;
+        fakes.set_chat_reply("1. refund timing\n2. when is money returned\n3. ignored");
+        let expansion = QueryExpansion::MultiQuery { model: "gpt-3.5-turbo".to_string(), n: 2 };
+        assert_eq!(expansion.expand("when are refunds issued").await.unwrap().len(), 2);
+        let search = SemanticSearch::builder().top_k(2).expansion(expansion).build();
+        assert_eq!(search.search("when are refunds issued").await.unwrap()[0].id(), "refunds#chunk0");
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_query_many() {
+        let _fakes = FakeServices::seeded().await;
+        let queries = vec!["when are refunds issued".to_string(), "refund policy".to_string()];
+        let results = SemanticSearch::builder().top_k(1).build().query_many(&queries, 2).await;
+        assert_eq!(results[1].query(), "refund policy");
+        assert!(results.iter().all(|result| result.error().is_none() && result.matches().len() == 1));
+        assert_eq!(LatencySummary::from_results(&results).unwrap().requests(), 2);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_replica_race() {
+        let _fakes = FakeServices::seeded().await;
+        // Nothing listens on the replica, so the index wins the race.
+        let raced = SemanticSearch::builder().top_k(1).build().with_replica("http://127.0.0.1:9".to_string());
+        assert_eq!(raced.search("when are refunds issued").await.unwrap()[0].id(), "refunds#chunk0");
+        assert_eq!((raced.race_stats().primary_wins(), raced.race_stats().replica_wins()), (1, 0));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_search_documents() {
+        let fakes = FakeServices::start().await;
+        let paragraphs = Document::builder()
+            .id("policies".to_string())
+            .text("Refunds are issued within 30 days.\n\nOrders ship within two business days.".to_string())
+            .build();
+        IngestionPipeline::builder()
+            .namespace("paragraphs".to_string())
+            .splitter(Arc::new(ParagraphSplitter::builder().build()))
+            .build()
+            .ingest(&[paragraphs, sample_documents()[0].clone()])
+            .await
+            .unwrap();
+        assert_eq!(fakes.vector_count(Some("paragraphs")), 3);
+        let by_document = SemanticSearch::builder()
+            .namespace("paragraphs".to_string())
+            .top_k(2)
+            .build()
+            .search_documents("when are refunds issued", ScoreAggregation::Max)
+            .await
+            .unwrap();
+        let mut ids: Vec<&str> = by_document.iter().map(|d| d.id().as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, ["policies", "refunds"]);
+        let policies = by_document.iter().find(|d| d.id() == "policies").unwrap();
+        assert_eq!((policies.chunks().len(), policies.score()), (2, policies.chunks()[0].score()));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_search_documents_fetches_once() {
+        let fakes = FakeServices::seeded().await;
+        let search = SemanticSearch::builder().top_k(1).mmr_lambda(0.5).build();
+        search.search_documents("when are refunds issued", ScoreAggregation::Max).await.unwrap();
+        let requests = fakes.server().received_requests().await.unwrap();
+        let query = requests.iter().rfind(|request| request.url.path() == "/query").unwrap();
+        let body: Value = serde_json::from_slice(&query.body).unwrap();
+        assert_eq!(body["topK"], 4);
+    }
+
+    #[cfg(all(feature = "test-util", feature = "sqlite"))]
+    #[tokio::test]
+    async fn test_cache_hits_drop_deleted() {
+        use crate::libs::sql_lite::SQLiteDB;
+
+        let _fakes = FakeServices::start().await;
+        let mirror = DatabaseObserver::new(Arc::new(SQLiteDB::new(":memory:").unwrap().with_soft_delete()));
+        let pipeline = IngestionPipeline::builder().observer(Arc::new(mirror.clone())).build();
+        pipeline.ingest(&sample_documents()).await.unwrap();
+        let search = SemanticSearch::builder()
+            .top_k(2)
+            .mirror(mirror.clone())
+            .build()
+            .with_cache(QueryCache::memory(Duration::from_secs(60)));
+        assert_eq!(search.search("when are refunds issued").await.unwrap().len(), 2);
+
+        mirror.on_delete(&["refunds#chunk0".to_string()]).await.unwrap();
+        let cached = search.search("when are refunds issued").await.unwrap();
+        assert_eq!(cached.iter().map(|m| m.id().as_str()).collect::<Vec<_>>(), ["shipping#chunk0"]);
+
+        let strict = SemanticSearch::builder()
+            .top_k(2)
+            .mirror(mirror.clone())
+            .require_at_least(2)
+            .build()
+            .with_cache(QueryCache::memory(Duration::from_secs(60)));
+        mirror.recover("refunds#chunk0").await.unwrap();
+        assert_eq!(strict.search("when are refunds issued").await.unwrap().len(), 2);
+        mirror.on_delete(&["refunds#chunk0".to_string()]).await.unwrap();
+        let insufficient = strict.search("when are refunds issued").await;
+        assert!(matches!(insufficient, Err(SearchError::InsufficientResults { found: 1, required: 2 })));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::libs::database::{convert_binary_to_embeddings, convert_embeddings_to_binary, Database};
+use async_trait::async_trait;
+use sled::{Config, Db, Tree};
+use std::error::Error;
+use std::path::Path;
+
+/// Embedded key-value store implementing `Database` with sled.
+///
+/// Pure Rust, so it builds where linking SQLite's C library is a problem. Item text and
+/// binary embeddings are kept in separate trees, mirroring the `data` and `embedding`
+/// columns of `SQLiteDB`.
+///
+/// # Example
+///
+/// ```rust
+/// let db = SledDB::new("openai-pinecone.sled")?;
+/// db.insert_embedding_data("doc-0", "text", &embedding).await?;
+/// let watcher = DirectoryWatcher::builder().state(Arc::new(db)).build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SledDB {
+    db: Db,
+    items: Tree,
+    embeddings: Tree,
+}
+
+impl SledDB {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Self::open(sled::open(path)?)
+    }
+
+    /// A database that lives in a temporary directory and is removed when dropped.
+    pub fn temporary() -> Result<Self, Box<dyn Error>> {
+        Self::open(Config::new().temporary(true).open()?)
+    }
+
+    fn open(db: Db) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            items: db.open_tree("items")?,
+            embeddings: db.open_tree("embeddings")?,
+            db,
+        })
+    }
+
+    pub async fn insert_embedding_data(&self, id: &str, data: &str, embeddings: &[f32]) -> Result<(), Box<dyn Error>> {
+        self.items.insert(id, data)?;
+        self.embeddings.insert(id, convert_embeddings_to_binary(embeddings))?;
+        self.flush().await
+    }
+
+    /// Every stored embedding with its id.
+    pub async fn embeddings(&self) -> Result<Vec<(String, Vec<f32>)>, Box<dyn Error>> {
+        let mut embeddings = Vec::new();
+        for entry in self.embeddings.iter() {
+            let (id, binary) = entry?;
+            embeddings.push((String::from_utf8(id.to_vec())?, convert_binary_to_embeddings(&binary)?));
+        }
+        Ok(embeddings)
+    }
+
+    async fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Database for SledDB {
+    async fn create(&self, id: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.items.compare_and_swap(id, None as Option<&[u8]>, Some(data))?.is_err() {
+            return Err(format!("item {} already exists", id).into());
+        }
+        self.flush().await
+    }
+
+    async fn read(&self, id: &str) -> Result<String, Box<dyn std::error::Error>> {
+        match self.items.get(id)? {
+            Some(data) => Ok(String::from_utf8(data.to_vec())?),
+            None => Err(format!("item {} not found", id).into()),
+        }
+    }
+
+    async fn update(&self, id: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.items
+            .fetch_and_update(id, |old| old.map(|_| data.as_bytes().to_vec()))?;
+        self.flush().await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.items.remove(id)?;
+        self.embeddings.remove(id)?;
+        self.flush().await
+    }
+
+    async fn ids(&self, prefix: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut ids = Vec::new();
+        for item in self.items.scan_prefix(prefix) {
+            let (id, _) = item?;
+            ids.push(String::from_utf8(id.to_vec())?);
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sled_db() {
+        let db = SledDB::temporary().unwrap();
+
+        db.create("a", "one").await.unwrap();
+        assert!(db.create("a", "again").await.is_err());
+        db.update("a", "two").await.unwrap();
+        assert_eq!(db.read("a").await.unwrap(), "two");
+
+        // Like SQLite's UPDATE, updating a missing item changes nothing.
+        db.update("missing", "data").await.unwrap();
+        assert!(db.read("missing").await.is_err());
+
+        db.insert_embedding_data("b", "text", &[0.25, -1.0]).await.unwrap();
+        assert_eq!(db.embeddings().await.unwrap(), vec![("b".to_string(), vec![0.25, -1.0])]);
+
+        db.delete("b").await.unwrap();
+        assert!(db.read("b").await.is_err());
+        assert!(db.embeddings().await.unwrap().is_empty());
+    }
+}
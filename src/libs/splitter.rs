@@ -0,0 +1,353 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use typed_builder::TypedBuilder;
+
+use super::math::cosine_similarity;
+use super::openai_api::get_tokens;
+use super::pipeline::{embed, PipelineError};
+use super::rate_limit::Priority;
+
+/// Strategy for cutting a document's text into chunks.
+///
+/// Splitters are plugged into an `IngestionPipeline`, so the strategy can be chosen per
+/// ingestion run.
+///
+/// # Example
+///
+/// ```rust
+/// let pipeline = IngestionPipeline::builder()
+///     .splitter(Arc::new(SlidingWindowSplitter::builder().window_tokens(256).overlap_tokens(64).build()))
+///     .build();
+/// ```
+#[async_trait]
+pub trait Splitter: Debug + Send + Sync {
+    async fn split(&self, text: &str) -> Result<Vec<String>, PipelineError>;
+}
+
+/// Packs whitespace-separated words into chunks of at most `max_tokens` tokens.
+///
+/// # Fields
+///
+/// * `max_tokens`: Optional. Maximum number of tokens per chunk. Defaults to 512.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct TokenSplitter {
+    #[builder(default = 512)]
+    max_tokens: usize,
+}
+
+/// Fixed-size token windows where each window repeats the tail of the previous one.
+///
+/// # Fields
+///
+/// * `window_tokens`: Optional. Maximum number of tokens per chunk. Defaults to 512.
+/// * `overlap_tokens`: Optional. Tokens shared by consecutive chunks. Defaults to 64.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct SlidingWindowSplitter {
+    #[builder(default = 512)]
+    window_tokens: usize,
+
+    #[builder(default = 64)]
+    overlap_tokens: usize,
+}
+
+/// Splits on the coarsest separator that yields pieces of at most `chunk_chars` characters,
+/// falling back to finer separators (paragraphs, lines, sentences, words, characters) for
+/// pieces that are still too long. Adjacent small pieces are merged back together.
+///
+/// # Fields
+///
+/// * `chunk_chars`: Optional. Maximum number of characters per chunk. Defaults to 2000.
+/// * `separators`: Optional. Separators from coarsest to finest. An empty separator splits
+///   into characters. Defaults to paragraphs, lines, sentences, words, characters.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct RecursiveCharacterSplitter {
+    #[builder(default = 2000)]
+    chunk_chars: usize,
+
+    #[builder(default = vec!["\n\n".to_string(), "\n".to_string(), ". ".to_string(), " ".to_string(), String::new()])]
+    separators: Vec<String>,
+}
+
+/// One chunk per paragraph, so a document gets a vector per paragraph, see
+/// `SemanticSearch::search_documents`. Paragraphs are separated by blank lines; one longer
+/// than `max_tokens` is cut into several chunks.
+///
+/// # Fields
+///
+/// * `max_tokens`: Optional. Maximum number of tokens per chunk. Defaults to 512.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct ParagraphSplitter {
+    #[builder(default = 512)]
+    max_tokens: usize,
+}
+
+/// Splits text into sentences, embeds each one, and starts a new chunk wherever adjacent
+/// sentences are less similar than `threshold`, or the chunk would exceed `max_tokens`.
+///
+/// # Fields
+///
+/// * `embedding_model`: Optional. Embedding model id. Defaults to "text-embedding-ada-002".
+/// * `threshold`: Optional. Cosine similarity below which a topic break is assumed. Defaults to 0.8.
+/// * `max_tokens`: Optional. Maximum number of tokens per chunk. Defaults to 512.
+/// * `concurrency`: Optional. Number of sentences embedded concurrently. Defaults to 4.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct SemanticSplitter {
+    #[builder(default = "text-embedding-ada-002".to_string())]
+    embedding_model: String,
+
+    #[builder(default = 0.8)]
+    threshold: f32,
+
+    #[builder(default = 512)]
+    max_tokens: usize,
+
+    #[builder(default = 4)]
+    concurrency: usize,
+}
+
+#[async_trait]
+impl Splitter for TokenSplitter {
+    async fn split(&self, text: &str) -> Result<Vec<String>, PipelineError> {
+        Ok(pack(text.split_whitespace(), " ", self.max_tokens))
+    }
+}
+
+#[async_trait]
+impl Splitter for SlidingWindowSplitter {
+    async fn split(&self, text: &str) -> Result<Vec<String>, PipelineError> {
+        let words: Vec<(&str, usize)> = text.split_whitespace().map(|word| (word, token_count(word))).collect();
+        let overlap = self.overlap_tokens.min(self.window_tokens / 2);
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < words.len() {
+            let mut end = start;
+            let mut tokens = 0;
+            while end < words.len() && (end == start || tokens + words[end].1 <= self.window_tokens) {
+                tokens += words[end].1;
+                end += 1;
+            }
+            chunks.push(words[start..end].iter().map(|(word, _)| *word).collect::<Vec<_>>().join(" "));
+            if end == words.len() {
+                break;
+            }
+
+            // Step back over up to `overlap` tokens, always advancing by at least one word.
+            let mut next = end;
+            let mut shared = 0;
+            while next > start + 1 && shared + words[next - 1].1 <= overlap {
+                next -= 1;
+                shared += words[next].1;
+            }
+            start = next;
+        }
+
+        Ok(chunks)
+    }
+}
+
+#[async_trait]
+impl Splitter for ParagraphSplitter {
+    async fn split(&self, text: &str) -> Result<Vec<String>, PipelineError> {
+        let mut chunks = Vec::new();
+        let mut paragraph = Vec::new();
+        for line in text.lines().chain(std::iter::once("")) {
+            if line.trim().is_empty() {
+                if !paragraph.is_empty() {
+                    let words = paragraph.iter().flat_map(|line: &&str| line.split_whitespace());
+                    chunks.extend(pack(words, " ", self.max_tokens));
+                    paragraph.clear();
+                }
+            } else {
+                paragraph.push(line);
+            }
+        }
+        Ok(chunks)
+    }
+}
+
+#[async_trait]
+impl Splitter for RecursiveCharacterSplitter {
+    async fn split(&self, text: &str) -> Result<Vec<String>, PipelineError> {
+        Ok(self.split_with(text, &self.separators))
+    }
+}
+
+impl RecursiveCharacterSplitter {
+    fn split_with(&self, text: &str, separators: &[String]) -> Vec<String> {
+        let Some((separator, finer)) = separators.split_first() else {
+            return vec![text.to_string()];
+        };
+
+        let pieces: Vec<String> = if separator.is_empty() {
+            text.chars().map(String::from).collect()
+        } else {
+            // Keep the separator on the piece it ends so merged chunks read as the original.
+            text.split_inclusive(separator.as_str()).map(String::from).collect()
+        };
+
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+        for piece in pieces {
+            if piece.chars().count() > self.chunk_chars {
+                if !current.trim().is_empty() {
+                    chunks.push(std::mem::take(&mut current).trim().to_string());
+                }
+                current.clear();
+                chunks.extend(self.split_with(&piece, finer));
+                continue;
+            }
+            if current.chars().count() + piece.chars().count() > self.chunk_chars && !current.trim().is_empty() {
+                chunks.push(std::mem::take(&mut current).trim().to_string());
+            }
+            current.push_str(&piece);
+        }
+        if !current.trim().is_empty() {
+            chunks.push(current.trim().to_string());
+        }
+
+        chunks
+    }
+}
+
+#[async_trait]
+impl Splitter for SemanticSplitter {
+    async fn split(&self, text: &str) -> Result<Vec<String>, PipelineError> {
+        let sentences = sentences(text);
+        if sentences.len() < 2 {
+            return Ok(sentences);
+        }
+
+        let embeddings: Vec<Vec<f32>> = stream::iter(sentences.clone())
+            .map(|sentence| embed_owned(self.embedding_model.clone(), sentence))
+            .buffered(self.concurrency.max(1))
+            .try_collect()
+            .await?;
+
+        let mut chunks = Vec::new();
+        let mut current = sentences[0].clone();
+        let mut current_tokens = token_count(&current);
+        for i in 1..sentences.len() {
+            let tokens = token_count(&sentences[i]);
+            let similar = cosine_similarity(&embeddings[i - 1], &embeddings[i]) >= self.threshold;
+            if !similar || current_tokens + tokens > self.max_tokens {
+                chunks.push(std::mem::take(&mut current));
+                current_tokens = 0;
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(&sentences[i]);
+            current_tokens += tokens;
+        }
+        chunks.push(current);
+
+        Ok(chunks)
+    }
+}
+
+async fn embed_owned(model: String, text: String) -> Result<Vec<f32>, PipelineError> {
+    embed(&model, &text, Priority::Batch).await
+}
+
+fn token_count(text: &str) -> usize {
+    get_tokens(text).map_or(1, |tokens| tokens.len())
+}
+
+/// Greedily joins `pieces` with `separator` into chunks of at most `max_tokens` tokens.
+/// A single piece larger than the budget becomes a chunk of its own.
+fn pack<'a>(pieces: impl Iterator<Item = &'a str>, separator: &str, max_tokens: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0;
+
+    for piece in pieces {
+        let tokens = token_count(piece);
+        if current_tokens + tokens > max_tokens && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+            current_tokens = 0;
+        }
+        if !current.is_empty() {
+            current.push_str(separator);
+        }
+        current.push_str(piece);
+        current_tokens += tokens;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Splits text after `.`, `!` or `?` followed by whitespace, and on blank lines.
+pub fn sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        current.push(c);
+        let next_is_space = chars.peek().is_none_or(|next| next.is_whitespace());
+        let paragraph = c == '\n' && chars.peek() == Some(&'\n');
+        if (matches!(c, '.' | '!' | '?') && next_is_space) || paragraph {
+            let sentence = current.trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.split_whitespace().collect::<Vec<_>>().join(" "));
+            }
+            current.clear();
+        }
+    }
+    let sentence = current.trim();
+    if !sentence.is_empty() {
+        sentences.push(sentence.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sliding_window() {
+        let splitter = SlidingWindowSplitter::builder().window_tokens(4).overlap_tokens(2).build();
+        let chunks = splitter.split("one two three four five six seven").await.unwrap();
+
+        assert_eq!(chunks, vec!["one two three four", "three four five six", "five six seven"]);
+    }
+
+    #[tokio::test]
+    async fn test_recursive_character() {
+        let splitter = RecursiveCharacterSplitter::builder().chunk_chars(24).build();
+        let text = "Short paragraph.\n\nThis paragraph is rather long. It has two sentences.";
+        let chunks = splitter.split(text).await.unwrap();
+
+        assert_eq!(chunks[0], "Short paragraph.");
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 24));
+        assert!(chunks.contains(&"It has two sentences.".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_paragraphs() {
+        let splitter = ParagraphSplitter::builder().max_tokens(8).build();
+        let text = "Refunds\ntake 30 days.\n\n  \n\nShipping is free on all orders over fifty dollars within the country.\n";
+        let chunks = splitter.split(text).await.unwrap();
+
+        assert_eq!(chunks[0], "Refunds take 30 days.");
+        assert!(chunks.len() > 2);
+        assert!(chunks[1..].iter().all(|chunk| !chunk.contains("Refunds")));
+    }
+
+    #[test]
+    fn test_sentences() {
+        let text = "First one. Second one? Version 1.2 is out!\n\nHeading\nbody";
+        assert_eq!(
+            sentences(text),
+            vec!["First one.", "Second one?", "Version 1.2 is out!", "Heading body"]
+        );
+    }
+}
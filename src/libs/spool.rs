@@ -0,0 +1,222 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+
+use super::database::Database;
+use super::pinecone_api::circuit_open;
+use super::pinecone_data::{PineconeRequest, Vector};
+
+/// Prefix of the keys spooled upsert batches are stored under in the `Database`.
+const SPOOL_KEY_PREFIX: &str = "upsert-spool:";
+
+/// Upsert errors of an unreachable or failing Pinecone, as opposed to requests it rejected.
+const OUTAGE_MARKERS: [&str; 6] = [
+    "Error status: 5",
+    "Error status: 429",
+    "error sending request",
+    "timed out",
+    "connection",
+    "circuit breaker open",
+];
+
+/// Durable write-behind queue for upserts during Pinecone outages, see
+/// `IngestionPipeline::with_spool`.
+///
+/// Once `spool_after` upserts in a row failed with an outage (unreachable index, server
+/// errors, rate limits, timeouts, or an open circuit breaker), failing batches are written
+/// to the database instead of being reported as failed, and so are all later batches until
+/// the spool is flushed, so a newer version of a vector is never overwritten by an older
+/// spooled one. `flush`, or `flush_every` in the background, upserts them once Pinecone
+/// answers again.
+///
+/// Batches are flushed oldest first. Those left by an earlier process are flushed too, but
+/// this process only knows to spool behind them after its own failures.
+///
+/// # Fields
+///
+/// * `db`: Required. Database the batches are spooled in; must be able to list its ids.
+/// * `spool_after`: Optional. Failed upserts in a row before batches are spooled. Defaults to 3.
+///
+/// # Example
+///
+/// ```rust
+/// let spool = Arc::new(UpsertSpool::builder().db(db.clone()).build());
+/// tokio::spawn(spool.clone().flush_every(Duration::from_secs(30)));
+/// let pipeline = IngestionPipeline::builder().build().with_spool(spool);
+/// ```
+#[derive(Debug, TypedBuilder)]
+pub struct UpsertSpool {
+    db: Arc<dyn Database>,
+
+    #[builder(default = 3)]
+    spool_after: u32,
+
+    #[builder(setter(skip), default)]
+    failures: AtomicU32,
+
+    #[builder(setter(skip), default)]
+    spooling: AtomicBool,
+
+    #[builder(setter(skip), default)]
+    sequence: AtomicU64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SpooledBatch {
+    namespace: Option<String>,
+    vectors: Vec<Vector>,
+}
+
+impl UpsertSpool {
+    /// Whether batches go to the spool without being sent, until it is flushed.
+    pub fn is_spooling(&self) -> bool {
+        self.spooling.load(Ordering::SeqCst)
+    }
+
+    /// Counts an upsert that was sent: a success resets the failures, an outage adds one.
+    /// Returns whether the batch should be spooled.
+    pub fn record(&self, error: Option<&str>) -> bool {
+        match error {
+            None => {
+                self.failures.store(0, Ordering::SeqCst);
+                false
+            }
+            Some(error) if is_outage(error) => {
+                let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+                failures >= self.spool_after || self.is_spooling()
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// Writes a batch of vectors for `namespace` to the spool.
+    pub async fn push(&self, namespace: Option<String>, vectors: Vec<Vector>) -> Result<(), Box<dyn Error>> {
+        let batch = SpooledBatch { namespace, vectors };
+        self.db.create(&self.next_key(), &serde_json::to_string(&batch)?).await?;
+        self.spooling.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Upserts the spooled batches, oldest first, removing each once Pinecone accepted it.
+    /// Stops at the first failure, keeping that batch and the later ones. Returns the number
+    /// of vectors upserted.
+    pub async fn flush(&self) -> Result<usize, Box<dyn Error>> {
+        let mut flushed = 0;
+        let keys = self.db.ids(SPOOL_KEY_PREFIX).await?;
+        for key in keys {
+            let json = self.db.read(&key).await?;
+            let batch: SpooledBatch = serde_json::from_str(&json)?;
+            let count = batch.vectors.len();
+            let request = match batch.namespace {
+                Some(namespace) => PineconeRequest::builder().vectors(batch.vectors).namespace(namespace).build(),
+                None => PineconeRequest::builder().vectors(batch.vectors).build(),
+            };
+            request.upsert().await?;
+            self.db.delete(&key).await?;
+            flushed += count;
+        }
+
+        // Batches may have been pushed while flushing.
+        if self.db.ids(SPOOL_KEY_PREFIX).await?.is_empty() {
+            self.spooling.store(false, Ordering::SeqCst);
+            self.failures.store(0, Ordering::SeqCst);
+        }
+        Ok(flushed)
+    }
+
+    /// Flushes the spool every `interval`, forever, skipping rounds while the circuit
+    /// breaker of the index is open; spawn it next to a long-running ingestion. Failed
+    /// flushes are reported and retried at the next interval.
+    pub async fn flush_every(self: Arc<Self>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if circuit_open() {
+                continue;
+            }
+            match self.flush().await {
+                Ok(0) => {}
+                Ok(flushed) => tracing::info!("Flushed {} spooled vectors to Pinecone", flushed),
+                Err(e) => tracing::warn!("Failed to flush spooled upserts: {}", e),
+            }
+        }
+    }
+
+    /// Number of spooled batches.
+    pub async fn len(&self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.db.ids(SPOOL_KEY_PREFIX).await?.len())
+    }
+
+    pub async fn is_empty(&self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Key of the next batch, sorting after every earlier one.
+    fn next_key(&self) -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+        format!("{}{:024}-{:010}", SPOOL_KEY_PREFIX, now, sequence)
+    }
+}
+
+/// Whether an upsert error means Pinecone was unreachable or failing.
+pub fn is_outage(message: &str) -> bool {
+    OUTAGE_MARKERS.iter().any(|marker| message.contains(marker))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[test]
+    fn test_record() {
+        let db = Arc::new(SQLiteDB::new(":memory:").unwrap());
+        let spool = UpsertSpool::builder().db(db).spool_after(2).build();
+        let outage = "UpsertError: Error status: 503 Service Unavailable";
+
+        assert!(!spool.record(Some(outage)));
+        assert!(!spool.record(Some("UpsertError: Error status: 400 Bad Request")));
+        assert!(spool.record(Some(outage)));
+        assert!(!spool.record(None));
+        assert!(!spool.record(Some(outage)));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_spool_during_outage() {
+        use serde_json::json;
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        use crate::libs::pipeline::IngestionPipeline;
+        use crate::libs::test_util::{sample_documents, FakeServices};
+
+        let fakes = FakeServices::start().await;
+        Mock::given(method("POST"))
+            .and(path("/vectors/upsert"))
+            .and(body_partial_json(json!({ "namespace": "spooled" })))
+            .respond_with(ResponseTemplate::new(503))
+            .with_priority(1)
+            .up_to_n_times(1)
+            .mount(fakes.server())
+            .await;
+
+        let db = Arc::new(SQLiteDB::new(":memory:").unwrap());
+        let spool = Arc::new(UpsertSpool::builder().db(db).spool_after(1).build());
+        let pipeline = IngestionPipeline::builder()
+            .namespace("spooled".to_string())
+            .build()
+            .with_spool(spool.clone());
+        let report = pipeline.ingest(&sample_documents()).await.unwrap();
+        assert_eq!((report.upserted(), report.spooled()), (0, 2));
+        assert!(spool.is_spooling() && !spool.is_empty().await.unwrap());
+        assert_eq!(fakes.vector_count(Some("spooled")), 0);
+        assert_eq!(spool.flush().await.unwrap(), 2);
+        assert!(!spool.is_spooling() && spool.is_empty().await.unwrap());
+        assert_eq!(fakes.vector_count(Some("spooled")), 2);
+    }
+}
@@ -1,34 +1,165 @@
-use crate::libs::database::Database;
-use rusqlite::{params, Connection};
+use crate::libs::database::{convert_binary_to_embeddings, convert_embeddings_to_binary, eviction_victims, Database};
+#[cfg(feature = "encryption")]
+use crate::libs::encryption::Cipher;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
 use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
 use async_trait::async_trait;
 use tokio::sync::Mutex;
 
+/// Version of the `items` table this build reads and writes, kept in the database's
+/// `user_version`. Version 1 is the original table, version 2 added `deleted_at`. Files of
+/// an older version are upgraded when opened; files of a newer one are refused.
+pub const SCHEMA_VERSION: u32 = 2;
+
 #[derive(Debug)]
 pub struct SQLiteDB {
     // SQLite database connection details here
     conn: Arc<Mutex<Connection>>,
+    soft_delete: bool,
+    #[cfg(feature = "encryption")]
+    cipher: Option<Cipher>,
 }
 
 impl SQLiteDB {
     pub fn new(db_name: &str) -> Result<Self, Box<dyn Error>> {
         let conn = Connection::open(db_name)?;
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(format!(
+                "{} has schema version {}, but this build supports up to {}",
+                db_name, version, SCHEMA_VERSION
+            )
+            .into());
+        }
         conn.execute(
             "CREATE TABLE IF NOT EXISTS items (
                 id TEXT PRIMARY KEY,
                 data TEXT NOT NULL,
                 embedding BLOB,
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                last_accessed TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                last_accessed TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                deleted_at TIMESTAMP
             )",
             [],
         )?;
+        // Tables created before soft deletes lack the column.
+        let has_deleted_at: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('items') WHERE name = 'deleted_at'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_deleted_at {
+            conn.execute("ALTER TABLE items ADD COLUMN deleted_at TIMESTAMP", [])?;
+        }
+        if version < SCHEMA_VERSION {
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
 
         Ok(Self {
             conn: Arc::new(Mutex::new(conn)),
+            soft_delete: false,
+            #[cfg(feature = "encryption")]
+            cipher: None,
         })
     }
+
+    /// Schema version of the database file at `path` without opening it for writing, so
+    /// without upgrading it; None if there is no such file. Files written before versioning
+    /// report 0.
+    pub fn schema_version(path: &str) -> Result<Option<u32>, Box<dyn Error>> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Some(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?))
+    }
+
+    /// Encrypts the `data` column and embedding BLOBs with `cipher`.
+    ///
+    /// Every row is then expected to be encrypted: rows written without the cipher, or
+    /// under another key, fail to read. Ids stay in plaintext.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let db = SQLiteDB::new("mirror.db")?.with_cipher(Cipher::from_env()?);
+    /// ```
+    #[cfg(feature = "encryption")]
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Makes `delete` stamp items' `deleted_at` instead of removing the rows. Deleted
+    /// items read as missing but can be restored, listed with `tombstones`, and removed
+    /// with `purge` once Pinecone's vectors are gone too.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// let mirror = DatabaseObserver::new(Arc::new(SQLiteDB::new("mirror.db")?.with_soft_delete()));
+    /// ```
+    pub fn with_soft_delete(mut self) -> Self {
+        self.soft_delete = true;
+        self
+    }
+
+    pub async fn insert_embedding_data(&self, id: &str, data: &str, embeddings: &[f32]) -> Result<(), Box<dyn Error>> {
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "INSERT OR REPLACE INTO items (id, data, embedding) VALUES (?1, ?2, ?3)",
+            params![id, self.seal_text(data)?, self.seal(convert_embeddings_to_binary(embeddings))?],
+        )?;
+        Ok(())
+    }
+
+    /// Every stored embedding with its id.
+    pub async fn embeddings(&self) -> Result<Vec<(String, Vec<f32>)>, Box<dyn Error>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare("SELECT id, embedding FROM items WHERE embedding IS NOT NULL AND deleted_at IS NULL")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?)))?;
+
+        let mut embeddings = Vec::new();
+        for row in rows {
+            let (id, binary) = row?;
+            embeddings.push((id, convert_binary_to_embeddings(&self.open(binary)?)?));
+        }
+        Ok(embeddings)
+    }
+
+    fn seal(&self, binary: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            return Ok(cipher.encrypt(&binary)?);
+        }
+        Ok(binary)
+    }
+
+    fn open(&self, binary: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            return Ok(cipher.decrypt(&binary)?);
+        }
+        Ok(binary)
+    }
+
+    fn seal_text(&self, data: &str) -> Result<String, Box<dyn Error>> {
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            return Ok(cipher.encrypt_text(data)?);
+        }
+        Ok(data.to_string())
+    }
+
+    fn open_text(&self, data: String) -> Result<String, Box<dyn Error>> {
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            return Ok(cipher.decrypt_text(&data)?);
+        }
+        Ok(data)
+    }
 }
 
 #[async_trait]
@@ -37,28 +168,75 @@ impl Database for SQLiteDB {
         let conn = self.conn.lock().await;
         conn.execute(
             "INSERT INTO items (id, data) VALUES (?1, ?2)",
-            params![id, data],
+            params![id, self.seal_text(data)?],
         )?;
         Ok(())
     }
 
     async fn read(&self, id: &str) -> Result<String, Box<dyn std::error::Error>> {
         let conn = self.conn.lock().await;
-        let mut stmt = conn.prepare("SELECT data FROM items WHERE id = ?1")?;
+        let mut stmt = conn.prepare("SELECT data FROM items WHERE id = ?1 AND deleted_at IS NULL")?;
         let data: String = stmt.query_row(params![id], |row| row.get(0))?;
-        Ok(data)
+        conn.execute("UPDATE items SET last_accessed = CURRENT_TIMESTAMP WHERE id = ?1", params![id])?;
+        self.open_text(data)
     }
 
     async fn update(&self, id: &str, data: &str) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.conn.lock().await;
         conn.execute(
-            "UPDATE items SET data = ?2 WHERE id = ?1",
-            params![id, data],
+            "UPDATE items SET data = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+            params![id, self.seal_text(data)?],
         )?;
         Ok(())
     }
 
     async fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.soft_delete {
+            return self.purge(id).await;
+        }
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "UPDATE items SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?1 AND deleted_at IS NULL",
+            params![id],
+        )?;
+        Ok(())
+    }
+
+    async fn restore(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().await;
+        let restored = conn.execute(
+            "UPDATE items SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![id],
+        )?;
+        if restored == 0 {
+            return Err(format!("item {} is not deleted", id).into());
+        }
+        Ok(())
+    }
+
+    async fn deleted_at(&self, id: &str) -> Result<Option<u64>, Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().await;
+        let deleted_at = conn
+            .query_row(
+                "SELECT CAST(strftime('%s', deleted_at) AS INTEGER) FROM items WHERE id = ?1 AND deleted_at IS NOT NULL",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(deleted_at)
+    }
+
+    async fn tombstones(&self) -> Result<Vec<(String, u64)>, Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT id, CAST(strftime('%s', deleted_at) AS INTEGER) FROM items
+             WHERE deleted_at IS NOT NULL ORDER BY deleted_at, id",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    async fn purge(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
         let conn = self.conn.lock().await;
         conn.execute(
             "DELETE FROM items WHERE id = ?1",
@@ -66,4 +244,120 @@ impl Database for SQLiteDB {
         )?;
         Ok(())
     }
+
+    async fn evict_embeddings(&self, max_bytes: u64) -> Result<usize, Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT id, LENGTH(data) + LENGTH(embedding) FROM items
+             WHERE embedding IS NOT NULL ORDER BY last_accessed, id",
+        )?;
+        let items = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, u64)>, _>>()?;
+
+        let victims = eviction_victims(items, max_bytes);
+        for id in &victims {
+            conn.execute("DELETE FROM items WHERE id = ?1", params![id])?;
+        }
+        Ok(victims.len())
+    }
+
+    async fn ids(&self, prefix: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT id FROM items WHERE substr(id, 1, length(?1)) = ?1 AND deleted_at IS NULL ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![prefix], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_soft_delete() {
+        let db = SQLiteDB::new(":memory:").unwrap().with_soft_delete();
+        db.create("a", "one").await.unwrap();
+        db.insert_embedding_data("b", "two", &[1.0]).await.unwrap();
+
+        db.delete("a").await.unwrap();
+        db.delete("b").await.unwrap();
+        assert!(db.read("a").await.is_err());
+        assert!(db.embeddings().await.unwrap().is_empty());
+        assert!(db.deleted_at("a").await.unwrap().is_some());
+        assert_eq!(db.tombstones().await.unwrap().len(), 2);
+
+        db.restore("a").await.unwrap();
+        assert_eq!(db.read("a").await.unwrap(), "one");
+        assert!(db.restore("a").await.is_err());
+        assert_eq!(db.deleted_at("a").await.unwrap(), None);
+        assert_eq!(db.ids("").await.unwrap(), ["a"]);
+
+        db.purge("b").await.unwrap();
+        assert!(db.tombstones().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evict_embeddings() {
+        let db = SQLiteDB::new(":memory:").unwrap();
+        for id in ["a", "b", "c"] {
+            db.insert_embedding_data(id, "text", &[1.0, 2.0]).await.unwrap();
+        }
+        db.create("state", "kept").await.unwrap();
+        {
+            let conn = db.conn.lock().await;
+            conn.execute("UPDATE items SET last_accessed = '2020-01-01 00:00:00'", []).unwrap();
+        }
+        // Reading "a" makes "b" and "c" the least recently used.
+        db.read("a").await.unwrap();
+
+        let size = 4 + 17;
+        assert_eq!(db.evict_embeddings(3 * size).await.unwrap(), 0);
+        assert_eq!(db.evict_embeddings(size).await.unwrap(), 2);
+        let ids: Vec<String> = db.embeddings().await.unwrap().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["a"]);
+        assert_eq!(db.read("state").await.unwrap(), "kept");
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let path = std::env::temp_dir().join(format!("schema_version_{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(SQLiteDB::schema_version(path).unwrap(), None);
+
+        // A file from before versioning is upgraded on open.
+        Connection::open(path)
+            .unwrap()
+            .execute("CREATE TABLE items (id TEXT PRIMARY KEY, data TEXT NOT NULL, embedding BLOB)", [])
+            .unwrap();
+        assert_eq!(SQLiteDB::schema_version(path).unwrap(), Some(0));
+        drop(SQLiteDB::new(path).unwrap());
+        assert_eq!(SQLiteDB::schema_version(path).unwrap(), Some(SCHEMA_VERSION));
+
+        Connection::open(path).unwrap().pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+        assert!(SQLiteDB::new(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn test_encrypted_sqlite_db() {
+        let db = SQLiteDB::new(":memory:").unwrap().with_cipher(Cipher::new(&[1; 32]).unwrap());
+
+        db.create("a", "confidential").await.unwrap();
+        db.insert_embedding_data("b", "secret", &[0.25, -1.0]).await.unwrap();
+        assert_eq!(db.read("a").await.unwrap(), "confidential");
+        assert_eq!(db.embeddings().await.unwrap(), vec![("b".to_string(), vec![0.25, -1.0])]);
+
+        let conn = db.conn.lock().await;
+        let (data, embedding): (String, Vec<u8>) = conn
+            .query_row("SELECT data, embedding FROM items WHERE id = 'b'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert!(!data.contains("secret"));
+        assert_ne!(embedding, convert_embeddings_to_binary(&[0.25, -1.0]));
+    }
 }
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::context::RequestContext;
+
+static EXPORTER: OnceLock<Arc<OtlpExporter>> = OnceLock::new();
+
+tokio::task_local! {
+    static CURRENT_SPAN: SpanContext;
+}
+
+/// OTLP span kind of the spans recorded, all internal to the application.
+const SPAN_KIND_INTERNAL: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum TelemetryError {
+    #[error("ExportError: {0}")]
+    ExportError(String),
+}
+
+/// Ids of a span and of the trace it belongs to, hex encoded as in W3C trace context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanContext {
+    trace_id: String,
+    span_id: String,
+}
+
+/// A finished span waiting to be exported.
+#[derive(Debug, Clone)]
+struct SpanData {
+    context: SpanContext,
+    parent_span_id: Option<String>,
+    name: String,
+    start_ns: u64,
+    end_ns: u64,
+    attributes: Vec<(&'static str, String)>,
+}
+
+/// Exports the spans of searches, ingestion and chat to an OpenTelemetry collector with
+/// OTLP over HTTP (JSON), see `set_exporter`.
+///
+/// The steps of a request (`embed`, `pinecone.query`, `rerank`, `hydrate`,
+/// `openai.chat`) are recorded as spans of one trace per `RequestContext` scope, under a
+/// `request` span. A context's `trace_id` is used as the trace id if it is one (32 hex
+/// digits), or else hashed into one, so traces can be found by request id. Spans are
+/// buffered and sent `batch_size` at a time; call `flush` before exiting.
+///
+/// The trace is propagated downstream: Pinecone requests carry a W3C `traceparent` header,
+/// and OpenAI requests without a user are sent with `user` set to "trace:{trace id}".
+///
+/// # Fields
+///
+/// * `endpoint`: Required. Base URL of the collector, e.g. "http://localhost:4318"; spans are
+///   posted to `/v1/traces` under it.
+/// * `service_name`: Optional. `service.name` of the spans. Defaults to "openai-pinecone".
+/// * `headers`: Optional. Headers sent with every export, e.g. an API key of the backend.
+/// * `batch_size`: Optional. Spans buffered before they are sent. Defaults to 512.
+///
+/// # Example
+///
+/// ```rust
+/// let exporter = Arc::new(OtlpExporter::builder().endpoint("http://localhost:4318".to_string()).build());
+/// telemetry::set_exporter(exporter.clone());
+///
+/// let context = RequestContext::builder().trace_id(request_id).build();
+/// let answer = context.scope(chat.answer(question)).await?;
+/// exporter.flush().await?;
+/// ```
+#[derive(Debug, TypedBuilder)]
+pub struct OtlpExporter {
+    endpoint: String,
+
+    #[builder(default = "openai-pinecone".to_string())]
+    service_name: String,
+
+    #[builder(default)]
+    headers: HashMap<String, String>,
+
+    #[builder(default = 512)]
+    batch_size: usize,
+
+    #[builder(setter(skip), default)]
+    pending: Mutex<Vec<SpanData>>,
+
+    #[builder(setter(skip), default)]
+    client: reqwest::Client,
+}
+
+/// Records spans with `exporter` from now on. Returns false if an exporter was already set.
+pub fn set_exporter(exporter: Arc<OtlpExporter>) -> bool {
+    EXPORTER.set(exporter).is_ok()
+}
+
+/// The exporter set with `set_exporter`, if any.
+pub fn exporter() -> Option<Arc<OtlpExporter>> {
+    EXPORTER.get().cloned()
+}
+
+/// The span `future` runs in, if spans are being recorded.
+pub fn current_span() -> Option<SpanContext> {
+    CURRENT_SPAN.try_with(Clone::clone).ok()
+}
+
+/// W3C `traceparent` header value of the current span, if spans are being recorded.
+pub fn traceparent() -> Option<String> {
+    current_span().map(|span| format!("00-{}-{}-01", span.trace_id, span.span_id))
+}
+
+/// Runs `future` in a span named `name`, a child of the current span, or the root of the
+/// trace of the current `RequestContext`. Does nothing without an exporter.
+pub(crate) async fn traced<F: Future>(name: &str, future: F) -> F::Output {
+    let Some(exporter) = EXPORTER.get() else {
+        return future.await;
+    };
+
+    let context = RequestContext::current().unwrap_or_default();
+    let context_trace = context.trace_id().as_deref().map(trace_id_of);
+    // A context with a trace of its own starts a new trace, even inside another span.
+    let parent = current_span().filter(|parent| context_trace.as_ref().is_none_or(|trace| *trace == parent.trace_id));
+    let span = SpanContext {
+        trace_id: match (&parent, context_trace) {
+            (Some(parent), _) => parent.trace_id.clone(),
+            (None, Some(trace_id)) => trace_id,
+            (None, None) => random_hex(16),
+        },
+        span_id: random_hex(8),
+    };
+
+    let start_ns = now_ns();
+    let output = CURRENT_SPAN.scope(span.clone(), future).await;
+    let attributes = vec![
+        ("enduser.id", context.user().clone()),
+        ("tenant", context.tenant().clone()),
+        ("request.id", context.trace_id().clone()),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value?)))
+    .collect();
+    exporter.record(SpanData {
+        context: span,
+        parent_span_id: parent.map(|parent| parent.span_id),
+        name: name.to_string(),
+        start_ns,
+        end_ns: now_ns(),
+        attributes,
+    });
+    output
+}
+
+impl OtlpExporter {
+    /// Sends every buffered span. Returns the number of spans sent.
+    pub async fn flush(&self) -> Result<usize, TelemetryError> {
+        let spans = std::mem::take(&mut *self.pending.lock().unwrap());
+        self.send(spans).await
+    }
+
+    /// Spans recorded and not sent yet.
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    fn record(self: &Arc<Self>, span: SpanData) {
+        let batch = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push(span);
+            if pending.len() < self.batch_size.max(1) {
+                return;
+            }
+            std::mem::take(&mut *pending)
+        };
+
+        let exporter = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = exporter.send(batch).await {
+                tracing::warn!("Failed to export spans: {}", e);
+            }
+        });
+    }
+
+    async fn send(&self, spans: Vec<SpanData>) -> Result<usize, TelemetryError> {
+        if spans.is_empty() {
+            return Ok(0);
+        }
+
+        let mut request = self
+            .client
+            .post(format!("{}/v1/traces", self.endpoint.trim_end_matches('/')))
+            .json(&self.body(&spans));
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let response = request.send().await.map_err(|e| TelemetryError::ExportError(e.to_string()))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let message = response.text().await.unwrap_or_default();
+            return Err(TelemetryError::ExportError(format!("{}: {}", status, message)));
+        }
+        Ok(spans.len())
+    }
+
+    /// `ExportTraceServiceRequest` of `spans` in the JSON encoding of OTLP.
+    fn body(&self, spans: &[SpanData]) -> Value {
+        let spans: Vec<Value> = spans
+            .iter()
+            .map(|span| {
+                let mut value = json!({
+                    "traceId": span.context.trace_id,
+                    "spanId": span.context.span_id,
+                    "name": span.name,
+                    "kind": SPAN_KIND_INTERNAL,
+                    "startTimeUnixNano": span.start_ns.to_string(),
+                    "endTimeUnixNano": span.end_ns.to_string(),
+                    "attributes": span.attributes.iter().map(|(key, value)| attribute(key, value)).collect::<Vec<_>>(),
+                });
+                if let Some(parent) = &span.parent_span_id {
+                    value["parentSpanId"] = parent.clone().into();
+                }
+                value
+            })
+            .collect();
+
+        json!({
+            "resourceSpans": [{
+                "resource": { "attributes": [attribute("service.name", &self.service_name)] },
+                "scopeSpans": [{ "scope": { "name": env!("CARGO_PKG_NAME") }, "spans": spans }],
+            }]
+        })
+    }
+}
+
+impl SpanContext {
+    pub fn trace_id(&self) -> &String {
+        &self.trace_id
+    }
+
+    pub fn span_id(&self) -> &String {
+        &self.span_id
+    }
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+/// `id` if it is a valid trace id, or else the first 16 bytes of its SHA-256 in hex.
+fn trace_id_of(id: &str) -> String {
+    let valid = id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit()) && id.bytes().any(|b| b != b'0');
+    if valid {
+        return id.to_ascii_lowercase();
+    }
+    hex(&Sha256::digest(id.as_bytes())[..16])
+}
+
+/// `bytes` random bytes in hex, never all zeros, which W3C reserves for invalid ids.
+fn random_hex(bytes: usize) -> String {
+    let mut buffer = vec![0; bytes];
+    if getrandom::getrandom(&mut buffer).is_err() || buffer.iter().all(|b| *b == 0) {
+        buffer[0] = 1;
+        buffer[1..].iter_mut().zip(now_ns().to_le_bytes()).for_each(|(b, t)| *b = t);
+    }
+    hex(&buffer)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn now_ns() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_id_of() {
+        let id = "4BF92F3577B34DA6A3CE929D0E0E4736";
+        assert_eq!(trace_id_of(id), id.to_ascii_lowercase());
+        assert_eq!(trace_id_of("req-42").len(), 32);
+        assert_eq!(trace_id_of("req-42"), trace_id_of("req-42"));
+        assert_ne!(trace_id_of(&"0".repeat(32)), "0".repeat(32));
+        assert_eq!(random_hex(8).len(), 16);
+    }
+}
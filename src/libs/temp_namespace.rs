@@ -0,0 +1,164 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::pinecone_api::{PineconeApiError, PineconeClient};
+use super::pipeline::{Document, IngestionPipeline, IngestionReport, PipelineError};
+use super::search::SemanticSearch;
+
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// A namespace holding one session's ad-hoc documents, e.g. the files a user attached to a
+/// conversation, that is emptied when the session ends.
+///
+/// The namespace gets a unique name, "chat-" followed by a random UUID-formatted id. Its
+/// vectors are deleted by `close`, or when the handle is dropped without being closed. The
+/// delete on drop is spawned on the current Tokio runtime and its errors are only logged,
+/// so prefer `close` where the session ends in async code.
+///
+/// # Example
+///
+/// ```rust
+/// let session = TempNamespace::new();
+/// session.upload(&[Document::builder().id("upload".to_string()).text(text).build()]).await?;
+/// let chat = RagChat::builder().search(session.search(4)).build();
+/// let answer = chat.ask("What does the attached contract say about termination?").await?;
+/// session.close().await?;
+/// ```
+#[derive(Debug)]
+pub struct TempNamespace {
+    client: PineconeClient,
+    pipeline: IngestionPipeline,
+    closed: bool,
+}
+
+impl TempNamespace {
+    pub fn new() -> Self {
+        TempNamespace::with_prefix("chat")
+    }
+
+    /// Namespace named "{prefix}-{id}".
+    pub fn with_prefix(prefix: &str) -> Self {
+        let name = format!("{}-{}", prefix, session_id());
+        TempNamespace {
+            pipeline: IngestionPipeline::builder().namespace(name.clone()).build(),
+            client: PineconeClient::namespace(&name),
+            closed: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.client.namespace_name().unwrap_or_default()
+    }
+
+    /// Handle on the index scoped to this namespace.
+    pub fn client(&self) -> &PineconeClient {
+        &self.client
+    }
+
+    /// Chunks, embeds, and upserts `documents` into this namespace.
+    pub async fn upload(&self, documents: &[Document]) -> Result<IngestionReport, PipelineError> {
+        self.pipeline.ingest(documents).await
+    }
+
+    /// Search over the `top_k` closest chunks of this namespace.
+    pub fn search(&self, top_k: i64) -> SemanticSearch {
+        SemanticSearch::builder().namespace(self.name().to_string()).top_k(top_k).build()
+    }
+
+    /// Deletes all vectors of this namespace.
+    pub async fn close(mut self) -> Result<(), PineconeApiError> {
+        self.closed = true;
+        self.client.delete_all().await?;
+        Ok(())
+    }
+}
+
+impl Default for TempNamespace {
+    fn default() -> Self {
+        TempNamespace::new()
+    }
+}
+
+impl Drop for TempNamespace {
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let client = self.client.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    if let Err(e) = client.delete_all().await {
+                        tracing::warn!("failed to delete namespace {:?}: {}", client.namespace_name(), e);
+                    }
+                });
+            }
+            Err(_) => tracing::warn!(
+                "namespace {:?} dropped outside a Tokio runtime, its vectors were not deleted",
+                client.namespace_name()
+            ),
+        }
+    }
+}
+
+/// Random 128-bit id formatted like a UUID.
+fn session_id() -> String {
+    let mut halves = [0u64; 2];
+    for half in halves.iter_mut() {
+        let mut hasher = RandomState::new().build_hasher();
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos()).hash(&mut hasher);
+        SESSIONS.fetch_add(1, Ordering::Relaxed).hash(&mut hasher);
+        *half = hasher.finish();
+    }
+    let id = format!("{:016x}{:016x}", halves[0], halves[1]);
+    format!("{}-{}-{}-{}-{}", &id[..8], &id[8..12], &id[12..16], &id[16..20], &id[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::{sample_documents, FakeServices};
+
+    #[test]
+    fn test_session_id() {
+        let id = session_id();
+        assert_eq!(id.split('-').map(str::len).collect::<Vec<_>>(), [8, 4, 4, 4, 12]);
+        assert_ne!(id, session_id());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_close_deletes_namespace() {
+        let fakes = FakeServices::start().await;
+        let session = TempNamespace::new();
+        assert!(session.name().starts_with("chat-"));
+        session.upload(&sample_documents()).await.unwrap();
+        assert_eq!(fakes.vector_count(Some(session.name())), 2);
+        assert_eq!(session.search(1).search("how fast do orders ship").await.unwrap()[0].id(), "shipping#chunk0");
+
+        let name = session.name().to_string();
+        session.close().await.unwrap();
+        assert_eq!(fakes.vector_count(Some(&name)), 0);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_drop_deletes_namespace() {
+        let fakes = FakeServices::seeded().await;
+        let session = TempNamespace::new();
+        session.upload(&sample_documents()).await.unwrap();
+        let name = session.name().to_string();
+        drop(session);
+        for _ in 0..50 {
+            if fakes.vector_count(Some(&name)) == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(fakes.vector_count(Some(&name)), 0);
+        assert_eq!(fakes.vector_count(None), 2);
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::MutexGuard;
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use super::filter;
+use super::math::cosine_similarity;
+use super::openai_api::FinishReason;
+use super::pinecone_data::{Filter, Metadata};
+#[cfg(feature = "native")]
+use super::pipeline::{Document, IngestionPipeline};
+use super::{openai_api, pinecone_api};
+
+/// Dimension of the embeddings the fake OpenAI server returns, as text-embedding-ada-002.
+pub const FAKE_EMBEDDING_DIMENSION: usize = 1536;
+
+/// Name of the fake Pinecone index, whose host is resolved through the fake control plane.
+pub const FAKE_INDEX_NAME: &str = "fake-index";
+
+/// Held by the running `FakeServices`, as the clients they point at are process-wide.
+static RUNNING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Vectors of the fake index by namespace ("" for the default one), then by id.
+type FakeIndex = HashMap<String, BTreeMap<String, StoredVector>>;
+
+#[derive(Debug, Clone)]
+struct StoredVector {
+    values: Vec<f32>,
+    metadata: Metadata,
+}
+
+/// Local fake OpenAI and Pinecone HTTP servers, for integration tests without API keys.
+///
+/// `start` points this crate's OpenAI and Pinecone clients at the fakes, which answer:
+/// * embeddings with `fake_embedding`, so texts sharing words are similar;
+/// * chat and legacy completions with the reply and finish reason set by `set_chat_reply`
+///   and `set_finish_reason`;
+/// * the models list with a fixed list;
+/// * `describe_index` of `FAKE_INDEX_NAME` with the fake's own address as host;
+/// * upsert, query, fetch, update, delete, and index stats from an in-memory index, with
+///   exact cosine similarity and metadata filters evaluated by `filter::matches`.
+///
+/// Each `start` gets a fresh server and an empty index. Base URLs and keys are
+/// process-wide, so `start` waits until the previous `FakeServices` is dropped: tests using
+/// them run one at a time. Settings that can be made once per process (a circuit breaker,
+/// an audit log, a model chain, an exporter), and routing the clients through a
+/// `FaultProxy`, outlive the test; test those in a test binary of their own.
+///
+/// # Example
+///
+/// ```rust
+/// let fakes = FakeServices::start().await;
+/// IngestionPipeline::builder().build().ingest(&documents).await?;
+///
+/// let matches = SemanticSearch::builder().build().search("refund policy").await?;
+/// assert_eq!(matches[0].metadata()["document_id"], "refunds");
+/// assert!(fakes.vector_count(None) > 0);
+/// ```
+pub struct FakeServices {
+    server: MockServer,
+    chat_reply: Arc<Mutex<Reply>>,
+    index: Arc<Mutex<FakeIndex>>,
+    _running: MutexGuard<'static, ()>,
+}
+
+impl FakeServices {
+    pub async fn start() -> Self {
+        let fakes = FakeServices {
+            server: MockServer::start().await,
+            chat_reply: Arc::new(Mutex::new(Reply {
+                content: "This is a fake reply.".to_string(),
+                finish_reason: FinishReason::Stop,
+            })),
+            index: Arc::new(Mutex::new(HashMap::new())),
+            _running: RUNNING.lock().await,
+        };
+
+        fakes.mount("POST", "/v1/embeddings", Embeddings).await;
+        fakes
+            .mount("POST", "/v1/chat/completions", Chat(fakes.chat_reply.clone()))
+            .await;
+        fakes
+            .mount("POST", "/v1/completions", Completions(fakes.chat_reply.clone()))
+            .await;
+        fakes.mount("GET", "/v1/models", Models).await;
+        Mock::given(method("GET"))
+            .and(path_regex("^/indexes/[^/]+$"))
+            .respond_with(DescribeIndex(fakes.server.uri()))
+            .mount(&fakes.server)
+            .await;
+        for (verb, endpoint, operation) in [
+            ("POST", "/vectors/upsert", Operation::Upsert),
+            ("POST", "/query", Operation::Query),
+            ("GET", "/vectors/fetch", Operation::Fetch),
+            ("GET", "/vectors/list", Operation::List),
+            ("POST", "/vectors/update", Operation::Update),
+            ("POST", "/vectors/delete", Operation::Delete),
+            ("POST", "/describe_index_stats", Operation::Stats),
+        ] {
+            fakes.mount(verb, endpoint, Index(operation, fakes.index.clone())).await;
+        }
+
+        openai_api::set_api_key("test-openai-key".to_string());
+        pinecone_api::set_api_key("test-pinecone-key".to_string());
+        openai_api::set_base_url(&format!("{}/v1", fakes.server.uri()));
+        pinecone_api::set_control_plane_url(&fakes.server.uri());
+        pinecone_api::set_index(FAKE_INDEX_NAME);
+        fakes
+    }
+
+    /// `start`, with `sample_documents` ingested into the default namespace.
+    #[cfg(feature = "native")]
+    pub async fn seeded() -> Self {
+        let fakes = Self::start().await;
+        let report = IngestionPipeline::builder().build().ingest(&sample_documents()).await.unwrap();
+        assert!(report.failed().is_empty());
+        fakes
+    }
+
+    /// Content of every chat completion from now on.
+    pub fn set_chat_reply(&self, reply: &str) {
+        self.chat_reply.lock().unwrap().content = reply.to_string();
+    }
+
+    /// Finish reason of every completion from now on, e.g. `Length` to simulate truncation.
+    pub fn set_finish_reason(&self, finish_reason: FinishReason) {
+        self.chat_reply.lock().unwrap().finish_reason = finish_reason;
+    }
+
+    /// Number of vectors stored in `namespace`, or the default namespace.
+    pub fn vector_count(&self, namespace: Option<&str>) -> usize {
+        let index = self.index.lock().unwrap();
+        index.get(namespace.unwrap_or("")).map_or(0, BTreeMap::len)
+    }
+
+    /// Metadata of the vector `id` in `namespace`, or the default namespace.
+    pub fn metadata(&self, namespace: Option<&str>, id: &str) -> Option<Metadata> {
+        let index = self.index.lock().unwrap();
+        index.get(namespace.unwrap_or("")).and_then(|vectors| vectors.get(id)).map(|vector| vector.metadata.clone())
+    }
+
+    /// The underlying server, to mount extra mocks (e.g. errors) or inspect requests.
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    async fn mount<R: Respond + 'static>(&self, verb: &str, endpoint: &str, responder: R) {
+        Mock::given(method(verb))
+            .and(path(endpoint))
+            .respond_with(responder)
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// Two short documents, "refunds" and "shipping", of one chunk each.
+#[cfg(feature = "native")]
+pub fn sample_documents() -> Vec<Document> {
+    [
+        ("refunds", "Refunds are issued within 30 days of purchase."),
+        ("shipping", "Orders ship within two business days."),
+    ]
+    .iter()
+    .map(|(id, text)| Document::builder().id(id.to_string()).text(text.to_string()).build())
+    .collect()
+}
+
+/// Deterministic stand-in for a text embedding: the normalized counts of the text's
+/// lowercase words, hashed into `FAKE_EMBEDDING_DIMENSION` buckets.
+pub fn fake_embedding(text: &str) -> Vec<f32> {
+    let mut values = vec![0.0; FAKE_EMBEDDING_DIMENSION];
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        let hash = Sha256::digest(word.to_lowercase().as_bytes());
+        let bucket = u64::from_le_bytes(hash[..8].try_into().unwrap()) as usize % FAKE_EMBEDDING_DIMENSION;
+        values[bucket] += 1.0;
+    }
+
+    let norm = values.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        values.iter_mut().for_each(|x| *x /= norm);
+    }
+    values
+}
+
+fn respond_json(body: Value) -> ResponseTemplate {
+    // Without pooled connections, clients created on one test's runtime keep working on the next.
+    ResponseTemplate::new(200)
+        .insert_header("connection", "close")
+        .set_body_json(body)
+}
+
+fn bad_request(message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(400)
+        .insert_header("connection", "close")
+        .set_body_json(json!({ "error": { "message": message } }))
+}
+
+fn tokens(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+struct Embeddings;
+
+impl Respond for Embeddings {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = match request.body_json() {
+            Ok(body) => body,
+            Err(e) => return bad_request(&e.to_string()),
+        };
+        let input = body["input"].as_str().unwrap_or_default();
+
+        respond_json(json!({
+            "object": "list",
+            "model": body["model"],
+            "data": [{ "object": "embedding", "index": 0, "embedding": fake_embedding(input) }],
+            "usage": { "prompt_tokens": tokens(input), "total_tokens": tokens(input) },
+        }))
+    }
+}
+
+struct Reply {
+    content: String,
+    finish_reason: FinishReason,
+}
+
+struct Chat(Arc<Mutex<Reply>>);
+
+impl Respond for Chat {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = match request.body_json() {
+            Ok(body) => body,
+            Err(e) => return bad_request(&e.to_string()),
+        };
+        let (reply, finish_reason) = {
+            let reply = self.0.lock().unwrap();
+            (reply.content.clone(), reply.finish_reason.clone())
+        };
+        let prompt_tokens: usize = body["messages"]
+            .as_array()
+            .map(|messages| messages.iter().map(|m| tokens(m["content"].as_str().unwrap_or_default())).sum())
+            .unwrap_or_default();
+
+        let choices: Vec<Value> = (0..body["n"].as_u64().unwrap_or(1))
+            .map(|index| {
+                json!({
+                    "index": index,
+                    "message": { "role": "assistant", "content": reply },
+                    "finish_reason": finish_reason,
+                })
+            })
+            .collect();
+
+        respond_json(json!({
+            "id": "chatcmpl-fake",
+            "object": "chat.completion",
+            "created": 0,
+            "model": body["model"],
+            "choices": choices,
+            "usage": {
+                "prompt_tokens": prompt_tokens,
+                "completion_tokens": tokens(&reply) * choices.len(),
+                "total_tokens": prompt_tokens + tokens(&reply) * choices.len(),
+            },
+        }))
+    }
+}
+
+struct Completions(Arc<Mutex<Reply>>);
+
+impl Respond for Completions {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = match request.body_json() {
+            Ok(body) => body,
+            Err(e) => return bad_request(&e.to_string()),
+        };
+        let (reply, finish_reason) = {
+            let reply = self.0.lock().unwrap();
+            (reply.content.clone(), reply.finish_reason.clone())
+        };
+        let prompt = body["prompt"].as_str().unwrap_or_default();
+        let text = match body["echo"].as_bool() {
+            Some(true) => format!("{}{}", prompt, reply),
+            _ => reply.clone(),
+        };
+        let choices: Vec<Value> = (0..body["n"].as_u64().unwrap_or(1))
+            .map(|index| json!({ "text": text, "index": index, "finish_reason": finish_reason }))
+            .collect();
+
+        respond_json(json!({
+            "id": "cmpl-fake",
+            "object": "text_completion",
+            "created": 0,
+            "model": body["model"],
+            "choices": choices,
+            "usage": {
+                "prompt_tokens": tokens(prompt),
+                "completion_tokens": tokens(&reply) * choices.len(),
+                "total_tokens": tokens(prompt) + tokens(&reply) * choices.len(),
+            },
+        }))
+    }
+}
+
+struct Models;
+
+impl Respond for Models {
+    fn respond(&self, _: &Request) -> ResponseTemplate {
+        let models: Vec<Value> = ["gpt-3.5-turbo", "gpt-4", "text-embedding-ada-002"]
+            .iter()
+            .map(|id| json!({ "id": id, "object": "model", "owned_by": "openai" }))
+            .collect();
+        respond_json(json!({ "object": "list", "data": models }))
+    }
+}
+
+struct DescribeIndex(String);
+
+impl Respond for DescribeIndex {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let name = request.url.path().trim_start_matches("/indexes/");
+        if name != FAKE_INDEX_NAME {
+            return ResponseTemplate::new(404)
+                .insert_header("connection", "close")
+                .set_body_json(json!({ "error": { "code": "NOT_FOUND", "message": "Index not found" } }));
+        }
+
+        respond_json(json!({
+            "name": name,
+            "dimension": FAKE_EMBEDDING_DIMENSION,
+            "metric": "cosine",
+            "host": self.0,
+            "spec": { "serverless": { "cloud": "aws", "region": "us-east-1" } },
+            "status": { "ready": true, "state": "Ready" },
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Upsert,
+    Query,
+    Fetch,
+    List,
+    Update,
+    Delete,
+    Stats,
+}
+
+struct Index(Operation, Arc<Mutex<FakeIndex>>);
+
+impl Respond for Index {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut index = self.1.lock().unwrap();
+        match self.0 {
+            Operation::Fetch => return fetch(&index, request),
+            Operation::List => return list(&index, request),
+            _ => {}
+        }
+
+        let body: Value = match request.body_json() {
+            Ok(body) => body,
+            Err(e) => return bad_request(&e.to_string()),
+        };
+        let namespace = body["namespace"].as_str().unwrap_or_default().to_string();
+        match self.0 {
+            Operation::Upsert => upsert(index.entry(namespace).or_default(), &body),
+            Operation::Query => query(index.entry(namespace).or_default(), &body),
+            Operation::Update => update(index.entry(namespace).or_default(), &body),
+            Operation::Delete => delete(index.entry(namespace).or_default(), &body),
+            Operation::Stats | Operation::Fetch | Operation::List => stats(&index),
+        }
+    }
+}
+
+fn upsert(vectors: &mut BTreeMap<String, StoredVector>, body: &Value) -> ResponseTemplate {
+    let upserts = body["vectors"].as_array().cloned().unwrap_or_default();
+    for vector in &upserts {
+        let Some(id) = vector["id"].as_str() else {
+            return bad_request("vector without id");
+        };
+        vectors.insert(
+            id.to_string(),
+            StoredVector {
+                values: serde_json::from_value(vector["values"].clone()).unwrap_or_default(),
+                metadata: serde_json::from_value(vector["metadata"].clone()).unwrap_or_default(),
+            },
+        );
+    }
+    respond_json(json!({ "upsertedCount": upserts.len() }))
+}
+
+fn query(vectors: &mut BTreeMap<String, StoredVector>, body: &Value) -> ResponseTemplate {
+    // The client sends the query vector as `{"values": [...]}`; Pinecone takes a bare array.
+    let values: Vec<f32> = serde_json::from_value(body["vector"]["values"].clone())
+        .or_else(|_| serde_json::from_value(body["vector"].clone()))
+        .unwrap_or_default();
+    let filter: Filter = serde_json::from_value(body["filter"].clone()).unwrap_or_default();
+    let top_k = body["topK"].as_u64().unwrap_or(10) as usize;
+    let include_values = body["includeValues"].as_bool().unwrap_or(false);
+    let include_metadata = body["includeMetadata"].as_bool().unwrap_or(false);
+
+    let mut matches: Vec<(f32, &String, &StoredVector)> = vectors
+        .iter()
+        .filter(|(_, vector)| filter::matches(&filter, &vector.metadata))
+        .map(|(id, vector)| (cosine_similarity(&values, &vector.values), id, vector))
+        .collect();
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let matches: Vec<Value> = matches
+        .into_iter()
+        .take(top_k)
+        .map(|(score, id, vector)| {
+            let mut found = json!({ "id": id, "score": score });
+            if include_values {
+                found["values"] = json!(vector.values);
+            }
+            if include_metadata {
+                found["metadata"] = json!(vector.metadata);
+            }
+            found
+        })
+        .collect();
+    respond_json(json!({ "matches": matches, "namespace": body["namespace"] }))
+}
+
+fn fetch(index: &FakeIndex, request: &Request) -> ResponseTemplate {
+    let mut ids = Vec::new();
+    let mut namespace = String::new();
+    for (key, value) in request.url.query_pairs() {
+        match key.as_ref() {
+            "ids" => ids.push(value.into_owned()),
+            "namespace" => namespace = value.into_owned(),
+            _ => {}
+        }
+    }
+
+    let found: HashMap<&String, Value> = index
+        .get(&namespace)
+        .map(|vectors| {
+            ids.iter()
+                .filter_map(|id| vectors.get_key_value(id))
+                .map(|(id, vector)| (id, json!({ "id": id, "values": vector.values, "metadata": vector.metadata })))
+                .collect()
+        })
+        .unwrap_or_default();
+    respond_json(json!({ "vectors": found, "namespace": namespace }))
+}
+
+/// Ids with the requested prefix, in pages of `limit` (default 100) like Pinecone's.
+fn list(index: &FakeIndex, request: &Request) -> ResponseTemplate {
+    let mut prefix = String::new();
+    let mut namespace = String::new();
+    let mut limit = 100;
+    let mut after = None;
+    for (key, value) in request.url.query_pairs() {
+        match key.as_ref() {
+            "prefix" => prefix = value.into_owned(),
+            "namespace" => namespace = value.into_owned(),
+            "limit" => limit = value.parse().unwrap_or(limit),
+            "paginationToken" => after = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+
+    let mut ids: Vec<&String> = index
+        .get(&namespace)
+        .map(|vectors| {
+            vectors
+                .keys()
+                .filter(|id| id.starts_with(&prefix) && after.as_ref().is_none_or(|after| *id > after))
+                .take(limit + 1)
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut page = json!({ "namespace": namespace });
+    if ids.len() > limit {
+        ids.truncate(limit);
+        page["pagination"] = json!({ "next": ids[limit - 1] });
+    }
+    page["vectors"] = ids.iter().map(|id| json!({ "id": id })).collect();
+    respond_json(page)
+}
+
+fn update(vectors: &mut BTreeMap<String, StoredVector>, body: &Value) -> ResponseTemplate {
+    let Some(vector) = body["id"].as_str().and_then(|id| vectors.get_mut(id)) else {
+        return respond_json(json!({}));
+    };
+    let metadata: Metadata = serde_json::from_value(body["setMetadata"].clone()).unwrap_or_default();
+    vector.metadata.extend(metadata);
+    respond_json(json!({}))
+}
+
+fn delete(vectors: &mut BTreeMap<String, StoredVector>, body: &Value) -> ResponseTemplate {
+    if body["deleteAll"].as_bool() == Some(true) {
+        vectors.clear();
+    } else if let Some(filter) = body["filter"].as_object() {
+        let filter: Filter = filter.clone().into_iter().collect();
+        vectors.retain(|_, vector| !filter::matches(&filter, &vector.metadata));
+    } else {
+        let ids: Vec<String> = serde_json::from_value(body["ids"].clone()).unwrap_or_default();
+        for id in ids {
+            vectors.remove(&id);
+        }
+    }
+    respond_json(json!({}))
+}
+
+fn stats(index: &FakeIndex) -> ResponseTemplate {
+    let namespaces: HashMap<&String, Value> = index
+        .iter()
+        .filter(|(_, vectors)| !vectors.is_empty())
+        .map(|(namespace, vectors)| (namespace, json!({ "vectorCount": vectors.len() })))
+        .collect();
+    let total: usize = index.values().map(BTreeMap::len).sum();
+
+    respond_json(json!({
+        "namespaces": namespaces,
+        "dimension": FAKE_EMBEDDING_DIMENSION,
+        "indexFullness": 0.0,
+        "totalVectorCount": total,
+    }))
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+    use crate::libs::health::{check_openai, check_pinecone, HealthStatus};
+    use crate::libs::search::SemanticSearch;
+
+    #[tokio::test]
+    async fn test_ingest_and_search() {
+        let fakes = FakeServices::seeded().await;
+        assert_eq!(fakes.vector_count(None), 2);
+
+        let matches = SemanticSearch::builder().top_k(1).build().search("when are refunds issued").await.unwrap();
+        assert_eq!(matches[0].id(), "refunds#chunk0");
+    }
+
+    #[tokio::test]
+    async fn test_health_checks() {
+        let _fakes = FakeServices::seeded().await;
+        assert_eq!(check_openai().await.status(), HealthStatus::Up);
+        assert_eq!(check_pinecone().await.detail(), "2 vectors");
+    }
+
+    #[tokio::test]
+    async fn test_fresh_index_per_start() {
+        drop(FakeServices::seeded().await);
+
+        let second = FakeServices::start().await;
+        assert_eq!(check_pinecone().await.detail(), "0 vectors");
+        assert!(!second.server().received_requests().await.unwrap().is_empty());
+    }
+}
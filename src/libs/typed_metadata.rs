@@ -0,0 +1,203 @@
+use serde::de::value::{Error as ValueError, MapDeserializer};
+use serde::de::{self, DeserializeOwned, Error as _, IntoDeserializer, Unexpected, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::pinecone_data::Metadata;
+
+#[derive(Debug, Error)]
+pub enum MetadataError {
+    #[error("MetadataError: metadata must serialize to a map")]
+    NotAMap,
+
+    #[error("MetadataError: {0:?} is not a string, number, boolean, or list of strings")]
+    Unsupported(String),
+
+    #[error("MetadataError: {0}")]
+    Invalid(String),
+}
+
+/// Metadata of a vector from `metadata`, a struct or map whose fields are strings, numbers,
+/// booleans, unit enum variants, lists of strings, or options of those (None fields are
+/// left out), the values Pinecone can store.
+///
+/// # Example
+///
+/// ```rust
+/// #[derive(Serialize, Deserialize)]
+/// struct Article {
+///     title: String,
+///     year: u16,
+/// }
+///
+/// let vector = Vector::builder().id(id).values(values).build().with_metadata(&article)?;
+/// ```
+pub fn to_metadata<T: Serialize>(metadata: &T) -> Result<Metadata, MetadataError> {
+    let Value::Object(fields) = serde_json::to_value(metadata).map_err(|e| MetadataError::Invalid(e.to_string()))?
+    else {
+        return Err(MetadataError::NotAMap);
+    };
+
+    let mut map = Metadata::new();
+    for (key, value) in fields {
+        match &value {
+            Value::Null => continue,
+            Value::String(_) | Value::Number(_) | Value::Bool(_) => {}
+            Value::Array(items) if items.iter().all(Value::is_string) => {}
+            Value::Array(_) | Value::Object(_) => return Err(MetadataError::Unsupported(key)),
+        }
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
+/// Parses `metadata` into `T`, the reverse of `to_metadata`. Keys `T` has no field for,
+/// such as the "text" and "document_id" of ingested chunks, are ignored unless it denies
+/// unknown fields.
+///
+/// Vectors upserted before metadata kept its JSON types hold only strings; for those,
+/// numeric and boolean fields are parsed from their text.
+pub fn from_metadata<T: DeserializeOwned>(metadata: &Metadata) -> Result<T, MetadataError> {
+    let object = Value::Object(metadata.iter().map(|(key, value)| (key.clone(), value.clone())).collect());
+    let error = match serde_json::from_value(object) {
+        Ok(parsed) => return Ok(parsed),
+        Err(e) => MetadataError::Invalid(e.to_string()),
+    };
+    if !metadata.values().all(Value::is_string) {
+        return Err(error);
+    }
+
+    let fields = metadata
+        .iter()
+        .map(|(key, value)| (key.as_str(), MetadataValue(value.as_str().unwrap_or_default())));
+    T::deserialize(MapDeserializer::<_, ValueError>::new(fields)).map_err(|_| error)
+}
+
+/// A string metadata value, deserialized as whatever primitive its field asks for.
+struct MetadataValue<'a>(&'a str);
+
+impl<'de, 'a> IntoDeserializer<'de, ValueError> for MetadataValue<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl MetadataValue<'_> {
+    fn parse<T: std::str::FromStr>(&self, expected: &'static str) -> Result<T, ValueError> {
+        self.0
+            .trim()
+            .parse()
+            .map_err(|_| ValueError::invalid_value(Unexpected::Str(self.0), &expected))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $kind:literal),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+                visitor.$visit(self.parse($kind)?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for MetadataValue<'_> {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_str(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool: "a boolean",
+        deserialize_i8 => visit_i8: "an integer",
+        deserialize_i16 => visit_i16: "an integer",
+        deserialize_i32 => visit_i32: "an integer",
+        deserialize_i64 => visit_i64: "an integer",
+        deserialize_u8 => visit_u8: "an unsigned integer",
+        deserialize_u16 => visit_u16: "an unsigned integer",
+        deserialize_u32 => visit_u32: "an unsigned integer",
+        deserialize_u64 => visit_u64: "an unsigned integer",
+        deserialize_f32 => visit_f32: "a number",
+        deserialize_f64 => visit_f64: "a number",
+        deserialize_char => visit_char: "a character",
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, ValueError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValueError> {
+        visitor.visit_enum(de::value::StrDeserializer::<ValueError>::new(self.0))
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Status {
+        Draft,
+        Published,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Article {
+        title: String,
+        year: u16,
+        rating: f32,
+        pinned: bool,
+        status: Status,
+        author: Option<String>,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let article = Article {
+            title: "Refunds, explained".to_string(),
+            year: 2023,
+            rating: 4.5,
+            pinned: false,
+            status: Status::Published,
+            author: None,
+        };
+        let mut metadata = to_metadata(&article).unwrap();
+        assert_eq!(metadata["year"], 2023);
+        assert_eq!(metadata["status"], "published");
+        assert!(!metadata.contains_key("author"));
+
+        metadata.insert("text".to_string(), "Refunds are issued within 30 days.".into());
+        assert_eq!(from_metadata::<Article>(&metadata).unwrap(), article);
+
+        // As upserted when all metadata was text.
+        let mut legacy: Metadata = metadata.iter().map(|(key, value)| (key.clone(), value.to_string().trim_matches('"').into())).collect();
+        assert_eq!(from_metadata::<Article>(&legacy).unwrap(), article);
+        legacy.insert("year".to_string(), "last year".into());
+        assert!(matches!(from_metadata::<Article>(&legacy), Err(MetadataError::Invalid(_))));
+
+        assert!(matches!(to_metadata(&vec!["a"]), Err(MetadataError::NotAMap)));
+        let tags = std::collections::HashMap::from([("tags", vec!["a"])]);
+        assert_eq!(to_metadata(&tags).unwrap()["tags"], serde_json::json!(["a"]));
+        let nested = std::collections::HashMap::from([("ratings", vec![1, 2])]);
+        assert!(matches!(to_metadata(&nested), Err(MetadataError::Unsupported(key)) if key == "ratings"));
+    }
+}
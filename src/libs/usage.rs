@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::database::{upsert, Database};
+use super::models;
+
+/// Key the cumulative usage is stored under in the `Database`.
+const USAGE_KEY: &str = "token-usage";
+
+/// Tokens used with one model since usage tracking started.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+/// Cumulative token usage per model, see `openai_api::set_usage_ledger`.
+///
+/// Unlike `TokenBudget`, which counts per key and day to enforce a limit, the ledger only
+/// adds up, so the total spend of a deployment can be reported. The usage is kept in `db`
+/// and shared by processes using the same database.
+///
+/// # Example
+///
+/// ```rust
+/// let ledger = Arc::new(UsageLedger::new(Arc::new(SQLiteDB::new("openai-pinecone.db")?)));
+/// openai_api::set_usage_ledger(ledger.clone());
+/// // ...
+/// for (model, usage) in ledger.usage().await? {
+///     println!("{}: {} tokens, ${:.2}", model, usage.total_tokens(), usage.cost(&model).unwrap_or(0.0));
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct UsageLedger {
+    db: Arc<dyn Database>,
+    lock: Arc<Mutex<()>>,
+}
+
+impl UsageLedger {
+    pub fn new(db: Arc<dyn Database>) -> Self {
+        UsageLedger {
+            db,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Adds a request to `model`'s usage.
+    pub async fn record(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> Result<(), Box<dyn Error>> {
+        let _guard = self.lock.lock().await;
+        let mut usage = self.usage().await?;
+        let entry = usage.entry(model.to_string()).or_default();
+        entry.requests += 1;
+        entry.prompt_tokens += prompt_tokens;
+        entry.completion_tokens += completion_tokens;
+        upsert(self.db.as_ref(), USAGE_KEY, &serde_json::to_string(&usage)?).await
+    }
+
+    /// Usage of every model used so far, by model id as reported by the API.
+    pub async fn usage(&self) -> Result<BTreeMap<String, ModelUsage>, Box<dyn Error>> {
+        match self.db.read(USAGE_KEY).await {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(_) => Ok(BTreeMap::new()),
+        }
+    }
+}
+
+impl ModelUsage {
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    pub fn prompt_tokens(&self) -> u64 {
+        self.prompt_tokens
+    }
+
+    pub fn completion_tokens(&self) -> u64 {
+        self.completion_tokens
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// List price in USD of this usage of `model`, if the model is in the registry.
+    pub fn cost(&self, model: &str) -> Option<f64> {
+        models::lookup(model).map(|info| info.cost(self.prompt_tokens, self.completion_tokens))
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::libs::sql_lite::SQLiteDB;
+
+    #[tokio::test]
+    async fn test_usage_ledger() {
+        let ledger = UsageLedger::new(Arc::new(SQLiteDB::new(":memory:").unwrap()));
+        ledger.record("gpt-4-0613", 1000, 500).await.unwrap();
+        ledger.record("gpt-4-0613", 1000, 0).await.unwrap();
+        ledger.record("text-embedding-ada-002", 200, 0).await.unwrap();
+
+        let usage = ledger.usage().await.unwrap();
+        let gpt4 = &usage["gpt-4-0613"];
+        assert_eq!((gpt4.requests(), gpt4.total_tokens()), (2, 2500));
+        assert!((gpt4.cost("gpt-4-0613").unwrap() - 0.09).abs() < 1e-9);
+        assert_eq!(usage.len(), 2);
+        assert_eq!(ModelUsage::default().cost("unknown"), None);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::{stream, StreamExt, TryStreamExt};
+use serde_json::Value;
+use thiserror::Error;
+use typed_builder::TypedBuilder;
+
+use super::pinecone_api::{PineconeApiError, PineconeClient};
+use super::pinecone_data::{chunk_prefix, Filter, Metadata, PineconeRequest};
+use super::pipeline::{Document, IngestionPipeline, IngestionReport, PipelineError};
+
+/// Separates a document id from its version in the id of a versioned document, e.g.
+/// "refunds@v3".
+pub const VERSION_SEPARATOR: &str = "@v";
+
+/// Metadata key of the version of a chunk, a number from 1.
+pub const VERSION_KEY: &str = "version";
+
+/// Metadata key flagging the chunks of the latest published version of their document.
+pub const LATEST_KEY: &str = "latest";
+
+/// Metadata key of the time, in seconds since the Unix epoch, a version was superseded.
+pub const SUPERSEDED_AT_KEY: &str = "superseded_at";
+
+/// Updates in flight at once when versions are published or superseded.
+const UPDATE_CONCURRENCY: usize = 8;
+
+/// Ids fetched per request.
+const FETCH_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum VersionError {
+    #[error(transparent)]
+    PipelineError(#[from] PipelineError),
+
+    #[error(transparent)]
+    PineconeError(#[from] PineconeApiError),
+
+    #[error("VersionError: {failed} chunks of version {version} failed, it was not published")]
+    Incomplete { version: u64, failed: usize },
+}
+
+/// Keeps every version of a document in the index, and flags the latest one so queries
+/// can be restricted to it with `latest_only`.
+///
+/// Version `n` of the document `id` is ingested as the document "{id}@v{n}", so its chunk
+/// ids share the prefix of that id, and its chunks carry `version` and `latest` metadata.
+/// A new version is upserted unpublished, then flagged latest once all of its chunks are
+/// in, and only then is the version it replaces unflagged: a query in between may see
+/// both, but never neither. Superseded versions are deleted by `collect_garbage` once
+/// they are older than `retention`, so clients holding their ids have time to move on.
+///
+/// Versions are numbered from the chunk ids `list` finds, which needs a serverless index.
+/// Upserts a spool is holding are not flagged when the version is published.
+///
+/// # Fields
+///
+/// * `pipeline`: Required. Pipeline the versions are ingested with, and whose namespace
+///   they are kept in.
+/// * `retention`: Optional. How long a superseded version is kept. Defaults to 7 days.
+///
+/// # Example
+///
+/// ```rust
+/// let versions = DocumentVersions::builder().pipeline(pipeline).build();
+/// let (version, report) = versions.upsert_version(&document).await?;
+///
+/// let search = SemanticSearch::builder().filter(latest_only(None)).build();
+/// versions.collect_garbage(document.id()).await?;
+/// ```
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct DocumentVersions {
+    pipeline: IngestionPipeline,
+
+    #[builder(default = Duration::from_secs(7 * 24 * 60 * 60))]
+    retention: Duration,
+}
+
+impl DocumentVersions {
+    /// Ingests `document` as its next version and publishes it as the latest. Returns the
+    /// version and the ingestion report. If any chunk fails, the version stays unpublished,
+    /// the previous one stays latest, and `VersionError::Incomplete` is returned.
+    pub async fn upsert_version(&self, document: &Document) -> Result<(u64, IngestionReport), VersionError> {
+        let versions = self.versions(document.id()).await?;
+        let version = versions.keys().next_back().map_or(1, |latest| latest + 1);
+
+        let mut metadata = document.metadata().clone();
+        metadata.insert(VERSION_KEY.to_string(), version.into());
+        metadata.insert(LATEST_KEY.to_string(), false.into());
+        let versioned = Document::builder()
+            .id(versioned_id(document.id(), version))
+            .text(document.text().clone())
+            .metadata(metadata)
+            .build();
+        let report = self.pipeline.ingest(std::slice::from_ref(&versioned)).await?;
+        if !report.failed().is_empty() {
+            return Err(VersionError::Incomplete {
+                version,
+                failed: report.failed().len(),
+            });
+        }
+
+        self.set_metadata(report.vector_ids(), Metadata::from([(LATEST_KEY.to_string(), true.into())]))
+            .await?;
+        let superseded = Metadata::from([
+            (LATEST_KEY.to_string(), false.into()),
+            (SUPERSEDED_AT_KEY.to_string(), now().into()),
+        ]);
+        for (old, metadata) in self.first_chunks(&versions).await? {
+            if is_latest(&metadata) {
+                self.set_metadata(&versions[&old], superseded.clone()).await?;
+            }
+        }
+        Ok((version, report))
+    }
+
+    /// Chunk ids of every version of the document `document_id` in the index, by version.
+    pub async fn versions(&self, document_id: &str) -> Result<BTreeMap<u64, Vec<String>>, VersionError> {
+        let prefix = format!("{}{}", document_id, VERSION_SEPARATOR);
+        let mut versions: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+        for id in self.client().list_ids(&prefix).await? {
+            if let Some(version) = parse_version(document_id, &id) {
+                versions.entry(version).or_default().push(id);
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Deletes the versions of the document `document_id` older than its latest published
+    /// one that were superseded more than `retention` ago, or never published. Returns the
+    /// number of chunks deleted.
+    pub async fn collect_garbage(&self, document_id: &str) -> Result<usize, VersionError> {
+        let versions = self.versions(document_id).await?;
+        let states = self.first_chunks(&versions).await?;
+        let Some(published) = states.iter().rev().find(|(_, metadata)| is_latest(metadata)).map(|(version, _)| *version)
+        else {
+            return Ok(0);
+        };
+
+        let now = now();
+        let mut deleted = 0;
+        for (version, metadata) in states.range(..published) {
+            let expired = match metadata.get(SUPERSEDED_AT_KEY).and_then(Value::as_u64) {
+                Some(superseded_at) => now.saturating_sub(superseded_at) >= self.retention.as_secs(),
+                None => !is_latest(metadata),
+            };
+            if expired {
+                let ids = &versions[version];
+                for batch in ids.chunks(FETCH_BATCH_SIZE) {
+                    self.client().delete(batch.to_vec()).await?;
+                }
+                deleted += ids.len();
+            }
+        }
+        Ok(deleted)
+    }
+
+    fn client(&self) -> PineconeClient {
+        match self.pipeline.namespace() {
+            Some(namespace) => PineconeClient::namespace(namespace),
+            None => PineconeClient::default(),
+        }
+    }
+
+    /// Metadata of the first chunk of each version, which every chunk of it shares as far
+    /// as versioning goes.
+    async fn first_chunks(&self, versions: &BTreeMap<u64, Vec<String>>) -> Result<BTreeMap<u64, Metadata>, VersionError> {
+        let firsts: Vec<(u64, &String)> = versions
+            .iter()
+            .filter_map(|(version, ids)| ids.first().map(|id| (*version, id)))
+            .collect();
+
+        let mut states = BTreeMap::new();
+        for batch in firsts.chunks(FETCH_BATCH_SIZE) {
+            let response = self.client().fetch(batch.iter().map(|(_, id)| id.to_string()).collect()).await?;
+            let vectors = response.vectors().clone().unwrap_or_default();
+            for (version, id) in batch {
+                if let Some(vector) = vectors.get(*id) {
+                    states.insert(*version, vector.metadata().clone());
+                }
+            }
+        }
+        Ok(states)
+    }
+
+    async fn set_metadata(&self, ids: &[String], metadata: Metadata) -> Result<(), VersionError> {
+        let client = self.client();
+        stream::iter(ids)
+            .map(|id| {
+                let request = PineconeRequest::builder().id(id.clone()).metadata(metadata.clone()).build();
+                client.update(request)
+            })
+            .buffer_unordered(UPDATE_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(())
+    }
+}
+
+/// Id of version `version` of the document `document_id`.
+pub fn versioned_id(document_id: &str, version: u64) -> String {
+    format!("{}{}{}", document_id, VERSION_SEPARATOR, version)
+}
+
+/// `filter` restricted to the chunks of the latest version of their document. Chunks
+/// ingested without `DocumentVersions` have no `latest` flag and are left out.
+///
+/// # Example
+///
+/// ```rust
+/// let search = SemanticSearch::builder().filter(latest_only(Some(filter))).build();
+/// ```
+pub fn latest_only(filter: Option<Filter>) -> Filter {
+    let mut filter = filter.unwrap_or_default();
+    filter.insert(LATEST_KEY.to_string(), true.into());
+    filter
+}
+
+/// Version of the chunk `chunk_id` of a version of `document_id`, if it is one.
+fn parse_version(document_id: &str, chunk_id: &str) -> Option<u64> {
+    let rest = chunk_id.strip_prefix(document_id)?.strip_prefix(VERSION_SEPARATOR)?;
+    let digits = &rest[..rest.find(|c: char| !c.is_ascii_digit())?];
+    let version = digits.parse().ok()?;
+    chunk_id.starts_with(&chunk_prefix(&versioned_id(document_id, version))).then_some(version)
+}
+
+fn is_latest(metadata: &Metadata) -> bool {
+    metadata.get(LATEST_KEY).and_then(Value::as_bool) == Some(true)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::pinecone_data::chunk_id;
+    #[cfg(feature = "test-util")]
+    use crate::libs::search::SemanticSearch;
+    #[cfg(feature = "test-util")]
+    use crate::libs::test_util::FakeServices;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("refunds", &chunk_id("refunds@v12", 3)), Some(12));
+        assert_eq!(parse_version("refunds", &chunk_id("refunds", 0)), None);
+        assert_eq!(parse_version("refunds", &chunk_id("refunds@v2@v1", 0)), None);
+        assert_eq!(parse_version("refunds", &chunk_id("refunds@vx", 0)), None);
+        assert_eq!(latest_only(None)[LATEST_KEY], true);
+    }
+
+    #[cfg(feature = "test-util")]
+    fn policy(text: &str) -> Document {
+        Document::builder().id("policy".to_string()).text(text.to_string()).build()
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_latest_only_search() {
+        let _fakes = FakeServices::start().await;
+        let versions = DocumentVersions::builder()
+            .pipeline(IngestionPipeline::builder().namespace("versioned".to_string()).build())
+            .build();
+        assert_eq!(versions.upsert_version(&policy("Refunds take 30 days.")).await.unwrap().0, 1);
+        assert_eq!(versions.upsert_version(&policy("Refunds take 60 days.")).await.unwrap().0, 2);
+        let latest = SemanticSearch::builder()
+            .namespace("versioned".to_string())
+            .filter(latest_only(None))
+            .build()
+            .search("how long do refunds take")
+            .await
+            .unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].metadata()["version"], 2);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_collect_garbage() {
+        let _fakes = FakeServices::start().await;
+        let pipeline = || IngestionPipeline::builder().namespace("versioned".to_string()).build();
+        let versions = DocumentVersions::builder().pipeline(pipeline()).build();
+        versions.upsert_version(&policy("Refunds take 30 days.")).await.unwrap();
+        versions.upsert_version(&policy("Refunds take 60 days.")).await.unwrap();
+        assert_eq!(versions.collect_garbage("policy").await.unwrap(), 0);
+
+        let expiring = DocumentVersions::builder().pipeline(pipeline()).retention(Duration::ZERO).build();
+        assert_eq!(expiring.collect_garbage("policy").await.unwrap(), 1);
+        assert_eq!(versions.versions("policy").await.unwrap().keys().collect::<Vec<_>>(), [&2]);
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use typed_builder::TypedBuilder;
+
+use super::database::{upsert, Database};
+use super::loaders::directory::{load_directory, load_document};
+use super::observer::ObserverError;
+use super::pinecone_api::PineconeApiError;
+use super::pinecone_data::{Filter, IdList, PineconeRequest};
+use super::pipeline::{IngestionPipeline, IngestionReport, PipelineError};
+
+/// Prefix of the state keys the vector ids of each file are recorded under.
+const IDS_KEY_PREFIX: &str = "watch-ids:";
+
+#[derive(Debug, Error)]
+pub enum WatchError {
+    #[error(transparent)]
+    PipelineError(#[from] PipelineError),
+
+    #[error(transparent)]
+    PineconeError(#[from] PineconeApiError),
+
+    #[error("NotifyError: {0}")]
+    NotifyError(#[from] notify::Error),
+
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+
+    #[error("StateError: {0}")]
+    StateError(String),
+
+    #[error(transparent)]
+    ObserverError(#[from] ObserverError),
+}
+
+/// Keeps the vectors of a directory in sync with its files.
+///
+/// The vector ids produced for each file are recorded in the `Database` under the file's
+/// path. When a file changes, its old vectors are deleted (by the recorded ids, or by a
+/// `source` metadata filter when nothing was recorded) and only that file is re-ingested.
+///
+/// # Fields
+///
+/// * `root`: Required. Directory to keep in sync.
+/// * `pipeline`: Required. Pipeline files are ingested with.
+/// * `state`: Required. Database the path to vector id mapping is persisted in.
+/// * `debounce`: Optional. Quiet period before a burst of change events is processed. Defaults to 500ms.
+///
+/// # Example
+///
+/// ```rust
+/// let watcher = DirectoryWatcher::builder()
+///     .root(PathBuf::from("docs"))
+///     .pipeline(pipeline)
+///     .state(Arc::new(SQLiteDB::new("state.db")?))
+///     .build();
+///
+/// watcher.sync_all().await?;
+/// watcher.run().await?;
+/// ```
+#[derive(TypedBuilder)]
+pub struct DirectoryWatcher {
+    root: PathBuf,
+    pipeline: IngestionPipeline,
+    state: Arc<dyn Database>,
+
+    #[builder(default = Duration::from_millis(500))]
+    debounce: Duration,
+}
+
+impl DirectoryWatcher {
+    /// Re-ingests every supported file under `root`, replacing its previous vectors.
+    pub async fn sync_all(&self) -> Result<IngestionReport, WatchError> {
+        let root = self.root.canonicalize()?;
+        let (documents, skipped) = load_directory(&root)?.into_parts();
+
+        let mut total = IngestionReport::default();
+        for document in documents {
+            let source = document.metadata().get("source").and_then(Value::as_str).unwrap_or_default().to_string();
+            self.remove(&source).await?;
+
+            let report = self.pipeline.ingest(std::slice::from_ref(&document)).await?;
+            self.record(&source, report.vector_ids()).await?;
+            total = total.merge(report);
+        }
+
+        Ok(total.with_skipped(skipped))
+    }
+
+    /// Brings the vectors of one file up to date: re-ingests it if it exists and is
+    /// supported, otherwise only deletes its old vectors.
+    pub async fn sync_path(&self, path: &Path) -> Result<Option<IngestionReport>, WatchError> {
+        let root = self.root.canonicalize()?;
+        let source = path.to_string_lossy().to_string();
+        self.remove(&source).await?;
+
+        match load_document(&root, path) {
+            Ok(document) => {
+                let report = self.pipeline.ingest(&[document]).await?;
+                self.record(&source, report.vector_ids()).await?;
+                Ok(Some(report))
+            }
+            // Removed, emptied, or no longer a supported file: its old vectors are gone.
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Watches `root` and syncs changed files until the watcher fails.
+    pub async fn run(&self) -> Result<(), WatchError> {
+        let root = self.root.canonicalize()?;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+
+        while let Some(event) = receiver.recv().await {
+            let mut paths: HashSet<PathBuf> = event?.paths.into_iter().collect();
+
+            // Editors write files in several steps; wait for the burst to settle.
+            while let Ok(Some(event)) = tokio::time::timeout(self.debounce, receiver.recv()).await {
+                paths.extend(event?.paths);
+            }
+
+            for path in paths {
+                if path.is_dir() {
+                    continue;
+                }
+                match self.sync_path(&path).await {
+                    Ok(Some(report)) => tracing::info!("{}: upserted {} vectors", path.display(), report.upserted()),
+                    Ok(None) => tracing::info!("{}: removed", path.display()),
+                    Err(e) => tracing::warn!("{}: {}", path.display(), e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&self, source: &str) -> Result<(), WatchError> {
+        let ids = self.recorded(source).await?;
+        let namespace = self.pipeline.namespace().clone();
+
+        let request = match (ids.is_empty(), namespace) {
+            (false, Some(namespace)) => PineconeRequest::builder()
+                .ids(IdList::TextIds(ids.clone()))
+                .namespace(namespace)
+                .build(),
+            (false, None) => PineconeRequest::builder().ids(IdList::TextIds(ids.clone())).build(),
+            (true, namespace) => {
+                let mut filter = Filter::new();
+                filter.insert("source".to_string(), source.into());
+                match namespace {
+                    Some(namespace) => PineconeRequest::builder().filter(filter).namespace(namespace).build(),
+                    None => PineconeRequest::builder().filter(filter).build(),
+                }
+            }
+        };
+
+        request.delete().await?;
+        if let (false, Some(observer)) = (ids.is_empty(), self.pipeline.observer()) {
+            observer.on_delete(&ids).await?;
+        }
+        self.record(source, &[]).await
+    }
+
+    async fn recorded(&self, source: &str) -> Result<Vec<String>, WatchError> {
+        match self.state.read(&ids_key(source)).await {
+            Ok(data) => serde_json::from_str(&data).map_err(|e| WatchError::StateError(e.to_string())),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+
+    async fn record(&self, source: &str, ids: &[String]) -> Result<(), WatchError> {
+        let data = serde_json::to_string(ids).map_err(|e| WatchError::StateError(e.to_string()))?;
+        upsert(self.state.as_ref(), &ids_key(source), &data)
+            .await
+            .map_err(|e| WatchError::StateError(e.to_string()))
+    }
+}
+
+fn ids_key(source: &str) -> String {
+    format!("{}{}", IDS_KEY_PREFIX, source)
+}
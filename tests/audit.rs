@@ -0,0 +1,118 @@
+//! The audit log is set once per process and records every later delete, so it is tested
+//! in a binary of its own.
+
+use std::error::Error;
+use std::sync::{Arc, OnceLock};
+
+use async_trait::async_trait;
+
+use openai_test::libs::audit::{AuditLog, AuditOperation};
+use openai_test::libs::context::RequestContext;
+use openai_test::libs::database::Database;
+use openai_test::libs::pinecone_api::{self, PineconeClient};
+use openai_test::libs::pipeline::IngestionPipeline;
+use openai_test::libs::sql_lite::SQLiteDB;
+use openai_test::libs::test_util::{sample_documents, FakeServices};
+
+/// Namespace whose deletes the audit log fails to record.
+const UNRECORDED_NAMESPACE: &str = "unrecorded";
+
+/// A database refusing to create the entries of deletes in `UNRECORDED_NAMESPACE`.
+#[derive(Debug)]
+struct RefusingDB(SQLiteDB);
+
+#[async_trait]
+impl Database for RefusingDB {
+    async fn create(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>> {
+        if data.contains(&format!("\"namespace\":\"{}\"", UNRECORDED_NAMESPACE)) {
+            return Err("disk full".into());
+        }
+        self.0.create(id, data).await
+    }
+
+    async fn read(&self, id: &str) -> Result<String, Box<dyn Error>> {
+        self.0.read(id).await
+    }
+
+    async fn update(&self, id: &str, data: &str) -> Result<(), Box<dyn Error>> {
+        self.0.update(id, data).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        self.0.delete(id).await
+    }
+
+    async fn ids(&self, prefix: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.0.ids(prefix).await
+    }
+}
+
+/// The audit log of the process, set by the first test to use it.
+fn audit_log() -> Arc<AuditLog> {
+    static LOG: OnceLock<Arc<AuditLog>> = OnceLock::new();
+    LOG.get_or_init(|| {
+        let log = Arc::new(AuditLog::new(Arc::new(RefusingDB(SQLiteDB::new(":memory:").unwrap()))));
+        assert!(pinecone_api::set_audit_log(log.clone()));
+        log
+    })
+    .clone()
+}
+
+#[tokio::test]
+async fn test_deletes_are_audited() {
+    let fakes = FakeServices::start().await;
+    let audit = audit_log();
+    IngestionPipeline::builder().namespace("audited".to_string()).build().ingest(&sample_documents()).await.unwrap();
+
+    let audited = PineconeClient::namespace("audited");
+    let admin = RequestContext::builder().user("admin".to_string()).build();
+    admin
+        .scope(async {
+            audited.delete(vec!["refunds#chunk0".to_string()]).await.unwrap();
+            audited.delete_all().await.unwrap();
+        })
+        .await;
+    assert_eq!(fakes.vector_count(Some("audited")), 0);
+
+    let entries: Vec<_> = audit
+        .entries()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.namespace().as_deref() == Some("audited"))
+        .collect();
+    let operations: Vec<_> = entries.iter().map(|entry| (entry.operation(), entry.affected())).collect();
+    assert_eq!(operations, [(AuditOperation::Delete, Some(1)), (AuditOperation::DeleteAll, Some(1))]);
+    assert_eq!(entries[0].ids(), &["refunds#chunk0"]);
+    assert!(entries.iter().all(|entry| entry.user().as_deref() == Some("admin") && entry.error().is_none()));
+}
+
+#[tokio::test]
+async fn test_unrecorded_delete_is_counted() {
+    let fakes = FakeServices::start().await;
+    let audit = audit_log();
+    let pipeline = IngestionPipeline::builder().namespace(UNRECORDED_NAMESPACE.to_string()).build();
+    pipeline.ingest(&sample_documents()).await.unwrap();
+
+    let failures = audit.failures();
+    let unrecorded = PineconeClient::namespace(UNRECORDED_NAMESPACE);
+    unrecorded.delete(vec!["refunds#chunk0".to_string()]).await.unwrap();
+    assert_eq!(fakes.vector_count(Some(UNRECORDED_NAMESPACE)), 1);
+    assert_eq!(audit.failures(), failures + 1);
+    let entries = audit.entries().await.unwrap();
+    assert!(entries.iter().all(|entry| entry.namespace().as_deref() != Some(UNRECORDED_NAMESPACE)));
+}
+
+#[tokio::test]
+async fn test_counted_delete_all_skips_stats() {
+    let fakes = FakeServices::start().await;
+    let audit = audit_log();
+    IngestionPipeline::builder().namespace("counted".to_string()).build().ingest(&sample_documents()).await.unwrap();
+
+    PineconeClient::namespace("counted").delete_all_counted(2).await.unwrap();
+    let requests = fakes.server().received_requests().await.unwrap();
+    assert!(requests.iter().all(|request| request.url.path() != "/describe_index_stats"));
+    let entries = audit.entries().await.unwrap();
+    let counted = entries.iter().find(|entry| entry.namespace().as_deref() == Some("counted")).unwrap();
+    assert_eq!((counted.operation(), counted.affected()), (AuditOperation::DeleteAll, Some(2)));
+}
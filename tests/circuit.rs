@@ -0,0 +1,45 @@
+//! The circuit breaker is set once per process and, once open, refuses every later request
+//! to the index, so it is tested in a binary of its own.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use openai_test::libs::circuit::CircuitBreaker;
+use openai_test::libs::local_index::LocalIndex;
+use openai_test::libs::observer::VectorRecord;
+use openai_test::libs::pinecone_api;
+use openai_test::libs::search::SemanticSearch;
+use openai_test::libs::test_util::{fake_embedding, FakeServices};
+
+#[tokio::test]
+async fn test_open_circuit_serves_degraded_results() {
+    let fakes = FakeServices::seeded().await;
+    Mock::given(method("POST"))
+        .and(path("/query"))
+        .respond_with(ResponseTemplate::new(503))
+        .with_priority(1)
+        .mount(fakes.server())
+        .await;
+    assert!(pinecone_api::set_circuit_breaker(Arc::new(CircuitBreaker::builder().failure_threshold(1).build())));
+    let record = VectorRecord::new(
+        "local#chunk0".to_string(),
+        None,
+        fake_embedding("refunds"),
+        HashMap::from([("text".to_string(), "Refunds take 30 days.".into())]),
+    );
+    let search = SemanticSearch::builder().top_k(1).build().with_fallback(Arc::new(LocalIndex::new(vec![record])));
+    let matches = search.search("refunds").await.unwrap();
+    assert_eq!(matches[0].id(), "local#chunk0");
+    assert!(matches[0].degraded() && pinecone_api::circuit_open());
+
+    let queries = || async {
+        let requests = fakes.server().received_requests().await.unwrap();
+        requests.iter().filter(|request| request.url.path() == "/query").count()
+    };
+    let sent = queries().await;
+    assert!(search.search("refunds").await.unwrap()[0].degraded());
+    assert_eq!(queries().await, sent);
+}
@@ -0,0 +1,33 @@
+//! The model chain is set once per process, so it is tested in a binary of its own.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::json;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use openai_test::libs::fallback::ModelChain;
+use openai_test::libs::openai_api::{self, Message, OpenAIRequest};
+use openai_test::libs::test_util::FakeServices;
+
+#[tokio::test]
+async fn test_model_chain_falls_back() {
+    let fakes = FakeServices::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(body_partial_json(json!({ "model": "fake-retired" })))
+        .respond_with(ResponseTemplate::new(404).set_body_string("The model `fake-retired` does not exist"))
+        .with_priority(1)
+        .mount(fakes.server())
+        .await;
+    let chain = Arc::new(ModelChain::new(vec!["fake-retired".to_string(), "gpt-3.5-turbo".to_string()]));
+    assert!(openai_api::set_model_chain(chain.clone()));
+
+    let retired = OpenAIRequest::builder()
+        .model("fake-retired".to_string())
+        .messages(vec![Message::builder().role("user".to_string()).content("Hi".to_string()).build()])
+        .build();
+    assert_eq!(retired.send().await.unwrap().model(), "gpt-3.5-turbo");
+    assert_eq!(chain.served(), HashMap::from([("gpt-3.5-turbo".to_string(), 1)]));
+}
@@ -0,0 +1,50 @@
+//! `FaultProxy::route_clients` sends every later request of the process through the proxy,
+//! so the proxy is tested in a binary of its own.
+
+use std::time::Duration;
+
+use openai_test::libs::faults::{FaultPlan, FaultProxy};
+use openai_test::libs::pipeline::IngestionPipeline;
+use openai_test::libs::search::SemanticSearch;
+use openai_test::libs::test_util::{sample_documents, FakeServices};
+
+#[tokio::test]
+async fn test_retries_recover_faulty_upserts() {
+    let fakes = FakeServices::start().await;
+    let proxy = FaultProxy::start(&fakes.uri()).await.unwrap();
+    proxy.route_clients();
+    proxy.set_plan(
+        FaultPlan::builder()
+            .paths(vec!["/vectors/upsert".to_string()])
+            .max_latency(Duration::from_millis(20))
+            .drop_rate(0.3)
+            .partial_batch_rate(0.3)
+            .seed(7)
+            .build(),
+    );
+
+    let pipeline = IngestionPipeline::builder().namespace("faulty".to_string()).chunk_tokens(3).build();
+    let mut report = pipeline.ingest(&sample_documents()).await.unwrap();
+    let chunks = report.chunks();
+    for _ in 0..20 {
+        if report.failed().is_empty() {
+            break;
+        }
+        report = pipeline.retry_failures(&report.failure_report()).await.unwrap();
+    }
+    assert!(report.failed().is_empty());
+    assert_eq!(fakes.vector_count(Some("faulty")), chunks);
+    let stats = proxy.stats();
+    assert!(stats.dropped() + stats.partial() > 0 && stats.delayed() > 0);
+}
+
+#[tokio::test]
+async fn test_malformed_responses_fail_search() {
+    let fakes = FakeServices::seeded().await;
+    let proxy = FaultProxy::start(&fakes.uri()).await.unwrap();
+    proxy.route_clients();
+    proxy.set_plan(FaultPlan::builder().paths(vec!["/query".to_string()]).malformed_rate(1.0).build());
+
+    assert!(SemanticSearch::builder().build().search("refunds").await.is_err());
+    assert_eq!(proxy.stats().malformed(), 1);
+}
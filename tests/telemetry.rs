@@ -0,0 +1,51 @@
+//! The span exporter is set once per process and records every later request, so it is
+//! tested in a binary of its own.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use openai_test::libs::context::RequestContext;
+use openai_test::libs::search::SemanticSearch;
+use openai_test::libs::telemetry::{self, OtlpExporter};
+use openai_test::libs::test_util::FakeServices;
+
+#[tokio::test]
+async fn test_search_spans_are_exported() {
+    let fakes = FakeServices::seeded().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/traces"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(fakes.server())
+        .await;
+    let exporter = Arc::new(OtlpExporter::builder().endpoint(fakes.uri()).batch_size(100_000).build());
+    assert!(telemetry::set_exporter(exporter.clone()));
+
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let context = RequestContext::builder().user("user-42".to_string()).trace_id(trace_id.to_string()).build();
+    let search = SemanticSearch::builder().top_k(1).mmr_lambda(0.5).build();
+    context.scope(search.search("how fast do orders ship")).await.unwrap();
+    assert!(exporter.flush().await.unwrap() > 0);
+
+    let requests = fakes.server().received_requests().await.unwrap();
+    let export = requests.iter().rfind(|request| request.url.path() == "/v1/traces").unwrap();
+    let body: Value = serde_json::from_slice(&export.body).unwrap();
+    let spans: Vec<&Value> = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|span| span["traceId"] == trace_id)
+        .collect();
+    let names: HashSet<&str> = spans.iter().map(|span| span["name"].as_str().unwrap()).collect();
+    assert_eq!(names, HashSet::from(["request", "embed", "pinecone.query", "rerank"]));
+    let root = spans.iter().find(|span| span["name"] == "request").unwrap();
+    assert!(root.get("parentSpanId").is_none());
+    assert!(spans.iter().filter(|span| span["name"] != "request").all(|span| span["parentSpanId"] == root["spanId"]));
+
+    let query = requests.iter().rfind(|request| request.url.path() == "/query").unwrap();
+    let traceparent = query.headers.get("traceparent").unwrap().to_str().unwrap();
+    assert!(traceparent.starts_with(&format!("00-{}-", trace_id)), "{}", traceparent);
+}